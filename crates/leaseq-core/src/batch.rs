@@ -0,0 +1,394 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+/// A batch job file: several tasks described in one JSON or YAML document.
+///
+/// ```json
+/// {
+///   "lease": "local:myhost",
+///   "tasks": [
+///     { "id": "prep", "command": "python prep.py" },
+///     { "id": "train", "command": "python train.py", "gpus": 1, "depends_on": ["prep"] }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchFile {
+    #[serde(default)]
+    pub lease: Option<String>,
+    pub tasks: Vec<BatchTask>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchTask {
+    pub id: String,
+    pub command: String,
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default)]
+    pub gpus: u32,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("line {line}, column {column}: {message}")]
+pub struct BatchParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchIssue {
+    EmptyId { index: usize },
+    EmptyCommand { task: String },
+    DuplicateId { task: String },
+    UnknownDependency { task: String, dependency: String },
+    DependencyCycle { cycle: Vec<String> },
+    UnknownNode { task: String, node: String },
+    InvalidEnv { task: String, key: String },
+}
+
+impl std::fmt::Display for BatchIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchIssue::EmptyId { index } => write!(f, "task #{} has an empty id", index),
+            BatchIssue::EmptyCommand { task } => write!(f, "task '{}' has an empty command", task),
+            BatchIssue::DuplicateId { task } => write!(f, "task id '{}' is used more than once", task),
+            BatchIssue::UnknownDependency { task, dependency } => {
+                write!(f, "task '{}' depends on unknown task '{}'", task, dependency)
+            }
+            BatchIssue::DependencyCycle { cycle } => {
+                write!(f, "dependency cycle: {}", cycle.join(" -> "))
+            }
+            BatchIssue::UnknownNode { task, node } => {
+                write!(f, "task '{}' targets unknown node '{}'", task, node)
+            }
+            BatchIssue::InvalidEnv { task, key } => {
+                write!(f, "task '{}' has invalid env var name {:?}", task, key)
+            }
+        }
+    }
+}
+
+/// Parse a batch file, keeping the position of syntax/schema errors.
+pub fn parse_batch(content: &str) -> Result<BatchFile, BatchParseError> {
+    serde_json::from_str(content).map_err(|e| BatchParseError {
+        line: e.line(),
+        column: e.column(),
+        message: e.to_string(),
+    })
+}
+
+/// Parse a batch file as YAML if `path` ends in `.yaml` or `.yml`, as JSON
+/// otherwise.
+pub fn parse_batch_file(path: &Path, content: &str) -> Result<BatchFile, BatchParseError> {
+    if !path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml") {
+        return parse_batch(content);
+    }
+    serde_yaml::from_str(content).map_err(|e| {
+        let (line, column) = e.location().map_or((0, 0), |l| (l.line(), l.column()));
+        BatchParseError { line, column, message: e.to_string() }
+    })
+}
+
+/// One command per line: blank lines and `#` comments are skipped, the
+/// rest are trimmed and kept as-is (no CSV quoting or per-task fields).
+pub fn parse_command_lines(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Parse a CSV sweep into batch tasks (ids `row1`, `row2`, ...).
+///
+/// Without a header each row is `command,key=value,...` and the pairs become
+/// labels. If the first row starts with a `command` column it is a header: the
+/// other columns name env vars set per row, and are recorded as labels too.
+///
+/// ```text
+/// command,LR,SEED
+/// python train.py,0.1,1
+/// python train.py,0.01,1
+/// ```
+pub fn parse_csv_batch(content: &str) -> Result<BatchFile, BatchParseError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(content.as_bytes());
+
+    let mut header: Option<Vec<String>> = None;
+    let mut tasks = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| {
+            let line = e.position().map(|p| p.line() as usize).unwrap_or(0);
+            BatchParseError { line, column: 0, message: e.to_string() }
+        })?;
+        let line = record.position().map(|p| p.line() as usize).unwrap_or(0);
+        let error = |column: usize, message: String| BatchParseError { line, column, message };
+
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+        if header.is_none() && tasks.is_empty() && record.get(0) == Some("command") {
+            header = Some(record.iter().map(|f| f.to_string()).collect());
+            continue;
+        }
+
+        let mut task = BatchTask {
+            id: format!("row{}", tasks.len() + 1),
+            command: record.get(0).unwrap_or_default().to_string(),
+            cwd: None,
+            node: None,
+            env: HashMap::new(),
+            gpus: 0,
+            depends_on: Vec::new(),
+            labels: BTreeMap::new(),
+        };
+        for (i, field) in record.iter().enumerate().skip(1) {
+            match &header {
+                Some(columns) => {
+                    let name = columns
+                        .get(i)
+                        .ok_or_else(|| error(i + 1, format!("{} fields but the header has {}", record.len(), columns.len())))?;
+                    task.env.insert(name.clone(), field.to_string());
+                    task.labels.insert(name.clone(), field.to_string());
+                }
+                None if field.is_empty() => {}
+                None => {
+                    let (key, value) = field
+                        .split_once('=')
+                        .ok_or_else(|| error(i + 1, format!("expected label=value, got '{}'", field)))?;
+                    task.labels.insert(key.to_string(), value.to_string());
+                }
+            }
+        }
+        tasks.push(task);
+    }
+
+    Ok(BatchFile { lease: None, tasks })
+}
+
+/// Check a parsed batch for semantic problems. `known_nodes` enables the
+/// node check; pass `None` when the target lease is unknown.
+pub fn validate_batch(batch: &BatchFile, known_nodes: Option<&HashSet<String>>) -> Vec<BatchIssue> {
+    let mut issues = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (index, task) in batch.tasks.iter().enumerate() {
+        if task.id.trim().is_empty() {
+            issues.push(BatchIssue::EmptyId { index });
+        } else if !seen.insert(task.id.as_str()) {
+            issues.push(BatchIssue::DuplicateId { task: task.id.clone() });
+        }
+
+        if task.command.trim().is_empty() {
+            issues.push(BatchIssue::EmptyCommand { task: task.id.clone() });
+        }
+
+        for key in task.env.keys() {
+            if key.is_empty() || key.contains('=') || key.contains('\0') {
+                issues.push(BatchIssue::InvalidEnv { task: task.id.clone(), key: key.clone() });
+            }
+        }
+
+        if let (Some(node), Some(known)) = (&task.node, known_nodes) {
+            if !known.contains(node) {
+                issues.push(BatchIssue::UnknownNode { task: task.id.clone(), node: node.clone() });
+            }
+        }
+    }
+
+    for task in &batch.tasks {
+        for dep in &task.depends_on {
+            if !seen.contains(dep.as_str()) {
+                issues.push(BatchIssue::UnknownDependency {
+                    task: task.id.clone(),
+                    dependency: dep.clone(),
+                });
+            }
+        }
+    }
+
+    if let Some(cycle) = find_cycle(batch) {
+        issues.push(BatchIssue::DependencyCycle { cycle });
+    }
+
+    issues
+}
+
+/// Depth-first search over `depends_on` edges. Returns the first cycle found,
+/// starting and ending with the same task id.
+fn find_cycle(batch: &BatchFile) -> Option<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    let edges: HashMap<&str, &[String]> = batch
+        .tasks
+        .iter()
+        .map(|t| (t.id.as_str(), t.depends_on.as_slice()))
+        .collect();
+    let mut marks: HashMap<&str, Mark> = edges.keys().map(|k| (*k, Mark::Unvisited)).collect();
+
+    fn visit<'a>(
+        id: &'a str,
+        edges: &HashMap<&'a str, &'a [String]>,
+        marks: &mut HashMap<&'a str, Mark>,
+        stack: &mut Vec<&'a str>,
+    ) -> Option<Vec<String>> {
+        marks.insert(id, Mark::InProgress);
+        stack.push(id);
+
+        for dep in edges.get(id).copied().unwrap_or_default() {
+            match marks.get(dep.as_str()).copied() {
+                Some(Mark::InProgress) => {
+                    let start = stack.iter().position(|s| *s == dep).unwrap_or(0);
+                    let mut cycle: Vec<String> = stack[start..].iter().map(|s| s.to_string()).collect();
+                    cycle.push(dep.clone());
+                    return Some(cycle);
+                }
+                Some(Mark::Unvisited) => {
+                    if let Some(cycle) = visit(dep.as_str(), edges, marks, stack) {
+                        return Some(cycle);
+                    }
+                }
+                // Done, or an unknown dependency (reported separately)
+                _ => {}
+            }
+        }
+
+        stack.pop();
+        marks.insert(id, Mark::Done);
+        None
+    }
+
+    for task in &batch.tasks {
+        if marks.get(task.id.as_str()) == Some(&Mark::Unvisited) {
+            let mut stack = Vec::new();
+            if let Some(cycle) = visit(task.id.as_str(), &edges, &mut marks, &mut stack) {
+                return Some(cycle);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_reports_line() {
+        let content = "{\n  \"tasks\": [\n    { \"id\": \"a\", \"command\": }\n  ]\n}";
+        let err = parse_batch(content).unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn test_parse_batch_rejects_unknown_field() {
+        let content = r#"{ "tasks": [ { "id": "a", "command": "true", "gpu": 1 } ] }"#;
+        assert!(parse_batch(content).is_err());
+    }
+
+    #[test]
+    fn test_parse_command_lines_skips_blanks_and_comments() {
+        let content = "# sweep\npython train.py --lr 0.1\n\n  python train.py --lr 0.01  \n   # done\n";
+        assert_eq!(parse_command_lines(content), ["python train.py --lr 0.1", "python train.py --lr 0.01"]);
+    }
+
+    #[test]
+    fn test_parse_csv_batch_header_maps_env() {
+        let content = "command,LR,SEED\n\"python train.py --tag a,b\",0.1,1\n\npython train.py,0.01,2\n";
+        let batch = parse_csv_batch(content).unwrap();
+        assert_eq!(batch.tasks.len(), 2);
+        assert_eq!(batch.tasks[0].command, "python train.py --tag a,b");
+        assert_eq!(batch.tasks[1].env.get("LR").map(String::as_str), Some("0.01"));
+        assert_eq!(batch.tasks[1].labels.get("SEED").map(String::as_str), Some("2"));
+
+        let err = parse_csv_batch("command,LR\necho,1,extra\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(parse_csv_batch("echo hi,not-a-label\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch_file_by_extension() {
+        let yaml = "lease: local:myhost\ntasks:\n  - id: prep\n    command: python prep.py\n  - id: train\n    command: python train.py\n    gpus: 1\n    depends_on: [prep]\n";
+        let batch = parse_batch_file(Path::new("jobs.yaml"), yaml).unwrap();
+        assert_eq!(batch.lease.as_deref(), Some("local:myhost"));
+        assert_eq!(batch.tasks[1].depends_on, ["prep"]);
+        assert_eq!(batch.tasks[1].gpus, 1);
+        assert!(validate_batch(&batch, None).is_empty());
+
+        let err = parse_batch_file(Path::new("jobs.yml"), "tasks:\n  - id: a\n    comand: echo a\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("comand"), "{}", err.message);
+        // Anything else is JSON, which YAML would have accepted
+        assert!(parse_batch_file(Path::new("jobs.json"), yaml).is_err());
+    }
+
+    #[test]
+    fn test_validate_batch_ok() {
+        let content = r#"{ "tasks": [
+            { "id": "prep", "command": "python prep.py" },
+            { "id": "train", "command": "python train.py", "depends_on": ["prep"] }
+        ] }"#;
+        let batch = parse_batch(content).unwrap();
+        assert!(validate_batch(&batch, None).is_empty());
+    }
+
+    #[test]
+    fn test_validate_batch_detects_cycle() {
+        let content = r#"{ "tasks": [
+            { "id": "a", "command": "echo a", "depends_on": ["c"] },
+            { "id": "b", "command": "echo b", "depends_on": ["a"] },
+            { "id": "c", "command": "echo c", "depends_on": ["b"] }
+        ] }"#;
+        let batch = parse_batch(content).unwrap();
+        let issues = validate_batch(&batch, None);
+
+        let cycle = issues
+            .iter()
+            .find_map(|i| match i {
+                BatchIssue::DependencyCycle { cycle } => Some(cycle.clone()),
+                _ => None,
+            })
+            .expect("cycle should be reported");
+        assert_eq!(cycle.first(), cycle.last());
+        assert_eq!(cycle.len(), 4);
+    }
+
+    #[test]
+    fn test_validate_batch_unknown_node_and_dependency() {
+        let content = r#"{ "tasks": [
+            { "id": "a", "command": "echo a", "node": "nodeZ", "depends_on": ["missing"] }
+        ] }"#;
+        let batch = parse_batch(content).unwrap();
+        let known: HashSet<String> = ["nodeA".to_string()].into_iter().collect();
+        let issues = validate_batch(&batch, Some(&known));
+
+        assert!(issues.contains(&BatchIssue::UnknownNode { task: "a".into(), node: "nodeZ".into() }));
+        assert!(issues.contains(&BatchIssue::UnknownDependency {
+            task: "a".into(),
+            dependency: "missing".into()
+        }));
+    }
+}
@@ -1,5 +1,8 @@
+use crate::models::LeaseMeta;
 use directories::ProjectDirs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 use std::env;
 
 pub fn leaseq_home_dir() -> PathBuf {
@@ -26,16 +29,291 @@ pub fn runtime_dir() -> PathBuf {
         return PathBuf::from(p);
     }
 
-    if let Some(runtime) = ProjectDirs::from("", "", "leaseq").and_then(|p| p.runtime_dir().map(|p| p.to_path_buf())) {
-        return runtime;
+    // Resolved once: the XDG runtime dir if usable, else /tmp/leaseq/$UID
+    static DEFAULT: OnceLock<PathBuf> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| {
+            let uid = unsafe { libc::getuid() };
+            let fallback = PathBuf::from(format!("/tmp/leaseq/{}", uid));
+            match ProjectDirs::from("", "", "leaseq").and_then(|p| p.runtime_dir().map(|p| p.to_path_buf())) {
+                Some(runtime) if ensure_writable_dir(&runtime).is_ok() => runtime,
+                _ => fallback,
+            }
+        })
+        .clone()
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("runtime directory {} is not writable. Set LEASEQ_RUNTIME_DIR to a writable directory (e.g. export LEASEQ_RUNTIME_DIR=$HOME/.leaseq/runtime)", path.display())]
+pub struct RuntimeDirError {
+    pub path: PathBuf,
+    #[source]
+    pub source: std::io::Error,
+}
+
+/// Create `dir` if needed and check a file can be written in it.
+pub fn ensure_writable_dir(dir: &Path) -> Result<(), RuntimeDirError> {
+    let err = |source| RuntimeDirError { path: dir.to_path_buf(), source };
+    std::fs::create_dir_all(dir).map_err(err)?;
+    let probe = dir.join(format!(".write-test.{}", std::process::id()));
+    std::fs::File::create(&probe).map_err(err)?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Where a lease's queue lives: under the runtime dir for a `local:` lease,
+/// under `~/.leaseq/runs/` (shared with the compute nodes) for a Slurm one.
+pub fn lease_root(lease_id: &str) -> PathBuf {
+    if lease_id.starts_with("local:") {
+        runtime_dir().join(lease_id)
+    } else {
+        leaseq_home_dir().join("runs").join(lease_id)
+    }
+}
+
+/// Startup check so an unwritable runtime dir fails with one clear message
+/// instead of a confusing error deep inside whichever command ran.
+pub fn ensure_runtime_dir() -> Result<PathBuf, RuntimeDirError> {
+    let dir = runtime_dir();
+    ensure_writable_dir(&dir)?;
+    Ok(dir)
+}
+
+/// What the runner passes of its own environment to tasks; a task's captured
+/// env is always applied on top.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EnvInherit {
+    /// Only the task's captured env
+    None,
+    /// A small allowlist (PATH, HOME, locale, ...) plus the task's env
+    Safe,
+    /// Everything the runner has, plus the task's env
+    #[default]
+    All,
+}
+
+impl std::str::FromStr for EnvInherit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "safe" => Ok(Self::Safe),
+            "all" => Ok(Self::All),
+            _ => Err(format!("invalid env policy '{}': expected none, safe or all", s)),
+        }
+    }
+}
+
+/// Runner settings in one place. `load` starts from the defaults, applies the
+/// lease's metadata, then `LEASEQ_*` environment variables; `leaseq run`
+/// flags are set on the result, and tests build one directly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunConfig {
+    /// How often the runner rewrites its heartbeat (`LEASEQ_HEARTBEAT_INTERVAL`)
+    pub heartbeat_interval: Duration,
+    /// How often an idle runner looks for work, and waits for busy GPUs (`LEASEQ_POLL_INTERVAL`)
+    pub poll_interval: Duration,
+    /// Write task logs here instead of `<root>/logs/`, e.g. fast local scratch (`LEASEQ_LOG_DIR`)
+    pub log_dir: Option<PathBuf>,
+    /// GPUs to hand out; the runner's CUDA_VISIBLE_DEVICES or nvidia-smi if unset (`total_gpus` of a local lease, `LEASEQ_GPUS`)
+    pub gpus: Option<u32>,
+    /// CPUs tasks' `cpus` requests are counted against; all cores if unset (`LEASEQ_CPUS`)
+    pub cpus: Option<u32>,
+    /// Tasks a runner runs at once (`parallel` of a local lease, `LEASEQ_PARALLEL`)
+    pub parallel: u32,
+    /// How much of the runner's environment tasks inherit (`LEASEQ_ENV_INHERIT`)
+    pub env_inherit: EnvInherit,
+    /// Start each task log line with `[<task_id> <timestamp>] ` (`LEASEQ_PREFIX_LINES=1`)
+    pub prefix_lines: bool,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(5),
+            poll_interval: Duration::from_secs(1),
+            log_dir: None,
+            gpus: None,
+            cpus: None,
+            parallel: 1,
+            env_inherit: EnvInherit::All,
+            prefix_lines: false,
+        }
+    }
+}
+
+impl RunConfig {
+    /// The configuration for a runner of the lease at `root`.
+    pub fn load(root: &Path) -> Self {
+        let meta = crate::fs::read_json::<LeaseMeta, _>(root.join(crate::models::LEASE_META_FILE)).ok();
+        Self::default().with_meta(meta.as_ref()).with_env(|k| env::var(k).ok())
     }
 
-    // Fallback to /tmp/leaseq/$UID
-    let uid = unsafe { libc::getuid() };
-    PathBuf::from(format!("/tmp/leaseq/{}", uid))
+    pub fn with_meta(mut self, meta: Option<&LeaseMeta>) -> Self {
+        if let Some(LeaseMeta::Local { local, .. }) = meta {
+            if local.total_gpus > 0 {
+                self.gpus = Some(local.total_gpus);
+            }
+            if local.parallel > 0 {
+                self.parallel = local.parallel;
+            }
+        }
+        self
+    }
+
+    /// Apply overrides looked up with `var`, normally the process environment.
+    /// Values that don't parse are ignored with a warning.
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Self {
+        let interval = |key: &str| {
+            let v = var(key).filter(|v| !v.is_empty())?;
+            match crate::duration::parse_duration(&v) {
+                Ok(d) if d.is_positive() => Some(Duration::from_millis(d.whole_milliseconds() as u64)),
+                _ => {
+                    tracing::warn!("Ignoring {}={}: expected a positive duration like 5s", key, v);
+                    None
+                }
+            }
+        };
+        if let Some(d) = interval("LEASEQ_HEARTBEAT_INTERVAL") {
+            self.heartbeat_interval = d;
+        }
+        if let Some(d) = interval("LEASEQ_POLL_INTERVAL") {
+            self.poll_interval = d;
+        }
+        if let Some(dir) = var("LEASEQ_LOG_DIR").filter(|p| !p.is_empty()) {
+            self.log_dir = Some(PathBuf::from(dir));
+        }
+        let count = |key: &str| {
+            let v = var(key).filter(|v| !v.is_empty())?;
+            v.parse()
+                .map_err(|_| tracing::warn!("Ignoring {}={}: expected a number", key, v))
+                .ok()
+        };
+        if let Some(n) = count("LEASEQ_GPUS") {
+            self.gpus = Some(n);
+        }
+        if let Some(n) = count("LEASEQ_CPUS") {
+            self.cpus = Some(n);
+        }
+        if let Some(n) = count("LEASEQ_PARALLEL").filter(|n| *n > 0) {
+            self.parallel = n;
+        }
+        if let Some(v) = var("LEASEQ_ENV_INHERIT").filter(|v| !v.is_empty()) {
+            match v.parse() {
+                Ok(policy) => self.env_inherit = policy,
+                Err(e) => tracing::warn!("Ignoring LEASEQ_ENV_INHERIT: {}", e),
+            }
+        }
+        if let Some(v) = var("LEASEQ_PREFIX_LINES").filter(|v| !v.is_empty()) {
+            self.prefix_lines = matches!(v.as_str(), "1" | "true" | "yes");
+        }
+        self
+    }
+}
+
+/// Default cap on a submitted command string, in bytes.
+pub const DEFAULT_MAX_COMMAND_LEN: usize = 64 * 1024;
+
+/// Max command length accepted by submit (`LEASEQ_MAX_COMMAND_LEN` overrides).
+pub fn max_command_len() -> usize {
+    env::var("LEASEQ_MAX_COMMAND_LEN")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_COMMAND_LEN)
 }
 
 pub fn local_lease_id() -> String {
     let hostname = hostname::get().map(|h| h.to_string_lossy().into_owned()).unwrap_or_else(|_| "localhost".to_string());
     format!("local:{}", hostname)
 }
+
+/// The lease a `--lease` value means. A bare name (no `:`) is taken as the
+/// local lease `local:<name>` when it is this host's name or a local lease
+/// exists under that name in the runtime dir, unless a Slurm lease of that
+/// name has a run dir. Anything else is returned unchanged.
+pub fn resolve_lease_id(id: &str) -> String {
+    if id.contains(':') || leaseq_home_dir().join("runs").join(id).is_dir() {
+        return id.to_string();
+    }
+    let local = format!("local:{}", id);
+    let is_this_host = hostname::get().is_ok_and(|h| h.to_string_lossy() == id);
+    if is_this_host || runtime_dir().join(&local).is_dir() {
+        local
+    } else {
+        id.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwritable_runtime_dir_reports_env_var() {
+        let dir = tempfile::tempdir().unwrap();
+        // A path below a regular file can't be created, even as root
+        let blocker = dir.path().join("not-a-dir");
+        std::fs::write(&blocker, "").unwrap();
+        let unusable = blocker.join("runtime");
+
+        let err = ensure_writable_dir(&unusable).unwrap_err();
+
+        assert_eq!(err.path, unusable);
+        let msg = err.to_string();
+        assert!(msg.contains(&unusable.display().to_string()), "{}", msg);
+        assert!(msg.contains("LEASEQ_RUNTIME_DIR"), "{}", msg);
+
+        let ok = dir.path().join("runtime");
+        assert!(ensure_writable_dir(&ok).is_ok());
+        assert_eq!(std::fs::read_dir(&ok).unwrap().count(), 0, "probe file should be removed");
+    }
+
+    #[test]
+    fn test_run_config_layers_meta_then_env() {
+        use crate::models::{LeaseId, LocalLeaseConfig};
+        use std::collections::HashMap;
+
+        let meta = LeaseMeta::Local {
+            lease_id: LeaseId("local:test".to_string()),
+            created_at: time::OffsetDateTime::UNIX_EPOCH,
+            local: LocalLeaseConfig { total_gpus: 4, parallel: 3 },
+            comment: None,
+            metadata: Default::default(),
+        };
+        let vars: HashMap<&str, &str> = [
+            ("LEASEQ_POLL_INTERVAL", "2s"),
+            ("LEASEQ_HEARTBEAT_INTERVAL", "soon"),
+            ("LEASEQ_LOG_DIR", "/scratch/logs"),
+            ("LEASEQ_ENV_INHERIT", "safe"),
+        ]
+        .into();
+
+        let cfg = RunConfig::default()
+            .with_meta(Some(&meta))
+            .with_env(|k| vars.get(k).map(|v| v.to_string()));
+        assert_eq!(
+            cfg,
+            RunConfig {
+                heartbeat_interval: Duration::from_secs(5), // unparseable, default kept
+                poll_interval: Duration::from_secs(2),
+                log_dir: Some(PathBuf::from("/scratch/logs")),
+                gpus: Some(4),
+                cpus: None,
+                parallel: 3,
+                env_inherit: EnvInherit::Safe,
+                prefix_lines: false,
+            }
+        );
+
+        let cfg = cfg.with_env(|k| (k == "LEASEQ_GPUS").then(|| "1".to_string()));
+        assert_eq!(cfg.gpus, Some(1));
+        // Zero slots would never run anything
+        let cfg = cfg.with_env(|k| (k == "LEASEQ_PARALLEL").then(|| "0".to_string()));
+        assert_eq!(cfg.parallel, 3);
+        let cfg = cfg.with_env(|k| (k == "LEASEQ_ENV_INHERIT").then(|| "some".to_string()));
+        assert_eq!(cfg.env_inherit, EnvInherit::Safe);
+        let cfg = cfg.with_env(|k| (k == "LEASEQ_PREFIX_LINES").then(|| "1".to_string()));
+        assert!(cfg.prefix_lines);
+    }
+}
@@ -0,0 +1,63 @@
+use time::Duration;
+
+/// Parse a human duration such as `90s`, `15m`, `2h`, `1d`, `1w` or a compound
+/// like `1h30m`. A bare number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    let too_long = || format!("invalid duration '{}': too long", s);
+    if let Ok(secs) = s.parse::<u64>() {
+        return i64::try_from(secs).map(Duration::seconds).map_err(|_| too_long());
+    }
+
+    let mut total: i64 = 0;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        if digits.is_empty() {
+            return Err(format!("invalid duration '{}': expected a number before '{}'", s, c));
+        }
+        let n: i64 = digits.parse().map_err(|_| too_long())?;
+        digits.clear();
+        let unit_secs = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            'w' => 604_800,
+            _ => return Err(format!("invalid duration '{}': unknown unit '{}' (use s, m, h, d, w)", s, c)),
+        };
+        total = n.checked_mul(unit_secs).and_then(|secs| total.checked_add(secs)).ok_or_else(too_long)?;
+    }
+    if !digits.is_empty() {
+        return Err(format!("invalid duration '{}': missing unit after {}", s, digits));
+    }
+    Ok(Duration::seconds(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45").unwrap(), Duration::seconds(45));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::days(1));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::minutes(90));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("1h30").is_err());
+        assert!(parse_duration("h").is_err());
+        // Too long for a duration: an error, not a wrapped value or a panic
+        assert!(parse_duration("18446744073709551615").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+        assert!(parse_duration("9223372036854775807s1s").is_err());
+    }
+}
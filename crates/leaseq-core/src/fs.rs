@@ -72,6 +72,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_list_files_sorted_skips_temp_files() -> io::Result<()> {
+        let dir = tempdir()?;
+        File::create(dir.path().join("001_T1.json"))?;
+        File::create(dir.path().join(".tmp.002_T2.json.1234"))?;
+        assert!(is_temp_file(dir.path().join(".tmp.002_T2.json.1234")));
+        assert!(!is_temp_file(dir.path().join("001_T1.json")));
+
+        let files = list_files_sorted(dir.path())?;
+        assert_eq!(files, vec![dir.path().join("001_T1.json")]);
+        Ok(())
+    }
+
     #[test]
     fn test_list_files_sorted_empty_dir() -> io::Result<()> {
         let dir = tempdir()?;
@@ -136,6 +149,340 @@ mod tests {
         assert_eq!(data, read);
         Ok(())
     }
+
+    #[test]
+    fn test_done_files_read_by_suffix() -> io::Result<()> {
+        use crate::models::{DoneFileKind, TaskResult, TaskSpec};
+
+        let dir = tempdir()?;
+        let done = dir.path().join("done").join("node1");
+        let spec = TaskSpec { task_id: "T1".to_string(), command: "echo hi".to_string(), ..Default::default() };
+        let result = TaskResult {
+            task_id: "T1".to_string(),
+            idempotency_key: spec.idempotency_key.clone(),
+            node: "node1".to_string(),
+            started_at: time::OffsetDateTime::now_utc(),
+            finished_at: time::OffsetDateTime::now_utc(),
+            exit_code: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            runtime_s: 0.1,
+            command: spec.command.clone(),
+            resolved_command: String::new(),
+            cwd: spec.cwd.clone(),
+            gpus_requested: 0,
+            gpus_assigned: String::new(),
+            checksum: None,
+            failure: None,
+            signal: None,
+        };
+        // The runner archives the spec under the name its result extends
+        atomic_write_json(done.join("0000000000000001_T1.json"), &spec)?;
+        atomic_write_json(done.join("0000000000000001_T1.result.json"), &result)?;
+
+        let (mut specs, mut results) = (Vec::new(), Vec::new());
+        for path in list_files_sorted(&done)? {
+            match DoneFileKind::of(&path) {
+                DoneFileKind::Spec => specs.push(read_json::<TaskSpec, _>(&path)?),
+                kind => {
+                    assert_eq!(kind, DoneFileKind::Result);
+                    results.push(read_json::<TaskResult, _>(&path)?);
+                }
+            }
+        }
+        assert_eq!(specs.len(), 1);
+        assert_eq!(specs[0].command, "echo hi");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].task_id, "T1");
+
+        assert_eq!(DoneFileKind::of(Path::new("1_T2.skipped.json")), DoneFileKind::Skipped);
+        assert_eq!(DoneFileKind::of(Path::new("1_T3.cancelled.json")), DoneFileKind::Cancelled);
+        assert!(!DoneFileKind::of(Path::new("1_T3.json")).is_result());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_heartbeat_during_rewrites() -> io::Result<()> {
+        use crate::models::Heartbeat;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let dir = tempdir()?;
+        let path = dir.path().join("hb").join("node1.json");
+        let hb = |n: u32| Heartbeat {
+            node: "node1".to_string(),
+            ts: time::OffsetDateTime::now_utc(),
+            running_task_id: Some(format!("T{}", n)),
+            running_task_ids: Vec::new(),
+            pending_estimate: n,
+            runner_pid: 1,
+            version: "test".to_string(),
+            schema_version: crate::models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        };
+        atomic_write_json(&path, &hb(0))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (path, stop) = (path.clone(), stop.clone());
+            std::thread::spawn(move || {
+                let mut n = 1;
+                while !stop.load(Ordering::Relaxed) {
+                    atomic_write_json(&path, &hb(n)).unwrap();
+                    n += 1;
+                }
+            })
+        };
+
+        let mut failures = 0;
+        for _ in 0..2000 {
+            if read_heartbeat(&path).is_err() {
+                failures += 1;
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+
+        assert_eq!(failures, 0, "node reported missing while its heartbeat was being rewritten");
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_file_has_one_winner() -> io::Result<()> {
+        use std::sync::{Arc, Barrier};
+
+        let dir = tempdir()?;
+        let inbox = dir.path().join("inbox");
+        let claimed = dir.path().join("claimed");
+        ensure_dir(&inbox)?;
+        ensure_dir(&claimed)?;
+
+        for round in 0..50 {
+            let task = inbox.join(format!("{:04}_T1.json", round));
+            fs::write(&task, "{}")?;
+            let barrier = Arc::new(Barrier::new(2));
+            let claimers: Vec<_> = (0..2)
+                .map(|_| {
+                    let (task, claimed, barrier) = (task.clone(), claimed.clone(), barrier.clone());
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        claim_file(&task, &claimed)
+                    })
+                })
+                .collect();
+            let wins: Vec<PathBuf> = claimers
+                .into_iter()
+                .filter_map(|c| c.join().unwrap().unwrap())
+                .collect();
+            assert_eq!(wins.len(), 1, "round {}", round);
+            assert!(wins[0].exists() && !task.exists());
+        }
+        // The locks are gone, and hidden from scans while held
+        assert_eq!(list_files_sorted(&claimed)?.len(), 50);
+        assert_eq!(fs::read_dir(&claimed)?.count(), 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_claim_file_takes_over_stale_lock() -> io::Result<()> {
+        let dir = tempdir()?;
+        let inbox = dir.path().join("inbox");
+        let claimed = dir.path().join("claimed");
+        ensure_dir(&inbox)?;
+        ensure_dir(&claimed)?;
+
+        // A held lock keeps the file where it is
+        let task = inbox.join("9999_T2.json");
+        fs::write(&task, "{}")?;
+        fs::write(claimed.join(".9999_T2.json.lock"), "")?;
+        assert_eq!(claim_file(&task, &claimed)?, None);
+        assert!(task.exists());
+
+        // Left behind by a claimer that died before the rename
+        let stale = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        File::options().write(true).open(claimed.join(".9999_T2.json.lock"))?.set_modified(stale)?;
+        assert_eq!(claim_file(&task, &claimed)?, Some(claimed.join("9999_T2.json")));
+        assert!(!task.exists());
+        assert!(!claimed.join(".9999_T2.json.lock").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_lock_is_exclusive_until_dropped() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("alloc.lock");
+        let stale = std::time::Duration::from_secs(30);
+
+        let lock = try_lock(&path, stale)?.expect("free lock is taken");
+        assert!(try_lock(&path, stale)?.is_none());
+        drop(lock);
+        assert!(!path.exists());
+        assert!(try_lock(&path, stale)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_lock_stale_takeover_has_one_winner() -> io::Result<()> {
+        use std::sync::{Arc, Barrier};
+
+        let dir = tempdir()?;
+        let path = dir.path().join("alloc.lock");
+        let stale = std::time::Duration::from_secs(30);
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+
+        for round in 0..50 {
+            fs::write(&path, "dead-holder")?;
+            File::options().write(true).open(&path)?.set_modified(old)?;
+            let barrier = Arc::new(Barrier::new(3));
+            let takers: Vec<_> = (0..3)
+                .map(|_| {
+                    let (path, barrier) = (path.clone(), barrier.clone());
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        try_lock(&path, stale).unwrap()
+                    })
+                })
+                .collect();
+            let locks: Vec<LockFile> = takers.into_iter().filter_map(|t| t.join().unwrap()).collect();
+            assert_eq!(locks.len(), 1, "round {}", round);
+            assert_eq!(fs::read_to_string(&path)?, locks[0].token);
+            drop(locks);
+            assert!(!path.exists());
+        }
+        // Neither the lock nor its takeover guard is left behind
+        assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_lock_taken_over_holder_leaves_new_lock() -> io::Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("alloc.lock");
+        let stale = std::time::Duration::from_secs(30);
+
+        // A holder so slow its lock looks abandoned
+        let slow = try_lock(&path, stale)?.expect("free lock is taken");
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        File::options().write(true).open(&path)?.set_modified(old)?;
+        let new = try_lock(&path, stale)?.expect("stale lock is taken over");
+
+        drop(slow);
+        assert_eq!(fs::read_to_string(&path)?, new.token);
+        assert!(try_lock(&path, stale)?.is_none());
+        drop(new);
+        assert!(!path.exists());
+        Ok(())
+    }
+}
+
+/// Append `data` as one JSON line. Lines are written with a single `write`
+/// on an `O_APPEND` file so concurrent appenders don't interleave.
+pub fn append_json_line<T: serde::Serialize, P: AsRef<Path>>(path: P, data: &T) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        ensure_dir(parent)?;
+    }
+    let mut line = serde_json::to_string(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())
+}
+
+/// A lock file taken with `try_lock`. It holds a token unique to its taker
+/// and is removed when dropped, unless it was taken over as stale meanwhile.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    token: String,
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // Once taken over, the file at the path is someone else's lock
+        if fs::read_to_string(&self.path).is_ok_and(|t| t == self.token) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Create the lock file at `path` with `create_new`; `None` if it exists.
+fn create_lock(path: &Path) -> io::Result<Option<LockFile>> {
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let token = Uuid::new_v4().to_string();
+    if let Err(e) = file.write_all(token.as_bytes()) {
+        let _ = fs::remove_file(path);
+        return Err(e);
+    }
+    Ok(Some(LockFile { path: path.to_path_buf(), token }))
+}
+
+/// Take the lock file at `path`, created with `create_new` so only one
+/// taker gets it. `None` if someone holds it. A lock older than `stale` was
+/// left by a holder that died and is taken over. Takers of a stale lock go
+/// one at a time through a guard lock `.<name>.takeover`, and the one in it
+/// checks the lock is still the stale one before replacing it, so a lock
+/// another taker just created is never removed.
+pub fn try_lock(path: &Path, stale: std::time::Duration) -> io::Result<Option<LockFile>> {
+    if let Some(lock) = create_lock(path)? {
+        return Ok(Some(lock));
+    }
+    let is_stale = |p: &Path| match fs::metadata(p).and_then(|m| m.modified()) {
+        Ok(t) => Ok(Some(t.elapsed().unwrap_or_default() > stale)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    };
+    match is_stale(path)? {
+        Some(true) => {}
+        Some(false) => return Ok(None),
+        // Released meanwhile
+        None => return create_lock(path),
+    }
+
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let guard_path = path.with_file_name(format!(".{}.takeover", name.to_string_lossy().trim_start_matches('.')));
+    let Some(_guard) = create_lock(&guard_path)? else {
+        // The guard is held only for a few calls; one this old was left by a
+        // taker that died in it
+        if is_stale(&guard_path)? == Some(true) {
+            let _ = fs::remove_file(&guard_path);
+        }
+        return Ok(None);
+    };
+    if is_stale(path)? == Some(true) {
+        fs::remove_file(path).or_else(|e| if e.kind() == io::ErrorKind::NotFound { Ok(()) } else { Err(e) })?;
+    }
+    create_lock(path)
+}
+
+/// A claim lock is held only across one rename; one older than this was
+/// abandoned by a runner that died holding it.
+const STALE_CLAIM_LOCK: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Move `src` into `dest_dir` only if no one else is claiming it. A lock
+/// `.<name>.lock` is created next to the destination with `create_new`, so
+/// of two claimers only one gets past it, then the file is renamed and the
+/// lock dropped. Returns the claimed path, or `None` if the lock was taken
+/// or the file is already gone. A lock older than `STALE_CLAIM_LOCK` was
+/// left by a claimer that died before the rename and is taken over.
+pub fn claim_file(src: &Path, dest_dir: &Path) -> io::Result<Option<PathBuf>> {
+    let name = src.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let dest = dest_dir.join(name);
+    let Some(lock) = try_lock(&dest_dir.join(format!(".{}.lock", name.to_string_lossy())), STALE_CLAIM_LOCK)? else {
+        return Ok(None);
+    };
+    let renamed = fs::rename(src, &dest);
+    drop(lock);
+    match renamed {
+        Ok(()) => Ok(Some(dest)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
 /// Read JSON from a file
@@ -145,6 +492,30 @@ pub fn read_json<T: serde::de::DeserializeOwned, P: AsRef<Path>>(path: P) -> io:
     serde_json::from_reader(reader).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
+/// Read a node heartbeat. The runner replaces it by atomic rename, but a reader
+/// that opened the old inode mid-rename can see a partial file on some
+/// filesystems, so a parse failure is retried once before giving up.
+pub fn read_heartbeat<P: AsRef<Path>>(path: P) -> io::Result<crate::models::Heartbeat> {
+    match read_json(path.as_ref()) {
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            std::thread::sleep(std::time::Duration::from_millis(HEARTBEAT_RETRY_MS));
+            read_json(path.as_ref())
+        }
+        other => other,
+    }
+}
+
+const HEARTBEAT_RETRY_MS: u64 = 10;
+
+/// Dot-prefixed names are in-flight `atomic_write_json` temp files (or other
+/// hidden files) that every directory scan must skip.
+pub fn is_temp_file<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .file_name()
+        .map(|n| n.to_string_lossy().starts_with('.'))
+        .unwrap_or(false)
+}
+
 /// List files in a directory sorted lexicographically (useful for task queues)
 pub fn list_files_sorted<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
     let mut entries = Vec::new();
@@ -157,7 +528,7 @@ pub fn list_files_sorted<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        if path.is_file() && !path.file_name().unwrap().to_string_lossy().starts_with('.') {
+        if path.is_file() && !is_temp_file(&path) {
             entries.push(path);
         }
     }
@@ -166,6 +537,24 @@ pub fn list_files_sorted<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
     Ok(entries)
 }
 
+/// `(node, <root>/<queue>/<node>)` for every node directory of a lease queue
+/// (`inbox`, `claimed`, `done`, ...), sorted by node; none if the queue
+/// doesn't exist yet.
+pub fn node_dirs(root: &Path, queue: &str) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut dirs = Vec::new();
+    let Ok(entries) = fs::read_dir(root.join(queue)) else {
+        return Ok(dirs);
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().is_dir() {
+            dirs.push((entry.file_name().to_string_lossy().into_owned(), entry.path()));
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
 pub fn touch<P: AsRef<Path>>(path: P) -> io::Result<()> {
     if path.as_ref().exists() {
         let _file = File::open(path.as_ref())?;
@@ -0,0 +1,291 @@
+use crate::fs as lfs;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Directory under a lease root holding one GPU ledger per node.
+pub const GPU_DIR: &str = "gpu";
+
+/// A lock file older than this is assumed left behind by a crashed runner.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+
+/// Which device is held by which task, and how many CPUs each task holds,
+/// shared by all runners of a node as `gpu/<node>/alloc.json`. Only read or
+/// written under `alloc.lock`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GpuLedger {
+    #[serde(default)]
+    pub devices: BTreeMap<u32, GpuHolder>,
+    #[serde(default)]
+    pub cpus: BTreeMap<String, CpuHold>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CpuHold {
+    pub count: u32,
+    pub runner_pid: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuHolder {
+    pub task_id: String,
+    pub runner_pid: u32,
+}
+
+pub fn ledger_path(root: &Path, node: &str) -> PathBuf {
+    root.join(GPU_DIR).join(node).join("alloc.json")
+}
+
+/// Number of GPUs on this host (`nvidia-smi -L`), 0 if it can't be run.
+pub fn detect_gpus() -> u32 {
+    std::process::Command::new("nvidia-smi")
+        .arg("-L")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).lines().filter(|l| l.starts_with("GPU ")).count() as u32)
+        .unwrap_or(0)
+}
+
+/// The devices a runner hands out. Ledger slot `i` is device `ids[i]`, so a
+/// runner confined by Slurm to e.g. `CUDA_VISIBLE_DEVICES=2,3` passes those
+/// ids on to its tasks rather than `0,1`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GpuPool {
+    ids: Vec<String>,
+}
+
+impl GpuPool {
+    /// Devices `0..count`.
+    pub fn new(count: u32) -> Self {
+        Self { ids: (0..count).map(|d| d.to_string()).collect() }
+    }
+
+    /// The runner's own `CUDA_VISIBLE_DEVICES` if set, at most `count` of them;
+    /// otherwise `count` devices, detected with nvidia-smi if unset.
+    pub fn from_visible(visible: Option<&str>, count: Option<u32>) -> Self {
+        match visible {
+            Some(list) => {
+                let ids = list.split(',').map(str::trim).filter(|id| !id.is_empty());
+                Self { ids: ids.take(count.unwrap_or(u32::MAX) as usize).map(String::from).collect() }
+            }
+            None => Self::new(count.unwrap_or_else(detect_gpus)),
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        self.ids.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// `CUDA_VISIBLE_DEVICES` for the given ledger slots.
+    pub fn ids(&self, slots: &[u32]) -> String {
+        let ids: Vec<&str> = slots.iter().filter_map(|s| self.ids.get(*s as usize)).map(String::as_str).collect();
+        ids.join(",")
+    }
+}
+
+/// Number of CPUs this runner may hand out, 1 if it can't be determined.
+pub fn detect_cpus() -> u32 {
+    std::thread::available_parallelism().map(|n| n.get() as u32).unwrap_or(1)
+}
+
+/// Reserve `count` of the node's `total` CPUs for `task_id`. Returns false if
+/// not enough are free right now. Holds of dead runners are dropped first.
+pub fn allocate_cpus(root: &Path, node: &str, task_id: &str, count: u32, total: u32) -> io::Result<bool> {
+    update(root, node, |ledger| {
+        ledger.cpus.retain(|_, h| pid_alive(h.runner_pid));
+        let held: u32 = ledger.cpus.values().map(|h| h.count).sum();
+        if held + count > total {
+            return false;
+        }
+        let hold = CpuHold { count, runner_pid: std::process::id() };
+        ledger.cpus.insert(task_id.to_string(), hold);
+        true
+    })
+}
+
+/// Reserve `count` of the node's `total` devices for `task_id`, lowest free
+/// ids first. Returns `None` if not enough are free right now. Entries of
+/// runners that are no longer alive are dropped first.
+pub fn allocate(root: &Path, node: &str, task_id: &str, count: u32, total: u32) -> io::Result<Option<Vec<u32>>> {
+    update(root, node, |ledger| {
+        ledger.devices.retain(|_, h| pid_alive(h.runner_pid));
+        let free: Vec<u32> = (0..total).filter(|d| !ledger.devices.contains_key(d)).take(count as usize).collect();
+        if free.len() < count as usize {
+            return None;
+        }
+        for d in &free {
+            let holder = GpuHolder { task_id: task_id.to_string(), runner_pid: std::process::id() };
+            ledger.devices.insert(*d, holder);
+        }
+        Some(free)
+    })
+}
+
+/// Free every device and CPU held by `task_id`.
+pub fn release(root: &Path, node: &str, task_id: &str) -> io::Result<()> {
+    update(root, node, |ledger| {
+        ledger.devices.retain(|_, h| h.task_id != task_id);
+        ledger.cpus.remove(task_id);
+    })
+}
+
+/// How many of the node's `total` devices no live runner holds right now.
+pub fn free_devices(root: &Path, node: &str, total: u32) -> io::Result<u32> {
+    let ledger = read_ledger(root, node)?;
+    let held = (0..total)
+        .filter(|d| ledger.devices.get(d).is_some_and(|h| pid_alive(h.runner_pid)))
+        .count() as u32;
+    Ok(total - held)
+}
+
+/// How many of the node's `total` CPUs no live runner holds right now.
+pub fn free_cpus(root: &Path, node: &str, total: u32) -> io::Result<u32> {
+    let ledger = read_ledger(root, node)?;
+    let held: u32 = ledger.cpus.values().filter(|h| pid_alive(h.runner_pid)).map(|h| h.count).sum();
+    Ok(total.saturating_sub(held))
+}
+
+pub fn read_ledger(root: &Path, node: &str) -> io::Result<GpuLedger> {
+    let path = ledger_path(root, node);
+    if !path.exists() {
+        return Ok(GpuLedger::default());
+    }
+    lfs::read_json(&path)
+}
+
+/// Read-modify-write the ledger while holding its lock file. Waiting for the
+/// lock blocks the thread, so async callers run this with `spawn_blocking`.
+fn update<T>(root: &Path, node: &str, f: impl FnOnce(&mut GpuLedger) -> T) -> io::Result<T> {
+    let path = ledger_path(root, node);
+    let dir = path.parent().expect("ledger path has a parent");
+    lfs::ensure_dir(dir)?;
+    let lock_path = dir.join("alloc.lock");
+
+    let start = Instant::now();
+    let _lock = loop {
+        if let Some(lock) = lfs::try_lock(&lock_path, STALE_LOCK)? {
+            break lock;
+        }
+        if start.elapsed() > STALE_LOCK {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, format!("Timed out waiting for {}", lock_path.display())));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let mut ledger = read_ledger(root, node)?;
+    let out = f(&mut ledger);
+    lfs::atomic_write_json(&path, &ledger)?;
+    Ok(out)
+}
+
+fn pid_alive(pid: u32) -> bool {
+    // Signal 0 only checks the process exists (EPERM still means it does)
+    let rc = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    rc == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_and_release() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+
+        assert_eq!(allocate(root, "n1", "T1", 2, 4)?, Some(vec![0, 1]));
+        assert_eq!(allocate(root, "n1", "T2", 1, 4)?, Some(vec![2]));
+        assert_eq!(allocate(root, "n1", "T3", 2, 4)?, None);
+
+        release(root, "n1", "T1")?;
+        assert_eq!(allocate(root, "n1", "T3", 2, 4)?, Some(vec![0, 1]));
+
+        // A holder whose runner is gone doesn't block the device
+        let mut ledger = read_ledger(root, "n1")?;
+        ledger.devices.insert(3, GpuHolder { task_id: "T-DEAD".to_string(), runner_pid: u32::MAX / 2 });
+        lfs::atomic_write_json(ledger_path(root, "n1"), &ledger)?;
+        assert_eq!(free_devices(root, "n1", 4)?, 1);
+        assert_eq!(allocate(root, "n1", "T4", 1, 4)?, Some(vec![3]));
+        assert_eq!(free_devices(root, "n1", 4)?, 0);
+        assert!(!root.join(GPU_DIR).join("n1").join("alloc.lock").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_lock_taken_over_by_one_allocator() -> io::Result<()> {
+        use std::sync::{Arc, Barrier};
+
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().to_path_buf();
+        let lock = root.join(GPU_DIR).join("n1").join("alloc.lock");
+        lfs::ensure_dir(lock.parent().unwrap())?;
+        let old = std::time::SystemTime::now() - Duration::from_secs(60);
+
+        let tasks: Vec<String> = (0..8).map(|i| format!("T{}", i)).collect();
+        for round in 0..30 {
+            // Left by a runner that died holding it
+            std::fs::write(&lock, "")?;
+            std::fs::File::options().write(true).open(&lock)?.set_modified(old)?;
+            let barrier = Arc::new(Barrier::new(tasks.len()));
+            let allocators: Vec<_> = tasks
+                .iter()
+                .map(|task| {
+                    let (root, task, barrier) = (root.clone(), task.clone(), barrier.clone());
+                    std::thread::spawn(move || {
+                        barrier.wait();
+                        allocate(&root, "n1", &task, 1, 8).unwrap()
+                    })
+                })
+                .collect();
+            let mut got: Vec<u32> = allocators.into_iter().flat_map(|a| a.join().unwrap().unwrap()).collect();
+            got.sort();
+            // No device handed out twice, and no allocation lost
+            assert_eq!(got, (0..8).collect::<Vec<_>>(), "round {}", round);
+            assert_eq!(read_ledger(&root, "n1")?.devices.len(), 8, "round {}", round);
+            for task in &tasks {
+                release(&root, "n1", task)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_gpu_pool_follows_visible_devices() {
+        let pool = GpuPool::from_visible(Some("2, 3"), None);
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.ids(&[1]), "3");
+        assert_eq!(pool.ids(&[0, 1]), "2,3");
+        // An explicit count narrows the visible set
+        assert_eq!(GpuPool::from_visible(Some("4,5,6"), Some(1)).ids(&[0]), "4");
+        // Visible but empty means no GPUs at all
+        assert!(GpuPool::from_visible(Some(""), Some(2)).is_empty());
+        assert_eq!(GpuPool::from_visible(None, Some(2)), GpuPool::new(2));
+    }
+
+    #[test]
+    fn test_allocate_cpus() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+
+        assert!(allocate_cpus(root, "n1", "T1", 2, 4)?);
+        assert!(allocate_cpus(root, "n1", "T2", 2, 4)?);
+        assert!(!allocate_cpus(root, "n1", "T3", 1, 4)?);
+        // GPUs are tracked separately
+        assert_eq!(allocate(root, "n1", "T3", 1, 1)?, Some(vec![0]));
+
+        assert_eq!(free_cpus(root, "n1", 4)?, 0);
+        release(root, "n1", "T1")?;
+        assert_eq!(free_cpus(root, "n1", 4)?, 2);
+        assert!(allocate_cpus(root, "n1", "T3", 2, 4)?);
+        release(root, "n1", "T3")?;
+        assert!(read_ledger(root, "n1")?.devices.is_empty());
+        Ok(())
+    }
+}
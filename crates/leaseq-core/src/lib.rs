@@ -1,3 +1,8 @@
+pub mod batch;
 pub mod config;
+pub mod duration;
 pub mod fs;
-pub mod models;
\ No newline at end of file
+pub mod gpu;
+pub mod models;
+pub mod quarantine;
+pub mod size;
\ No newline at end of file
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -13,6 +13,13 @@ impl std::fmt::Display for LeaseId {
     }
 }
 
+/// Version of the on-disk formats (specs, results, heartbeats, lease
+/// metadata). Bumped on changes older binaries can't read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `lease_type` values of `LeaseMeta` this build understands.
+pub const LEASE_TYPES: &[&str] = &["local", "slurm"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "lease_type", rename_all = "lowercase")]
 pub enum LeaseMeta {
@@ -21,6 +28,10 @@ pub enum LeaseMeta {
         #[serde(with = "time::serde::timestamp")]
         created_at: OffsetDateTime,
         local: LocalLeaseConfig,
+        #[serde(default)]
+        comment: Option<String>,
+        #[serde(default)]
+        metadata: BTreeMap<String, String>,
     },
     Slurm {
         lease_id: LeaseId,
@@ -29,9 +40,49 @@ pub enum LeaseMeta {
         created_at: OffsetDateTime,
         slurm: SlurmLeaseConfig,
         mode: ExecutionMode,
+        /// Submitted with `--hold`; stays pending until `lease unhold`.
+        #[serde(default)]
+        held: bool,
+        /// Free text from `lease create --comment`, e.g. what the allocation is for.
+        #[serde(default)]
+        comment: Option<String>,
+        /// `lease create --meta KEY=VALUE` pairs.
+        #[serde(default)]
+        metadata: BTreeMap<String, String>,
     },
 }
 
+impl LeaseMeta {
+    pub fn comment(&self) -> Option<&str> {
+        match self {
+            Self::Local { comment, .. } | Self::Slurm { comment, .. } => comment.as_deref(),
+        }
+    }
+
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        match self {
+            Self::Local { metadata, .. } | Self::Slurm { metadata, .. } => metadata,
+        }
+    }
+
+    /// The serialized `lease_type` tag, one of `LEASE_TYPES`.
+    pub fn lease_type(&self) -> &'static str {
+        match self {
+            Self::Local { .. } => "local",
+            Self::Slurm { .. } => "slurm",
+        }
+    }
+
+    pub fn created_at(&self) -> OffsetDateTime {
+        match self {
+            Self::Local { created_at, .. } | Self::Slurm { created_at, .. } => *created_at,
+        }
+    }
+}
+
+/// `LeaseMeta` of a Slurm lease, stored in its run dir when it is created.
+pub const LEASE_META_FILE: &str = "lease.json";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalLeaseConfig {
     pub total_gpus: u32,
@@ -66,7 +117,155 @@ pub struct TaskSpec {
     pub env: HashMap<String, String>,
     #[serde(default)]
     pub gpus: u32, // 0 for CPU, >0 for GPU
+    #[serde(default)]
+    pub cpus: u32, // counted against the node's CPUs so runners don't oversubscribe it; 0 = not tracked
+    #[serde(default)]
+    pub mem_mb: u64, // memory the task expects, for reports; not enforced by runners; 0 = unspecified
     pub command: String,
+    #[serde(default)]
+    pub combine_output: bool, // stdout and stderr share logs/<id>.log
+    #[serde(default)]
+    pub requeue_on_preempt: bool, // may be moved to a fresh lease if its node vanishes
+    #[serde(default)]
+    pub stdin_file: Option<String>, // absolute path fed to the command's stdin
+    #[serde(default)]
+    pub snapshot_dir: Option<String>, // files copied at submit, relative to run dir
+    #[serde(default)]
+    pub run_in_snapshot: bool, // use snapshot_dir as cwd
+    #[serde(default)]
+    pub no_output_s: Option<u64>, // report POSSIBLY_HUNG after this long without log output
+    #[serde(default)]
+    pub batch_id: Option<String>, // group for `leaseq group` lifecycle ops
+    #[serde(default)]
+    pub output_dir: Option<String>, // absolute per-task dir holding the logs (also the cwd)
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>, // free-form key=value tags, e.g. sweep parameters
+    #[serde(default)]
+    pub depends_on: Vec<String>, // task IDs that must finish successfully first
+    #[serde(default)]
+    pub stdin_from_task: Option<String>, // task whose stdout log is fed to stdin (also in depends_on)
+    #[serde(default)]
+    pub priority: i32, // higher is claimed first; pending tasks also age upwards
+    #[serde(default)]
+    pub on_success: Option<String>, // queued by the runner as a new task if this one exits 0
+    #[serde(default)]
+    pub on_failure: Option<String>, // queued by the runner as a new task if this one fails
+    #[serde(default)]
+    pub chain_depth: u32, // follow-ups and nested submits between this task and a user's submit
+    #[serde(default)]
+    pub strict: bool, // submitted with --strict; carried over to follow-up tasks
+    #[serde(default)]
+    pub max_pending_s: Option<u64>, // expire if still unclaimed this long after submit; overrides the lease's
+    #[serde(default)]
+    pub stdout_to: Option<String>, // log file name in the log dir instead of <id>.out
+    #[serde(default)]
+    pub stderr_to: Option<String>, // log file name in the log dir instead of <id>.err
+    #[serde(default)]
+    pub timeout_s: Option<u64>, // terminated with exit TIMEOUT_EXIT_CODE if still running this long after starting
+    #[serde(default)]
+    pub allow_rerun: bool, // an intentional rerun (retry/replay): runs even if its idempotency key already ran
+    #[serde(default)]
+    pub capture_rc: Option<String>, // the runner writes the exit code here once the task exits; relative to its cwd
+    #[serde(default)]
+    pub argv: Option<Vec<String>>, // run as this program and arguments instead of `bash -lc command`
+    #[serde(default)]
+    pub retry_of: Option<String>, // set by `leaseq retry`: the first task of the attempts this one repeats
+}
+
+/// Task IDs name files under a lease (`logs/<id>.out`, `snapshots/<id>/`,
+/// output dirs), so one that isn't a plain file name could reach outside
+/// them. Every such path is built under the lease's own root, which is what
+/// keeps equal IDs of different leases apart.
+pub fn validate_task_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || id == "." || id == ".." || id.contains(['/', '\\', '\0']) {
+        return Err(format!("invalid task ID '{}': must be a plain name without path separators", id.escape_default()));
+    }
+    Ok(())
+}
+
+/// Node names are directories under `inbox/`, `claimed/` and `done/`, so
+/// they follow the same rule as task IDs.
+pub fn validate_node_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        return Err(format!("invalid node name '{}': must be a plain name without path separators", name.escape_default()));
+    }
+    Ok(())
+}
+
+/// Names given with `--stdout-to`/`--stderr-to` are created in the task's
+/// log dir, so they must be plain file names as well. Dot files are how temp
+/// files are told apart, and `<id>.where` is the task's log pointer.
+pub fn validate_log_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('.') || name.ends_with(".where") || name.contains(['/', '\\', '\0']) {
+        return Err(format!(
+            "invalid log name '{}': must be a plain file name, not hidden and not *.where",
+            name.escape_default()
+        ));
+    }
+    Ok(())
+}
+
+/// Follow-ups stop being queued at this `chain_depth`, so a follow-up that
+/// submits itself again can't loop forever.
+pub const MAX_CHAIN_DEPTH: u32 = 16;
+
+/// Set for every task to its `chain_depth`; `submit` run inside a task reads
+/// it so nested submissions count towards the limit.
+pub const CHAIN_DEPTH_ENV: &str = "LEASEQ_CHAIN_DEPTH";
+
+/// Where `submit --capture-rc` without a path writes the exit code, in the task's cwd.
+pub const DEFAULT_RC_FILE: &str = ".leaseq_rc";
+
+/// Exit code recorded for a task whose cwd doesn't exist on the node; the
+/// runner fails it without running the command.
+pub const CWD_MISSING_EXIT_CODE: i32 = -2;
+
+/// Exit code recorded for a task the runner stopped at its `timeout_s`;
+/// negative so it can't be mistaken for the command's own `exit 124`.
+pub const TIMEOUT_EXIT_CODE: i32 = -124;
+
+impl Default for TaskSpec {
+    fn default() -> Self {
+        Self {
+            task_id: String::new(),
+            idempotency_key: String::new(),
+            lease_id: LeaseId(String::new()),
+            target_node: String::new(),
+            seq: 0,
+            uuid: Uuid::nil(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            cwd: String::new(),
+            env: HashMap::new(),
+            gpus: 0,
+            cpus: 0,
+            mem_mb: 0,
+            command: String::new(),
+            combine_output: false,
+            requeue_on_preempt: false,
+            stdin_file: None,
+            snapshot_dir: None,
+            run_in_snapshot: false,
+            no_output_s: None,
+            batch_id: None,
+            output_dir: None,
+            labels: BTreeMap::new(),
+            depends_on: Vec::new(),
+            stdin_from_task: None,
+            priority: 0,
+            on_success: None,
+            on_failure: None,
+            chain_depth: 0,
+            strict: false,
+            max_pending_s: None,
+            stdout_to: None,
+            stderr_to: None,
+            timeout_s: None,
+            allow_rerun: false,
+            capture_rc: None,
+            argv: None,
+            retry_of: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,22 +283,280 @@ pub struct TaskResult {
     pub runtime_s: f64,
     pub command: String, // Original command for reference
     #[serde(default)]
+    pub resolved_command: String, // What was executed: placeholders filled in, wrapped in the shell; empty for tasks that never ran
+    #[serde(default)]
     pub cwd: String, // Original working directory
     #[serde(default)]
     pub gpus_requested: u32, // GPUs requested
     #[serde(default)]
     pub gpus_assigned: String, // Actual GPU IDs assigned (e.g., "0,1" or "0,1,2,3")
+    #[serde(default)]
+    pub checksum: Option<String>, // over the key fields, see `compute_checksum`
+    #[serde(default)]
+    pub failure: Option<FailureReason>, // None for successful (and older) results
+    #[serde(default)]
+    pub signal: Option<i32>, // the signal that killed the process; exit_code is then -1
+}
+
+/// `SIGKILL`-style name of a signal number; `SIG<n>` for ones without a
+/// common name.
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => return format!("SIG{}", signal),
+    };
+    name.to_string()
+}
+
+/// Coarse category of a failed task, so large sweeps can be triaged
+/// ("80% OOM") without reading every log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FailureReason {
+    /// Killed by SIGKILL (exit 137 from a shell), almost always the OOM killer
+    Oom,
+    /// Exit 124, as `timeout(1)` reports, or stopped at the task's `timeout_s`
+    Timeout,
+    /// Killed by any other signal
+    Killed,
+    NonZeroExit,
+    /// The command could not be started at all
+    SpawnError,
+    Cancelled,
+    /// A task it was waiting on (`--after`) failed
+    DependencyFailed,
+    /// Never claimed within its maximum pending age
+    Expired,
+}
+
+impl FailureReason {
+    /// Classify how a process ended: its exit code, or the signal that killed it.
+    pub fn classify(exit_code: Option<i32>, signal: Option<i32>) -> Option<Self> {
+        match (exit_code, signal) {
+            (_, Some(9)) | (Some(137), _) => Some(Self::Oom),
+            (_, Some(_)) => Some(Self::Killed),
+            (Some(0), None) => None,
+            (Some(124), None) => Some(Self::Timeout),
+            _ => Some(Self::NonZeroExit),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Oom => "OOM",
+            Self::Timeout => "TIMEOUT",
+            Self::Killed => "KILLED",
+            Self::NonZeroExit => "NON_ZERO_EXIT",
+            Self::SpawnError => "SPAWN_ERROR",
+            Self::Cancelled => "CANCELLED",
+            Self::DependencyFailed => "DEPENDENCY_FAILED",
+            Self::Expired => "EXPIRED",
+        }
+    }
+}
+
+impl std::fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
+impl TaskResult {
+    /// "killed by SIGKILL" for a process that ended by a signal.
+    pub fn killed_by(&self) -> Option<String> {
+        self.signal.map(|s| format!("killed by {}", signal_name(s)))
+    }
+
+    /// FNV-1a over the fields readers rely on. Timestamps are hashed at second
+    /// precision because that is all the JSON keeps.
+    pub fn compute_checksum(&self) -> String {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| {
+            for b in bytes.iter().chain(std::iter::once(&0u8)) {
+                hash ^= *b as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+        feed(self.task_id.as_bytes());
+        feed(self.idempotency_key.as_bytes());
+        feed(self.node.as_bytes());
+        feed(&self.started_at.unix_timestamp().to_le_bytes());
+        feed(&self.finished_at.unix_timestamp().to_le_bytes());
+        feed(&self.exit_code.to_le_bytes());
+        feed(self.stdout.as_bytes());
+        feed(self.stderr.as_bytes());
+        feed(self.command.as_bytes());
+        format!("{:016x}", hash)
+    }
+
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = Some(self.compute_checksum());
+        self
+    }
+
+    /// True if the checksum matches, or if the result predates checksums.
+    pub fn checksum_ok(&self) -> bool {
+        match &self.checksum {
+            Some(c) => *c == self.compute_checksum(),
+            None => true,
+        }
+    }
+}
+
+/// What a file in `done/<node>/` holds, going by its name. The runner writes
+/// the outcome as `<name>.result.json` (`.skipped.json` for duplicates,
+/// `.cancelled.json` when cancelled before running) and archives the original
+/// `<name>.json` spec next to it, so the two must not be told apart by parsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoneFileKind {
+    Result,
+    Skipped,
+    Cancelled,
+    Spec,
+}
+
+impl DoneFileKind {
+    pub fn of(path: &std::path::Path) -> Self {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.ends_with(".result.json") {
+            Self::Result
+        } else if name.ends_with(".skipped.json") {
+            Self::Skipped
+        } else if name.ends_with(".cancelled.json") {
+            Self::Cancelled
+        } else {
+            Self::Spec
+        }
+    }
+
+    /// True for files holding a `TaskResult`, false for archived `TaskSpec`s.
+    pub fn is_result(self) -> bool {
+        self != Self::Spec
+    }
+}
+
+/// One line of a node's executed-key index, `keys/<node>.jsonl`: the
+/// idempotency key of a result the runner wrote to `done/<node>/<result>`.
+/// Lets a restarting runner load its keys without parsing every result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyIndexEntry {
+    pub key: String,
+    pub result: String, // file name in done/<node>/
+}
+
+/// Per-lease runner settings, stored as `<root>/settings.json` and re-read for
+/// every task so changes apply without restarting runners.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LeaseSettings {
+    /// Prepended to every task, e.g. `nice -n 10` or `taskset -c 0-7`.
+    /// Split on whitespace; the task's `bash -lc`, or its argv program, follows it.
+    #[serde(default)]
+    pub command_prefix: Option<String>,
+    /// Priority a pending task gains per minute of waiting, so low-priority
+    /// tasks aren't starved. Unset means `DEFAULT_PRIORITY_AGING`.
+    #[serde(default)]
+    pub priority_aging: Option<f64>,
+    /// How many times a GPU task waiting for devices may be passed over by
+    /// smaller tasks that fit the free GPUs. Unset means
+    /// `DEFAULT_BACKFILL_LIMIT`; 0 disables backfill.
+    #[serde(default)]
+    pub backfill_limit: Option<u32>,
+    /// Seconds a task may wait unclaimed before it is expired, for tasks
+    /// that don't set their own. Unset means pending tasks never expire.
+    #[serde(default)]
+    pub max_pending_age_s: Option<u64>,
+    /// Seconds after which runners delete a node's heartbeat, for leases
+    /// whose nodes come and go. Unset means heartbeats are kept.
+    #[serde(default)]
+    pub node_gc_age_s: Option<u64>,
+    /// CPUs a `--gpu-job` task asks for per GPU. Unset adds no CPUs.
+    #[serde(default)]
+    pub cpus_per_gpu: Option<u32>,
+    /// Memory in MB a `--gpu-job` task asks for per GPU. Unset adds none.
+    #[serde(default)]
+    pub mem_per_gpu_mb: Option<u64>,
+}
+
+pub const DEFAULT_PRIORITY_AGING: f64 = 1.0;
+pub const DEFAULT_BACKFILL_LIMIT: u32 = 4;
+
+impl LeaseSettings {
+    pub fn priority_aging(&self) -> f64 {
+        self.priority_aging.unwrap_or(DEFAULT_PRIORITY_AGING)
+    }
+
+    pub fn backfill_limit(&self) -> u32 {
+        self.backfill_limit.unwrap_or(DEFAULT_BACKFILL_LIMIT)
+    }
+}
+
+pub const LEASE_SETTINGS_FILE: &str = "settings.json";
+
+/// Where a task's logs went when the runner writes them outside `<root>/logs/`.
+/// Stored as `logs/<task_id>.where` so readers can find logs of running tasks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLocation {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// A node whose heartbeat is older than this has no live runner: `status`
+/// shows it STALE, `watch` reports it down, `submit` routes around it and
+/// `lease recover` takes its tasks.
+pub const NODE_LIVE_THRESHOLD: time::Duration = time::Duration::minutes(2);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Heartbeat {
     pub node: String,
     #[serde(with = "time::serde::timestamp")]
     pub ts: OffsetDateTime,
+    /// The first of `running_task_ids`, for readers that only know one.
     pub running_task_id: Option<String>,
+    /// Every task the runner is running; empty for runners from before
+    /// parallel slots.
+    #[serde(default)]
+    pub running_task_ids: Vec<String>,
     pub pending_estimate: u32,
     pub runner_pid: u32,
     pub version: String,
+    /// `SCHEMA_VERSION` of the runner; 0 for runners from before it was recorded.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// CPUs the runner counts task `cpus` against; 0 for runners that don't report it.
+    #[serde(default)]
+    pub cpus: u32,
+    /// Poll/execute errors the runner has hit since it started.
+    #[serde(default)]
+    pub errors_since_start: u64,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl Heartbeat {
+    /// What the runner is running, whether it reports one task or several.
+    pub fn running_tasks(&self) -> Vec<String> {
+        if self.running_task_ids.is_empty() {
+            self.running_task_id.iter().cloned().collect()
+        } else {
+            self.running_task_ids.clone()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -111,13 +568,49 @@ pub enum Event {
     Failed { task_id: String, error: String },
     SkippedDup { task_id: String, key: String },
     Cancelled { task_id: String },
+    /// The node's heartbeat went stale; emitted by `leaseq watch`.
+    NodeDown {
+        node: String,
+        #[serde(with = "time::serde::timestamp")]
+        last_seen: OffsetDateTime,
+    },
+    /// A node reported `NodeDown` is heartbeating again.
+    NodeUp { node: String },
 }
 
+/// Append-only JSON-lines log of a node's `Event`s, in `events/<node>/`.
+pub const EVENTS_LOG_FILE: &str = "events.jsonl";
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::collections::HashMap;
 
+    #[test]
+    fn test_validate_task_id() {
+        assert!(validate_task_id("T1a2b3c").is_ok());
+        assert!(validate_task_id("sweep-1.retry").is_ok());
+        for bad in ["", ".", "..", "../T1", "a/b", "/etc/passwd", "a\\b", "a\0b"] {
+            assert!(validate_task_id(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_validate_node_name() {
+        assert!(validate_node_name("gpu-node-01").is_ok());
+        for bad in ["", ".", "..", "../inbox", "a/b", "a\\b"] {
+            assert!(validate_node_name(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_validate_log_name() {
+        assert!(validate_log_name("train.stdout").is_ok());
+        for bad in ["", ".", "..", ".hidden", "../x.log", "a/b", "T1.where"] {
+            assert!(validate_log_name(bad).is_err(), "{:?}", bad);
+        }
+    }
+
     #[test]
     fn test_lease_id_display() {
         let local = LeaseId("local:myhost".to_string());
@@ -141,6 +634,7 @@ mod tests {
             env: HashMap::new(),
             gpus: 0,
             command: "echo hello".to_string(),
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&spec).unwrap();
@@ -163,8 +657,13 @@ mod tests {
             stderr: "logs/T001.err".to_string(),
             runtime_s: 10.5,
             command: "echo hello".to_string(),
+            resolved_command: String::new(),
+            cwd: "/home/user".to_string(),
             gpus_requested: 2,
             gpus_assigned: "0,1".to_string(),
+            checksum: None,
+            failure: None,
+            signal: None,
         };
 
         let json = serde_json::to_string(&result).unwrap();
@@ -177,15 +676,54 @@ mod tests {
         assert_eq!(parsed.gpus_assigned, "0,1");
     }
 
+    #[test]
+    fn test_task_result_checksum_detects_tampering() {
+        let result = TaskResult {
+            task_id: "T001".to_string(),
+            idempotency_key: "key-001".to_string(),
+            node: "myhost".to_string(),
+            started_at: OffsetDateTime::now_utc(),
+            finished_at: OffsetDateTime::now_utc(),
+            exit_code: 1,
+            stdout: "logs/T001.out".to_string(),
+            stderr: "logs/T001.err".to_string(),
+            runtime_s: 0.5,
+            command: "false".to_string(),
+            resolved_command: String::new(),
+            cwd: "/".to_string(),
+            gpus_requested: 0,
+            gpus_assigned: String::new(),
+            checksum: None,
+            failure: None,
+            signal: None,
+        }
+        .with_checksum();
+
+        // Survives a JSON round trip (sub-second precision is dropped)
+        let json = serde_json::to_string(&result).unwrap();
+        let parsed: TaskResult = serde_json::from_str(&json).unwrap();
+        assert!(parsed.checksum_ok());
+
+        let tampered = json.replace("\"exit_code\":1", "\"exit_code\":0");
+        assert_ne!(tampered, json);
+        let parsed: TaskResult = serde_json::from_str(&tampered).unwrap();
+        assert!(!parsed.checksum_ok());
+    }
+
     #[test]
     fn test_heartbeat_serialization() {
         let hb = Heartbeat {
             node: "myhost".to_string(),
             ts: OffsetDateTime::UNIX_EPOCH,
             running_task_id: Some("T001".to_string()),
+            running_task_ids: Vec::new(),
             pending_estimate: 5,
             runner_pid: 12345,
             version: "0.1.0".to_string(),
+            schema_version: SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
         };
 
         let json = serde_json::to_string(&hb).unwrap();
@@ -224,6 +762,8 @@ mod tests {
                 total_gpus: 8,
                 parallel: 1,
             },
+            comment: None,
+            metadata: BTreeMap::new(),
         };
 
         let json = serde_json::to_string(&meta).unwrap();
@@ -0,0 +1,121 @@
+use crate::fs as lfs;
+use crate::models::TaskResult;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+/// Directory under a lease root holding files that failed to parse or verify.
+pub const CORRUPT_DIR: &str = "corrupt";
+
+const RECORD_SUFFIX: &str = ".reason.json";
+
+/// Why and from where a file was quarantined, stored next to it as
+/// `corrupt/<name>.reason.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    pub original: String, // path relative to the lease root
+    pub error: String,
+    #[serde(with = "time::serde::timestamp")]
+    pub quarantined_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuarantinedFile {
+    pub path: PathBuf,
+    pub record: Option<QuarantineRecord>,
+}
+
+/// Move `path` (somewhere under `root`) into `root/corrupt/`, recording the error.
+pub fn quarantine(root: &Path, path: &Path, error: &str) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let dir = root.join(CORRUPT_DIR);
+    lfs::ensure_dir(&dir)?;
+
+    let dest = dir.join(name);
+    std::fs::rename(path, &dest)?;
+
+    let record = QuarantineRecord {
+        original: path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned(),
+        error: error.to_string(),
+        quarantined_at: OffsetDateTime::now_utc(),
+    };
+    lfs::atomic_write_json(record_path(&dest), &record)?;
+    Ok(dest)
+}
+
+/// Quarantined files (not their records), oldest name first.
+pub fn list(root: &Path) -> io::Result<Vec<QuarantinedFile>> {
+    let mut files = Vec::new();
+    for path in lfs::list_files_sorted(root.join(CORRUPT_DIR))? {
+        if path.to_string_lossy().ends_with(RECORD_SUFFIX) {
+            continue;
+        }
+        let record = lfs::read_json(record_path(&path)).ok();
+        files.push(QuarantinedFile { path, record });
+    }
+    Ok(files)
+}
+
+/// Remove a quarantined file together with its record.
+pub fn remove(file: &QuarantinedFile) -> io::Result<()> {
+    lfs::remove_file_if_exists(&file.path)?;
+    lfs::remove_file_if_exists(record_path(&file.path))
+}
+
+/// Read a result for reporting. Files that are not results yield `None`;
+/// results whose checksum doesn't match are quarantined and also yield `None`.
+pub fn read_verified_result(root: &Path, path: &Path) -> Option<TaskResult> {
+    let result: TaskResult = lfs::read_json(path).ok()?;
+    if result.checksum_ok() {
+        return Some(result);
+    }
+    tracing::warn!("Checksum mismatch in {:?}, quarantining", path);
+    if let Err(e) = quarantine(root, path, "result checksum mismatch") {
+        tracing::warn!("Failed to quarantine {:?}: {}", path, e);
+    }
+    None
+}
+
+pub fn record_path(quarantined: &Path) -> PathBuf {
+    let mut name = quarantined.file_name().unwrap_or_default().to_os_string();
+    name.push(RECORD_SUFFIX);
+    quarantined.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_quarantine_and_list() -> io::Result<()> {
+        let dir = tempdir()?;
+        let root = dir.path();
+        let inbox = root.join("inbox").join("node1");
+        lfs::ensure_dir(&inbox)?;
+        let bad = inbox.join("001_T1.json");
+        std::fs::write(&bad, "{ not json")?;
+
+        let dest = quarantine(root, &bad, "expected value at line 1")?;
+        assert!(!bad.exists());
+        assert!(dest.exists());
+
+        let files = list(root)?;
+        assert_eq!(files.len(), 1);
+        let record = files[0].record.as_ref().expect("record should be written");
+        assert_eq!(record.original, "inbox/node1/001_T1.json");
+        assert_eq!(record.error, "expected value at line 1");
+
+        remove(&files[0])?;
+        assert!(list(root)?.is_empty());
+        assert!(!record_path(&dest).exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+/// Parse a memory size such as `512M`, `16G` or `1T` into megabytes. A bare
+/// number is taken as megabytes, like Slurm's `--mem`.
+pub fn parse_mem_mb(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}': expected a number, e.g. 16G", s))?;
+    let scale = match unit.to_ascii_uppercase().as_str() {
+        "" | "M" | "MB" => 1,
+        "G" | "GB" => 1024,
+        "T" | "TB" => 1024 * 1024,
+        _ => return Err(format!("invalid size '{}': unknown unit '{}' (use M, G, T)", s, unit)),
+    };
+    n.checked_mul(scale).ok_or_else(|| format!("invalid size '{}': too large", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mem_mb_units() {
+        assert_eq!(parse_mem_mb("512"), Ok(512));
+        assert_eq!(parse_mem_mb("512M"), Ok(512));
+        assert_eq!(parse_mem_mb("16G"), Ok(16 * 1024));
+        assert_eq!(parse_mem_mb("2tb"), Ok(2 * 1024 * 1024));
+        assert!(parse_mem_mb("G").is_err());
+        assert!(parse_mem_mb("16X").is_err());
+    }
+}
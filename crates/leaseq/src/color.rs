@@ -0,0 +1,81 @@
+//! Process-wide color policy for command output, set once from `--color`.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is unset
+    #[default]
+    Auto,
+    /// Always emit ANSI colors, even when piped
+    Always,
+    /// Never emit ANSI colors
+    Never,
+}
+
+static CHOICE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_choice(choice: ColorChoice) {
+    CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+pub fn choice() -> ColorChoice {
+    match CHOICE.load(Ordering::Relaxed) {
+        1 => ColorChoice::Always,
+        2 => ColorChoice::Never,
+        _ => ColorChoice::Auto,
+    }
+}
+
+/// Whether to color output going to a sink that is (`is_tty`) or isn't a terminal.
+pub fn enabled_for(is_tty: bool) -> bool {
+    match choice() {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_tty && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    }
+}
+
+/// Whether to color what is printed to stdout.
+pub fn enabled() -> bool {
+    enabled_for(std::io::stdout().is_terminal())
+}
+
+/// Wrap `text` in the color for a task or node state; unknown states stay plain.
+/// `state` picks the color, `text` is what's printed (e.g. the padded state).
+pub fn state(text: &str, state: &str, enabled: bool) -> String {
+    let code = match state {
+        "DONE" | "OK" => "32",                  // green
+        "FAILED" | "STUCK" | "STALE" => "31",   // red
+        "RUNNING" => "36",                      // cyan
+        "POSSIBLY_HUNG" | "CANCELLED" => "33",  // yellow
+        "CORDONED" => "33",                     // yellow
+        _ => return text.to_string(),
+    };
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_choice_overrides_tty_detection() {
+        set_choice(ColorChoice::Never);
+        assert!(!enabled_for(true));
+        assert_eq!(state("FAILED", "FAILED", enabled_for(true)), "FAILED");
+
+        set_choice(ColorChoice::Always);
+        assert!(enabled_for(false));
+        assert_eq!(state("DONE  ", "DONE", enabled_for(false)), "\x1b[32mDONE  \x1b[0m");
+
+        set_choice(ColorChoice::Auto);
+        assert!(!enabled_for(false));
+        assert_eq!(state("PENDING", "PENDING", true), "PENDING");
+    }
+}
@@ -17,6 +17,13 @@ pub async fn run(slurm_args: Vec<String>) -> Result<()> {
         account: None,
         sbatch_arg: slurm_args,
         wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
     };
 
     let result = create_lease_quiet(args).await?;
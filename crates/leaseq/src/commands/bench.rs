@@ -0,0 +1,130 @@
+use crate::commands::group;
+use crate::commands::submit::{self, SubmitOptions};
+use anyhow::Result;
+use leaseq_core::{config, fs as lfs, models};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// What a calibration run measured.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub tasks: usize,
+    /// From the first submit until the last result was seen
+    pub elapsed: Duration,
+    pub tasks_per_sec: f64,
+    /// Mean time from submitting a task to a runner starting it
+    pub mean_claim_latency: Duration,
+    /// One pass over inbox/, claimed/ and done/, as the runner and `status` do
+    pub scan_time: Duration,
+    pub scanned_files: usize,
+}
+
+pub async fn run(lease: Option<String>, node: Option<String>, tasks: usize, timeout: u64) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+
+    println!("Submitting {} trivial task(s) to {}...", tasks, lease_id);
+    let report = bench(&root, &lease_id, node, tasks, Duration::from_secs(timeout)).await?;
+    println!("Tasks:          {}", report.tasks);
+    println!("Elapsed:        {:.2}s", report.elapsed.as_secs_f64());
+    println!("Throughput:     {:.2} tasks/s", report.tasks_per_sec);
+    println!("Claim latency:  {:.2}s mean", report.mean_claim_latency.as_secs_f64());
+    println!(
+        "Lease scan:     {:.1}ms ({} files)",
+        report.scan_time.as_secs_f64() * 1000.0,
+        report.scanned_files
+    );
+    Ok(())
+}
+
+/// Submit `tasks` no-op tasks as one group, wait for all of them and time
+/// it. Needs a runner on the lease; gives up after `timeout`.
+pub async fn bench(
+    root: &Path,
+    lease_id: &str,
+    node: Option<String>,
+    tasks: usize,
+    timeout: Duration,
+) -> Result<BenchReport> {
+    if tasks == 0 {
+        return Err(anyhow::anyhow!("Nothing to bench: --tasks must be at least 1"));
+    }
+    let group_id = format!("bench-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let opts = SubmitOptions { group_as: Some(group_id.clone()), quiet: true, ..Default::default() };
+
+    let start = Instant::now();
+    let mut specs = HashMap::new();
+    for _ in 0..tasks {
+        let spec = submit::add_task_with_options("true".to_string(), Some(lease_id.to_string()), node.clone(), &opts)
+            .await?;
+        specs.insert(spec.task_id.clone(), spec);
+    }
+
+    loop {
+        let members = group::group_members(root, &group_id)?;
+        if members.len() == tasks && members.iter().all(|m| m.is_finished()) {
+            let failed = members.iter().filter(|m| m.state != "DONE").count();
+            if failed > 0 {
+                return Err(anyhow::anyhow!("{} bench task(s) in group {} did not succeed", failed, group_id));
+            }
+            break;
+        }
+        if start.elapsed() > timeout {
+            return Err(anyhow::anyhow!(
+                "Timed out waiting for bench group {}; is a runner up on lease {}?",
+                group_id,
+                lease_id
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    let elapsed = start.elapsed();
+
+    let nodes: HashSet<&str> = specs.values().map(|s| s.target_node.as_str()).collect();
+    let mut latencies = Vec::new();
+    for node in nodes {
+        for f in lfs::list_files_sorted(root.join("done").join(node))? {
+            if models::DoneFileKind::of(&f) != models::DoneFileKind::Result {
+                continue;
+            }
+            let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) else {
+                continue;
+            };
+            if let Some(spec) = specs.get(&result.task_id) {
+                latencies.push((result.started_at - spec.created_at).as_seconds_f64().max(0.0));
+            }
+        }
+    }
+    let mean_claim_latency = if latencies.is_empty() {
+        Duration::ZERO
+    } else {
+        Duration::from_secs_f64(latencies.iter().sum::<f64>() / latencies.len() as f64)
+    };
+
+    let (scanned_files, scan_time) = scan(root)?;
+    Ok(BenchReport {
+        tasks,
+        elapsed,
+        tasks_per_sec: tasks as f64 / elapsed.as_secs_f64(),
+        mean_claim_latency,
+        scan_time,
+        scanned_files,
+    })
+}
+
+/// Time listing and parsing every queue file of the lease.
+fn scan(root: &Path) -> Result<(usize, Duration)> {
+    let start = Instant::now();
+    let mut files = 0;
+    for queue in ["inbox", "claimed", "done"] {
+        for (_, dir) in lfs::node_dirs(root, queue)? {
+            for f in lfs::list_files_sorted(&dir)? {
+                let _ = lfs::read_json::<serde_json::Value, _>(&f);
+                files += 1;
+            }
+        }
+    }
+    Ok((files, start.elapsed()))
+}
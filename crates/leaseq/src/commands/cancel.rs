@@ -1,19 +1,18 @@
 use anyhow::Result;
 use leaseq_core::{config, fs as lfs, models};
-use uuid::Uuid;
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::path::Path;
+use time::OffsetDateTime;
+use uuid::Uuid;
 
 pub async fn run(task: String, lease: Option<String>) -> Result<()> {
     let lease_id = lease.unwrap_or_else(config::local_lease_id);
 
-    let root = if lease_id.starts_with("local:") {
-        config::runtime_dir().join(&lease_id)
-    } else {
-        config::leaseq_home_dir().join("runs").join(&lease_id)
-    };
+    let root = config::lease_root(&lease_id);
 
     // Find the task and determine which node it's on
-    let (node, task_state) = find_task(&root, &task)?;
+    let FoundTask { task_id: task, node, state: task_state } = find_task(&root, &task)?;
 
     match task_state.as_str() {
         "PENDING" => {
@@ -36,66 +35,107 @@ pub async fn run(task: String, lease: Option<String>) -> Result<()> {
     Ok(())
 }
 
-fn find_task(root: &Path, task_id: &str) -> Result<(String, String)> {
-    // Check inbox (pending)
-    let inbox_dir = root.join("inbox");
-    if inbox_dir.exists() {
-        for entry in std::fs::read_dir(&inbox_dir)? {
-            let entry = entry?;
-            if entry.path().is_dir() {
-                let node = entry.file_name().to_string_lossy().into_owned();
-                for task_file in lfs::list_files_sorted(entry.path())? {
-                    if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
-                        if spec.task_id == task_id || spec.task_id.starts_with(task_id) {
-                            return Ok((node, "PENDING".to_string()));
-                        }
-                    }
-                }
-            }
-        }
+/// A task `find_task` resolved, under its full ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundTask {
+    pub task_id: String,
+    pub node: String,
+    pub state: String, // PENDING, RUNNING, DONE or FAILED
+}
+
+/// How a task matched the query; better matches win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Match {
+    Prefix,
+    Uuid,
+    Exact,
+}
+
+fn match_task(query: &str, task_id: &str, uuid: Option<&Uuid>) -> Option<Match> {
+    if task_id == query {
+        Some(Match::Exact)
+    } else if uuid.is_some_and(|u| Uuid::parse_str(query).is_ok_and(|q| q == *u)) {
+        Some(Match::Uuid)
+    } else if task_id.starts_with(query) {
+        Some(Match::Prefix)
+    } else {
+        None
     }
+}
 
-    // Check claimed (running)
-    let claimed_dir = root.join("claimed");
-    if claimed_dir.exists() {
-        for entry in std::fs::read_dir(&claimed_dir)? {
-            let entry = entry?;
-            if entry.path().is_dir() {
-                let node = entry.file_name().to_string_lossy().into_owned();
-                for task_file in lfs::list_files_sorted(entry.path())? {
-                    if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
-                        if spec.task_id == task_id || spec.task_id.starts_with(task_id) {
-                            return Ok((node, "RUNNING".to_string()));
-                        }
-                    }
+/// Resolve `query` (a task ID, a unique prefix of one, or the task's UUID in
+/// either form) to a task of the lease. A full ID wins over a UUID, which
+/// wins over prefixes; a prefix shared by several tasks is an error.
+///
+/// A task that was retried or requeued can have several results; a pending
+/// or running attempt wins over them, and otherwise the newest result does.
+pub fn find_task(root: &Path, query: &str) -> Result<FoundTask> {
+    // The finish time is None for tasks still queued or running
+    let mut matches: Vec<(Match, Option<OffsetDateTime>, FoundTask)> = Vec::new();
+    let mut push = |m: Option<Match>, task_id: &str, node: &str, state: &str, finished: Option<OffsetDateTime>| {
+        if let Some(m) = m {
+            let found = FoundTask { task_id: task_id.to_string(), node: node.to_string(), state: state.to_string() };
+            matches.push((m, finished, found));
+        }
+    };
+
+    // Pending and running specs
+    for (dir, state) in [("inbox", "PENDING"), ("claimed", "RUNNING")] {
+        for (node, node_dir) in lfs::node_dirs(root, dir)? {
+            for task_file in lfs::list_files_sorted(&node_dir)? {
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
+                    push(match_task(query, &spec.task_id, Some(&spec.uuid)), &spec.task_id, &node, state, None);
                 }
             }
         }
     }
 
-    // Check done
-    let done_dir = root.join("done");
-    if done_dir.exists() {
-        for entry in std::fs::read_dir(&done_dir)? {
-            let entry = entry?;
-            if entry.path().is_dir() {
-                let node = entry.file_name().to_string_lossy().into_owned();
-                for result_file in lfs::list_files_sorted(entry.path())? {
-                    if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&result_file) {
-                        if result.task_id == task_id || result.task_id.starts_with(task_id) {
-                            let state = if result.exit_code == 0 { "DONE" } else { "FAILED" };
-                            return Ok((node, state.to_string()));
-                        }
+    // Finished: the state is in the result, the UUID in the spec archived next to it
+    for (node, node_dir) in lfs::node_dirs(root, "done")? {
+        let mut states: HashMap<String, (&str, OffsetDateTime)> = HashMap::new();
+        let mut specs = Vec::new();
+        for file in lfs::list_files_sorted(&node_dir)? {
+            if models::DoneFileKind::of(&file).is_result() {
+                if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&file) {
+                    let state = if result.exit_code == 0 { "DONE" } else { "FAILED" };
+                    let newest = states.get(&result.task_id).is_none_or(|(_, at)| result.finished_at > *at);
+                    if newest {
+                        states.insert(result.task_id, (state, result.finished_at));
                     }
                 }
+            } else if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&file) {
+                specs.push(spec);
             }
         }
+        for spec in &specs {
+            if let Some((state, at)) = states.remove(&spec.task_id) {
+                push(match_task(query, &spec.task_id, Some(&spec.uuid)), &spec.task_id, &node, state, Some(at));
+            }
+        }
+        // Results whose spec is gone can still be found by ID
+        for (task_id, (state, at)) in states {
+            push(match_task(query, &task_id, None), &task_id, &node, state, Some(at));
+        }
     }
 
-    Err(anyhow::anyhow!("Task {} not found", task_id))
+    let Some(best) = matches.iter().map(|(m, _, _)| *m).max() else {
+        return Err(anyhow::anyhow!("Task {} not found", query));
+    };
+    let mut found: Vec<_> = matches.into_iter().filter(|(m, _, _)| *m == best).map(|(_, at, f)| (at, f)).collect();
+    // Per task: live attempts first, then results newest first; keep the first
+    found.sort_by(|(a_at, a), (b_at, b)| {
+        a.task_id.cmp(&b.task_id).then(a_at.map(Reverse).cmp(&b_at.map(Reverse)))
+    });
+    let mut found: Vec<FoundTask> = found.into_iter().map(|(_, f)| f).collect();
+    found.dedup_by(|a, b| a.task_id == b.task_id);
+    if found.len() > 1 {
+        let ids: Vec<_> = found.iter().map(|f| f.task_id.as_str()).collect();
+        return Err(anyhow::anyhow!("{} matches several tasks: {}; use more of the ID or the UUID", query, ids.join(", ")));
+    }
+    Ok(found.remove(0))
 }
 
-fn cancel_pending_task(root: &Path, task_id: &str, node: &str) -> Result<()> {
+pub(crate) fn cancel_pending_task(root: &Path, task_id: &str, node: &str) -> Result<()> {
     let inbox_dir = root.join("inbox").join(node);
     let done_dir = root.join("done").join(node);
 
@@ -117,17 +157,22 @@ fn cancel_pending_task(root: &Path, task_id: &str, node: &str) -> Result<()> {
                     stderr: String::new(),
                     runtime_s: 0.0,
                     command: spec.command.clone(),
+                    resolved_command: String::new(),
                     cwd: spec.cwd.clone(),
                     gpus_requested: spec.gpus,
                     gpus_assigned: String::new(),
-                };
+                    checksum: None,
+                    failure: Some(models::FailureReason::Cancelled),
+                    signal: None,
+                }
+                .with_checksum();
 
                 let original_name = task_file.file_name().unwrap().to_string_lossy();
                 let result_name = format!("{}.cancelled.json", original_name.trim_end_matches(".json"));
                 lfs::atomic_write_json(done_dir.join(&result_name), &result)?;
 
-                // Remove from inbox
-                std::fs::remove_file(&task_file)?;
+                // Archive the spec next to its result, like the runner does
+                std::fs::rename(&task_file, done_dir.join(task_file.file_name().unwrap()))?;
                 return Ok(());
             }
         }
@@ -136,7 +181,7 @@ fn cancel_pending_task(root: &Path, task_id: &str, node: &str) -> Result<()> {
     Err(anyhow::anyhow!("Task file not found in inbox"))
 }
 
-fn cancel_running_task(root: &Path, task_id: &str, node: &str) -> Result<()> {
+pub(crate) fn cancel_running_task(root: &Path, task_id: &str, node: &str) -> Result<()> {
     let control_dir = root.join("control").join(node);
     lfs::ensure_dir(&control_dir)?;
 
@@ -1,7 +1,7 @@
 use anyhow::{Result, Context};
-use leaseq_core::config;
+use leaseq_core::{config, fs as lfs, models};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
 fn pid_file() -> PathBuf {
@@ -26,6 +26,7 @@ pub async fn start() -> Result<()> {
 
     // Ensure directories exist
     fs::create_dir_all(&root)?;
+    write_local_meta(&root, &lease_id)?;
 
     // Use current executable with `run` subcommand
     let current_exe = std::env::current_exe().context("Failed to get current executable")?;
@@ -33,14 +34,27 @@ pub async fn start() -> Result<()> {
     // Start the runner
     let log = fs::File::create(log_file())?;
 
-    let child = Command::new(&current_exe)
-        .arg("run")
+    let mut cmd = Command::new(&current_exe);
+    cmd.arg("run")
         .arg("--lease")
         .arg(&lease_id)
         .stdout(Stdio::from(log.try_clone()?))
-        .stderr(Stdio::from(log))
-        .spawn()
-        .context("Failed to start runner")?;
+        .stderr(Stdio::from(log));
+    // Own session and process group: detached from the terminal, and `stop`
+    // can signal the runner as a group
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    let child = cmd.spawn().context("Failed to start runner")?;
 
     let pid = child.id();
 
@@ -54,16 +68,80 @@ pub async fn start() -> Result<()> {
     Ok(())
 }
 
+/// Run the local runner inline: no detach, no pid file, logs on stdout.
+/// SIGTERM or Ctrl-C stops it after the current task finishes.
+pub async fn run_foreground() -> Result<()> {
+    let lease_id = config::local_lease_id();
+    write_local_meta(&config::runtime_dir().join(&lease_id), &lease_id)?;
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+
+    tokio::spawn(async move {
+        wait_for_shutdown_signal().await;
+        let _ = shutdown_tx.send(true);
+    });
+
+    super::run::run_with_shutdown(
+        super::run::RunArgs { lease: lease_id, ..Default::default() },
+        shutdown_rx,
+    )
+    .await
+}
+
+/// Record the local lease in `<root>/lease.json` the first time the daemon
+/// starts it. An existing file is left alone: its `total_gpus`/`parallel`
+/// configure the runner. Zero means "detect" / "the default" there.
+pub fn write_local_meta(root: &Path, lease_id: &str) -> Result<()> {
+    let path = root.join(models::LEASE_META_FILE);
+    if path.exists() {
+        return Ok(());
+    }
+    let meta = models::LeaseMeta::Local {
+        lease_id: models::LeaseId(lease_id.to_string()),
+        created_at: time::OffsetDateTime::now_utc(),
+        local: models::LocalLeaseConfig { total_gpus: 0, parallel: 0 },
+        comment: None,
+        metadata: Default::default(),
+    };
+    lfs::atomic_write_json(&path, &meta).context(format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
 pub async fn stop() -> Result<()> {
     let pid = read_pid();
 
     match pid {
         Some(pid) if is_process_running(pid) => {
-            // Send SIGTERM
+            // The runner leads its process group and passes SIGTERM on to the
+            // groups of its running tasks. One started by an older leaseq
+            // without its own group gets ESRCH; signal just the runner then.
             #[cfg(unix)]
             {
                 unsafe {
-                    libc::kill(pid as i32, libc::SIGTERM);
+                    if libc::kill(-(pid as i32), libc::SIGTERM) != 0 {
+                        libc::kill(pid as i32, libc::SIGTERM);
+                    }
                 }
             }
             #[cfg(not(unix))]
@@ -74,8 +152,11 @@ pub async fn stop() -> Result<()> {
                     .status();
             }
 
-            // Wait a bit and check
-            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+            // Give it a moment to signal its tasks and exit
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            while is_process_running(pid) && std::time::Instant::now() < deadline {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
 
             if !is_process_running(pid) {
                 fs::remove_file(pid_file()).ok();
@@ -118,17 +199,14 @@ pub async fn status() -> Result<()> {
     // Check heartbeat
     let hb_dir = root.join("hb");
     if hb_dir.exists() {
-        for entry in fs::read_dir(&hb_dir)? {
-            let entry = entry?;
-            if let Ok(content) = fs::read_to_string(entry.path()) {
-                if let Ok(hb) = serde_json::from_str::<leaseq_core::models::Heartbeat>(&content) {
-                    let age = (time::OffsetDateTime::now_utc() - hb.ts).as_seconds_f64();
-                    let status = if age > 60.0 { "STALE" } else { "OK" };
-                    println!(
-                        "Runner {}: {} (heartbeat {:.0}s ago)",
-                        hb.node, status, age
-                    );
-                }
+        for path in leaseq_core::fs::list_files_sorted(&hb_dir)? {
+            if let Ok(hb) = leaseq_core::fs::read_heartbeat(&path) {
+                let age = (time::OffsetDateTime::now_utc() - hb.ts).as_seconds_f64();
+                let status = if age >= models::NODE_LIVE_THRESHOLD.as_seconds_f64() { "STALE" } else { "OK" };
+                println!(
+                    "Runner {}: {} (heartbeat {:.0}s ago)",
+                    hb.node, status, age
+                );
             }
         }
     }
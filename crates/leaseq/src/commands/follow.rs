@@ -1,22 +1,35 @@
 use anyhow::Result;
+use crate::commands::logs;
 use leaseq_core::{config, fs as lfs, models};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::io::{self, Read, Seek, SeekFrom, Write};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+
+/// Output already in the log to print before following new output.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Replay {
+    /// Only new output
+    #[default]
+    None,
+    /// The last N lines
+    Lines(usize),
+    /// What was written in the last stretch of time. Logs carry no
+    /// timestamps, so this assumes output came at a steady rate since the
+    /// file was created.
+    Since(time::Duration),
+}
 
 pub async fn run(
     task: Option<String>,
     lease: Option<String>,
     node: Option<String>,
     stderr: bool,
-) -> Result<()> {
+    replay: Replay,
+    exit_code: bool,
+) -> Result<Option<i32>> {
     let lease_id = lease.unwrap_or_else(config::local_lease_id);
 
-    let root = if lease_id.starts_with("local:") {
-        config::runtime_dir().join(&lease_id)
-    } else {
-        config::leaseq_home_dir().join("runs").join(&lease_id)
-    };
+    let root = config::lease_root(&lease_id);
 
     // Determine which task to follow
     let task_id = if let Some(t) = task {
@@ -26,16 +39,62 @@ pub async fn run(
         find_running_task(&root, node.as_deref())?
     };
 
-    let log_path = if stderr {
-        root.join("logs").join(format!("{}.err", task_id))
-    } else {
-        root.join("logs").join(format!("{}.out", task_id))
+    if exit_code {
+        eprintln!("Following {} until it finishes", task_id);
+        let code = follow_task(&root, &task_id, stderr, replay, &mut io::stdout()).await?;
+        return Ok(Some(code));
+    }
+
+    // Wait for the runner to create the log; its name depends on whether
+    // the task combines stdout and stderr
+    let log_path = loop {
+        let path = logs::log_path(&root, &task_id, stderr);
+        if path.exists() {
+            break path;
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
     };
 
     eprintln!("Following {} (Ctrl+C to stop)", log_path.display());
 
     // Tail follow the file
-    tail_follow(&log_path).await
+    tail_follow(&log_path, replay, &mut io::stdout()).await?;
+    Ok(None)
+}
+
+/// Write the task's log to `out` until its result appears, then return its
+/// exit code. A task that finished without ever starting (e.g. cancelled
+/// while pending) has no log; only its code is returned.
+pub async fn follow_task(
+    root: &Path,
+    task_id: &str,
+    stderr: bool,
+    replay: Replay,
+    out: &mut impl Write,
+) -> Result<i32> {
+    let log_path = loop {
+        let path = logs::log_path(root, task_id, stderr);
+        if path.exists() {
+            break path;
+        }
+        if let Some(result) = latest_result(root, task_id) {
+            return Ok(result.exit_code);
+        }
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    };
+    tail_until(&log_path, replay, out, || latest_result(root, task_id).map(|r| r.exit_code)).await
+}
+
+/// The task's newest result in any node's done/, if it has finished.
+pub(crate) fn latest_result(root: &Path, task_id: &str) -> Option<models::TaskResult> {
+    lfs::node_dirs(root, "done")
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|(_, dir)| lfs::list_files_sorted(dir).unwrap_or_default())
+        .filter(|f| models::DoneFileKind::of(f).is_result())
+        .filter_map(|f| lfs::read_json::<models::TaskResult, _>(f).ok())
+        .filter(|r| r.task_id == task_id)
+        .max_by_key(|r| r.finished_at)
 }
 
 fn find_running_task(root: &Path, node_filter: Option<&str>) -> Result<String> {
@@ -82,7 +141,20 @@ fn find_running_task(root: &Path, node_filter: Option<&str>) -> Result<String> {
     }
 }
 
-async fn tail_follow(path: &PathBuf) -> Result<()> {
+/// Write `path`'s output to `out` as it grows, starting with what `replay`
+/// asks for. Runs until cancelled.
+pub async fn tail_follow(path: &Path, replay: Replay, out: &mut impl Write) -> Result<()> {
+    tail_until(path, replay, out, || None).await
+}
+
+/// `tail_follow` until `finished` returns a value, then write what the file
+/// still holds and return it.
+pub async fn tail_until<T>(
+    path: &Path,
+    replay: Replay,
+    out: &mut impl Write,
+    mut finished: impl FnMut() -> Option<T>,
+) -> Result<T> {
     let poll_interval = Duration::from_millis(250);
 
     // Wait for file to exist
@@ -92,12 +164,18 @@ async fn tail_follow(path: &PathBuf) -> Result<()> {
 
     let mut file = std::fs::File::open(path)?;
 
-    // Start from current end
-    let mut pos = file.seek(SeekFrom::End(0))?;
+    let mut pos = match replay {
+        Replay::None => file.seek(SeekFrom::End(0))?,
+        Replay::Lines(n) => last_lines_offset(&mut file, n)?,
+        Replay::Since(window) => since_offset(&mut file, window)?,
+    };
 
     let mut buffer = vec![0u8; 4096];
 
     loop {
+        // Asked before reading, so output written before the end isn't lost
+        let done = finished();
+
         // Check for new data
         let current_len = file.metadata()?.len();
 
@@ -109,8 +187,8 @@ async fn tail_follow(path: &PathBuf) -> Result<()> {
                 if n == 0 {
                     break;
                 }
-                io::stdout().write_all(&buffer[..n])?;
-                io::stdout().flush()?;
+                out.write_all(&buffer[..n])?;
+                out.flush()?;
                 pos += n as u64;
             }
         } else if current_len < pos {
@@ -119,6 +197,83 @@ async fn tail_follow(path: &PathBuf) -> Result<()> {
             file.seek(SeekFrom::Start(0))?;
         }
 
+        if let Some(value) = done {
+            return Ok(value);
+        }
         tokio::time::sleep(poll_interval).await;
     }
 }
+
+/// Byte offset where the last `n` lines of `file` start, found by scanning
+/// backward from the end. A final line without a newline counts as a line.
+pub fn last_lines_offset(file: &mut std::fs::File, n: usize) -> io::Result<u64> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if n == 0 || len == 0 {
+        return Ok(len);
+    }
+
+    let mut buffer = vec![0u8; 4096];
+    let mut end = len;
+    let mut newlines = 0;
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        for (i, byte) in chunk.iter().enumerate().rev() {
+            let offset = start + i as u64;
+            // The newline ending the last line doesn't start another one
+            if *byte == b'\n' && offset + 1 != len {
+                newlines += 1;
+                if newlines == n {
+                    return Ok(offset + 1);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Byte offset of the first line written in the last `window`, estimated
+/// from the file's creation and modification times. Without a usable
+/// creation time (NFS and Lustre often have none) nothing is replayed,
+/// rather than a log of any size.
+fn since_offset(file: &mut std::fs::File, window: time::Duration) -> io::Result<u64> {
+    let meta = file.metadata()?;
+    let len = meta.len();
+    let no_estimate = || {
+        eprintln!("Warning: the log's creation time is unknown on this filesystem, so --since can't be estimated; showing new output only (try --lines N)");
+        Ok(len)
+    };
+    let (Ok(created), Ok(modified)) = (meta.created(), meta.modified()) else {
+        return no_estimate();
+    };
+    let cutoff = SystemTime::now() - window.unsigned_abs();
+    if cutoff >= modified {
+        return Ok(len);
+    }
+    let Ok(lifetime) = modified.duration_since(created) else {
+        return no_estimate();
+    };
+    let Ok(before_cutoff) = cutoff.duration_since(created) else {
+        return Ok(0);
+    };
+    let estimate = (len as f64 * before_cutoff.as_secs_f64() / lifetime.as_secs_f64().max(f64::EPSILON)) as u64;
+
+    // Start at the beginning of a line, not partway through one
+    let mut pos = estimate.min(len);
+    if pos == 0 {
+        return Ok(0);
+    }
+    let mut byte = [0u8; 1];
+    file.seek(SeekFrom::Start(pos - 1))?;
+    while pos < len {
+        file.read_exact(&mut byte)?;
+        if byte[0] == b'\n' {
+            break;
+        }
+        pos += 1;
+    }
+    Ok(pos)
+}
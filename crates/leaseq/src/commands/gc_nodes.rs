@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use leaseq_core::{config, fs as lfs, models};
+use std::path::Path;
+use time::OffsetDateTime;
+
+pub async fn run(lease: Option<String>, older_than: time::Duration) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+    if !root.exists() {
+        return Err(anyhow::anyhow!("Lease {} not found at {}", lease_id, root.display()));
+    }
+
+    let max_age_s = older_than.whole_seconds().max(0) as u64;
+    let removed = remove_stale_heartbeats(&root, max_age_s, OffsetDateTime::now_utc())?;
+    for node in &removed {
+        println!("Removed heartbeat of {}", node);
+    }
+    println!("Removed {} stale heartbeat(s) from lease {}", removed.len(), lease_id);
+    Ok(())
+}
+
+/// Delete heartbeat files last written more than `max_age_s` ago, so nodes
+/// that left the lease for good stop showing up in `status` and routing.
+/// Unreadable heartbeats are judged by their mtime. Returns the nodes removed.
+pub fn remove_stale_heartbeats(root: &Path, max_age_s: u64, now: OffsetDateTime) -> Result<Vec<String>> {
+    let mut removed = Vec::new();
+    for path in lfs::list_files_sorted(root.join("hb"))? {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(node) = name.strip_suffix(".json").map(String::from) else {
+            continue;
+        };
+        let last_seen = match lfs::read_heartbeat(&path) {
+            Ok(hb) => hb.ts,
+            Err(_) => match std::fs::metadata(&path).and_then(|m| m.modified()) {
+                Ok(mtime) => OffsetDateTime::from(mtime),
+                Err(_) => continue,
+            },
+        };
+        if (now - last_seen).whole_seconds() < max_age_s as i64 {
+            continue;
+        }
+        lfs::remove_file_if_exists(&path).context(format!("Failed to remove {}", path.display()))?;
+        removed.push(node);
+    }
+    Ok(removed)
+}
+
+/// The runner's sweep: only leases with `node_gc_age_s` set are compacted.
+pub fn sweep(root: &Path, now: OffsetDateTime) -> Result<Vec<String>> {
+    let settings: models::LeaseSettings =
+        lfs::read_json(root.join(models::LEASE_SETTINGS_FILE)).unwrap_or_default();
+    match settings.node_gc_age_s {
+        Some(max_age_s) => remove_stale_heartbeats(root, max_age_s, now),
+        None => Ok(Vec::new()),
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use leaseq_core::{config, fs as lfs, models};
+use std::path::Path;
+use time::OffsetDateTime;
+
+pub async fn run(lease: Option<String>) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+    if !root.exists() {
+        return Err(anyhow::anyhow!("Lease {} not found at {}", lease_id, root.display()));
+    }
+
+    let expired = expire_pending(&root, OffsetDateTime::now_utc())?;
+    for task_id in &expired {
+        println!("Expired {}", task_id);
+    }
+    println!("Expired {} pending task(s) in lease {}", expired.len(), lease_id);
+    Ok(())
+}
+
+/// Move pending tasks of every node that have waited longer than their
+/// `max_pending_s` (or the lease's `max_pending_age_s`) into done/, with an
+/// EXPIRED result. Returns the expired task IDs. Runners call this too, so
+/// tasks for a node that never came up still expire.
+pub fn expire_pending(root: &Path, now: OffsetDateTime) -> Result<Vec<String>> {
+    let settings: models::LeaseSettings =
+        lfs::read_json(root.join(models::LEASE_SETTINGS_FILE)).unwrap_or_default();
+    let mut expired = Vec::new();
+    for (node, inbox_dir) in lfs::node_dirs(root, "inbox")? {
+        let claimed_dir = root.join("claimed").join(&node);
+        let done_dir = root.join("done").join(&node);
+        for task_file in lfs::list_files_sorted(&inbox_dir)? {
+            let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) else {
+                continue;
+            };
+            let Some(max_age) = spec.max_pending_s.or(settings.max_pending_age_s) else {
+                continue;
+            };
+            if (now - spec.created_at).whole_seconds() < max_age as i64 {
+                continue;
+            }
+
+            // Claimed like a runner would, so none can run it meanwhile;
+            // losing that race means a runner got it first. Then the result
+            // is written and the spec archived, in the runner's order, so an
+            // interrupted expiry is finished by the node's zombie recovery.
+            lfs::ensure_dir(&claimed_dir)?;
+            lfs::ensure_dir(&done_dir)?;
+            let claimed = match lfs::claim_file(&task_file, &claimed_dir) {
+                Ok(Some(claimed)) => claimed,
+                Ok(None) => continue,
+                Err(e) => return Err(e).context(format!("Failed to expire {}", task_file.display())),
+            };
+
+            let result = models::TaskResult {
+                task_id: spec.task_id.clone(),
+                idempotency_key: spec.idempotency_key.clone(),
+                node: node.clone(),
+                started_at: now,
+                finished_at: now,
+                exit_code: -1,
+                stdout: String::new(),
+                stderr: String::new(),
+                runtime_s: 0.0,
+                command: spec.command.clone(),
+                resolved_command: String::new(),
+                cwd: spec.cwd.clone(),
+                gpus_requested: spec.gpus,
+                gpus_assigned: String::new(),
+                checksum: None,
+                failure: Some(models::FailureReason::Expired),
+                signal: None,
+            }
+            .with_checksum();
+            let stem = claimed.file_name().unwrap_or_default().to_string_lossy();
+            let result_name = format!("{}.result.json", stem.trim_end_matches(".json"));
+            lfs::atomic_write_json(done_dir.join(result_name), &result)?;
+            super::run::archive_spec(&claimed, &done_dir)?;
+            expired.push(spec.task_id);
+        }
+    }
+    Ok(expired)
+}
@@ -0,0 +1,206 @@
+use anyhow::Result;
+use clap::Subcommand;
+use leaseq_core::{config, fs as lfs, models};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::commands::cancel;
+
+#[derive(Subcommand)]
+pub enum GroupCommands {
+    /// Show the state of every task in a group
+    Status {
+        batch_id: String,
+        #[arg(long)]
+        lease: Option<String>,
+    },
+    /// Cancel all pending and running tasks in a group
+    Cancel {
+        batch_id: String,
+        #[arg(long)]
+        lease: Option<String>,
+    },
+    /// Wait until every task in a group has finished
+    Wait {
+        batch_id: String,
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Give up after this many seconds
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupMember {
+    pub task_id: String,
+    pub node: String,
+    pub state: &'static str, // PENDING, RUNNING, DONE, FAILED, CANCELLED
+}
+
+impl GroupMember {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self.state, "PENDING" | "RUNNING")
+    }
+}
+
+pub async fn run(command: GroupCommands) -> Result<()> {
+    match command {
+        GroupCommands::Status { batch_id, lease } => {
+            let root = config::lease_root(&lease.unwrap_or_else(config::local_lease_id));
+            let members = group_members(&root, &batch_id)?;
+            if members.is_empty() {
+                return Err(anyhow::anyhow!("No tasks in group {}", batch_id));
+            }
+            println!("{:<10} {:<10} NODE", "TASK", "STATE");
+            for m in &members {
+                println!("{:<10} {:<10} {}", m.task_id, m.state, m.node);
+            }
+            println!("{}", summary(&members));
+            Ok(())
+        }
+        GroupCommands::Cancel { batch_id, lease } => {
+            let root = config::lease_root(&lease.unwrap_or_else(config::local_lease_id));
+            let cancelled = cancel_group(&root, &batch_id)?;
+            println!("Cancelled {} task(s) in group {}", cancelled.len(), batch_id);
+            Ok(())
+        }
+        GroupCommands::Wait { batch_id, lease, timeout } => {
+            let root = config::lease_root(&lease.unwrap_or_else(config::local_lease_id));
+            let start = Instant::now();
+            loop {
+                let members = group_members(&root, &batch_id)?;
+                if members.is_empty() {
+                    return Err(anyhow::anyhow!("No tasks in group {}", batch_id));
+                }
+                if members.iter().all(|m| m.is_finished()) {
+                    println!("{}", summary(&members));
+                    let failed = members.iter().filter(|m| m.state == "FAILED").count();
+                    if failed > 0 {
+                        return Err(anyhow::anyhow!("{} task(s) in group {} failed", failed, batch_id));
+                    }
+                    return Ok(());
+                }
+                if let Some(t) = timeout {
+                    if start.elapsed() > Duration::from_secs(t) {
+                        return Err(anyhow::anyhow!(
+                            "Timed out waiting for group {} ({})",
+                            batch_id,
+                            summary(&members)
+                        ));
+                    }
+                }
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+fn summary(members: &[GroupMember]) -> String {
+    let count = |state: &str| members.iter().filter(|m| m.state == state).count();
+    format!(
+        "{} tasks: {} pending, {} running, {} done, {} failed, {} cancelled",
+        members.len(),
+        count("PENDING"),
+        count("RUNNING"),
+        count("DONE"),
+        count("FAILED"),
+        count("CANCELLED")
+    )
+}
+
+/// All tasks stamped with `batch_id`, across every node of the lease. Of a
+/// task and its `leaseq retry` copies only the latest attempt is a member,
+/// so a failure that a retry made good no longer fails the group.
+pub fn group_members(root: &Path, batch_id: &str) -> Result<Vec<GroupMember>> {
+    // (first attempt, submit seq, member)
+    let mut members = Vec::new();
+    let lineage = |spec: &models::TaskSpec| spec.retry_of.clone().unwrap_or_else(|| spec.task_id.clone());
+
+    for (dir, state) in [("inbox", "PENDING"), ("claimed", "RUNNING")] {
+        for (node, node_dir) in lfs::node_dirs(root, dir)? {
+            for f in lfs::list_files_sorted(node_dir)? {
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&f) {
+                    if spec.batch_id.as_deref() == Some(batch_id) {
+                        let member = GroupMember { task_id: spec.task_id.clone(), node: node.clone(), state };
+                        members.push((lineage(&spec), spec.seq, member));
+                    }
+                }
+            }
+        }
+    }
+
+    // Finished tasks: the archived spec carries the group, its sibling file the outcome
+    for (node, node_dir) in lfs::node_dirs(root, "done")? {
+        for f in lfs::list_files_sorted(&node_dir)? {
+            if models::DoneFileKind::of(&f).is_result() {
+                continue;
+            }
+            let name = f.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&f) else {
+                continue;
+            };
+            if spec.batch_id.as_deref() != Some(batch_id) {
+                continue;
+            }
+
+            let stem = name.trim_end_matches(".json");
+            let outcome = |suffix: &str| node_dir.join(format!("{}.{}.json", stem, suffix));
+            let state = if outcome("cancelled").exists() {
+                "CANCELLED"
+            } else if outcome("skipped").exists() {
+                "DONE"
+            } else {
+                match lfs::read_json::<models::TaskResult, _>(outcome("result")) {
+                    Ok(r) if r.exit_code == 0 => "DONE",
+                    _ => "FAILED",
+                }
+            };
+            let member = GroupMember { task_id: spec.task_id.clone(), node: node.clone(), state };
+            members.push((lineage(&spec), spec.seq, member));
+        }
+    }
+
+    let mut latest: HashMap<String, u64> = HashMap::new();
+    for (first, seq, _) in &members {
+        let l = latest.entry(first.clone()).or_insert(*seq);
+        *l = (*l).max(*seq);
+    }
+    Ok(members.into_iter().filter(|(first, seq, _)| latest[first] == *seq).map(|(_, _, m)| m).collect())
+}
+
+/// Cancel every unfinished member; returns the IDs that were cancelled or signalled.
+pub fn cancel_group(root: &Path, batch_id: &str) -> Result<Vec<String>> {
+    let mut cancelled = Vec::new();
+    for m in group_members(root, batch_id)? {
+        match m.state {
+            "PENDING" => {
+                if !cancel_pending_member(root, &m.task_id, &m.node)? {
+                    continue;
+                }
+            }
+            "RUNNING" => cancel::cancel_running_task(root, &m.task_id, &m.node)?,
+            _ => continue,
+        }
+        cancelled.push(m.task_id);
+    }
+    Ok(cancelled)
+}
+
+/// Cancel a member that was pending when the group was listed. A runner may
+/// have claimed it since, so it's looked up again and its run cancelled
+/// instead. False if it finished in the meantime.
+fn cancel_pending_member(root: &Path, task_id: &str, node: &str) -> Result<bool> {
+    if cancel::cancel_pending_task(root, task_id, node).is_ok() {
+        return Ok(true);
+    }
+    let found = cancel::find_task(root, task_id)?;
+    match found.state.as_str() {
+        "PENDING" => cancel::cancel_pending_task(root, &found.task_id, &found.node)?,
+        "RUNNING" => cancel::cancel_running_task(root, &found.task_id, &found.node)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use crate::commands::submit::{self, NodeOrder};
+use leaseq_core::{config, fs as lfs, models};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// A spec file that can't be imported, with the position serde reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSpec {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for InvalidSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.path.display(), self.line, self.column, self.message)
+    }
+}
+
+/// What `import_dir` did: either every spec was queued, or none were and
+/// `invalid` says why.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<models::TaskSpec>,
+    pub invalid: Vec<InvalidSpec>,
+}
+
+pub async fn run(dir: PathBuf, lease: Option<String>, node: Option<String>, move_files: bool) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+
+    let report = import_dir(&dir, &root, &lease_id, node, move_files)?;
+    if !report.invalid.is_empty() {
+        for problem in &report.invalid {
+            eprintln!("{}", problem);
+        }
+        return Err(anyhow::anyhow!(
+            "{} invalid spec file(s) in {}; nothing imported",
+            report.invalid.len(),
+            dir.display()
+        ));
+    }
+
+    for spec in &report.imported {
+        println!("Imported {} -> {} (node {})", spec.task_id, lease_id, spec.target_node);
+    }
+    println!("Imported {} task(s) from {}", report.imported.len(), dir.display());
+    Ok(())
+}
+
+/// Queue every `*.json` TaskSpec in `dir` on the lease at `root`, in filename
+/// order. Nothing is queued unless all files are valid. Specs keep their ID,
+/// UUID and idempotency key but get the lease, a node (`node`, else their own
+/// `target_node`, else routed like `submit`) and a fresh sequence number.
+/// `move_files` removes the originals.
+pub fn import_dir(
+    dir: &Path,
+    root: &Path,
+    lease_id: &str,
+    node: Option<String>,
+    move_files: bool,
+) -> Result<ImportReport> {
+    if !dir.is_dir() {
+        return Err(anyhow::anyhow!("{} is not a directory", dir.display()));
+    }
+    if let Some(n) = &node {
+        models::validate_node_name(n).map_err(anyhow::Error::msg)?;
+    }
+
+    let mut parsed = Vec::new();
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    for path in lfs::list_files_sorted(dir)? {
+        if path.extension().is_none_or(|e| e != "json") {
+            continue;
+        }
+        let content = std::fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+        let problem = |line, column, message: String| InvalidSpec { path: path.clone(), line, column, message };
+        match serde_json::from_str::<models::TaskSpec>(&content) {
+            Err(e) => report.invalid.push(problem(e.line(), e.column(), e.to_string())),
+            Ok(spec) => match spec_problem(&spec) {
+                Some(message) => report.invalid.push(problem(1, 1, message)),
+                None if !seen.insert(spec.task_id.clone()) => {
+                    report.invalid.push(problem(1, 1, format!("duplicate task_id {}", spec.task_id)))
+                }
+                None => parsed.push((path, spec)),
+            },
+        }
+    }
+    if !report.invalid.is_empty() {
+        return Ok(report);
+    }
+
+    // Sequence numbers are submit times, so imported tasks queue behind
+    // what's already there, in the order of their files
+    let base = (time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1000) as u64;
+    let mut picker = submit::NodePicker::default();
+    for (i, (path, mut spec)) in parsed.into_iter().enumerate() {
+        let target_node = match &node {
+            Some(n) => n.clone(),
+            None if !spec.target_node.is_empty() => spec.target_node.clone(),
+            None => picker.pick(root, lease_id, None, NodeOrder::default())?,
+        };
+        spec.lease_id = models::LeaseId(lease_id.to_string());
+        spec.target_node = target_node;
+        spec.seq = base + i as u64;
+
+        let filename = format!("{:016}_{}_{}.json", spec.seq, spec.task_id, spec.uuid);
+        lfs::atomic_write_json(root.join("inbox").join(&spec.target_node).join(filename), &spec)
+            .context(format!("Failed to queue {}", path.display()))?;
+        if move_files {
+            std::fs::remove_file(&path)?;
+        }
+        report.imported.push(spec);
+    }
+    Ok(report)
+}
+
+/// Why `spec` can't be queued as is, if it can't. The task ID and node name
+/// paths under the lease root, so they must be plain names.
+fn spec_problem(spec: &models::TaskSpec) -> Option<String> {
+    if spec.task_id.is_empty() {
+        return Some("task_id is empty".to_string());
+    }
+    if let Err(e) = models::validate_task_id(&spec.task_id) {
+        return Some(e);
+    }
+    if !spec.target_node.is_empty() {
+        if let Err(e) = models::validate_node_name(&spec.target_node) {
+            return Some(e);
+        }
+    }
+    if spec.command.trim().is_empty() {
+        return Some("command is empty".to_string());
+    }
+    None
+}
@@ -3,8 +3,8 @@ use anyhow::{Result, Context};
 use std::process::Command;
 use std::io::Write;
 use tempfile::NamedTempFile;
-use leaseq_core::config;
-use std::collections::{HashSet, HashMap};
+use leaseq_core::{config, fs as lfs, models};
+use std::collections::{BTreeMap, HashSet, HashMap};
 
 #[derive(Subcommand)]
 pub enum LeaseCommands {
@@ -14,8 +14,61 @@ pub enum LeaseCommands {
     Release {
         lease_id: String,
     },
+    /// Let a lease created with `--hold` start (`scontrol release`; does not cancel)
+    Unhold {
+        lease_id: String,
+    },
     /// List leases (from index)
     Ls,
+    /// Change runner settings for a lease (applies from the next task)
+    Set {
+        lease_id: String,
+
+        /// Prefix for every task command, e.g. "nice -n 10" or "taskset -c 0-7"
+        #[arg(long, value_name = "PREFIX", conflicts_with = "clear_command_prefix")]
+        command_prefix: Option<String>,
+
+        /// Remove the command prefix
+        #[arg(long)]
+        clear_command_prefix: bool,
+
+        /// Priority a pending task gains per minute of waiting (default 1; 0 disables aging)
+        #[arg(long, value_name = "RATE")]
+        priority_aging: Option<f64>,
+
+        /// Times a GPU task short of free devices may be passed over by tasks that fit (default 4; 0 disables backfill)
+        #[arg(long, value_name = "N")]
+        backfill_limit: Option<u32>,
+
+        /// Expire tasks still pending after this long, e.g. 6h (tasks' --max-pending-age wins)
+        #[arg(long, value_name = "DURATION", value_parser = leaseq_core::duration::parse_duration)]
+        max_pending_age: Option<time::Duration>,
+
+        /// Have runners drop heartbeats of nodes silent for this long, e.g. 1h
+        #[arg(long, value_name = "DURATION", value_parser = leaseq_core::duration::parse_duration)]
+        node_gc_age: Option<time::Duration>,
+
+        /// CPUs a `submit --gpu-job` task gets per GPU
+        #[arg(long, value_name = "N")]
+        cpus_per_gpu: Option<u32>,
+
+        /// Memory a `submit --gpu-job` task gets per GPU, e.g. 32G
+        #[arg(long, value_name = "SIZE", value_parser = leaseq_core::size::parse_mem_mb)]
+        mem_per_gpu: Option<u64>,
+    },
+    /// Requeue tasks stranded on vanished nodes (e.g. after preemption)
+    Recover {
+        /// Lease whose nodes are gone
+        lease_id: String,
+
+        /// Fresh lease to requeue `--requeue-on-preempt` tasks into
+        #[arg(long)]
+        into_lease: String,
+
+        /// Node in the fresh lease (defaults to a live node)
+        #[arg(long)]
+        node: Option<String>,
+    },
 }
 
 #[derive(Args, Debug, Clone)]
@@ -51,34 +104,165 @@ pub struct CreateLeaseArgs {
     /// Timeout in seconds to wait for job to start. If exceeded, job is cancelled. 0 = no wait.
     #[arg(long, default_value = "30")]
     pub wait: u64,
+
+    /// Check that the leaseq binary runs on the allocated nodes before starting runners
+    #[arg(long)]
+    pub self_test: bool,
+
+    /// Restart a crashed runner up to N times (default 3) instead of leaving the
+    /// allocation idle
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "3")]
+    pub restart_runner_on_fail: Option<u32>,
+
+    /// Start only after other Slurm jobs, e.g. afterok:12345 (passed to sbatch --dependency)
+    #[arg(long, value_name = "SPEC", value_parser = parse_dependency)]
+    pub dependency: Option<String>,
+
+    /// Queue the allocation held, keeping its place without starting; start it with `lease unhold`
+    #[arg(long)]
+    pub hold: bool,
+
+    /// Warn (via sinfo) if the partition can't provide --nodes nodes with --gpus-per-node GPUs
+    #[arg(long)]
+    pub check: bool,
+
+    /// Note what the allocation is for; shown by `lease ls` and `status`
+    #[arg(long, value_name = "TEXT")]
+    pub comment: Option<String>,
+
+    /// Attach a KEY=VALUE pair to the lease (repeatable)
+    #[arg(long = "meta", value_name = "KEY=VALUE", value_parser = parse_meta)]
+    pub metadata: Vec<(String, String)>,
+}
+
+fn parse_meta(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("invalid metadata '{}': expected KEY=VALUE", s)),
+    }
+}
+
+const DEPENDENCY_TYPES: &[&str] = &["after", "afterany", "afterburstbuffer", "aftercorr", "afternotok", "afterok"];
+
+/// Validate a Slurm dependency list such as `afterok:123:456,afterany:789`
+/// (`?` separates alternatives, `singleton` takes no job IDs).
+pub fn parse_dependency(spec: &str) -> Result<String, String> {
+    if spec.trim().is_empty() {
+        return Err("empty dependency".to_string());
+    }
+    for part in spec.split([',', '?']) {
+        if part == "singleton" {
+            continue;
+        }
+        let mut fields = part.split(':');
+        let kind = fields.next().unwrap_or_default();
+        if !DEPENDENCY_TYPES.contains(&kind) {
+            return Err(format!(
+                "invalid dependency '{}': unknown type '{}' (use {} or singleton)",
+                part,
+                kind,
+                DEPENDENCY_TYPES.join(", ")
+            ));
+        }
+        let ids: Vec<&str> = fields.collect();
+        if ids.is_empty() {
+            return Err(format!("invalid dependency '{}': expected {}:<jobid>", part, kind));
+        }
+        for id in ids {
+            // <jobid>[_<array task>][+<minutes>], the delay only for `after`
+            let (id, delay) = id.split_once('+').unwrap_or((id, ""));
+            let (job, array_task) = id.split_once('_').unwrap_or((id, ""));
+            let numeric = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+            let ok = numeric(job)
+                && (array_task.is_empty() || numeric(array_task))
+                && (delay.is_empty() || (kind == "after" && numeric(delay)));
+            if !ok {
+                return Err(format!("invalid dependency '{}': bad job ID '{}'", part, id));
+            }
+        }
+    }
+    Ok(spec.to_string())
 }
 
 pub async fn run(command: LeaseCommands) -> Result<()> {
     match command {
         LeaseCommands::Create(args) => create_lease(args).await,
         LeaseCommands::Release { lease_id } => release_lease(lease_id).await,
+        LeaseCommands::Unhold { lease_id } => unhold_lease(lease_id).await,
         LeaseCommands::Ls => list_leases().await,
+        LeaseCommands::Recover { lease_id, into_lease, node } => {
+            recover_lease(lease_id, into_lease, node).await
+        }
+        LeaseCommands::Set {
+            lease_id,
+            command_prefix,
+            clear_command_prefix,
+            priority_aging,
+            backfill_limit,
+            max_pending_age,
+            node_gc_age,
+            cpus_per_gpu,
+            mem_per_gpu,
+        } => {
+            let root = config::lease_root(&lease_id);
+            let settings = update_settings(&root, |s| {
+                if clear_command_prefix {
+                    s.command_prefix = None;
+                } else if let Some(prefix) = command_prefix {
+                    s.command_prefix = Some(prefix);
+                }
+                if let Some(rate) = priority_aging {
+                    s.priority_aging = Some(rate);
+                }
+                if let Some(limit) = backfill_limit {
+                    s.backfill_limit = Some(limit);
+                }
+                if let Some(age) = max_pending_age {
+                    s.max_pending_age_s = Some(age.whole_seconds().max(0) as u64);
+                }
+                if let Some(age) = node_gc_age {
+                    s.node_gc_age_s = Some(age.whole_seconds().max(0) as u64);
+                }
+                if let Some(n) = cpus_per_gpu {
+                    s.cpus_per_gpu = Some(n);
+                }
+                if let Some(mb) = mem_per_gpu {
+                    s.mem_per_gpu_mb = Some(mb);
+                }
+            })?;
+            match &settings.command_prefix {
+                Some(p) => println!("Lease {}: command prefix '{}'", lease_id, p),
+                None => println!("Lease {}: no command prefix", lease_id),
+            }
+            println!("Lease {}: priority aging {}/min", lease_id, settings.priority_aging());
+            println!("Lease {}: backfill limit {}", lease_id, settings.backfill_limit());
+            match settings.max_pending_age_s {
+                Some(s) => println!("Lease {}: pending tasks expire after {}s", lease_id, s),
+                None => println!("Lease {}: pending tasks never expire", lease_id),
+            }
+            if let Some(s) = settings.node_gc_age_s {
+                println!("Lease {}: heartbeats removed after {}s", lease_id, s);
+            }
+            if settings.cpus_per_gpu.is_some() || settings.mem_per_gpu_mb.is_some() {
+                println!(
+                    "Lease {}: --gpu-job gets {} CPU(s) and {}MB per GPU",
+                    lease_id,
+                    settings.cpus_per_gpu.unwrap_or(0),
+                    settings.mem_per_gpu_mb.unwrap_or(0)
+                );
+            }
+            Ok(())
+        }
     }
 }
 
-/// Result of lease creation for TUI display
-#[allow(dead_code)]
-pub struct LeaseCreateResult {
-    pub job_id: String,
-    pub message: String,
-}
-
-/// Create a lease, returning result for TUI (no printing)
-pub async fn create_lease_quiet(args: CreateLeaseArgs) -> Result<LeaseCreateResult> {
-    // 1. Check if sbatch is available
-    if Command::new("sbatch").arg("--version").output().is_err() {
-        return Err(anyhow::anyhow!("'sbatch' not found. Cannot create Slurm lease on this machine."));
-    }
-
-    // 2. Generate Keeper Script
-    let leaseq_bin = std::env::current_exe()?;
-    let runner_cmd = format!("{} run", leaseq_bin.to_string_lossy());
-
+/// Build the sbatch script that keeps runners alive for the lease.
+///
+/// If the runner fails to launch (e.g. the binary is missing on compute nodes)
+/// the script reports why and exits with the runner's status instead of idling.
+/// With `restart_runner_on_fail` a runner that exits non-zero is started
+/// again, a bounded number of times, except when it couldn't be run at all.
+pub fn keeper_script(args: &CreateLeaseArgs, leaseq_bin: &str) -> String {
     let mut script = String::new();
     script.push_str("#!/bin/bash\n");
     script.push_str(&format!("#SBATCH --nodes={}\n", args.nodes));
@@ -97,6 +281,12 @@ pub async fn create_lease_quiet(args: CreateLeaseArgs) -> Result<LeaseCreateResu
     if args.gpus_per_node > 0 {
         script.push_str(&format!("#SBATCH --gpus-per-node={}\n", args.gpus_per_node));
     }
+    if let Some(d) = &args.dependency {
+        script.push_str(&format!("#SBATCH --dependency={}\n", d));
+    }
+    if args.hold {
+        script.push_str("#SBATCH --hold\n");
+    }
     script.push_str("#SBATCH --job-name=leaseq\n");
     script.push_str("#SBATCH --output=leaseq-%j.log\n");
 
@@ -105,9 +295,70 @@ pub async fn create_lease_quiet(args: CreateLeaseArgs) -> Result<LeaseCreateResu
     }
 
     script.push('\n');
+    script.push_str(&format!("LEASEQ_BIN='{}'\n", leaseq_bin.replace('\'', r"'\''")));
     script.push_str("echo \"Starting leaseq runner on $SLURM_JOB_ID\"\n");
-    script.push_str(&format!("srun {} --lease $SLURM_JOB_ID --node $(hostname)\n", runner_cmd));
+
+    if args.self_test {
+        script.push_str("if ! srun --ntasks-per-node=1 \"$LEASEQ_BIN\" --version; then\n");
+        script.push_str("    echo \"leaseq: self-test failed: $LEASEQ_BIN does not run on the allocated nodes\" >&2\n");
+        script.push_str("    exit 1\n");
+        script.push_str("fi\n");
+    }
+
+    let runner = "srun \"$LEASEQ_BIN\" run --lease $SLURM_JOB_ID --node $(hostname)";
+    match args.restart_runner_on_fail {
+        Some(max) if max > 0 => {
+            script.push_str("restarts=0\n");
+            script.push_str("while true; do\n");
+            script.push_str(&format!("    {}\n", runner));
+            script.push_str("    rc=$?\n");
+            script.push_str(&format!(
+                "    if [ $rc -eq 0 ] || [ $rc -eq 127 ] || [ $restarts -ge {} ]; then\n",
+                max
+            ));
+            script.push_str("        break\n");
+            script.push_str("    fi\n");
+            script.push_str("    restarts=$((restarts + 1))\n");
+            script.push_str(&format!(
+                "    echo \"leaseq: runner exited with status $rc; restarting ($restarts/{})\" >&2\n",
+                max
+            ));
+            script.push_str("    sleep 5\n");
+            script.push_str("done\n");
+        }
+        _ => {
+            script.push_str(&format!("{}\n", runner));
+            script.push_str("rc=$?\n");
+        }
+    }
+    script.push_str("if [ $rc -ne 0 ]; then\n");
+    script.push_str("    echo \"leaseq: runner exited with status $rc on $(hostname) (job $SLURM_JOB_ID)\" >&2\n");
+    script.push_str("    if [ $rc -eq 127 ]; then\n");
+    script.push_str("        echo \"leaseq: is $LEASEQ_BIN installed on the compute nodes?\" >&2\n");
+    script.push_str("    fi\n");
+    script.push_str("    exit $rc\n");
+    script.push_str("fi\n");
     script.push_str("sleep 30\n");
+    script
+}
+
+/// Result of lease creation for TUI display
+#[allow(dead_code)]
+pub struct LeaseCreateResult {
+    pub job_id: String,
+    pub message: String,
+}
+
+/// Create a lease, returning result for TUI (no printing)
+pub async fn create_lease_quiet(args: CreateLeaseArgs) -> Result<LeaseCreateResult> {
+    // 1. Check if sbatch is available
+    if Command::new("sbatch").arg("--version").output().is_err() {
+        return Err(anyhow::anyhow!("'sbatch' not found. Cannot create Slurm lease on this machine."));
+    }
+
+    // 2. Generate Keeper Script
+    let leaseq_bin = std::env::current_exe()?;
+    let script = keeper_script(&args, &leaseq_bin.to_string_lossy());
 
     // 3. Write to temp file
     let mut temp = NamedTempFile::new()?;
@@ -126,12 +377,14 @@ pub async fn create_lease_quiet(args: CreateLeaseArgs) -> Result<LeaseCreateResu
     }
 
     let job_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // The job is live either way; losing its metadata mustn't lose its ID
+    let mut message = format!("Submitted Slurm job: {}", job_id);
+    if let Err(e) = write_lease_meta(&job_id, &args) {
+        message.push_str(&format!(" (warning: {:#})", e));
+    }
 
     // Don't wait in TUI mode - just return immediately
-    Ok(LeaseCreateResult {
-        job_id: job_id.clone(),
-        message: format!("Submitted Slurm job: {}", job_id),
-    })
+    Ok(LeaseCreateResult { job_id, message })
 }
 
 /// Create a lease with CLI output (for non-TUI usage)
@@ -141,39 +394,21 @@ pub async fn create_lease(args: CreateLeaseArgs) -> Result<()> {
         return Err(anyhow::anyhow!("'sbatch' not found. Cannot create Slurm lease on this machine."));
     }
 
-    // 2. Generate Keeper Script
-    let leaseq_bin = std::env::current_exe()?;
-    let runner_cmd = format!("{} run", leaseq_bin.to_string_lossy());
-
-    let mut script = String::new();
-    script.push_str("#!/bin/bash\n");
-    script.push_str(&format!("#SBATCH --nodes={}\n", args.nodes));
-    if let Some(t) = &args.time {
-        script.push_str(&format!("#SBATCH --time={}\n", t));
-    }
-    if let Some(p) = &args.partition {
-        script.push_str(&format!("#SBATCH --partition={}\n", p));
-    }
-    if let Some(q) = &args.qos {
-        script.push_str(&format!("#SBATCH --qos={}\n", q));
-    }
-    if let Some(a) = &args.account {
-        script.push_str(&format!("#SBATCH --account={}\n", a));
-    }
-    if args.gpus_per_node > 0 {
-        script.push_str(&format!("#SBATCH --gpus-per-node={}\n", args.gpus_per_node));
-    }
-    script.push_str("#SBATCH --job-name=leaseq\n");
-    script.push_str("#SBATCH --output=leaseq-%j.log\n");
-
-    for arg in &args.sbatch_arg {
-        script.push_str(&format!("#SBATCH {}\n", arg));
+    // A request the partition can never satisfy pends forever; say so up front
+    if args.check {
+        match preflight(&args) {
+            Ok(warnings) => {
+                for w in warnings {
+                    eprintln!("warning: {}", w);
+                }
+            }
+            Err(e) => eprintln!("warning: skipping --check: {}", e),
+        }
     }
 
-    script.push('\n');
-    script.push_str("echo \"Starting leaseq runner on $SLURM_JOB_ID\"\n");
-    script.push_str(&format!("srun {} --lease $SLURM_JOB_ID --node $(hostname)\n", runner_cmd));
-    script.push_str("sleep 30\n");
+    // 2. Generate Keeper Script
+    let leaseq_bin = std::env::current_exe()?;
+    let script = keeper_script(&args, &leaseq_bin.to_string_lossy());
 
     // 3. Write to temp file
     let mut temp = NamedTempFile::new()?;
@@ -193,9 +428,18 @@ pub async fn create_lease(args: CreateLeaseArgs) -> Result<()> {
 
     let job_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
     println!("Submitted Slurm job: {}", job_id);
+    // The job is live either way, so this is only worth a warning
+    if let Err(e) = write_lease_meta(&job_id, &args) {
+        eprintln!("warning: {:#}", e);
+    }
 
-    // Wait for job to start if requested
-    if args.wait > 0 {
+    // Wait for job to start if requested; a dependent or held job can't start
+    // on its own, so waiting would just cancel it
+    if args.hold {
+        println!("Lease {} is held; start it with `leaseq lease unhold {}`", job_id, job_id);
+    } else if let Some(d) = &args.dependency {
+        println!("Lease {} is pending on dependency {}", job_id, d);
+    } else if args.wait > 0 {
         println!("Waiting up to {}s for job to start...", args.wait);
         match wait_for_job_start(&job_id, args.wait).await {
             Ok(()) => {
@@ -215,6 +459,84 @@ pub async fn create_lease(args: CreateLeaseArgs) -> Result<()> {
     Ok(())
 }
 
+/// Ask `sinfo` whether the partition has enough usable nodes with enough
+/// GPUs for the request. Returns one warning per shortfall; empty means the
+/// request fits (though nodes may still be busy).
+pub fn preflight(args: &CreateLeaseArgs) -> Result<Vec<String>> {
+    let mut cmd = Command::new("sinfo");
+    cmd.args(["-N", "-h", "-o", "%N %G %T"]);
+    if let Some(p) = &args.partition {
+        cmd.arg("-p").arg(p);
+    }
+    let output = cmd.output().context("'sinfo' not found")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("sinfo failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(preflight_warnings(args, &String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Compare the request with `sinfo -N -h -o "%N %G %T"` output.
+pub fn preflight_warnings(args: &CreateLeaseArgs, sinfo: &str) -> Vec<String> {
+    // Node-oriented output lists a node once per partition it is in
+    let mut nodes: HashMap<&str, u32> = HashMap::new();
+    for line in sinfo.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(name), Some(gres), Some(state)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let state = state.trim_end_matches(['*', '~', '#', '!', '%', '$', '@', '^', '-', '+']);
+        if ["down", "drain", "fail", "inval", "maint", "future"].iter().any(|s| state.starts_with(s)) {
+            continue;
+        }
+        nodes.insert(name, gres_gpus(gres));
+    }
+
+    let partition = args.partition.as_deref().map_or("the default partition".to_string(), |p| format!("partition {}", p));
+    let mut warnings = Vec::new();
+    if (nodes.len() as u32) < args.nodes {
+        warnings.push(format!(
+            "{} has {} usable node(s) but --nodes {} was requested; the job would pend forever",
+            partition,
+            nodes.len(),
+            args.nodes
+        ));
+    } else if args.gpus_per_node > 0 {
+        let fitting = nodes.values().filter(|g| **g >= args.gpus_per_node).count() as u32;
+        if fitting < args.nodes {
+            warnings.push(format!(
+                "{} has {} usable node(s) with {} or more GPUs but --nodes {} was requested; the job would pend forever",
+                partition, fitting, args.gpus_per_node, args.nodes
+            ));
+        }
+    }
+    warnings
+}
+
+/// GPUs in a GRES string such as `gpu:a100:4(S:0-1),shard:8` or `(null)`.
+fn gres_gpus(gres: &str) -> u32 {
+    gres.split(',')
+        .filter(|g| g.starts_with("gpu"))
+        .filter_map(|g| g.split('(').next()?.rsplit(':').next()?.parse::<u32>().ok())
+        .sum()
+}
+
+/// Record how the lease was requested in `<lease root>/lease.json`.
+fn write_lease_meta(job_id: &str, args: &CreateLeaseArgs) -> Result<()> {
+    let meta = models::LeaseMeta::Slurm {
+        lease_id: models::LeaseId(job_id.to_string()),
+        name: None,
+        created_at: time::OffsetDateTime::now_utc(),
+        slurm: models::SlurmLeaseConfig { sbatch_args: args.sbatch_arg.clone() },
+        mode: models::ExecutionMode::default(),
+        held: args.hold,
+        comment: args.comment.clone(),
+        metadata: args.metadata.iter().cloned().collect(),
+    };
+    let path = config::lease_root(job_id).join(models::LEASE_META_FILE);
+    lfs::atomic_write_json(&path, &meta).context(format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
 async fn wait_for_job_start(job_id: &str, timeout_secs: u64) -> Result<()> {
     use std::time::{Duration, Instant};
 
@@ -274,7 +596,119 @@ async fn release_lease(lease_id: String) -> Result<()> {
     Ok(())
 }
 
+async fn unhold_lease(lease_id: String) -> Result<()> {
+    if lease_id.starts_with("local:") {
+        return Err(anyhow::anyhow!("Local leases are never held."));
+    }
+
+    let output = Command::new("scontrol")
+        .args(["release", &lease_id])
+        .output()
+        .context("Failed to run scontrol")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("scontrol release {} failed: {}", lease_id, stderr.trim()));
+    }
+
+    let path = config::lease_root(&lease_id).join(models::LEASE_META_FILE);
+    if let Ok(mut meta) = lfs::read_json::<models::LeaseMeta, _>(&path) {
+        if let models::LeaseMeta::Slurm { held, .. } = &mut meta {
+            *held = false;
+        }
+        lfs::atomic_write_json(&path, &meta)?;
+    }
+    println!("Lease {} is no longer held", lease_id);
+    Ok(())
+}
+
+/// Read-modify-write `<root>/settings.json`, returning the new settings.
+pub fn update_settings(
+    root: &std::path::Path,
+    change: impl FnOnce(&mut models::LeaseSettings),
+) -> Result<models::LeaseSettings> {
+    let path = root.join(models::LEASE_SETTINGS_FILE);
+    let mut settings: models::LeaseSettings = if path.exists() {
+        lfs::read_json(&path).context(format!("Failed to read {}", path.display()))?
+    } else {
+        models::LeaseSettings::default()
+    };
+    change(&mut settings);
+    lfs::atomic_write_json(&path, &settings)?;
+    Ok(settings)
+}
+
+async fn recover_lease(lease_id: String, into_lease: String, node: Option<String>) -> Result<()> {
+    let old_root = config::lease_root(&lease_id);
+    let new_root = config::lease_root(&into_lease);
+
+    let target_node = super::submit::resolve_target_node(&new_root, &into_lease, node, super::submit::NodeOrder::default())?;
+    let requeued = super::run::requeue_preempted(&old_root, &new_root, &into_lease, &target_node)?;
+
+    if requeued.is_empty() {
+        println!("No preempted tasks to requeue from {}", lease_id);
+    } else {
+        for task_id in &requeued {
+            println!("Requeued {} -> {} ({})", task_id, into_lease, target_node);
+        }
+    }
+    Ok(())
+}
+
+/// One line of `lease ls`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeaseRow {
+    pub lease_id: String,
+    pub status: String,
+    pub lease_type: Option<String>, // None when the lease has no lease.json
+    pub created_at: Option<time::OffsetDateTime>,
+    pub nodes: usize, // runners that have written a heartbeat
+    pub sbatch_args: Vec<String>,
+    pub comment: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
 async fn list_leases() -> Result<()> {
+    let rows = collect_leases()?;
+    if rows.is_empty() {
+        println!("No leases found.");
+        return Ok(());
+    }
+
+    let created_format = time::macros::format_description!("[year]-[month]-[day] [hour]:[minute]");
+    println!(
+        "{:<20}  {:<6}  {:<24}  {:<16}  {:>5}  {:<24}  {}",
+        "LEASE ID", "TYPE", "STATUS", "CREATED (UTC)", "NODES", "SBATCH ARGS", "COMMENT"
+    );
+    println!(
+        "{:<20}  {:<6}  {:<24}  {:<16}  {:>5}  {:<24}  {}",
+        "--------", "----", "------", "-------------", "-----", "-----------", "-------"
+    );
+    for row in rows {
+        let created = row
+            .created_at
+            .and_then(|t| t.format(&created_format).ok())
+            .unwrap_or_else(|| "-".to_string());
+        let sbatch = if row.sbatch_args.is_empty() { "-".to_string() } else { row.sbatch_args.join(" ") };
+        let mut note: Vec<String> = row.comment.into_iter().collect();
+        note.extend(row.metadata.iter().map(|(k, v)| format!("{}={}", k, v)));
+        println!(
+            "{:<20}  {:<6}  {:<24}  {:<16}  {:>5}  {:<24}  {}",
+            row.lease_id,
+            row.lease_type.as_deref().unwrap_or("-"),
+            row.status,
+            created,
+            row.nodes,
+            sbatch,
+            note.join(" ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Leases known from the run dirs and squeue, sorted by ID, with what their
+/// `lease.json` recorded at creation (left empty for leases without one).
+pub fn collect_leases() -> Result<Vec<LeaseRow>> {
     let mut leases = HashMap::new();
 
     // 1. Scan Local Directory (~/.leaseq/runs/)
@@ -311,26 +745,29 @@ async fn list_leases() -> Result<()> {
     }
 
     // 3. Add Local Lease
-    // (Always present technically, but we check if it's explicitly initialized?)
-    // Actually, local lease is always "ACTIVE" conceptually.
-    leases.insert("local:hostname".to_string(), "ACTIVE (Local)".to_string());
-
-    if leases.is_empty() {
-        println!("No leases found.");
-        return Ok(());
-    }
-
-    println!("{:<20}  {}", "LEASE ID", "STATUS");
-    println!("{:<20}  {}", "--------", "------");
-    
-    // Sort keys
-    let mut keys: Vec<_> = leases.keys().collect();
-    keys.sort();
-
-    for id in keys {
-        let status = leases.get(id).unwrap();
-        println!("{:<20}  {}", id, status);
-    }
-
-    Ok(())
+    // Always "ACTIVE" conceptually; the daemon records its lease.json
+    leases.insert(config::local_lease_id(), "ACTIVE (Local)".to_string());
+
+    let mut rows: Vec<LeaseRow> = leases
+        .into_iter()
+        .map(|(lease_id, status)| {
+            let root = config::lease_root(&lease_id);
+            let meta = lfs::read_json::<models::LeaseMeta, _>(root.join(models::LEASE_META_FILE)).ok();
+            LeaseRow {
+                lease_type: meta.as_ref().map(|m| m.lease_type().to_string()),
+                created_at: meta.as_ref().map(|m| m.created_at()),
+                nodes: lfs::list_files_sorted(root.join("hb")).map(|f| f.len()).unwrap_or(0),
+                sbatch_args: match &meta {
+                    Some(models::LeaseMeta::Slurm { slurm, .. }) => slurm.sbatch_args.clone(),
+                    _ => Vec::new(),
+                },
+                comment: meta.as_ref().and_then(|m| m.comment()).map(String::from),
+                metadata: meta.as_ref().map(|m| m.metadata().clone()).unwrap_or_default(),
+                lease_id,
+                status,
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.lease_id.cmp(&b.lease_id));
+    Ok(rows)
 }
@@ -1,27 +1,44 @@
+use crate::commands::cancel;
 use anyhow::{Result, Context};
-use leaseq_core::config;
+use leaseq_core::{config, fs as lfs, models};
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-pub async fn run(task: String, lease: Option<String>, stderr: bool, tail: Option<usize>) -> Result<()> {
+/// `strip_ansi` forces escape stripping on or off; unset strips only when
+/// stdout is not a terminal, so piped logs come out as plain text. With
+/// `open_browser` the log is rendered to an HTML file instead, whose path is
+/// printed and handed to the desktop's browser.
+pub async fn run(
+    task: String,
+    lease: Option<String>,
+    stderr: bool,
+    tail: Option<usize>,
+    raw: bool,
+    strip_ansi: Option<bool>,
+    open_browser: bool,
+) -> Result<()> {
     let lease_id = lease.unwrap_or_else(config::local_lease_id);
 
-    let root = if lease_id.starts_with("local:") {
-        config::runtime_dir().join(&lease_id)
-    } else {
-        config::leaseq_home_dir().join("runs").join(&lease_id)
-    };
+    let root = config::lease_root(&lease_id);
 
-    let log_path = if stderr {
-        root.join("logs").join(format!("{}.err", task))
-    } else {
-        root.join("logs").join(format!("{}.out", task))
-    };
+    models::validate_task_id(&task).map_err(anyhow::Error::msg)?;
+    let log_path = log_path(&root, &task, stderr);
+    let strip = strip_ansi.unwrap_or_else(|| !std::io::stdout().is_terminal());
 
     if !log_path.exists() {
         // Try to find task by partial ID
-        let found = find_task_log(&root, &task, stderr)?;
+        let found = find_task_log(&root, &task, stderr)?
+            .or_else(|| {
+                // ... or by UUID
+                let found = cancel::find_task(&root, &task).ok()?;
+                Some(self::log_path(&root, &found.task_id, stderr)).filter(|p| p.exists())
+            });
         if let Some(path) = found {
-            print_log(&path, tail)?;
+            if open_browser {
+                share_html(&path, tail)?;
+            } else {
+                show_log(&path, tail, raw, strip)?;
+            }
         } else {
             eprintln!("Log file not found: {}", log_path.display());
             eprintln!("Task {} may not exist or hasn't produced output yet.", task);
@@ -29,7 +46,279 @@ pub async fn run(task: String, lease: Option<String>, stderr: bool, tail: Option
         return Ok(());
     }
 
-    print_log(&log_path, tail)
+    if open_browser {
+        return share_html(&log_path, tail);
+    }
+    show_log(&log_path, tail, raw, strip)
+}
+
+/// Render the log to `<runtime dir>/html/<log file name>.html`, print the path
+/// and try to open it. Headless nodes have no browser; the path is enough there.
+/// The runtime dir is per user, unlike a shared `/tmp`.
+fn share_html(path: &Path, tail: Option<usize>) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = config::runtime_dir().join("html");
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .context(format!("Failed to create {}", dir.display()))?;
+    let out = dir.join(format!("{}.html", name));
+    write_html(path, tail, &out)?;
+    println!("{}", out.display());
+    let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+    let _ = std::process::Command::new(opener)
+        .arg(&out)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    Ok(())
+}
+
+/// Write the log (or its last `tail` lines) to `out` as a standalone HTML
+/// page, with ANSI colors and bold/italic/underline turned into styles. The
+/// page is readable by its owner only, and a symlink at `out` is refused
+/// rather than followed.
+pub fn write_html(path: &Path, tail: Option<usize>, out: &Path) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut text = Vec::new();
+    write_log(path, tail, false, &mut text)?;
+    let title = path.file_name().unwrap_or_default().to_string_lossy();
+    let html = render_html(&title, &String::from_utf8_lossy(&text));
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(out)
+        .context(format!("Failed to create {}", out.display()))?;
+    file.write_all(html.as_bytes()).context(format!("Failed to write {}", out.display()))
+}
+
+pub fn render_html(title: &str, log: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n\
+         body {{ background: #1e1e1e; color: #d4d4d4; margin: 0; }}\n\
+         pre {{ font-family: monospace; white-space: pre-wrap; margin: 1em; }}\n\
+         </style>\n</head>\n<body>\n<pre>{}</pre>\n</body>\n</html>\n",
+        escape_html(title),
+        ansi_to_html(log)
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// The SGR attributes in effect at a point of the log.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Sgr {
+    fg: Option<String>,
+    bg: Option<String>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Sgr {
+    /// Apply the `;`-separated parameters of one `ESC [ ... m` sequence.
+    fn apply(&mut self, params: &str) {
+        let mut codes = params.split(';').map(|p| p.parse::<u32>().unwrap_or(0));
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.fg = Some(ANSI_COLORS[(code - 30) as usize].to_string()),
+                90..=97 => self.fg = Some(ANSI_COLORS[(code - 90 + 8) as usize].to_string()),
+                40..=47 => self.bg = Some(ANSI_COLORS[(code - 40) as usize].to_string()),
+                100..=107 => self.bg = Some(ANSI_COLORS[(code - 100 + 8) as usize].to_string()),
+                39 => self.fg = None,
+                49 => self.bg = None,
+                38 | 48 => {
+                    let color = match codes.next() {
+                        Some(5) => codes.next().map(color_256),
+                        Some(2) => {
+                            let (r, g, b) = (codes.next(), codes.next(), codes.next());
+                            Some(format!("#{:02x}{:02x}{:02x}", r.unwrap_or(0), g.unwrap_or(0), b.unwrap_or(0)))
+                        }
+                        _ => None,
+                    };
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn css(&self) -> String {
+        let mut css = Vec::new();
+        if let Some(c) = &self.fg {
+            css.push(format!("color: {}", c));
+        }
+        if let Some(c) = &self.bg {
+            css.push(format!("background: {}", c));
+        }
+        if self.bold {
+            css.push("font-weight: bold".to_string());
+        }
+        if self.italic {
+            css.push("font-style: italic".to_string());
+        }
+        if self.underline {
+            css.push("text-decoration: underline".to_string());
+        }
+        css.join("; ")
+    }
+}
+
+/// The 16 standard and bright terminal colors, as xterm draws them.
+const ANSI_COLORS: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+/// A color of the xterm 256-color palette.
+fn color_256(n: u32) -> String {
+    match n {
+        0..=15 => ANSI_COLORS[n as usize].to_string(),
+        16..=231 => {
+            let level = |v: u32| if v == 0 { 0 } else { 55 + v * 40 };
+            let n = n - 16;
+            format!("#{:02x}{:02x}{:02x}", level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (n.min(255) - 232) * 10;
+            format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+        }
+    }
+}
+
+/// HTML-escape `text` and turn its SGR sequences into `<span style>`s.
+/// Other escape sequences are dropped, as `strip_ansi` does.
+pub fn ansi_to_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut style = Sgr::default();
+    let mut open = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push_str(&escape_html(c.encode_utf8(&mut [0; 4])));
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                let mut params = String::new();
+                let mut last = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        last = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if last != Some('m') {
+                    continue;
+                }
+                let before = style.clone();
+                style.apply(&params);
+                if style == before {
+                    continue;
+                }
+                if open {
+                    out.push_str("</span>");
+                }
+                open = style != Sgr::default();
+                if open {
+                    out.push_str(&format!("<span style=\"{}\">", style.css()));
+                }
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if open {
+        out.push_str("</span>");
+    }
+    out
+}
+
+fn show_log(path: &Path, tail: Option<usize>, raw: bool, strip: bool) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if raw {
+        copy_raw(path, tail.map(|n| n as u64), &mut stdout)?;
+    } else {
+        write_log(path, tail, strip, &mut stdout)?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Copy the log's bytes to `out` untouched, or only the last `tail_bytes`
+/// bytes. No line splitting or UTF-8 decoding, so binary output survives.
+pub fn copy_raw(path: &Path, tail_bytes: Option<u64>, out: &mut impl Write) -> Result<u64> {
+    let mut file = std::fs::File::open(path)
+        .context(format!("Failed to read {}", path.display()))?;
+    if let Some(n) = tail_bytes {
+        let len = file.metadata()?.len();
+        file.seek(SeekFrom::Start(len.saturating_sub(n)))?;
+        return Ok(std::io::copy(&mut file.take(n), out)?);
+    }
+    Ok(std::io::copy(&mut file, out)?)
+}
+
+/// Resolve the log file for a task. Tasks submitted with `--combine-output`
+/// write both streams to `logs/<id>.log`, which then serves either request.
+/// Runners with a custom log dir leave a `logs/<id>.where` pointer instead.
+pub fn log_path(root: &Path, task_id: &str, stderr: bool) -> PathBuf {
+    let logs_dir = root.join("logs");
+    let pointer = logs_dir.join(format!("{}.where", task_id));
+    if let Ok(location) = lfs::read_json::<models::LogLocation, _>(&pointer) {
+        // Absolute paths replace root on join
+        return root.join(if stderr { location.stderr } else { location.stdout });
+    }
+    let combined = logs_dir.join(format!("{}.log", task_id));
+    if combined.exists() {
+        return combined;
+    }
+    if stderr {
+        logs_dir.join(format!("{}.err", task_id))
+    } else {
+        logs_dir.join(format!("{}.out", task_id))
+    }
 }
 
 fn find_task_log(root: &Path, task_prefix: &str, stderr: bool) -> Result<Option<PathBuf>> {
@@ -42,8 +331,14 @@ fn find_task_log(root: &Path, task_prefix: &str, stderr: bool) -> Result<Option<
 
     for entry in std::fs::read_dir(&logs_dir)? {
         let entry = entry?;
+        if lfs::is_temp_file(entry.path()) {
+            continue;
+        }
         let name = entry.file_name().to_string_lossy().into_owned();
-        if name.starts_with(task_prefix) && name.ends_with(ext) {
+        if name.starts_with(task_prefix) && name.ends_with(".where") {
+            return Ok(Some(log_path(root, name.trim_end_matches(".where"), stderr)));
+        }
+        if name.starts_with(task_prefix) && (name.ends_with(ext) || name.ends_with(".log")) {
             return Ok(Some(entry.path()));
         }
     }
@@ -51,19 +346,59 @@ fn find_task_log(root: &Path, task_prefix: &str, stderr: bool) -> Result<Option<
     Ok(None)
 }
 
-fn print_log(path: &PathBuf, tail: Option<usize>) -> Result<()> {
+/// Write the log as text to `out`, or only its last `tail` lines, with ANSI
+/// escape sequences removed if `strip` is set.
+pub fn write_log(path: &Path, tail: Option<usize>, strip: bool, out: &mut impl Write) -> Result<()> {
     let content = std::fs::read_to_string(path)
         .context(format!("Failed to read {}", path.display()))?;
+    let content = if strip { strip_ansi(&content) } else { content };
 
     if let Some(n) = tail {
         let lines: Vec<&str> = content.lines().collect();
         let start = if lines.len() > n { lines.len() - n } else { 0 };
         for line in &lines[start..] {
-            println!("{}", line);
+            writeln!(out, "{}", line)?;
         }
     } else {
-        print!("{}", content);
+        write!(out, "{}", content)?;
     }
 
     Ok(())
 }
+
+/// Remove ANSI escape sequences: CSI (colors, cursor movement), OSC (titles,
+/// hyperlinks) and two-character escapes. Other text is kept as is.
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameters and intermediates up to a final byte in @..=~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Runs until BEL or the ESC-backslash terminator
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
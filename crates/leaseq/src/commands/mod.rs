@@ -1,11 +1,34 @@
 pub mod add;
+pub mod bench;
 pub mod cancel;
 pub mod daemon;
 pub mod follow;
+pub mod gc_nodes;
+pub mod gc_pending;
+pub mod group;
+pub mod import;
 pub mod lease;
 pub mod logs;
+pub mod node;
+pub mod prune_logs;
+pub mod purge_corrupt;
+pub mod retry;
 pub mod run;
 pub mod shell;
 pub mod status;
 pub mod submit;
-pub mod tasks;
\ No newline at end of file
+pub mod tasks;
+pub mod validate;
+pub mod verify;
+pub mod version;
+pub mod watch;
+
+/// How a command prints its report.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned text for terminals
+    #[default]
+    Human,
+    /// A single JSON document, e.g. for dashboards
+    Json,
+}
@@ -0,0 +1,65 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use leaseq_core::{config, fs as lfs, models};
+use std::path::{Path, PathBuf};
+
+#[derive(Subcommand)]
+pub enum NodeCommands {
+    /// Stop routing new tasks to a node; its runner keeps working through
+    /// what is already queued there
+    Cordon {
+        node: String,
+        #[arg(long)]
+        lease: Option<String>,
+    },
+    /// Route new tasks to a cordoned node again
+    Uncordon {
+        node: String,
+        #[arg(long)]
+        lease: Option<String>,
+    },
+}
+
+pub async fn run(command: NodeCommands) -> Result<()> {
+    match command {
+        NodeCommands::Cordon { node, lease } => {
+            let root = config::lease_root(&lease.unwrap_or_else(config::local_lease_id));
+            cordon(&root, &node)?;
+            println!("Cordoned node {}", node);
+        }
+        NodeCommands::Uncordon { node, lease } => {
+            let root = config::lease_root(&lease.unwrap_or_else(config::local_lease_id));
+            if !is_cordoned(&root, &node) {
+                println!("Node {} is not cordoned", node);
+                return Ok(());
+            }
+            uncordon(&root, &node)?;
+            println!("Uncordoned node {}", node);
+        }
+    }
+    Ok(())
+}
+
+/// `control/<node>/cordon`; while it exists `submit` doesn't pick the node.
+pub fn cordon_marker(root: &Path, node: &str) -> PathBuf {
+    root.join("control").join(node).join("cordon")
+}
+
+pub fn is_cordoned(root: &Path, node: &str) -> bool {
+    cordon_marker(root, node).exists()
+}
+
+pub fn cordon(root: &Path, node: &str) -> Result<()> {
+    // The marker goes under control/<node>/, which must stay inside the lease
+    models::validate_node_name(node).map_err(anyhow::Error::msg)?;
+    let marker = cordon_marker(root, node);
+    if let Some(dir) = marker.parent() {
+        lfs::ensure_dir(dir)?;
+    }
+    lfs::touch(&marker).context(format!("Failed to create {}", marker.display()))
+}
+
+pub fn uncordon(root: &Path, node: &str) -> Result<()> {
+    models::validate_node_name(node).map_err(anyhow::Error::msg)?;
+    Ok(lfs::remove_file_if_exists(cordon_marker(root, node))?)
+}
@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use crate::commands::logs;
+use leaseq_core::{config, fs as lfs, models};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub deleted: Vec<PathBuf>,
+    pub kept: Vec<PathBuf>,
+    pub bytes: u64, // freed, or that would be freed on a dry run
+}
+
+pub async fn run(lease: Option<String>, keep_matching: Option<String>, dry_run: bool) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+
+    let keep = match keep_matching {
+        Some(pattern) => Some(Regex::new(&pattern).context(format!("Invalid --keep-matching regex '{}'", pattern))?),
+        None => None,
+    };
+
+    let report = prune_logs(&root, keep.as_ref(), dry_run)?;
+    let verb = if dry_run { "Would delete" } else { "Deleted" };
+    for path in &report.deleted {
+        println!("{} {}", verb, path.display());
+    }
+    println!(
+        "{} {} log file(s) ({} bytes), kept {}",
+        verb,
+        report.deleted.len(),
+        report.bytes,
+        report.kept.len()
+    );
+    Ok(())
+}
+
+/// Delete the logs of finished tasks, except tasks where any log line matches
+/// `keep`. Results are never touched, and neither are logs of tasks still
+/// pending or running, even under the ID of a finished run (e.g. restored by
+/// `purge-corrupt --restore`). Only files in `<root>/logs` are deleted: logs
+/// a `.where` pointer places elsewhere (a runner's `--log-dir`, a task's
+/// `--output-dir`) are the user's to clean up.
+pub fn prune_logs(root: &Path, keep: Option<&Regex>, dry_run: bool) -> Result<PruneReport> {
+    let mut report = PruneReport::default();
+    let logs_dir = root.join("logs");
+    let queued = queued_tasks(root)?;
+
+    for task_id in finished_tasks(root)?.difference(&queued) {
+        // Combined logs resolve to the same file for both streams
+        let paths: BTreeSet<PathBuf> = [false, true]
+            .iter()
+            .map(|stderr| logs::log_path(root, task_id, *stderr))
+            .filter(|p| p.starts_with(&logs_dir) && p.exists())
+            .collect();
+
+        let matched = match keep {
+            Some(re) => paths.iter().any(|p| contains_match(p, re)),
+            None => false,
+        };
+        if matched {
+            report.kept.extend(paths);
+            continue;
+        }
+
+        for path in paths {
+            report.bytes += std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            if !dry_run {
+                lfs::remove_file_if_exists(&path)?;
+            }
+            report.deleted.push(path);
+        }
+    }
+
+    Ok(report)
+}
+
+fn contains_match(path: &Path, re: &Regex) -> bool {
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    // Line by line so huge logs aren't loaded at once; tolerate non-UTF-8 output
+    while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+        if re.is_match(&String::from_utf8_lossy(&line)) {
+            return true;
+        }
+        line.clear();
+    }
+    false
+}
+
+fn finished_tasks(root: &Path) -> Result<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+    let done = root.join("done");
+    if !done.exists() {
+        return Ok(ids);
+    }
+    for entry in std::fs::read_dir(&done)? {
+        let entry = entry?;
+        for f in lfs::list_files_sorted(entry.path())? {
+            if models::DoneFileKind::of(&f) != models::DoneFileKind::Result {
+                continue;
+            }
+            if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) {
+                ids.insert(result.task_id);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Tasks still in inbox/ or claimed/.
+fn queued_tasks(root: &Path) -> Result<BTreeSet<String>> {
+    let mut ids = BTreeSet::new();
+    for queue in ["inbox", "claimed"] {
+        for (_, dir) in lfs::node_dirs(root, queue)? {
+            for f in lfs::list_files_sorted(&dir)? {
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&f) {
+                    ids.insert(spec.task_id);
+                }
+            }
+        }
+    }
+    Ok(ids)
+}
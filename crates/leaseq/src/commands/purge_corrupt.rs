@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use leaseq_core::quarantine::{self, QuarantinedFile};
+use leaseq_core::{config, fs as lfs, models};
+use std::path::{Path, PathBuf};
+
+pub async fn run(lease: Option<String>, delete: bool, restore: bool) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+
+    let files = quarantine::list(&root)?;
+    if files.is_empty() {
+        println!("No quarantined files in lease {}", lease_id);
+        return Ok(());
+    }
+
+    if delete {
+        for file in &files {
+            quarantine::remove(file)?;
+        }
+        println!("Deleted {} quarantined file(s)", files.len());
+        return Ok(());
+    }
+
+    if restore {
+        for file in &files {
+            match restore_file(&root, file) {
+                Ok(dest) => println!("Restored {}", dest.strip_prefix(&root).unwrap_or(&dest).display()),
+                Err(e) => println!("Still corrupt {}: {}", display_name(file), e),
+            }
+        }
+        return Ok(());
+    }
+
+    for file in &files {
+        println!("{}", display_name(file));
+        match &file.record {
+            Some(record) => {
+                println!("  from:  {}", record.original);
+                println!("  error: {}", record.error);
+            }
+            None => println!("  (no quarantine record)"),
+        }
+    }
+    Ok(())
+}
+
+/// Re-parse a quarantined file and, if it is valid now, move it back.
+/// Tasks that were claimed go back to the inbox since they never ran.
+pub fn restore_file(root: &Path, file: &QuarantinedFile) -> Result<PathBuf> {
+    let record = file
+        .record
+        .as_ref()
+        .context("no quarantine record, original location unknown")?;
+
+    let original = Path::new(&record.original);
+    if models::DoneFileKind::of(original).is_result() {
+        let result: models::TaskResult = lfs::read_json(&file.path)?;
+        if !result.checksum_ok() {
+            return Err(anyhow::anyhow!("result checksum mismatch"));
+        }
+    } else {
+        lfs::read_json::<models::TaskSpec, _>(&file.path)?;
+    }
+
+    let dest = match original.strip_prefix("claimed") {
+        Ok(rest) => root.join("inbox").join(rest),
+        Err(_) => root.join(original),
+    };
+    if let Some(parent) = dest.parent() {
+        lfs::ensure_dir(parent)?;
+    }
+    std::fs::rename(&file.path, &dest)?;
+    lfs::remove_file_if_exists(quarantine::record_path(&file.path))?;
+    Ok(dest)
+}
+
+fn display_name(file: &QuarantinedFile) -> String {
+    file.path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
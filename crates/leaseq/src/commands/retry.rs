@@ -0,0 +1,103 @@
+use crate::commands::submit::{self, NodeOrder};
+use anyhow::{Context, Result};
+use leaseq_core::{config, fs as lfs, models};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub async fn run(task: String, lease: Option<String>, same_node: bool) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+
+    let spec = retry_task(&root, &lease_id, &task, same_node)?;
+    println!("Requeued {} as {} (node {})", task, spec.task_id, spec.target_node);
+    Ok(())
+}
+
+/// Queue a finished task (its ID, a unique prefix of it or its UUID) again
+/// as a new task with a fresh ID, UUID and idempotency key. Its archived spec is copied when it is still next to
+/// the latest result; otherwise the spec is rebuilt from what the result
+/// recorded (command, cwd, GPUs). Without `same_node` the node is picked
+/// like `submit` does. The copy keeps the task's group; `retry_of` links it
+/// to the first attempt, so a group counts only the latest attempt of each.
+pub fn retry_task(root: &Path, lease_id: &str, query: &str, same_node: bool) -> Result<models::TaskSpec> {
+    let found = super::cancel::find_task(root, query)?;
+    let task_id = found.task_id.as_str();
+    let (result, result_path) = latest_result_file(root, task_id)?
+        .ok_or_else(|| anyhow::anyhow!("No finished task {} in lease {}", task_id, lease_id))?;
+
+    let archived = archived_spec_path(&result_path);
+    let mut spec = match lfs::read_json::<models::TaskSpec, _>(&archived) {
+        Ok(spec) => spec,
+        Err(_) => models::TaskSpec {
+            command: result.command.clone(),
+            cwd: result.cwd.clone(),
+            gpus: result.gpus_requested,
+            ..Default::default()
+        },
+    };
+    if spec.command.trim().is_empty() {
+        return Err(anyhow::anyhow!("Task {} recorded no command to retry", task_id));
+    }
+
+    let uuid = Uuid::new_v4();
+    let now = time::OffsetDateTime::now_utc();
+    let unix_micros = (now.unix_timestamp_nanos() / 1000) as u64;
+    let target_node = if same_node {
+        result.node.clone()
+    } else {
+        submit::resolve_target_node(root, lease_id, None, NodeOrder::default())?
+    };
+    spec.retry_of.get_or_insert_with(|| task_id.to_string());
+    spec.task_id = format!("T{}", &uuid.simple().to_string()[..6]);
+    spec.idempotency_key = format!("{}-{}-{}", lease_id, target_node, unix_micros);
+    spec.lease_id = models::LeaseId(lease_id.to_string());
+    spec.target_node = target_node;
+    spec.seq = unix_micros;
+    spec.uuid = uuid;
+    spec.created_at = now;
+    spec.allow_rerun = true;
+    // Per-task output dirs are named after the task; the retry gets its own
+    if let Some(dir) = spec.output_dir.clone() {
+        let base = Path::new(&dir).parent().unwrap_or(Path::new("."));
+        let fresh = submit::prepare_output_dir(base, &spec.task_id)?;
+        if spec.cwd == dir {
+            spec.cwd = fresh.to_string_lossy().into_owned();
+        }
+        spec.output_dir = Some(fresh.to_string_lossy().into_owned());
+    }
+
+    let filename = format!("{:016}_{}_{}.json", unix_micros, spec.task_id, uuid);
+    lfs::atomic_write_json(root.join("inbox").join(&spec.target_node).join(filename), &spec)
+        .context("Failed to write task")?;
+    Ok(spec)
+}
+
+/// The task's most recent result, skip or cancel record and its path.
+fn latest_result_file(root: &Path, task_id: &str) -> Result<Option<(models::TaskResult, PathBuf)>> {
+    let mut latest: Option<(models::TaskResult, PathBuf)> = None;
+    for (_, dir) in lfs::node_dirs(root, "done")? {
+        for f in lfs::list_files_sorted(&dir)? {
+            if !models::DoneFileKind::of(&f).is_result() {
+                continue;
+            }
+            let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) else {
+                continue;
+            };
+            if result.task_id == task_id && latest.as_ref().is_none_or(|(l, _)| result.finished_at > l.finished_at) {
+                latest = Some((result, f));
+            }
+        }
+    }
+    Ok(latest)
+}
+
+/// `<name>.json`, archived next to its `<name>.<kind>.json` result.
+fn archived_spec_path(result: &Path) -> PathBuf {
+    let name = result.file_name().unwrap_or_default().to_string_lossy();
+    let stem = [".result.json", ".skipped.json", ".cancelled.json"]
+        .iter()
+        .find_map(|suffix| name.strip_suffix(suffix))
+        .unwrap_or(&name);
+    result.with_file_name(format!("{}.json", stem))
+}
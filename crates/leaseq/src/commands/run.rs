@@ -1,37 +1,121 @@
 use anyhow::{Context, Result};
-use leaseq_core::{config, fs as lfs, models};
-use std::collections::HashSet;
+use leaseq_core::config::{self, EnvInherit};
+use leaseq_core::{fs as lfs, gpu, models, quarantine};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex};
+use tokio::task::{JoinError, JoinSet};
 use tracing::{error, info, warn};
 
+#[derive(Default)]
 pub struct RunArgs {
     pub lease: String,
     pub node: Option<String>,
     pub root: Option<PathBuf>,
+    pub config: Option<config::RunConfig>, // RunConfig::load of the lease if unset
 }
 
+/// Runner variables passed through under `--env-inherit safe`.
+const SAFE_ENV_VARS: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LC_ALL", "TERM", "TMPDIR", "TZ",
+];
+
+/// Runs until killed. SIGTERM or SIGINT stop the runner at once and are
+/// passed on to the running task's process group; the task stays in
+/// claimed/ and is requeued when a runner starts again.
 pub async fn run(args: RunArgs) -> Result<()> {
+    // Never signalled; the sender just has to outlive the runner
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::select! {
+        res = run_with_shutdown(args, shutdown_rx) => res,
+        // Signal inside the branch: select! drops the runner (and with it the
+        // task registrations) before running the handler
+        stopped = async {
+            terminated().await;
+            signal_task_groups(libc::SIGTERM)
+        } => {
+            info!("Terminated; sent SIGTERM to {} running task(s)", stopped);
+            Ok(())
+        }
+    }
+}
+
+async fn terminated() {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = term.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+/// Process groups of the tasks running in this process. Each task leads its
+/// own group, and signals go to the task's process group so they reach
+/// whatever it started.
+static TASK_GROUPS: std::sync::Mutex<Vec<u32>> = std::sync::Mutex::new(Vec::new());
+
+/// Registered in `TASK_GROUPS` while the task's process runs.
+struct TaskGroup(u32);
+
+impl TaskGroup {
+    fn new(pgid: u32) -> Self {
+        TASK_GROUPS.lock().unwrap_or_else(|e| e.into_inner()).push(pgid);
+        Self(pgid)
+    }
+}
+
+impl Drop for TaskGroup {
+    fn drop(&mut self) {
+        TASK_GROUPS.lock().unwrap_or_else(|e| e.into_inner()).retain(|g| *g != self.0);
+    }
+}
+
+/// Send `signal` to every running task's process group; returns how many.
+fn signal_task_groups(signal: libc::c_int) -> usize {
+    let groups = TASK_GROUPS.lock().unwrap_or_else(|e| e.into_inner());
+    for pgid in groups.iter() {
+        unsafe {
+            libc::kill(-(*pgid as libc::pid_t), signal);
+        }
+    }
+    groups.len()
+}
+
+/// Make sure the lease root is a writable directory, creating it if needed,
+/// so a bad `--root` fails with one clear error before any subdir is made.
+fn check_root(root: &Path) -> Result<()> {
+    if root.exists() && !root.is_dir() {
+        return Err(anyhow::anyhow!("Lease root {} is not a directory", root.display()));
+    }
+    std::fs::create_dir_all(root).context(format!("Cannot create lease root {}", root.display()))?;
+    let probe = root.join(format!(".write-test-{}", std::process::id()));
+    std::fs::File::create(&probe).context(format!("Lease root {} is not writable", root.display()))?;
+    lfs::remove_file_if_exists(&probe)?;
+    Ok(())
+}
+
+/// Like `run`, but returns once `shutdown` flips to true. A task already
+/// running is allowed to finish first; nothing new is claimed.
+pub async fn run_with_shutdown(args: RunArgs, shutdown: watch::Receiver<bool>) -> Result<()> {
     let hostname = hostname::get()?.to_string_lossy().into_owned();
     let node = args.node.unwrap_or_else(|| hostname.clone());
 
-    let root = if let Some(r) = args.root {
-        r
-    } else {
-        if args.lease.starts_with("local:") {
-            config::runtime_dir().join(&args.lease)
-        } else {
-            config::leaseq_home_dir().join("runs").join(&args.lease)
-        }
-    };
+    let root = args.root.unwrap_or_else(|| config::lease_root(&args.lease));
 
     info!(
         "Starting runner for lease={} node={} root={:?}",
         args.lease, node, root
     );
 
+    check_root(&root)?;
     // Ensure directory structure exists
     let dirs = ["inbox", "claimed", "ack", "done", "logs", "hb", "events"];
     for d in &dirs {
@@ -40,12 +124,29 @@ pub async fn run(args: RunArgs) -> Result<()> {
     }
     lfs::ensure_dir(root.join("logs"))?;
 
+    let cfg = args.config.unwrap_or_else(|| config::RunConfig::load(&root));
+    if let Some(dir) = &cfg.log_dir {
+        lfs::ensure_dir(dir).context(format!("Failed to create log dir {}", dir.display()))?;
+        info!("Writing task logs to {:?}", dir);
+    }
+
+    // A runner confined to some devices (e.g. by Slurm) hands out only those
+    let visible = std::env::var("CUDA_VISIBLE_DEVICES").ok();
+    let gpu_pool = gpu::GpuPool::from_visible(visible.as_deref(), cfg.gpus);
+    let cpus_total = cfg.cpus.unwrap_or_else(gpu::detect_cpus);
+
     let executed_keys = Arc::new(Mutex::new(HashSet::new()));
     let runner = Runner {
         _lease_id: args.lease.clone(),
         node: node.clone(),
         root: root.clone(),
+        gpu_pool,
+        cpus_total,
+        config: cfg,
         executed_keys: executed_keys.clone(),
+        errors: Arc::default(),
+        backfill: Arc::default(),
+        running: Arc::default(),
     };
 
     // 1. Recover Zombies (Self-Healing)
@@ -57,28 +158,203 @@ pub async fn run(args: RunArgs) -> Result<()> {
 
     // 2. Start Background Heartbeat
     // Ensure initial heartbeat exists
-    if let Err(e) = runner.update_heartbeat(None).await {
+    if let Err(e) = runner.update_heartbeat().await {
         warn!("Failed to write initial heartbeat: {}", e);
     }
 
     let hb_runner = runner.clone();
-    // Shared state for current task ID
-    let current_task = Arc::new(Mutex::new(None::<String>));
-    let hb_current_task = current_task.clone();
-
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(Duration::from_secs(5)); // Send HB every 5s
+    let hb_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(hb_runner.config.heartbeat_interval);
         loop {
             interval.tick().await;
-            let task_id = hb_current_task.lock().await.clone();
-            if let Err(e) = hb_runner.update_heartbeat(task_id.as_deref()).await {
+            if let Err(e) = hb_runner.update_heartbeat().await {
                 error!("Heartbeat failed: {}", e);
             }
         }
     });
 
+    // Lease-wide sweeps read every node's queue, so they run on their own,
+    // where a big queue or slow filesystem can't hold up the heartbeat.
+    // Also covers nodes without a runner of their own.
+    let sweep_root = runner.root.clone();
+    let sweep_handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let root = sweep_root.clone();
+            let swept = tokio::task::spawn_blocking(move || {
+                let now = time::OffsetDateTime::now_utc();
+                (super::gc_pending::expire_pending(&root, now), super::gc_nodes::sweep(&root, now))
+            })
+            .await;
+            let (expired, removed) = match swept {
+                Ok(swept) => swept,
+                Err(e) => {
+                    warn!("Lease sweep failed: {}", e);
+                    continue;
+                }
+            };
+            match expired {
+                Ok(expired) if !expired.is_empty() => info!("Expired pending tasks {:?}", expired),
+                Ok(_) => {}
+                Err(e) => warn!("Expiring pending tasks failed: {:#}", e),
+            }
+            match removed {
+                Ok(removed) if !removed.is_empty() => info!("Removed stale heartbeats of {:?}", removed),
+                Ok(_) => {}
+                Err(e) => warn!("Removing stale heartbeats failed: {:#}", e),
+            }
+        }
+    });
+
     // 3. Main Loop
-    runner.run_loop(current_task).await
+    let res = runner.run_loop(shutdown).await;
+
+    hb_handle.abort();
+    sweep_handle.abort();
+    if let Err(e) = runner.update_heartbeat().await {
+        warn!("Failed to write final heartbeat: {}", e);
+    }
+    res
+}
+
+/// `spec.priority` plus `aging` per minute the task has been waiting.
+fn effective_priority(spec: &models::TaskSpec, now: time::OffsetDateTime, aging: f64) -> f64 {
+    let waited = (now - spec.created_at).as_seconds_f64().max(0.0) / 60.0;
+    spec.priority as f64 + waited * aging
+}
+
+/// Exit codes of finished tasks on any node of the lease, by task ID. A
+/// cancelled task counts as failed, so its dependents don't wait forever.
+fn finished_exit_codes(root: &Path) -> HashMap<String, i32> {
+    let mut codes = HashMap::new();
+    for (_, node_dir) in lfs::node_dirs(root, "done").unwrap_or_default() {
+        for f in lfs::list_files_sorted(&node_dir).unwrap_or_default() {
+            if !matches!(models::DoneFileKind::of(&f), models::DoneFileKind::Result | models::DoneFileKind::Cancelled) {
+                continue;
+            }
+            if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) {
+                codes.insert(result.task_id, result.exit_code);
+            }
+        }
+    }
+    codes
+}
+
+/// The first of `spec`'s dependencies that finished unsuccessfully.
+fn failed_dependency<'a>(spec: &'a models::TaskSpec, finished: &HashMap<String, i32>) -> Option<&'a str> {
+    spec.depends_on.iter().find(|d| finished.get(*d).is_some_and(|c| *c != 0)).map(String::as_str)
+}
+
+/// Move a finished task's spec from claimed/ into `done_dir`, the second
+/// phase after its result has been written there.
+pub(crate) fn archive_spec(task_path: &Path, done_dir: &Path) -> Result<()> {
+    let archived = done_dir.join(task_path.file_name().unwrap_or_default());
+    std::fs::rename(task_path, &archived).context(format!("Failed to archive {} into done/", task_path.display()))
+}
+
+/// Write a task's exit code to its `capture_rc` file, if it asked for one.
+/// A relative path is resolved against the task's cwd.
+fn write_capture_rc(spec: &models::TaskSpec, cwd: &Path, exit_code: i32) {
+    if let Some(rc) = spec.capture_rc.as_deref() {
+        let rc_path = cwd.join(rc);
+        if let Err(e) = std::fs::write(&rc_path, format!("{}\n", exit_code)) {
+            warn!("Task {}: failed to write exit code to {}: {}", spec.task_id, rc_path.display(), e);
+        }
+    }
+}
+
+/// The result, skip or cancel record in `done_dir` for the claimed spec at
+/// `claimed`, if one was written. Results must pass their checksum to count.
+fn finished_result(root: &Path, done_dir: &Path, claimed: &Path) -> Option<PathBuf> {
+    let name = claimed.file_name()?.to_string_lossy();
+    let stem = name.trim_end_matches(".json");
+    let result = done_dir.join(format!("{}.result.json", stem));
+    if result.exists() {
+        return quarantine::read_verified_result(root, &result).map(|_| result);
+    }
+    ["skipped", "cancelled"].iter().map(|kind| done_dir.join(format!("{}.{}.json", stem, kind))).find(|p| p.exists())
+}
+
+/// Fill in `{{task_id}}`, `{{node}}` and `{{lease}}`; other braces are the
+/// shell's business and stay as they are.
+fn expand_placeholders(command: &str, spec: &models::TaskSpec, node: &str) -> String {
+    command
+        .replace("{{task_id}}", &spec.task_id)
+        .replace("{{node}}", node)
+        .replace("{{lease}}", &spec.lease_id.0)
+}
+
+/// Copy `output` into `log` line by line, each line starting with
+/// `[<task_id> <UTC timestamp>] `, stamped as it is read. A last line
+/// without a newline is written as is, prefix included.
+fn prefix_lines<R>(output: Option<R>, mut log: std::fs::File, task_id: &str) -> tokio::task::JoinHandle<()>
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+{
+    use std::io::Write;
+    use tokio::io::AsyncBufReadExt;
+
+    let task_id = task_id.to_string();
+    tokio::spawn(async move {
+        let Some(output) = output else {
+            return;
+        };
+        let mut reader = tokio::io::BufReader::new(output);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Task {}: failed to read output: {}", task_id, e);
+                    break;
+                }
+            }
+            let ts = time::OffsetDateTime::now_utc()
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap_or_default();
+            let mut prefixed = format!("[{} {}] ", task_id, ts).into_bytes();
+            prefixed.extend_from_slice(&line);
+            // One write per line, so stdout and stderr sharing a log interleave by line
+            if let Err(e) = log.write_all(&prefixed) {
+                warn!("Task {}: failed to write log: {}", task_id, e);
+                break;
+            }
+        }
+    })
+}
+
+/// `args` as one line a shell would split back into the same words.
+fn shell_join<'a>(args: impl IntoIterator<Item = &'a str>) -> String {
+    let quote = |arg: &str| {
+        let plain = !arg.is_empty()
+            && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c));
+        if plain {
+            arg.to_string()
+        } else {
+            format!("'{}'", arg.replace('\'', r"'\''"))
+        }
+    };
+    args.into_iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+/// `keys/<node>.jsonl`, the node's index of executed idempotency keys.
+pub fn key_index_path(root: &Path, node: &str) -> PathBuf {
+    root.join("keys").join(format!("{}.jsonl", node))
+}
+
+/// Result file name -> key, from the entries of a key index. Lines that don't
+/// parse, e.g. one cut short by a crash, are skipped.
+fn key_index(path: &Path) -> HashMap<String, String> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<models::KeyIndexEntry>(line).ok())
+        .map(|e| (e.result, e.key))
+        .collect()
 }
 
 #[derive(Clone)]
@@ -86,15 +362,138 @@ struct Runner {
     _lease_id: String,
     node: String,
     root: PathBuf,
+    gpu_pool: gpu::GpuPool,
+    cpus_total: u32,
+    config: config::RunConfig,
     executed_keys: Arc<Mutex<HashSet<String>>>,
+    errors: Arc<Mutex<RunnerErrors>>,
+    backfill: Arc<Mutex<Backfill>>,
+    /// IDs of the tasks in flight, for the heartbeat
+    running: Arc<Mutex<Vec<String>>>,
+}
+
+/// An inbox task that could be claimed now, with the GPUs and CPUs it has
+/// to hold while it runs (0 for what it runs without a reservation of).
+struct Candidate<'a> {
+    path: &'a PathBuf,
+    score: f64,
+    task_id: String,
+    gpus: u32,
+    cpus: u32,
+    /// A dependency that finished unsuccessfully, if any
+    failed_dependency: Option<String>,
+}
+
+/// The GPU task currently being passed over by backfilled tasks, and how
+/// many times it has been.
+#[derive(Default)]
+struct Backfill {
+    head: Option<PathBuf>,
+    skipped: u32,
+}
+
+/// Devices and CPUs held in the node's ledger. `release` gives them back
+/// and waits for it; one dropped without that (an early return) gives them
+/// back in the background.
+struct Reservation {
+    root: PathBuf,
+    node: String,
+    task_id: String,
+    devices: Vec<u32>,
 }
 
+impl Reservation {
+    /// Give the devices and CPUs back, returning once the ledger has them.
+    async fn release(mut self) -> Result<()> {
+        let root = std::mem::take(&mut self.root);
+        let node = std::mem::take(&mut self.node);
+        let task_id = std::mem::take(&mut self.task_id);
+        in_ledger(move || gpu::release(&root, &node, &task_id)).await
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        // Already given back by `release`
+        if self.task_id.is_empty() {
+            return;
+        }
+        let root = std::mem::take(&mut self.root);
+        let node = std::mem::take(&mut self.node);
+        let task_id = std::mem::take(&mut self.task_id);
+        let release = move || {
+            if let Err(e) = gpu::release(&root, &node, &task_id) {
+                warn!("Failed to release GPUs/CPUs of task {}: {}", task_id, e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(release);
+            }
+            Err(_) => release(),
+        }
+    }
+}
+
+/// Run `f`, a change to the node's GPU/CPU ledger, on the blocking pool:
+/// waiting for the ledger's lock blocks the thread, which on an async
+/// worker would hold up heartbeats and cancel polls.
+async fn in_ledger<T: Send + 'static>(f: impl FnOnce() -> std::io::Result<T> + Send + 'static) -> Result<T> {
+    Ok(tokio::task::spawn_blocking(f).await??)
+}
+
+/// Loop errors, reported in heartbeats so a struggling runner is visible.
+#[derive(Default)]
+struct RunnerErrors {
+    count: u64,
+    last: Option<String>,
+}
+
+/// A `leaseq cancel` request for a running task, `control/<node>/cancel_*.json`.
 #[derive(serde::Deserialize)]
-#[allow(dead_code)]
 struct CancelCommand {
     task_id: String,
 }
 
+/// How often a runner expires old pending tasks and compacts stale heartbeats.
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+/// How often a running task is checked for cancel requests.
+const CANCEL_POLL: std::time::Duration = std::time::Duration::from_secs(1);
+/// How long a cancelled or timed-out task has to exit after SIGTERM before it gets SIGKILL.
+const CANCEL_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long prefixed output may keep arriving after the task exits, from
+/// processes it left running, before the runner stops copying it.
+const PIPE_DRAIN_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Why `wait_or_cancel` stopped waiting for a task's process.
+enum Stop {
+    Exited,
+    /// With the cancel requests acted on
+    Cancelled(Vec<PathBuf>),
+    TimedOut,
+}
+
+/// Send the task's process group SIGTERM, then SIGKILL if it is still
+/// running after `CANCEL_GRACE`, and reap it.
+async fn terminate(child: &mut tokio::process::Child, task_id: &str) -> std::io::Result<std::process::ExitStatus> {
+    let signal_group = |child: &tokio::process::Child, signal| {
+        if let Some(pgid) = child.id() {
+            unsafe {
+                libc::kill(-(pgid as libc::pid_t), signal);
+            }
+        }
+    };
+    signal_group(child, libc::SIGTERM);
+    match tokio::time::timeout(CANCEL_GRACE, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            warn!("Task {} ignored SIGTERM for {:?}; killing it", task_id, CANCEL_GRACE);
+            signal_group(child, libc::SIGKILL);
+            child.wait().await
+        }
+    }
+}
+
 impl Runner {
     async fn load_executed_keys(&self) -> Result<()> {
         let done_dir = self.root.join("done").join(&self.node);
@@ -103,23 +502,20 @@ impl Runner {
         }
 
         let mut keys = self.executed_keys.lock().await;
-        // Optimization: In a real large system, we might not want to read ALL done files.
-        // But for deduplication, we need them.
-        // We could limit to last N hours or use a separate index.
-        // For now, keep existing logic but wrapped in Mutex.
-        
+        // Results in the key index are known without parsing them; results
+        // written before it existed, or by other commands, are read
+        let indexed = key_index(&key_index_path(&self.root, &self.node));
+
         let mut count = 0;
-        if let Ok(entries) = std::fs::read_dir(&done_dir) {
-            for entry in entries {
-                let entry = entry?;
-                let path = entry.path();
-                if path.extension().map(|e| e == "json").unwrap_or(false)
-                    && path.file_name().map(|n| n.to_string_lossy().ends_with(".result.json")).unwrap_or(false)
-                {
-                    if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&path) {
-                        keys.insert(result.idempotency_key);
-                        count += 1;
-                    }
+        for path in lfs::list_files_sorted(&done_dir)? {
+            if models::DoneFileKind::of(&path) == models::DoneFileKind::Result {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if let Some(key) = indexed.get(name.as_ref()) {
+                    keys.insert(key.clone());
+                    count += 1;
+                } else if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&path) {
+                    keys.insert(result.idempotency_key);
+                    count += 1;
                 }
             }
         }
@@ -128,6 +524,19 @@ impl Runner {
         Ok(())
     }
 
+    /// Append the key of a result just written to `done/<node>/<result>` to
+    /// the node's key index. The executed-keys lock is held so concurrent
+    /// task slots append one whole line each; a failed append only costs a
+    /// result parse on the next start.
+    async fn index_key(&self, key: &str, result: &str) {
+        let _keys = self.executed_keys.lock().await;
+        let entry = models::KeyIndexEntry { key: key.to_string(), result: result.to_string() };
+        let path = key_index_path(&self.root, &self.node);
+        if let Err(e) = lfs::append_json_line(&path, &entry) {
+            warn!("Failed to append to key index {}: {}", path.display(), e);
+        }
+    }
+
     async fn recover_zombies(&self) -> Result<()> {
         let claimed_dir = self.root.join("claimed").join(&self.node);
         let inbox_dir = self.root.join("inbox").join(&self.node);
@@ -136,69 +545,194 @@ impl Runner {
             return Ok(());
         }
 
-        for entry in std::fs::read_dir(&claimed_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                let filename = path.file_name().unwrap();
-                info!("Found zombie task {:?}. Recovering to inbox...", filename);
-                
-                // Move back to inbox
-                // Note: This puts it at the "end" of the queue conceptually if we sorted by mtime,
-                // but our sort is by filename (lexicographical), so it will jump back to its 
-                // correct priority position! (Because filename contains timestamp prefix).
-                let new_path = inbox_dir.join(filename);
-                std::fs::rename(&path, &new_path)?;
+        let done_dir = self.root.join("done").join(&self.node);
+
+        // Temp files from an interrupted write are not tasks
+        for path in lfs::list_files_sorted(&claimed_dir)? {
+            let filename = path.file_name().unwrap();
+
+            // Finished, but archiving the spec failed: complete that step
+            // rather than running the task again
+            if let Some(result) = finished_result(&self.root, &done_dir, &path) {
+                info!("Task {:?} already has result {:?}. Archiving...", filename, result);
+                if let Err(e) = archive_spec(&path, &done_dir) {
+                    warn!("{:#}; leaving it claimed", e);
+                    continue;
+                }
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(done_dir.join(filename)) {
+                    let (root, node) = (self.root.clone(), self.node.clone());
+                    in_ledger(move || gpu::release(&root, &node, &spec.task_id)).await?;
+                }
+                continue;
+            }
+
+            info!("Found zombie task {:?}. Recovering to inbox...", filename);
+
+            // Move back to inbox
+            // Note: This puts it at the "end" of the queue conceptually if we sorted by mtime,
+            // but our sort is by filename (lexicographical), so it will jump back to its 
+            // correct priority position! (Because filename contains timestamp prefix).
+            let new_path = inbox_dir.join(filename);
+            std::fs::rename(&path, &new_path)?;
+            if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&new_path) {
+                let (root, node) = (self.root.clone(), self.node.clone());
+                in_ledger(move || gpu::release(&root, &node, &spec.task_id)).await?;
             }
         }
         Ok(())
     }
 
-    async fn is_duplicate(&self, idempotency_key: &str) -> bool {
-        self.executed_keys.lock().await.contains(idempotency_key)
+    /// Hold the just-claimed candidate's GPUs and CPUs in the node's ledger,
+    /// before the next slot's claim, which then counts them as taken. None if
+    /// they aren't all free (another runner of the node took some since the
+    /// check); the task then goes back to the inbox rather than waiting.
+    async fn try_reserve(&self, candidate: &Candidate<'_>) -> Result<Option<Reservation>> {
+        let (root, node, task_id) = (self.root.clone(), self.node.clone(), candidate.task_id.clone());
+        let (gpus, cpus, gpus_total, cpus_total) = (candidate.gpus, candidate.cpus, self.gpu_pool.len(), self.cpus_total);
+        let reservation = in_ledger(move || {
+            let devices = if gpus > 0 {
+                match gpu::allocate(&root, &node, &task_id, gpus, gpus_total)? {
+                    Some(devices) => devices,
+                    None => return Ok(None),
+                }
+            } else {
+                Vec::new()
+            };
+            if cpus > 0 && !gpu::allocate_cpus(&root, &node, &task_id, cpus, cpus_total)? {
+                // Given back here rather than by a dropped Reservation, whose
+                // release could land after this task's next reservation
+                if gpus > 0 {
+                    gpu::release(&root, &node, &task_id)?;
+                }
+                return Ok(None);
+            }
+            Ok(Some(Reservation { root, node, task_id, devices }))
+        })
+        .await?;
+        if let Some(r) = &reservation {
+            info!("Task {} holds GPUs {:?} and {} CPUs", r.task_id, r.devices, cpus);
+        }
+        Ok(reservation)
     }
 
-    async fn run_loop(&self, current_task: Arc<Mutex<Option<String>>>) -> Result<()> {
-        let mut interval = tokio::time::interval(Duration::from_secs(1));
+    /// Record the key as executed; false if it already was. Done before the
+    /// task runs, so two slots can't both run the same key.
+    async fn mark_executed(&self, idempotency_key: &str) -> bool {
+        self.executed_keys.lock().await.insert(idempotency_key.to_string())
+    }
 
-        loop {
-            interval.tick().await;
+    /// Claim and run tasks, up to `config.parallel` at once, until shutdown.
+    /// Tasks in flight then finish before it returns.
+    async fn run_loop(&self, mut shutdown: watch::Receiver<bool>) -> Result<()> {
+        let mut interval = tokio::time::interval(self.config.poll_interval);
+        let mut in_flight = JoinSet::new();
 
-            // We don't manually update heartbeat here anymore (background task does it)
+        loop {
+            let closed = tokio::select! {
+                _ = interval.tick() => false,
+                changed = shutdown.changed() => changed.is_err(),
+                // A freed slot is filled right away rather than on the next tick
+                Some(joined) = in_flight.join_next() => {
+                    self.finish_task(joined).await;
+                    false
+                }
+            };
+            // A dropped sender can never signal again; treat it as a shutdown
+            if closed || *shutdown.borrow() {
+                info!("Shutdown requested, runner for node {} exiting", self.node);
+                while let Some(joined) = in_flight.join_next().await {
+                    self.finish_task(joined).await;
+                }
+                return Ok(());
+            }
 
-            match self.poll_and_claim().await {
-                Ok(Some(task_path)) => {
-                    // Update current task for heartbeat
-                    if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_path) {
-                        *current_task.lock().await = Some(spec.task_id.clone());
+            while in_flight.len() < self.config.parallel as usize {
+                match self.poll_and_claim().await {
+                    Ok(Some((task_path, reservation, failed_dependency))) => {
+                        let task_id = lfs::read_json::<models::TaskSpec, _>(&task_path).ok().map(|s| s.task_id);
+                        if let Some(id) = &task_id {
+                            self.set_running(id, true).await;
+                        }
+                        let runner = self.clone();
+                        in_flight.spawn(async move { (task_id, runner.execute_task(&task_path, reservation, failed_dependency).await) });
                     }
-
-                    if let Err(e) = self.execute_task(&task_path).await {
-                        error!("Task execution failed: {}", e);
+                    Ok(None) => break,
+                    Err(e) => {
+                        error!("Poll failed: {}", e);
+                        self.record_error(format!("poll: {}", e)).await;
+                        break;
                     }
-                    
-                    // Clear current task
-                    *current_task.lock().await = None;
-                }
-                Ok(None) => {}
-                Err(e) => {
-                    error!("Poll failed: {}", e);
                 }
             }
         }
     }
 
-    async fn update_heartbeat(&self, running_task: Option<&str>) -> Result<()> {
+    /// Bookkeeping for a task of `run_loop` that is done. A panic in it is
+    /// re-raised, as when tasks ran inline.
+    async fn finish_task(&self, joined: std::result::Result<(Option<String>, Result<()>), JoinError>) {
+        let (task_id, res) = joined.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        if let Err(e) = &res {
+            error!("Task execution failed: {}", e);
+        }
+        if let Some(id) = &task_id {
+            self.set_running(id, false).await;
+        }
+        if let Err(e) = res {
+            self.record_error(format!("execute: {}", e)).await;
+        }
+    }
+
+    /// Add a task to (or drop it from) what the heartbeat reports and publish
+    /// it right away; the background beat only repeats it every
+    /// `heartbeat_interval`.
+    async fn set_running(&self, task_id: &str, running: bool) {
+        let mut tasks = self.running.lock().await;
+        if running {
+            tasks.push(task_id.to_string());
+        } else {
+            tasks.retain(|t| t != task_id);
+        }
+        if let Err(e) = self.write_heartbeat(&tasks).await {
+            error!("Heartbeat failed: {}", e);
+        }
+    }
+
+    /// Count a loop error and publish it right away rather than on the next beat.
+    async fn record_error(&self, message: String) {
+        {
+            let mut errors = self.errors.lock().await;
+            errors.count += 1;
+            errors.last = Some(message);
+        }
+        let _ = self.update_heartbeat().await;
+    }
+
+    async fn update_heartbeat(&self) -> Result<()> {
+        // Held while writing, so a beat can't overwrite a newer task change
+        let running = self.running.lock().await;
+        self.write_heartbeat(&running).await
+    }
+
+    async fn write_heartbeat(&self, running: &[String]) -> Result<()> {
         let hb_path = self.root.join("hb").join(format!("{}.json", self.node));
         // lfs::ensure_dir(hb_path.parent().unwrap())?; // Done at init
+        let (errors_since_start, last_error) = {
+            let errors = self.errors.lock().await;
+            (errors.count, errors.last.clone())
+        };
 
         let hb = models::Heartbeat {
             node: self.node.clone(),
             ts: time::OffsetDateTime::now_utc(),
-            running_task_id: running_task.map(|s| s.to_string()),
+            running_task_id: running.first().cloned(),
+            running_task_ids: running.to_vec(),
             pending_estimate: 0, // TODO: calculate
             runner_pid: std::process::id(),
             version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: self.cpus_total,
+            errors_since_start,
+            last_error,
         };
 
         // Suppress error if write fails (don't crash background thread)
@@ -208,40 +742,201 @@ impl Runner {
         Ok(())
     }
 
-    async fn poll_and_claim(&self) -> Result<Option<PathBuf>> {
+    /// Claim the next task to run, with the GPUs and CPUs it holds.
+    async fn poll_and_claim(&self) -> Result<Option<(PathBuf, Option<Reservation>, Option<String>)>> {
         let inbox_dir = self.root.join("inbox").join(&self.node);
         // Optimization: Don't read whole dir if not needed? 
         // For now, keep list_files_sorted to maintain FIFO
         let entries = lfs::list_files_sorted(&inbox_dir)?;
 
-        if let Some(task_file) = entries.first() {
-            let filename = task_file.file_name().unwrap();
-            let claimed_dir = self.root.join("claimed").join(&self.node);
-            let claimed_path = claimed_dir.join(filename);
-
-            info!("Claiming task: {:?}", filename);
-
-            match std::fs::rename(task_file, &claimed_path) {
-                Ok(_) => {
-                    return Ok(Some(claimed_path));
+        // Highest effective priority among tasks whose dependencies are done,
+        // FIFO among equals. Tasks with a failed dependency are claimed too,
+        // so execute_task can fail them, and so are unparseable specs, so it
+        // can quarantine them.
+        let settings: models::LeaseSettings =
+            lfs::read_json(self.root.join(models::LEASE_SETTINGS_FILE)).unwrap_or_default();
+        let now = time::OffsetDateTime::now_utc();
+        let mut finished: Option<HashMap<String, i32>> = None;
+        let mut ready: Vec<Candidate> = Vec::new();
+        for task_file in &entries {
+            let spec = match lfs::read_json::<models::TaskSpec, _>(task_file) {
+                Ok(spec) => spec,
+                Err(_) => {
+                    ready = vec![Candidate {
+                        path: task_file,
+                        score: f64::INFINITY,
+                        task_id: String::new(),
+                        gpus: 0,
+                        cpus: 0,
+                        failed_dependency: None,
+                    }];
+                    break;
                 }
-                Err(e) => {
-                    warn!("Failed to claim (race condition?): {}", e);
-                    return Ok(None);
+            };
+            // Results are only scanned once a task has dependencies to check
+            let failed = if spec.depends_on.is_empty() {
+                None
+            } else {
+                let finished = finished.get_or_insert_with(|| finished_exit_codes(&self.root));
+                let failed = failed_dependency(&spec, finished).map(str::to_string);
+                if failed.is_none() && !spec.depends_on.iter().all(|d| finished.get(d) == Some(&0)) {
+                    continue;
                 }
+                failed
+            };
+            let settled = failed.is_none();
+            let score = effective_priority(&spec, now, settings.priority_aging());
+            // A task that will only be failed needs no GPUs or CPUs for that,
+            // including one wanting more than the node has
+            let gpus = if settled && spec.gpus <= self.gpu_pool.len() { spec.gpus } else { 0 };
+            let cpus = if settled && spec.cpus <= self.cpus_total { spec.cpus } else { 0 };
+            ready.push(Candidate { path: task_file, score, task_id: spec.task_id, gpus, cpus, failed_dependency: failed });
+        }
+        // Stable, so FIFO among equals
+        ready.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let Some(candidate) = self.backfill(&ready, settings.backfill_limit()).await else {
+            return Ok(None);
+        };
+
+        let filename = candidate.path.file_name().unwrap();
+        let claimed_dir = self.root.join("claimed").join(&self.node);
+
+        info!("Claiming task: {:?}", filename);
+
+        let claimed = match lfs::claim_file(candidate.path, &claimed_dir) {
+            Ok(Some(claimed)) => claimed,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                warn!("Failed to claim {:?}: {}", filename, e);
+                return Ok(None);
+            }
+        };
+        let failed = candidate.failed_dependency.clone();
+        if candidate.gpus == 0 && candidate.cpus == 0 {
+            return Ok(Some((claimed, None, failed)));
+        }
+        // Reserved only once claimed, as the ledger is keyed by task ID
+        match self.try_reserve(candidate).await {
+            Ok(Some(reservation)) => Ok(Some((claimed, Some(reservation), failed))),
+            reserved => {
+                info!("Task {:?} no longer fits; back to the inbox", filename);
+                std::fs::rename(&claimed, candidate.path)?;
+                reserved.map(|_| None)
             }
         }
+    }
 
-        Ok(None)
+    /// The task to claim from `ready` (best first). If the best one needs more
+    /// GPUs than are free it stays in the inbox, and the next one that fits
+    /// runs in the gap instead, at most `limit` times in a row for the same
+    /// waiting task. None if nothing can start now.
+    async fn backfill<'a, 'b>(&self, ready: &'b [Candidate<'a>], limit: u32) -> Option<&'b Candidate<'a>> {
+        let head = ready.first()?;
+        if head.gpus == 0 && head.cpus == 0 {
+            return Some(head);
+        }
+        let free = gpu::free_devices(&self.root, &self.node, self.gpu_pool.len())
+            .and_then(|gpus| Ok((gpus, gpu::free_cpus(&self.root, &self.node, self.cpus_total)?)));
+        let (free_gpus, free_cpus) = match free {
+            Ok(free) => free,
+            Err(e) => {
+                warn!("Failed to read GPU ledger: {}", e);
+                return Some(head);
+            }
+        };
+        let fits = |c: &Candidate| c.gpus <= free_gpus && c.cpus <= free_cpus;
+        if fits(head) {
+            return Some(head);
+        }
+
+        let mut state = self.backfill.lock().await;
+        if state.head.as_ref() != Some(head.path) {
+            *state = Backfill { head: Some(head.path.clone()), skipped: 0 };
+        }
+        if state.skipped >= limit {
+            return None;
+        }
+        let candidate = ready[1..].iter().find(|c| fits(c))?;
+        state.skipped += 1;
+        info!(
+            "Backfilling {:?} while {:?} waits for {} GPUs and {} CPUs ({} and {} free)",
+            candidate.path.file_name().unwrap_or_default(),
+            head.path.file_name().unwrap_or_default(),
+            head.gpus,
+            head.cpus,
+            free_gpus,
+            free_cpus
+        );
+        Some(candidate)
     }
 
-    async fn execute_task(&self, task_path: &Path) -> Result<()> {
-        let spec: models::TaskSpec = lfs::read_json(task_path)?;
+    /// Run a claimed task, holding `reservation` (its GPUs and CPUs) until it's
+    /// done, or fail it if `failed_dependency` (found when it was claimed) is set.
+    async fn execute_task(
+        &self,
+        task_path: &Path,
+        reservation: Option<Reservation>,
+        failed_dependency: Option<String>,
+    ) -> Result<()> {
+        let spec: models::TaskSpec = match lfs::read_json(task_path) {
+            Ok(spec) => spec,
+            Err(e) => {
+                // Left in claimed/ it would be recovered and fail again on every restart
+                let dest = quarantine::quarantine(&self.root, task_path, &e.to_string())?;
+                warn!("Quarantined unreadable task file {:?} -> {:?}: {}", task_path, dest, e);
+                return Ok(());
+            }
+        };
+        // The IDs become file names under logs/; keep crafted ones from leaving it
+        let bad_id = std::iter::once(&spec.task_id)
+            .chain(&spec.stdin_from_task)
+            .find_map(|id| models::validate_task_id(id).err())
+            .or_else(|| spec.stdout_to.iter().chain(&spec.stderr_to).find_map(|n| models::validate_log_name(n).err()));
+        if let Some(e) = bad_id {
+            let dest = quarantine::quarantine(&self.root, task_path, &e)?;
+            warn!("Quarantined task file {:?} -> {:?}: {}", task_path, dest, e);
+            return Ok(());
+        }
         info!("Executing task {} ({})", spec.task_id, spec.command);
 
         let done_dir = self.root.join("done").join(&self.node);
 
-        if self.is_duplicate(&spec.idempotency_key).await {
+        if let Some(dep) = failed_dependency {
+            warn!("Task {} not run: dependency {} failed", spec.task_id, dep);
+            let now = time::OffsetDateTime::now_utc();
+            let result = models::TaskResult {
+                task_id: spec.task_id.clone(),
+                idempotency_key: spec.idempotency_key.clone(),
+                node: self.node.clone(),
+                started_at: now,
+                finished_at: now,
+                exit_code: -1,
+                stdout: String::new(),
+                stderr: String::new(),
+                runtime_s: 0.0,
+                command: spec.command.clone(),
+                resolved_command: String::new(),
+                cwd: spec.cwd.clone(),
+                gpus_requested: spec.gpus,
+                gpus_assigned: String::new(),
+                checksum: None,
+                failure: Some(models::FailureReason::DependencyFailed),
+                signal: None,
+            }
+            .with_checksum();
+
+            let original_name = task_path.file_name().unwrap().to_string_lossy();
+            let result_name = format!("{}.result.json", original_name.trim_end_matches(".json"));
+            lfs::atomic_write_json(done_dir.join(&result_name), &result)?;
+            self.index_key(&spec.idempotency_key, &result_name).await;
+            return archive_spec(task_path, &done_dir);
+        }
+
+        // Reruns still record the key, so plain resubmits of it stay deduped
+        let first_run = self.mark_executed(&spec.idempotency_key).await;
+        if !first_run && spec.allow_rerun {
+            info!("Rerunning task {} (key={})", spec.task_id, spec.idempotency_key);
+        } else if !first_run {
             warn!(
                 "Skipping duplicate task {} (key={})",
                 spec.task_id, spec.idempotency_key
@@ -258,102 +953,470 @@ impl Runner {
                 stderr: String::new(),
                 runtime_s: 0.0,
                 command: spec.command.clone(),
+                resolved_command: String::new(),
                 cwd: spec.cwd.clone(),
                 gpus_requested: spec.gpus,
                 gpus_assigned: String::new(),
-            };
+                checksum: None,
+                failure: None,
+                signal: None,
+            }
+            .with_checksum();
 
             let original_name = task_path.file_name().unwrap().to_string_lossy();
             let result_name = format!("{}.skipped.json", original_name.trim_end_matches(".json"));
             lfs::atomic_write_json(done_dir.join(&result_name), &result)?;
+            return archive_spec(task_path, &done_dir);
+        }
 
-            let archived_task_path = done_dir.join(task_path.file_name().unwrap());
-            std::fs::rename(task_path, &archived_task_path)?;
+        // Heartbeat is handled by background task now
 
-            return Ok(());
+        // Combined output shares one file description, so both streams interleave in write order
+        let log_base = match &spec.output_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => self.config.log_dir.clone().unwrap_or_else(|| self.root.join("logs")),
+        };
+        let (stdout_path, stderr_path) = if spec.combine_output {
+            let combined = log_base.join(format!("{}.log", spec.task_id));
+            (combined.clone(), combined)
+        } else {
+            let name = |custom: &Option<String>, ext: &str| {
+                custom.clone().unwrap_or_else(|| format!("{}.{}", spec.task_id, ext))
+            };
+            (log_base.join(name(&spec.stdout_to, "out")), log_base.join(name(&spec.stderr_to, "err")))
+        };
+        // Results keep paths relative to the run dir unless logs live elsewhere
+        let record = |p: &Path| match p.strip_prefix(&self.root) {
+            Ok(rel) => rel.to_string_lossy().into_owned(),
+            Err(_) => p.to_string_lossy().into_owned(),
+        };
+        let (stdout_rel, stderr_rel) = (record(&stdout_path), record(&stderr_path));
+
+        let custom_names = spec.stdout_to.is_some() || spec.stderr_to.is_some();
+        if log_base != self.root.join("logs") || custom_names {
+            let location = models::LogLocation {
+                stdout: stdout_rel.clone(),
+                stderr: stderr_rel.clone(),
+            };
+            lfs::atomic_write_json(
+                self.root.join("logs").join(format!("{}.where", spec.task_id)),
+                &location,
+            )?;
         }
 
-        // Heartbeat is handled by background task now
+        let cwd = match &spec.snapshot_dir {
+            Some(dir) if spec.run_in_snapshot => self.root.join(dir),
+            _ => PathBuf::from(&spec.cwd),
+        };
+        // Running anywhere else, or without its input, would only produce
+        // confusing results; and without a reservation it would use GPUs and
+        // CPUs the ledger has given to other tasks
+        let oversized = if spec.gpus > self.gpu_pool.len() && !self.gpu_pool.is_empty() {
+            Some(format!("task wants {} GPUs but node {} has {}", spec.gpus, self.node, self.gpu_pool.len()))
+        } else if spec.cpus > self.cpus_total && self.cpus_total > 0 {
+            Some(format!("task wants {} CPUs but node {} has {}", spec.cpus, self.node, self.cpus_total))
+        } else {
+            None
+        };
+        let not_started = if let Some(message) = oversized {
+            Err((-1, message))
+        } else if !cwd.is_dir() {
+            Err((models::CWD_MISSING_EXIT_CODE, format!("cwd not found: {}", cwd.display())))
+        } else {
+            self.open_stdin(&spec).map_err(|message| (-1, message))
+        };
+        let stdin = match not_started {
+            Ok(stdin) => stdin,
+            Err((exit_code, message)) => {
+                error!("Task {} not started: {}", spec.task_id, message);
+                std::fs::File::create(&stdout_path)?;
+                std::fs::write(&stderr_path, format!("leaseq: {}\n", message))?;
+                let now = time::OffsetDateTime::now_utc();
+                let result = models::TaskResult {
+                    task_id: spec.task_id.clone(),
+                    idempotency_key: spec.idempotency_key.clone(),
+                    node: self.node.clone(),
+                    started_at: now,
+                    finished_at: now,
+                    exit_code,
+                    stdout: stdout_rel,
+                    stderr: stderr_rel,
+                    runtime_s: 0.0,
+                    command: spec.command.clone(),
+                    resolved_command: String::new(),
+                    cwd: spec.cwd.clone(),
+                    gpus_requested: spec.gpus,
+                    gpus_assigned: String::new(),
+                    checksum: None,
+                    failure: Some(models::FailureReason::SpawnError),
+                    signal: None,
+                }
+                .with_checksum();
 
-        let stdout_path = self.root.join("logs").join(format!("{}.out", spec.task_id));
-        let stderr_path = self.root.join("logs").join(format!("{}.err", spec.task_id));
+                // Whoever waits on the rc file hears about it too
+                write_capture_rc(&spec, &cwd, exit_code);
+
+                let original_name = task_path.file_name().unwrap().to_string_lossy();
+                let result_name = format!("{}.result.json", original_name.trim_end_matches(".json"));
+                lfs::atomic_write_json(done_dir.join(&result_name), &result)?;
+                self.index_key(&spec.idempotency_key, &result_name).await;
+                archive_spec(task_path, &done_dir)?;
+                // It failed, so on_failure still runs
+                return self.queue_follow_up(&spec, false);
+            }
+        };
 
         let stdout_file = std::fs::File::create(&stdout_path)?;
-        let stderr_file = std::fs::File::create(&stderr_path)?;
+        let stderr_file = if stderr_path == stdout_path {
+            stdout_file.try_clone()?
+        } else {
+            std::fs::File::create(&stderr_path)?
+        };
 
         let start_time = time::OffsetDateTime::now_utc();
 
-        // Use spawn_blocking or just await process. 
-        // tokio::process::Command is async, so it doesn't block the thread, 
-        // but it does "block" the task. Since we are in `execute_task` which is awaited by `run_loop`,
-        // `run_loop` is suspended. 
-        // BUT, we spawned the heartbeat loop separately using `tokio::spawn`.
-        // So the heartbeat loop WILL continue to run while `run_loop` is suspended here.
-        // This fixes the heartbeat gap!
-
-        let status = tokio::process::Command::new("bash")
-            .arg("-lc")
-            .arg(&spec.command)
-            .current_dir(if Path::new(&spec.cwd).exists() {
-                &spec.cwd
-            } else {
-                "."
-            })
-            .stdout(stdout_file)
-            .stderr(stderr_file)
+        // A lease-wide prefix (nice, taskset, ...) wraps the shell, or the
+        // program itself for a task submitted as argv
+        let settings: models::LeaseSettings =
+            lfs::read_json(self.root.join(models::LEASE_SETTINGS_FILE)).unwrap_or_default();
+        let prefix: Vec<&str> = settings.command_prefix.as_deref().unwrap_or_default().split_whitespace().collect();
+        let argv: Vec<String> = match &spec.argv {
+            Some(argv) if !argv.is_empty() => argv.iter().map(|a| expand_placeholders(a, &spec, &self.node)).collect(),
+            _ => vec!["bash".to_string(), "-lc".to_string(), expand_placeholders(&spec.command, &spec, &self.node)],
+        };
+        let words: Vec<&str> = prefix.iter().copied().chain(argv.iter().map(String::as_str)).collect();
+        let resolved_command = shell_join(words.iter().copied());
+        let mut cmd = tokio::process::Command::new(words[0]);
+        cmd.args(&words[1..]);
+        if self.config.env_inherit != EnvInherit::All {
+            cmd.env_clear();
+        }
+        if self.config.env_inherit == EnvInherit::Safe {
+            cmd.envs(SAFE_ENV_VARS.iter().filter_map(|k| std::env::var_os(k).map(|v| (k, v))));
+        }
+        // With prefixed lines the output goes through the runner on its way
+        // to the logs; otherwise the task writes them itself
+        let log_files = if self.config.prefix_lines {
+            cmd.stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped());
+            Some((stdout_file, stderr_file))
+        } else {
+            cmd.stdout(stdout_file).stderr(stderr_file);
+            None
+        };
+        cmd.process_group(0)
+            .current_dir(&cwd)
             .envs(&spec.env)
-            .status()
-            .await?;
+            .env(models::CHAIN_DEPTH_ENV, spec.chain_depth.to_string());
+        if let Some(r) = &reservation {
+            cmd.env("CUDA_VISIBLE_DEVICES", self.gpu_pool.ids(&r.devices));
+        }
+        if let Some(file) = stdin {
+            cmd.stdin(file);
+        }
+        // A command that can't even start still gets a (failed) result
+        let (status, stop) = match cmd.spawn() {
+            Ok(mut child) => {
+                let _group = child.id().map(TaskGroup::new);
+                let copies = log_files.map(|(out, err)| {
+                    [
+                        prefix_lines(child.stdout.take(), out, &spec.task_id),
+                        prefix_lines(child.stderr.take(), err, &spec.task_id),
+                    ]
+                });
+                let timeout = spec.timeout_s.map(std::time::Duration::from_secs);
+                let waited = self.wait_or_cancel(&mut child, &spec.task_id, timeout).await;
+                // The rest of the output, up to the pipes closing or a
+                // background process holding them past the grace period
+                let deadline = tokio::time::Instant::now() + PIPE_DRAIN_GRACE;
+                for mut copy in copies.into_iter().flatten() {
+                    match tokio::time::timeout_at(deadline, &mut copy).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => warn!("Task {}: failed to copy output: {}", spec.task_id, e),
+                        Err(_) => {
+                            warn!(
+                                "Task {}: output still open {:?} after it exited, held by a process it left running; dropping the rest",
+                                spec.task_id, PIPE_DRAIN_GRACE
+                            );
+                            copy.abort();
+                        }
+                    }
+                }
+                waited
+            }
+            Err(e) => (Err(e), Stop::Exited),
+        };
+        let timed_out = matches!(stop, Stop::TimedOut);
+        let cancel_requests = match stop {
+            Stop::Cancelled(requests) => requests,
+            _ => Vec::new(),
+        };
+        let cancelled = !cancel_requests.is_empty();
+        // Cancelled and timed-out tasks keep the signal they were stopped with
+        let signal = status.as_ref().ok().and_then(|s| s.signal());
+        let (exit_code, failure) = match status {
+            _ if cancelled => (-1, Some(models::FailureReason::Cancelled)),
+            _ if timed_out => (models::TIMEOUT_EXIT_CODE, Some(models::FailureReason::Timeout)),
+            Ok(status) => {
+                info!("Task {} finished with {}", spec.task_id, status);
+                (status.code().unwrap_or(-1), models::FailureReason::classify(status.code(), status.signal()))
+            }
+            Err(e) => {
+                error!("Task {} failed to start: {}", spec.task_id, e);
+                (-1, Some(models::FailureReason::SpawnError))
+            }
+        };
+        if !cancelled {
+            write_capture_rc(&spec, &cwd, exit_code);
+        }
+        let gpus_assigned = match &reservation {
+            Some(r) => self.gpu_pool.ids(&r.devices),
+            None => String::new(),
+        };
+        // Released before the result is published
+        if let Some(r) = reservation {
+            if let Err(e) = r.release().await {
+                warn!("Failed to release GPUs/CPUs of task {}: {}", spec.task_id, e);
+            }
+        }
 
         let end_time = time::OffsetDateTime::now_utc();
         let runtime = (end_time - start_time).as_seconds_f64();
 
-        info!("Task {} finished with {}", spec.task_id, status);
-
-        let gpus_assigned = if spec.gpus > 0 {
-            (0..spec.gpus)
-                .map(|i| i.to_string())
-                .collect::<Vec<_>>()
-                .join(",")
-        } else {
-            String::new()
-        };
-
         let result = models::TaskResult {
             task_id: spec.task_id.clone(),
             idempotency_key: spec.idempotency_key.clone(),
             node: self.node.clone(),
             started_at: start_time,
             finished_at: end_time,
-            exit_code: status.code().unwrap_or(-1),
-            stdout: format!("logs/{}.out", spec.task_id),
-            stderr: format!("logs/{}.err", spec.task_id),
+            exit_code,
+            stdout: stdout_rel,
+            stderr: stderr_rel,
             runtime_s: runtime,
             command: spec.command.clone(),
+            resolved_command,
             cwd: spec.cwd.clone(),
             gpus_requested: spec.gpus,
             gpus_assigned,
-        };
+            checksum: None,
+            failure,
+            signal,
+        }
+        .with_checksum();
 
-        self.executed_keys.lock().await.insert(spec.idempotency_key.clone());
+        // Like a cancelled pending task, a cancelled run is not a finished one.
+        // A cancelled rerun leaves the key to the run before it.
+        if cancelled && first_run {
+            self.executed_keys.lock().await.remove(&spec.idempotency_key);
+        }
 
+        let kind = if cancelled { "cancelled" } else { "result" };
         let original_name = task_path.file_name().unwrap().to_string_lossy();
-        let result_name = if original_name.ends_with(".json") {
-            original_name.replace(".json", ".result.json")
-        } else {
-            format!("{}.result.json", original_name)
+        let result_name = match original_name.strip_suffix(".json") {
+            Some(stem) => format!("{}.{}.json", stem, kind),
+            None => format!("{}.{}.json", original_name, kind),
         };
 
+        // Result first, then the spec: a failure in between leaves the spec
+        // claimed next to its result, which recovery finishes instead of rerunning
         let result_path = done_dir.join(&result_name);
         lfs::atomic_write_json(&result_path, &result)?;
+        if !cancelled {
+            self.index_key(&spec.idempotency_key, &result_name).await;
+        }
+        // Only now: a runner restarting before this still sees the request
+        // and cancels the requeued task. Requests that came too late go too.
+        for request in cancel_requests.iter().chain(&self.check_cancel(&spec.task_id)) {
+            lfs::remove_file_if_exists(request)?;
+        }
+        let archived = archive_spec(task_path, &done_dir);
+
+        // The task ran either way, unless it was cancelled
+        if !cancelled {
+            self.queue_follow_up(&spec, exit_code == 0)?;
+        }
+        archived
+    }
+
+    /// The file to feed the task's stdin, if it has one: its `--stdin-file` or
+    /// the stdout log of its `--stdin-from-task` source. An error says why it
+    /// can't be opened, for the task's stderr log.
+    fn open_stdin(&self, spec: &models::TaskSpec) -> std::result::Result<Option<std::fs::File>, String> {
+        if let Some(input) = &spec.stdin_file {
+            return std::fs::File::open(input)
+                .map(Some)
+                .map_err(|e| format!("cannot open stdin file {}: {}", input, e));
+        }
+        // Its source succeeded, but its log may have been pruned or written elsewhere
+        if let Some(source) = &spec.stdin_from_task {
+            let input = super::logs::log_path(&self.root, source, false);
+            return std::fs::File::open(&input)
+                .map(Some)
+                .map_err(|e| format!("cannot open stdout of task {} ({}): {}", source, input.display(), e));
+        }
+        Ok(None)
+    }
+
+    /// Cancel requests for `task_id` waiting in this node's `control/` dir.
+    fn check_cancel(&self, task_id: &str) -> Vec<PathBuf> {
+        let control_dir = self.root.join("control").join(&self.node);
+        lfs::list_files_sorted(&control_dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| p.file_name().unwrap_or_default().to_string_lossy().starts_with("cancel_"))
+            .filter(|p| lfs::read_json::<CancelCommand, _>(p).is_ok_and(|c| c.task_id == task_id))
+            .collect()
+    }
+
+    /// Wait for the task's process, checking for cancel requests every
+    /// `CANCEL_POLL` and stopping it once `timeout` has passed. Returns the
+    /// exit status and why the wait ended; the caller removes the cancel
+    /// requests acted on once the result is written.
+    async fn wait_or_cancel(
+        &self,
+        child: &mut tokio::process::Child,
+        task_id: &str,
+        timeout: Option<std::time::Duration>,
+    ) -> (std::io::Result<std::process::ExitStatus>, Stop) {
+        let mut poll = tokio::time::interval(CANCEL_POLL);
+        let deadline = async {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                status = child.wait() => return (status, Stop::Exited),
+                _ = &mut deadline => {
+                    warn!("Task {} still running after its timeout of {:?}; stopping it", task_id, timeout.unwrap_or_default());
+                    return (terminate(child, task_id).await, Stop::TimedOut);
+                }
+                _ = poll.tick() => {}
+            }
+            let requests = self.check_cancel(task_id);
+            if requests.is_empty() {
+                continue;
+            }
 
-        let archived_task_path = done_dir.join(task_path.file_name().unwrap());
-        std::fs::rename(task_path, &archived_task_path)?;
+            info!("Cancelling task {}", task_id);
+            return (terminate(child, task_id).await, Stop::Cancelled(requests));
+        }
+    }
 
+    /// Queue the task's `on_success` or `on_failure` command on this node,
+    /// with the task's cwd, env and group, unless the chain is too deep.
+    fn queue_follow_up(&self, spec: &models::TaskSpec, succeeded: bool) -> Result<()> {
+        let follow_up = if succeeded { &spec.on_success } else { &spec.on_failure };
+        let Some(command) = follow_up else {
+            return Ok(());
+        };
+        if spec.chain_depth >= models::MAX_CHAIN_DEPTH {
+            warn!(
+                "Not queueing follow-up of task {}: chain depth {} reached",
+                spec.task_id, models::MAX_CHAIN_DEPTH
+            );
+            return Ok(());
+        }
+
+        let uuid = uuid::Uuid::new_v4();
+        let now = time::OffsetDateTime::now_utc();
+        let unix_micros = (now.unix_timestamp_nanos() / 1000) as u64;
+        let next = models::TaskSpec {
+            task_id: format!("T{}", &uuid.simple().to_string()[..6]),
+            idempotency_key: format!("{}-{}-{}", spec.lease_id, self.node, unix_micros),
+            lease_id: spec.lease_id.clone(),
+            target_node: self.node.clone(),
+            seq: unix_micros,
+            uuid,
+            created_at: now,
+            cwd: spec.cwd.clone(),
+            env: spec.env.clone(),
+            command: command.clone(),
+            batch_id: spec.batch_id.clone(),
+            chain_depth: spec.chain_depth + 1,
+            strict: spec.strict,
+            ..Default::default()
+        };
+        let filename = format!("{:016}_{}_{}.json", unix_micros, next.task_id, uuid);
+        lfs::atomic_write_json(self.root.join("inbox").join(&self.node).join(filename), &next)?;
+        info!(
+            "Task {} {}; queued follow-up {} ({})",
+            spec.task_id,
+            if succeeded { "succeeded" } else { "failed" },
+            next.task_id,
+            next.command
+        );
         Ok(())
     }
 }
 
+/// Zombie recovery for nodes that will never come back.
+///
+/// `recover_zombies` only helps when a runner restarts on the same node. When a
+/// whole allocation is preempted, its claimed tasks are stranded; this moves the
+/// ones submitted with `--requeue-on-preempt` into `target_node`'s inbox of a
+/// fresh lease. Tasks on nodes with a live heartbeat are left alone, and ones
+/// that already have a result are archived rather than run again.
+/// Returns the requeued task IDs.
+pub fn requeue_preempted(
+    old_root: &Path,
+    new_root: &Path,
+    new_lease: &str,
+    target_node: &str,
+) -> Result<Vec<String>> {
+    let inbox_dir = new_root.join("inbox").join(target_node);
+    let mut requeued = Vec::new();
+
+    let now = time::OffsetDateTime::now_utc();
+    for (node, node_dir) in lfs::node_dirs(old_root, "claimed")? {
+        let hb_path = old_root.join("hb").join(format!("{}.json", node));
+        let alive = lfs::read_heartbeat(&hb_path)
+            .map(|hb| now - hb.ts < models::NODE_LIVE_THRESHOLD)
+            .unwrap_or(false);
+        if alive {
+            continue;
+        }
+
+        let done_dir = old_root.join("done").join(&node);
+        for path in lfs::list_files_sorted(&node_dir)? {
+            let mut spec: models::TaskSpec = match lfs::read_json(&path) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Skipping unreadable claimed task {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            // Finished before the node went, like in `recover_zombies`
+            if let Some(result) = finished_result(old_root, &done_dir, &path) {
+                info!("Task {} already has result {:?}. Archiving...", spec.task_id, result);
+                archive_spec(&path, &done_dir)?;
+                gpu::release(old_root, &node, &spec.task_id)?;
+                continue;
+            }
+            if !spec.requeue_on_preempt {
+                continue;
+            }
+
+            info!(
+                "Requeueing preempted task {} from node {} into {}/{}",
+                spec.task_id, node, new_lease, target_node
+            );
+            let task_id = spec.task_id.clone();
+            spec.lease_id = models::LeaseId(new_lease.to_string());
+            spec.target_node = target_node.to_string();
+
+            // Keep the filename so the task keeps its queue position
+            lfs::atomic_write_json(inbox_dir.join(path.file_name().unwrap()), &spec)?;
+            std::fs::remove_file(&path)?;
+            gpu::release(old_root, &node, &task_id)?;
+            requeued.push(task_id);
+        }
+    }
+
+    Ok(requeued)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -385,6 +1448,7 @@ mod tests {
             env: std::collections::HashMap::new(),
             gpus: 0,
             command: "echo test".to_string(),
+            ..Default::default()
         };
         lfs::atomic_write_json(&task_file, &spec)?;
 
@@ -393,10 +1457,17 @@ mod tests {
             _lease_id: "test-lease".to_string(),
             node: node.clone(),
             root: root.clone(),
+            gpu_pool: gpu::GpuPool::default(),
+            cpus_total: 0,
+            config: config::RunConfig::default(),
             executed_keys,
+            errors: Arc::default(),
+            backfill: Arc::default(),
+            running: Arc::default(),
         };
 
-        let claimed_path = runner.poll_and_claim().await?.expect("Should claim task");
+        let (claimed_path, reservation, _) = runner.poll_and_claim().await?.expect("Should claim task");
+        assert!(reservation.is_none());
         assert!(claimed_path.exists());
         assert!(claimed_path.to_str().unwrap().contains("claimed"));
         assert!(!task_file.exists());
@@ -46,4 +46,34 @@ pub async fn run(lease: Option<String>, node: Option<String>) -> Result<()> {
         
         return Err(anyhow::Error::from(err).context("Failed to exec srun"));
     }
+}
+
+/// Run `command` in the foreground with a terminal, as `shell` would start a
+/// shell: directly for a local lease, through `srun --pty` in the allocation
+/// for a Slurm one. Only returns if the exec fails.
+pub fn exec_command(lease_id: &str, node: Option<&str>, command: &[String]) -> Result<()> {
+    let mut cmd = interactive_command(lease_id, node, command);
+    let err = cmd.exec();
+    Err(anyhow::Error::from(err).context(format!("Failed to exec {}", cmd.get_program().to_string_lossy())))
+}
+
+/// The process `exec_command` execs. Several words run as given, like an argv
+/// task; a single one is a shell command line for `bash -lc`.
+pub fn interactive_command(lease_id: &str, node: Option<&str>, command: &[String]) -> Command {
+    let words: Vec<String> = match command {
+        [line] => vec!["bash".to_string(), "-lc".to_string(), line.clone()],
+        words => words.to_vec(),
+    };
+    if lease_id.starts_with("local:") {
+        let mut cmd = Command::new(&words[0]);
+        cmd.args(&words[1..]);
+        return cmd;
+    }
+    let mut cmd = Command::new("srun");
+    cmd.arg("--jobid").arg(lease_id);
+    if let Some(n) = node {
+        cmd.arg("--nodelist").arg(n);
+    }
+    cmd.arg("--pty").args(&words);
+    cmd
 }
\ No newline at end of file
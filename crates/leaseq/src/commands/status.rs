@@ -1,69 +1,147 @@
 use anyhow::Result;
+use crate::commands::OutputFormat;
 use leaseq_core::{fs as lfs, models, config};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
 
-pub async fn run(lease: Option<String>) -> Result<()> {
+/// Everything `status` reports, in the shape `--format json` emits.
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub lease: String,
+    pub root: String,
+    pub comment: Option<String>, // from `lease create --comment`
+    pub metadata: BTreeMap<String, String>,
+    pub nodes: Vec<NodeStatus>,
+    pub running: Vec<TaskEntry>,
+    pub pending: Vec<TaskEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NodeStatus {
+    pub node: String,
+    pub stale: bool,
+    pub cordoned: bool, // kept out of task routing by `node cordon`
+    pub seen_secs_ago: f64,
+    pub running_task_id: Option<String>,
+    pub running_task_ids: Vec<String>, // all of them, with parallel slots
+    pub errors_since_start: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskEntry {
+    pub task_id: String,
+    pub node: String,
+    pub command: String,
+}
+
+pub async fn run(lease: Option<String>, format: OutputFormat) -> Result<()> {
     let lease_id = lease.unwrap_or_else(config::local_lease_id);
     
-    let root = if lease_id.starts_with("local:") {
-        config::runtime_dir().join(&lease_id)
-    } else {
-        config::leaseq_home_dir().join("runs").join(&lease_id)
-    };
-    
-    println!("Lease: {}", lease_id);
-    println!("Root:  {}", root.display());
+    let root = config::lease_root(&lease_id);
+
+    let report = collect(&lease_id, &root)?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Human => print_human(&report),
+    }
+    Ok(())
+}
+
+pub fn collect(lease_id: &str, root: &Path) -> Result<StatusReport> {
+    let mut nodes = Vec::new();
+    for f in lfs::list_files_sorted(root.join("hb")).unwrap_or_default() {
+        if let Ok(hb) = lfs::read_heartbeat(&f) {
+            let age = (time::OffsetDateTime::now_utc() - hb.ts).as_seconds_f64();
+            nodes.push(NodeStatus {
+                cordoned: super::node::is_cordoned(root, &hb.node),
+                running_task_ids: hb.running_tasks(),
+                node: hb.node,
+                stale: age >= models::NODE_LIVE_THRESHOLD.as_seconds_f64(),
+                seen_secs_ago: age,
+                running_task_id: hb.running_task_id,
+                errors_since_start: hb.errors_since_start,
+                last_error: hb.last_error,
+            });
+        }
+    }
+
+    let meta = lfs::read_json::<models::LeaseMeta, _>(root.join(models::LEASE_META_FILE)).ok();
+    Ok(StatusReport {
+        lease: lease_id.to_string(),
+        root: root.display().to_string(),
+        comment: meta.as_ref().and_then(|m| m.comment()).map(String::from),
+        metadata: meta.as_ref().map(|m| m.metadata().clone()).unwrap_or_default(),
+        nodes,
+        running: read_tasks(&root.join("claimed"))?,
+        pending: read_tasks(&root.join("inbox"))?,
+    })
+}
+
+/// Specs under `<dir>/<node>/`, node by node.
+fn read_tasks(dir: &Path) -> Result<Vec<TaskEntry>> {
+    let mut tasks = Vec::new();
+    if !dir.exists() {
+        return Ok(tasks);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.path().is_dir() {
+            let node = entry.file_name().to_string_lossy().into_owned();
+            for task_file in lfs::list_files_sorted(entry.path())? {
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
+                    tasks.push(TaskEntry { task_id: spec.task_id, node: node.clone(), command: spec.command });
+                }
+            }
+        }
+    }
+    Ok(tasks)
+}
+
+fn print_human(report: &StatusReport) {
+    println!("Lease: {}", report.lease);
+    println!("Root:  {}", report.root);
+    if let Some(comment) = &report.comment {
+        println!("Note:  {}", comment);
+    }
+    if !report.metadata.is_empty() {
+        let pairs: Vec<String> = report.metadata.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        println!("Meta:  {}", pairs.join(" "));
+    }
     println!();
 
-    // Read heartbeats
-    let hb_dir = root.join("hb");
-    let hb_files = lfs::list_files_sorted(&hb_dir).unwrap_or_default();
     println!("Nodes:");
-    if hb_files.is_empty() {
+    if report.nodes.is_empty() {
         println!("  (none)");
     }
-    for f in hb_files {
-        if let Ok(hb) = lfs::read_json::<models::Heartbeat, _>(&f) {
-            let age = (time::OffsetDateTime::now_utc() - hb.ts).as_seconds_f64();
-            let status = if age > 60.0 { "STALE" } else { "OK" };
-            println!("  {:<10} {} (seen {:.0}s ago) running={:?}", hb.node, status, age, hb.running_task_id);
+    let colored = crate::color::enabled();
+    for n in &report.nodes {
+        let status = match (n.stale, n.cordoned) {
+            (true, _) => "STALE",
+            (false, true) => "CORDONED",
+            (false, false) => "OK",
+        };
+        let status = crate::color::state(status, status, colored);
+        if n.running_task_ids.len() > 1 {
+            println!("  {:<10} {} (seen {:.0}s ago) running={:?}", n.node, status, n.seen_secs_ago, n.running_task_ids);
+        } else {
+            println!("  {:<10} {} (seen {:.0}s ago) running={:?}", n.node, status, n.seen_secs_ago, n.running_task_id);
+        }
+        if n.errors_since_start > 0 {
+            println!("             {} error(s) since start, last: {}", n.errors_since_start, n.last_error.as_deref().unwrap_or("?"));
         }
     }
     println!();
 
-    // Read claimed (running)
-    let claimed_dir = root.join("claimed");
     println!("Running Tasks:");
-    if claimed_dir.exists() {
-        for entry in std::fs::read_dir(&claimed_dir)? {
-             let entry = entry?;
-             if entry.path().is_dir() {
-                 let node = entry.file_name();
-                 for task_file in lfs::list_files_sorted(entry.path())? {
-                     if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
-                         println!("  {:<10} {:<10} {}", spec.task_id, node.to_string_lossy(), spec.command);
-                     }
-                 }
-             }
-        }
+    for t in &report.running {
+        println!("  {:<10} {:<10} {}", t.task_id, t.node, t.command);
     }
     println!();
 
-    // Read inbox (pending)
-    let inbox_dir = root.join("inbox");
     println!("Pending Tasks:");
-    if inbox_dir.exists() {
-        for entry in std::fs::read_dir(&inbox_dir)? {
-             let entry = entry?;
-             if entry.path().is_dir() {
-                 let node = entry.file_name();
-                 for task_file in lfs::list_files_sorted(entry.path())? {
-                     if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
-                         println!("  {:<10} {:<10} {}", spec.task_id, node.to_string_lossy(), spec.command);
-                     }
-                 }
-             }
-        }
+    for t in &report.pending {
+        println!("  {:<10} {:<10} {}", t.task_id, t.node, t.command);
     }
-
-    Ok(())
 }
@@ -1,52 +1,474 @@
 use anyhow::{Result, Context};
-use leaseq_core::{fs as lfs, models, config};
+use clap::Args;
+use crate::commands::{shell, OutputFormat};
+use leaseq_core::{batch, duration, fs as lfs, models, config};
 use uuid::Uuid;
+use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
+
+/// Per-task options for `leaseq submit`
+#[derive(Args, Debug, Clone, Default)]
+pub struct SubmitOptions {
+    /// Write stdout and stderr into a single combined log (logs/<id>.log)
+    #[arg(long)]
+    pub combine_output: bool,
+
+    /// Write stdout to this file name in the log dir instead of <task_id>.out
+    #[arg(long, value_name = "NAME", value_parser = parse_log_name, conflicts_with = "combine_output")]
+    pub stdout_to: Option<String>,
+
+    /// Write stderr to this file name in the log dir instead of <task_id>.err
+    #[arg(long, value_name = "NAME", value_parser = parse_log_name, conflicts_with = "combine_output")]
+    pub stderr_to: Option<String>,
+
+    /// If the node vanishes (e.g. Slurm preemption), allow `leaseq lease recover`
+    /// to requeue this task into a fresh lease
+    #[arg(long)]
+    pub requeue_on_preempt: bool,
+
+    /// Feed this file to the command's stdin (for inputs too large for the command line)
+    #[arg(long, value_name = "PATH")]
+    pub stdin_file: Option<PathBuf>,
+
+    /// Feed another task's stdout to this one's stdin, once that task has succeeded
+    #[arg(long, value_name = "TASK_ID", conflicts_with = "stdin_file", value_parser = parse_task_id)]
+    pub stdin_from_task: Option<String>,
+
+    /// Copy files matching this glob into snapshots/<task_id>/ at submit time (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    pub working_copy: Vec<String>,
+
+    /// Run the task from its snapshot directory instead of the submit cwd
+    #[arg(long, requires = "working_copy")]
+    pub run_in_snapshot: bool,
+
+    /// Run from the top of the current git repository instead of the current directory
+    #[arg(long)]
+    pub chdir_to_git_root: bool,
+
+    /// Flag the task as POSSIBLY_HUNG if it writes no log output for this long (e.g. 30m)
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub hung_after: Option<time::Duration>,
+
+    /// Terminate the task if it is still running this long after starting, e.g. 2h
+    /// or 90 (seconds); it fails with exit code -124
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub timeout: Option<time::Duration>,
+
+    /// Once the task exits, write its exit code to PATH (relative to the task's
+    /// cwd) for shell-level chaining; `--capture-rc` alone writes .leaseq_rc
+    #[arg(long, value_name = "PATH", num_args = 0..=1, default_missing_value = models::DEFAULT_RC_FILE)]
+    pub capture_rc: Option<String>,
+
+    /// Tag the task with a group ID for `leaseq group status|cancel|wait`
+    #[arg(long, value_name = "ID")]
+    pub group_as: Option<String>,
+
+    /// Run in DIR/<task_id>/ with the logs there, and point DIR/latest at it
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["chdir_to_git_root", "run_in_snapshot"])]
+    pub output_dir: Option<PathBuf>,
+
+    /// Don't start until this task has finished successfully (repeatable)
+    #[arg(long = "after", value_name = "TASK_ID", value_parser = parse_task_id)]
+    pub depends_on: Vec<String>,
+
+    /// Expire the task if no runner has claimed it this long after submitting, e.g. 6h
+    #[arg(long, value_name = "DURATION", value_parser = duration::parse_duration)]
+    pub max_pending_age: Option<time::Duration>,
+
+    /// Claim before tasks of lower priority (default 0; waiting tasks gain priority over time)
+    #[arg(long, value_name = "N", default_value_t = 0, allow_hyphen_values = true)]
+    pub priority_boost: i32,
+
+    /// How to pick a node when --node isn't given
+    #[arg(long, value_enum, default_value_t = NodeOrder::LeastLoaded)]
+    pub node_order: NodeOrder,
+
+    /// GPUs the task needs; it waits until that many of the node's GPUs are free (default 0: CPU task)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub gpus: u32,
+
+    /// Shortcut for N GPUs plus the lease's per-GPU CPUs and memory (`lease set --cpus-per-gpu/--mem-per-gpu`)
+    #[arg(long, value_name = "N", conflicts_with = "gpus")]
+    pub gpu_job: Option<u32>,
+
+    /// Memory the task needs per GPU, e.g. 32G (overrides the lease's for --gpu-job)
+    #[arg(long, value_name = "SIZE", value_parser = leaseq_core::size::parse_mem_mb)]
+    pub mem_per_gpu: Option<u64>,
+
+    /// CPUs the task needs; runners of a node don't start more than fit its cores (default 0: untracked)
+    #[arg(long, visible_alias = "cpus-per-task", value_name = "N", default_value_t = 0)]
+    pub cpus: u32,
+
+    /// Queue this command as a new task if this one succeeds
+    #[arg(long, value_name = "CMD")]
+    pub on_success: Option<String>,
+
+    /// Queue this command as a new task if this one fails
+    #[arg(long, value_name = "CMD")]
+    pub on_failure: Option<String>,
+
+    /// Refuse to submit unless the target node's runner has a fresh heartbeat
+    #[arg(long)]
+    pub detach_check: bool,
+
+    /// Attach a KEY=VALUE label to the task (repeatable)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub labels: Vec<(String, String)>,
+
+    /// Don't report the task's place in the queue after submitting
+    #[arg(long, short)]
+    pub quiet: bool,
+
+    /// Fail instead of falling back: no guessed node without a live runner, no
+    /// dropped env vars (for CI)
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Pass the submitting shell's SLURM_* variables to the task, even to runners
+    /// started with --env-inherit none (otherwise they are not captured)
+    #[arg(long)]
+    pub inherit_slurm_env: bool,
+
+    /// Run the command now with a terminal (like `leaseq shell`, via `srun --pty`
+    /// on a Slurm lease) instead of queueing it; for editors, pagers and REPLs
+    #[arg(long, conflicts_with_all = ["from_csv", "batch"])]
+    pub interactive: bool,
+
+    /// Print the task that would be queued instead of queueing it
+    #[arg(long, alias = "test", conflicts_with = "interactive")]
+    pub dry_run: bool,
+
+    /// How --dry-run prints the task
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human, requires = "dry_run")]
+    pub format: OutputFormat,
+
+    /// Extra environment for the task on top of the submitting shell's
+    #[arg(skip)]
+    pub env: HashMap<String, String>,
+
+    /// Run the task as this program and arguments, without a shell
+    #[arg(skip)]
+    pub argv: Option<Vec<String>>,
+}
+
+/// How a task is routed to one of the lease's live nodes when `--node` isn't given.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NodeOrder {
+    /// The live node with the fewest pending and running tasks, round-robin among equals
+    #[default]
+    LeastLoaded,
+    /// The live node after the one that was given the latest task
+    RoundRobin,
+    /// Any live node, at random
+    Random,
+    /// The first live node by name
+    First,
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(format!("invalid label '{}': expected KEY=VALUE", s)),
+    }
+}
+
+fn parse_task_id(s: &str) -> Result<String, String> {
+    models::validate_task_id(s).map(|_| s.to_string())
+}
+
+fn parse_log_name(s: &str) -> Result<String, String> {
+    models::validate_log_name(s).map(|_| s.to_string())
+}
 
 pub async fn run(command: Vec<String>, lease: Option<String>, node: Option<String>) -> Result<()> {
-    add_task(command.join(" "), lease, node).await
+    run_with_options(command, lease, node, SubmitOptions::default()).await
 }
 
-pub async fn add_task(command: String, lease: Option<String>, node: Option<String>) -> Result<()> {
-    let lease_id = lease.unwrap_or_else(config::local_lease_id);
-    
-    // Resolve root
-    let root = if lease_id.starts_with("local:") {
-        config::runtime_dir().join(&lease_id)
-    } else {
-        config::leaseq_home_dir().join("runs").join(&lease_id)
+pub async fn run_with_options(
+    command: Vec<String>,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: SubmitOptions,
+) -> Result<()> {
+    if opts.interactive {
+        let lease_id = lease.unwrap_or_else(config::local_lease_id);
+        return shell::exec_command(&lease_id, node.as_deref(), &command);
+    }
+    // Several words are run as given; a single one may be a shell
+    // command line (`-- 'make && ./run'`), so it still goes through bash
+    let opts = SubmitOptions { argv: (command.len() > 1).then(|| command.clone()), ..opts };
+    let spec = add_task_with_options(command.join(" "), lease, node, &opts).await?;
+    if opts.dry_run {
+        println!("{}", format_spec(&spec, opts.format)?);
+    } else if !opts.quiet {
+        let lease_id = &spec.lease_id.0;
+        let root = config::lease_root(lease_id);
+        match queue_position(&root, &spec.target_node, &spec.task_id)? {
+            Some(pos) => println!(
+                "Submitted {} to {} (node {}): position {} of {} pending",
+                spec.task_id, lease_id, spec.target_node, pos.position, pos.backlog
+            ),
+            // Already claimed by a runner
+            None => println!("Submitted {} to {} (node {})", spec.task_id, lease_id, spec.target_node),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueuePosition {
+    pub position: usize, // 1-based rank among the node's pending tasks
+    pub backlog: usize,  // pending tasks on the node, this one included
+}
+
+/// Where `task_id` sits in `node`'s inbox, in filename (submit) order.
+/// `None` once it has left the inbox.
+pub fn queue_position(root: &Path, node: &str, task_id: &str) -> Result<Option<QueuePosition>> {
+    let pending = lfs::list_files_sorted(root.join("inbox").join(node))?;
+    // Inbox files are `<seq>_<task_id>_<uuid>.json`
+    let is_task = |p: &PathBuf| {
+        let name = p.file_name().unwrap_or_default().to_string_lossy();
+        name.split_once('_').is_some_and(|(_, rest)| {
+            rest.strip_prefix(task_id).is_some_and(|tail| tail.starts_with('_') || tail == ".json")
+        })
     };
+    let position = pending.iter().position(is_task);
+    Ok(position.map(|i| QueuePosition { position: i + 1, backlog: pending.len() }))
+}
 
-    let target_node = if let Some(n) = node {
-        n
-    } else if lease_id.starts_with("local:") {
-        // Local lease -> local node
-        hostname::get()?.to_string_lossy().into_owned()
-    } else {
-        // Slurm lease -> pick a LIVE node from heartbeats
-        let hb_dir = root.join("hb");
-        let files = lfs::list_files_sorted(&hb_dir).unwrap_or_default();
-        
-        let mut best_node = None;
-        let now = time::OffsetDateTime::now_utc();
-        let threshold = time::Duration::minutes(2);
+/// Render a task the way `--dry-run` shows it. JSON is the exact inbox file.
+pub fn format_spec(spec: &models::TaskSpec, format: OutputFormat) -> Result<String> {
+    if format == OutputFormat::Json {
+        return Ok(serde_json::to_string_pretty(spec)?);
+    }
+    let mut out = vec![
+        format!("task_id:  {}", spec.task_id),
+        format!("lease:    {}", spec.lease_id.0),
+        format!("node:     {}", spec.target_node),
+        format!("cwd:      {}", spec.cwd),
+        format!("command:  {}", spec.command),
+    ];
+    if let Some(argv) = &spec.argv {
+        out.push(format!("argv:     {:?}", argv));
+    }
+    out.extend([
+        format!("gpus:     {}", spec.gpus),
+        format!("cpus:     {}", spec.cpus),
+        format!("env:      {} variable(s)", spec.env.len()),
+    ]);
+    if spec.mem_mb > 0 {
+        out.push(format!("mem:      {}MB", spec.mem_mb));
+    }
+    if let Some(stdin) = &spec.stdin_file {
+        out.push(format!("stdin:    {}", stdin));
+    }
+    if let Some(source) = &spec.stdin_from_task {
+        out.push(format!("stdin:    stdout of {}", source));
+    }
+    if let Some(dir) = &spec.snapshot_dir {
+        out.push(format!("snapshot: {}", dir));
+    }
+    if !spec.depends_on.is_empty() {
+        out.push(format!("after:    {}", spec.depends_on.join(", ")));
+    }
+    if !spec.labels.is_empty() {
+        let labels: Vec<String> = spec.labels.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        out.push(format!("labels:   {}", labels.join(" ")));
+    }
+    if spec.priority != 0 {
+        out.push(format!("priority: {}", spec.priority));
+    }
+    if let Some(cmd) = &spec.on_success {
+        out.push(format!("on ok:    {}", cmd));
+    }
+    if let Some(cmd) = &spec.on_failure {
+        out.push(format!("on fail:  {}", cmd));
+    }
+    if spec.strict {
+        out.push("strict:   yes".to_string());
+    }
+    if let Some(s) = spec.max_pending_s {
+        out.push(format!("expires:  if unclaimed after {}s", s));
+    }
+    if let Some(s) = spec.timeout_s {
+        out.push(format!("timeout:  {}s", s));
+    }
+    if spec.allow_rerun {
+        out.push("rerun:    yes".to_string());
+    }
+    if let Some(path) = &spec.capture_rc {
+        out.push(format!("rc file:  {}", path));
+    }
+    if let Some(name) = &spec.stdout_to {
+        out.push(format!("stdout:   {}", name));
+    }
+    if let Some(name) = &spec.stderr_to {
+        out.push(format!("stderr:   {}", name));
+    }
+    Ok(out.join("\n"))
+}
 
-        for f in files {
-            if let Ok(hb) = lfs::read_json::<models::Heartbeat, _>(&f) {
-                if (now - hb.ts) < threshold {
-                    best_node = Some(hb.node);
-                    break;
-                }
-            }
+/// Submit one task per row of a CSV sweep (see `batch::parse_csv_batch`).
+pub async fn run_csv(
+    file: PathBuf,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: SubmitOptions,
+) -> Result<()> {
+    let specs = submit_csv(&file, lease, node, &opts).await?;
+    if opts.dry_run {
+        for spec in &specs {
+            println!("{}\n", format_spec(spec, opts.format)?);
         }
+        println!("Would submit {} task(s) from {}", specs.len(), file.display());
+        return Ok(());
+    }
+    println!("Submitted {} task(s) from {}", specs.len(), file.display());
+    Ok(())
+}
 
-        if let Some(n) = best_node {
-            n
-        } else {
-            return Err(anyhow::anyhow!("No active nodes found for lease {} (checked {} heartbeats). Please specify --node or ensure runners are active.", lease_id, root.join("hb").display()));
+pub async fn submit_csv(
+    file: &Path,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: &SubmitOptions,
+) -> Result<Vec<models::TaskSpec>> {
+    let content = std::fs::read_to_string(file)
+        .context(format!("Failed to read {}", file.display()))?;
+    let parsed = batch::parse_csv_batch(&content)
+        .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+
+    // Check every row before submitting any
+    let issues = batch::validate_batch(&parsed, None);
+    if let Some(issue) = issues.first() {
+        return Err(anyhow::anyhow!("{}: {}", file.display(), issue));
+    }
+
+    let mut specs = Vec::new();
+    let mut picker = NodePicker::default();
+    for task in parsed.tasks {
+        let mut row_opts = opts.clone();
+        row_opts.labels.extend(task.labels);
+        row_opts.env.extend(task.env);
+        specs.push(add_task_with_picker(task.command, lease.clone(), node.clone(), &row_opts, &mut picker).await?);
+    }
+    Ok(specs)
+}
+
+/// Submit one task per line of `file` (`-` for stdin); see `add_batch`.
+pub async fn run_batch(
+    file: PathBuf,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: SubmitOptions,
+) -> Result<()> {
+    let source = if file == Path::new("-") { "stdin".to_string() } else { file.display().to_string() };
+    let content = if file == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read commands from stdin")?
+    } else {
+        std::fs::read_to_string(&file).context(format!("Failed to read {}", file.display()))?
+    };
+    let specs = add_batch(batch::parse_command_lines(&content), lease, node, &opts).await?;
+    if opts.dry_run {
+        for spec in &specs {
+            println!("{}\n", format_spec(spec, opts.format)?);
         }
+        println!("Would submit {} task(s) from {}", specs.len(), source);
+        return Ok(());
+    }
+    println!("Submitted {} task(s) from {}", specs.len(), source);
+    Ok(())
+}
+
+/// Submit each command as its own task with the same options. Every command
+/// is checked before any is queued; nodes are picked per task, so the
+/// batch spreads over the lease like separate submits would.
+pub async fn add_batch(
+    lines: Vec<String>,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: &SubmitOptions,
+) -> Result<Vec<models::TaskSpec>> {
+    if lines.is_empty() {
+        return Err(anyhow::anyhow!("No commands to submit (blank lines and # comments are skipped)"));
+    }
+    let max = config::max_command_len();
+    for (i, command) in lines.iter().enumerate() {
+        check_command_len(command, max).context(format!("Command #{} of the batch", i + 1))?;
+    }
+
+    let mut specs = Vec::with_capacity(lines.len());
+    let mut picker = NodePicker::default();
+    for command in lines {
+        specs.push(add_task_with_picker(command, lease.clone(), node.clone(), opts, &mut picker).await?);
+    }
+    Ok(specs)
+}
+
+/// Same as `run` for a command that is already one string (the TUI's "Add task").
+pub async fn add_task(command: String, lease: Option<String>, node: Option<String>) -> Result<()> {
+    add_task_with_options(command, lease, node, &SubmitOptions::default()).await?;
+    Ok(())
+}
+
+pub async fn add_task_with_options(
+    command: String,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: &SubmitOptions,
+) -> Result<models::TaskSpec> {
+    add_task_with_picker(command, lease, node, opts, &mut NodePicker::default()).await
+}
+
+/// One task of a submission of several, whose nodes are all picked by `picker`.
+async fn add_task_with_picker(
+    command: String,
+    lease: Option<String>,
+    node: Option<String>,
+    opts: &SubmitOptions,
+    picker: &mut NodePicker,
+) -> Result<models::TaskSpec> {
+    check_command_len(&command, config::max_command_len())?;
+    if let Some(program) = interactive_program(&command, opts.stdin_file.is_some() || opts.stdin_from_task.is_some()) {
+        eprintln!(
+            "Warning: {} expects a terminal, but queued tasks get none and it may hang; use --interactive to run it with one",
+            program
+        );
+    }
+    if opts.stdout_to.is_some() && opts.stdout_to == opts.stderr_to {
+        return Err(anyhow::anyhow!("--stdout-to and --stderr-to name the same file; use --combine-output"));
+    }
+
+    let stdin_file = match &opts.stdin_file {
+        Some(p) => Some(
+            std::fs::canonicalize(p)
+                .context(format!("Cannot read --stdin-file {}", p.display()))?
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        None => None,
     };
 
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+    
+    // Resolve root
+    let root = config::lease_root(&lease_id);
+
+    let explicit_node = node.is_some();
+    let target_node = picker.pick(&root, &lease_id, node, opts.node_order)?;
+    // A local lease falls back to this host whether or not anything runs there
+    if opts.strict && !explicit_node && lease_id.starts_with("local:") {
+        check_node_live(&root, &lease_id, &target_node).context(format!(
+            "--strict: no node could be resolved for lease {} (this host has no live runner; pass --node)",
+            lease_id
+        ))?;
+    }
+    if opts.detach_check {
+        check_node_live(&root, &lease_id, &target_node)?;
+    }
+
     // Create TaskSpec
     let task_uuid = Uuid::new_v4();
     let now = time::OffsetDateTime::now_utc();
@@ -54,6 +476,41 @@ pub async fn add_task(command: String, lease: Option<String>, node: Option<Strin
     
     let task_id = format!("T{}", &task_uuid.simple().to_string()[..6]);
     
+    let cwd = env::current_dir()?;
+    // A dry run resolves everything but creates nothing
+    let output_dir = match &opts.output_dir {
+        Some(base) if opts.dry_run => Some(cwd.join(base).join(&task_id)),
+        Some(base) => Some(prepare_output_dir(&cwd.join(base), &task_id)?),
+        None => None,
+    };
+    let task_cwd = if let Some(dir) = &output_dir {
+        dir.clone()
+    } else if opts.chdir_to_git_root {
+        git_toplevel(&cwd)?
+    } else {
+        cwd.clone()
+    };
+    let snapshot_dir = if opts.working_copy.is_empty() {
+        None
+    } else {
+        let rel = format!("snapshots/{}", task_id);
+        if !opts.dry_run {
+            snapshot_files(&opts.working_copy, &cwd, &root.join(&rel))?;
+        }
+        Some(rel)
+    };
+
+    let settings: models::LeaseSettings = lfs::read_json(root.join(models::LEASE_SETTINGS_FILE)).unwrap_or_default();
+    let (gpus, cpus, mem_mb) = resources(opts, &settings);
+
+    // Piping from a task means waiting for it
+    let mut depends_on = opts.depends_on.clone();
+    if let Some(source) = &opts.stdin_from_task {
+        if !depends_on.contains(source) {
+            depends_on.push(source.clone());
+        }
+    }
+
     let spec = models::TaskSpec {
         task_id: task_id.clone(),
         idempotency_key: format!("{}-{}-{}", lease_id, target_node, unix_micros),
@@ -62,17 +519,413 @@ pub async fn add_task(command: String, lease: Option<String>, node: Option<Strin
         seq: unix_micros, 
         uuid: task_uuid,
         created_at: now,
-        cwd: env::current_dir()?.to_string_lossy().into_owned(),
-        env: env::vars().collect(),
-        gpus: 0,
+        cwd: task_cwd.to_string_lossy().into_owned(),
+        env: capture_env(opts.strict, opts.inherit_slurm_env)?.into_iter().chain(opts.env.clone()).collect(),
+        gpus,
+        cpus,
+        mem_mb,
         command: command.clone(),
+        combine_output: opts.combine_output,
+        requeue_on_preempt: opts.requeue_on_preempt,
+        stdin_file,
+        snapshot_dir,
+        run_in_snapshot: opts.run_in_snapshot,
+        no_output_s: opts.hung_after.map(|d| d.whole_seconds().max(0) as u64),
+        batch_id: opts.group_as.clone(),
+        output_dir: output_dir.map(|d| d.to_string_lossy().into_owned()),
+        labels: opts.labels.iter().cloned().collect(),
+        depends_on,
+        stdin_from_task: opts.stdin_from_task.clone(),
+        priority: opts.priority_boost,
+        on_success: opts.on_success.clone(),
+        on_failure: opts.on_failure.clone(),
+        strict: opts.strict,
+        max_pending_s: opts.max_pending_age.map(|d| d.whole_seconds().max(0) as u64),
+        stdout_to: opts.stdout_to.clone(),
+        stderr_to: opts.stderr_to.clone(),
+        timeout_s: opts.timeout.map(|d| d.whole_seconds().max(0) as u64),
+        allow_rerun: false,
+        capture_rc: opts.capture_rc.clone(),
+        argv: opts.argv.clone(),
+        retry_of: None,
+        // Submitted from inside a task: one step further down its chain
+        chain_depth: env::var(models::CHAIN_DEPTH_ENV)
+            .ok()
+            .and_then(|d| d.parse::<u32>().ok())
+            .map_or(0, |d| d + 1),
     };
 
+    if opts.dry_run {
+        return Ok(spec);
+    }
+    check_dependencies(&root, &spec.depends_on)?;
+
     let filename = format!("{:016}_{}_{}.json", unix_micros, task_id, task_uuid);
     let inbox_path = root.join("inbox").join(&target_node).join(filename);
 
     lfs::atomic_write_json(&inbox_path, &spec).context("Failed to write task")?;
     
     // println!("Submitted task {} to lease {} node {}", task_id, lease_id, target_node);
+    Ok(spec)
+}
+
+/// Copy files matching `patterns` (relative to `base`) into `dest`, keeping
+/// their layout below `base`. Every pattern must match at least one file.
+pub fn snapshot_files(patterns: &[String], base: &Path, dest: &Path) -> Result<Vec<PathBuf>> {
+    let mut copied = Vec::new();
+    for pattern in patterns {
+        let full = if Path::new(pattern).is_absolute() {
+            pattern.clone()
+        } else {
+            // Escape the base so brackets etc. in the cwd aren't treated as glob syntax
+            format!("{}/{}", glob::Pattern::escape(&base.to_string_lossy()), pattern)
+        };
+
+        let mut matched = false;
+        for entry in glob::glob(&full).context(format!("Invalid --working-copy pattern '{}'", pattern))? {
+            let path = entry?;
+            if !path.is_file() {
+                continue;
+            }
+            matched = true;
+
+            // Files outside the submit dir keep just their name
+            let rel = match path.strip_prefix(base) {
+                Ok(rel) => rel.to_path_buf(),
+                Err(_) => PathBuf::from(path.file_name().unwrap_or_default()),
+            };
+            let target = dest.join(&rel);
+            if let Some(parent) = target.parent() {
+                lfs::ensure_dir(parent)?;
+            }
+            std::fs::copy(&path, &target)
+                .context(format!("Failed to snapshot {}", path.display()))?;
+            copied.push(target);
+        }
+
+        if !matched {
+            return Err(anyhow::anyhow!("--working-copy '{}' matched no files", pattern));
+        }
+    }
+    Ok(copied)
+}
+
+/// Create `base/<task_id>/` and repoint `base/latest` at it.
+pub fn prepare_output_dir(base: &Path, task_id: &str) -> Result<PathBuf> {
+    let dir = base.join(task_id);
+    lfs::ensure_dir(&dir).context(format!("Cannot create --output-dir {}", dir.display()))?;
+    let dir = std::fs::canonicalize(&dir)?;
+
+    // Swap the link in with a rename so readers never see it missing
+    #[cfg(unix)]
+    {
+        let tmp = base.join(format!(".latest.{}", Uuid::new_v4().simple()));
+        std::os::unix::fs::symlink(task_id, &tmp)?;
+        std::fs::rename(&tmp, base.join("latest"))?;
+    }
+    Ok(dir)
+}
+
+/// Top-level directory of the git repository containing `dir`.
+pub fn git_toplevel(dir: &Path) -> Result<PathBuf> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .context("Failed to run git (needed for --chdir-to-git-root)")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "--chdir-to-git-root: {} is not inside a git repository",
+            dir.display()
+        ));
+    }
+    Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/// Reject commands over `max` bytes; huge inputs belong in a file, not the spec.
+pub fn check_command_len(command: &str, max: usize) -> Result<()> {
+    if command.len() > max {
+        return Err(anyhow::anyhow!(
+            "Command is {} bytes, over the {} byte limit. Put large inputs in a file and pass it with --stdin-file <PATH> (or raise LEASEQ_MAX_COMMAND_LEN).",
+            command.len(),
+            max
+        ));
+    }
     Ok(())
 }
+
+/// Programs that need a terminal whatever their arguments: editors, pagers
+/// and full-screen tools.
+const TTY_PROGRAMS: &[&str] = &[
+    "vi", "vim", "nvim", "nano", "emacs", "less", "more", "man", "top", "htop", "nvtop", "tmux", "screen",
+];
+
+/// Programs that only read a terminal when started without arguments, i.e. as a REPL.
+const REPL_PROGRAMS: &[&str] = &[
+    "bash", "sh", "zsh", "python", "python3", "ipython", "R", "julia", "node", "irb", "sqlite3",
+];
+
+/// The program in `command` that will wait on a terminal, if any. Only the
+/// first program of the line is checked, after `VAR=value` assignments and
+/// `env`/`sudo`-style wrappers. A bare REPL isn't flagged when it gets its
+/// input elsewhere: `has_stdin` or a `<` redirect.
+pub fn interactive_program(command: &str, has_stdin: bool) -> Option<String> {
+    let mut words = command.split_whitespace().skip_while(|w| {
+        w.contains('=') && !w.starts_with('=') || matches!(*w, "env" | "sudo" | "exec" | "nohup" | "time")
+    });
+    let program = words.next()?;
+    let name = program.rsplit('/').next().unwrap_or(program);
+    let bare = words.next().is_none_or(|w| w.starts_with([';', '&']));
+    if TTY_PROGRAMS.contains(&name) || (bare && !has_stdin && REPL_PROGRAMS.contains(&name)) {
+        Some(name.to_string())
+    } else {
+        None
+    }
+}
+
+/// Pick the node a task should go to: the explicit `--node`, this host for a
+/// local lease, or one of the nodes with a fresh heartbeat for a Slurm lease,
+/// chosen by `order`. Submissions of many tasks use one `NodePicker` instead.
+pub fn resolve_target_node(root: &Path, lease_id: &str, node: Option<String>, order: NodeOrder) -> Result<String> {
+    NodePicker::default().pick(root, lease_id, node, order)
+}
+
+/// Node picking across the tasks of one submission. The node given the
+/// latest task and the nodes' queue lengths are read from disk once, then
+/// advanced in memory as tasks are assigned, so queueing N tasks doesn't
+/// rescan the whole queue N times.
+#[derive(Debug, Default)]
+pub struct NodePicker {
+    /// The node given the latest task; `None` until read from disk
+    latest: Option<Option<String>>,
+    /// Pending and running tasks of each node looked at so far
+    loads: HashMap<String, usize>,
+}
+
+impl NodePicker {
+    /// Like `resolve_target_node`, counting the picked node as given a task.
+    pub fn pick(&mut self, root: &Path, lease_id: &str, node: Option<String>, order: NodeOrder) -> Result<String> {
+        let picked = self.resolve(root, lease_id, node, order)?;
+        self.latest = Some(Some(picked.clone()));
+        if let Some(load) = self.loads.get_mut(&picked) {
+            *load += 1;
+        }
+        Ok(picked)
+    }
+
+    fn load(&mut self, root: &Path, node: &str) -> usize {
+        *self
+            .loads
+            .entry(node.to_string())
+            .or_insert_with(|| ["inbox", "claimed"].iter().map(|d| task_count(&root.join(d).join(node))).sum())
+    }
+
+    /// Of `candidates` (sorted indices into `live`), the one after the node
+    /// given the latest task, wrapping around.
+    fn next_after_latest(&mut self, root: &Path, live: &[String], candidates: &[usize]) -> usize {
+        match self.latest.get_or_insert_with(|| latest_node(root)) {
+            Some(last) => candidates.iter().copied().find(|i| live[*i] > *last).unwrap_or(candidates[0]),
+            None => candidates[0],
+        }
+    }
+
+    fn resolve(&mut self, root: &Path, lease_id: &str, node: Option<String>, order: NodeOrder) -> Result<String> {
+        if let Some(n) = node {
+            return Ok(n);
+        }
+        if lease_id.starts_with("local:") {
+            // Local lease -> local node
+            let host = hostname::get()?.to_string_lossy().into_owned();
+            if super::node::is_cordoned(root, &host) {
+                return Err(anyhow::anyhow!("Node {} of lease {} is cordoned; specify --node to submit anyway", host, lease_id));
+            }
+            return Ok(host);
+        }
+
+        // Slurm lease -> pick a LIVE node from heartbeats
+        let hb_dir = root.join("hb");
+        let files = lfs::list_files_sorted(&hb_dir).unwrap_or_default();
+
+        let now = time::OffsetDateTime::now_utc();
+        let mut live = Vec::new();
+        let mut stale = Vec::new();
+        let mut cordoned = Vec::new();
+
+        for f in &files {
+            if let Ok(hb) = lfs::read_heartbeat(f) {
+                let age = now - hb.ts;
+                if super::node::is_cordoned(root, &hb.node) {
+                    cordoned.push(hb.node);
+                } else if age < models::NODE_LIVE_THRESHOLD {
+                    live.push(hb.node);
+                } else {
+                    stale.push(format!("{} ({}s ago)", hb.node, age.whole_seconds()));
+                }
+            }
+        }
+
+        if files.is_empty() && !root.join(models::LEASE_META_FILE).exists() {
+            // Nothing on disk yet: typically a job still waiting in the Slurm queue
+            return Err(anyhow::anyhow!(
+                "Lease {} has no running nodes yet; wait for it to start or specify --node",
+                lease_id
+            ));
+        }
+        if live.is_empty() {
+            let mut seen = Vec::new();
+            if !stale.is_empty() {
+                seen.push(format!("stale: {}", stale.join(", ")));
+            }
+            if !cordoned.is_empty() {
+                seen.push(format!("cordoned: {}", cordoned.join(", ")));
+            }
+            if seen.is_empty() {
+                seen.push(format!("no heartbeats in {}", hb_dir.display()));
+            }
+            let seen = seen.join("; ");
+            return Err(anyhow::anyhow!("No active nodes found for lease {} ({}). Please specify --node or ensure runners are active.", lease_id, seen));
+        }
+        live.sort();
+
+        let pick = match order {
+            NodeOrder::First => 0,
+            NodeOrder::Random => (Uuid::new_v4().as_u128() % live.len() as u128) as usize,
+            NodeOrder::LeastLoaded => {
+                let loads: Vec<usize> = live.iter().map(|n| self.load(root, n)).collect();
+                let min = loads.iter().copied().min().unwrap_or(0);
+                let idlest: Vec<usize> = (0..live.len()).filter(|i| loads[*i] == min).collect();
+                self.next_after_latest(root, &live, &idlest)
+            }
+            NodeOrder::RoundRobin => self.next_after_latest(root, &live, &(0..live.len()).collect::<Vec<_>>()),
+        };
+        Ok(live.swap_remove(pick))
+    }
+}
+
+/// GPUs, CPUs and memory (MB) of a task. `--gpu-job N` bundles N GPUs with
+/// the lease's per-GPU CPUs and memory; explicit `--cpus` and `--mem-per-gpu`
+/// win over the lease's.
+pub fn resources(opts: &SubmitOptions, settings: &models::LeaseSettings) -> (u32, u32, u64) {
+    let gpus = opts.gpu_job.unwrap_or(opts.gpus);
+    let bundle = opts.gpu_job.is_some();
+    let cpus = match settings.cpus_per_gpu {
+        Some(per_gpu) if bundle && opts.cpus == 0 => gpus * per_gpu,
+        _ => opts.cpus,
+    };
+    let mem_per_gpu = opts.mem_per_gpu.or(settings.mem_per_gpu_mb.filter(|_| bundle));
+    (gpus, cpus, gpus as u64 * mem_per_gpu.unwrap_or(0))
+}
+
+fn task_count(dir: &Path) -> usize {
+    lfs::list_files_sorted(dir).map(|f| f.len()).unwrap_or(0)
+}
+
+/// The node holding the most recently submitted task, pending, running or
+/// finished. Task files start with their zero-padded submit time, so the
+/// greatest filename is the latest one; results share their spec's prefix.
+fn latest_node(root: &Path) -> Option<String> {
+    let mut latest: Option<(String, String)> = None;
+    for state in ["inbox", "claimed", "done"] {
+        let Ok(nodes) = std::fs::read_dir(root.join(state)) else {
+            continue;
+        };
+        for node in nodes.flatten() {
+            let node_name = node.file_name().to_string_lossy().into_owned();
+            let newest = lfs::list_files_sorted(node.path()).unwrap_or_default().pop();
+            if let Some(f) = newest {
+                let name = f.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                if latest.as_ref().is_none_or(|(l, _)| name > *l) {
+                    latest = Some((name, node_name));
+                }
+            }
+        }
+    }
+    latest.map(|(_, node)| node)
+}
+
+/// Prefixes of the scheduler variables describing the submitting shell's
+/// allocation, which are only captured with `--inherit-slurm-env`.
+const SLURM_ENV_PREFIXES: &[&str] = &["SLURM_", "SLURMD_", "SBATCH_", "SALLOC_", "SRUN_"];
+
+/// The submitting shell's environment. Variables that aren't valid UTF-8
+/// can't be stored in the spec and are left out, or refused under `--strict`.
+/// Slurm's variables are left out unless `inherit_slurm`: by default a task
+/// sees those of the allocation its runner is in.
+fn capture_env(strict: bool, inherit_slurm: bool) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (key, value) in env::vars_os() {
+        match (key.into_string(), value.into_string()) {
+            (Ok(key), _) if !inherit_slurm && SLURM_ENV_PREFIXES.iter().any(|p| key.starts_with(p)) => {}
+            (Ok(key), Ok(value)) => vars.push((key, value)),
+            (key, _) => {
+                let key = key.unwrap_or_else(|k| k.to_string_lossy().into_owned());
+                if strict {
+                    return Err(anyhow::anyhow!("--strict: environment variable {} is not valid UTF-8", key));
+                }
+                eprintln!("Warning: not passing environment variable {} (not valid UTF-8)", key);
+            }
+        }
+    }
+    Ok(vars)
+}
+
+/// Error unless every task in `ids` is queued, running or finished in the
+/// lease at `root`; a dependency that never finishes would hold its
+/// dependents in the inbox forever.
+pub fn check_dependencies(root: &Path, ids: &[String]) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    // Queued and running specs, and the specs and results in done/
+    #[derive(serde::Deserialize)]
+    struct Named {
+        task_id: String,
+    }
+    let mut known = std::collections::HashSet::new();
+    for dir in ["inbox", "claimed", "done"] {
+        for (_, node_dir) in lfs::node_dirs(root, dir)? {
+            for file in lfs::list_files_sorted(&node_dir)? {
+                if let Ok(named) = lfs::read_json::<Named, _>(&file) {
+                    known.insert(named.task_id);
+                }
+            }
+        }
+    }
+    match ids.iter().find(|id| !known.contains(*id)) {
+        Some(id) => Err(anyhow::anyhow!("Cannot wait for task {}: no such task in this lease", id)),
+        None => Ok(()),
+    }
+}
+
+/// True if `node` has a heartbeat newer than the staleness threshold.
+pub fn node_is_live(root: &Path, node: &str) -> bool {
+    lfs::read_heartbeat(root.join("hb").join(format!("{}.json", node)))
+        .is_ok_and(|hb| time::OffsetDateTime::now_utc() - hb.ts < models::NODE_LIVE_THRESHOLD)
+}
+
+/// Error unless `node` has a heartbeat newer than the staleness threshold.
+pub fn check_node_live(root: &Path, lease_id: &str, node: &str) -> Result<()> {
+    let hb_path = root.join("hb").join(format!("{}.json", node));
+    match lfs::read_heartbeat(&hb_path) {
+        Ok(hb) => {
+            let age = time::OffsetDateTime::now_utc() - hb.ts;
+            if age < models::NODE_LIVE_THRESHOLD {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "Runner for node {} on lease {} is not live (last heartbeat {}s ago)",
+                    node,
+                    lease_id,
+                    age.whole_seconds()
+                ))
+            }
+        }
+        Err(_) => Err(anyhow::anyhow!(
+            "No runner heartbeat for node {} on lease {} (start one with `leaseq run` or `leaseq daemon start`)",
+            node,
+            lease_id
+        )),
+    }
+}
@@ -1,6 +1,12 @@
 use anyhow::Result;
-use leaseq_core::{config, fs as lfs, models};
-use std::collections::HashMap;
+use clap::Args;
+use crate::color;
+use crate::commands::logs;
+use leaseq_core::{config, duration, fs as lfs, models, quarantine};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum TaskStateFilter {
@@ -26,94 +32,454 @@ impl TaskStateFilter {
     }
 }
 
+/// Extra filters and display options for `leaseq tasks`
+#[derive(Args, Debug, Clone, Default)]
+pub struct TaskListOptions {
+    /// Only tasks older than this (e.g. 1d, 12h); uses finish time for done tasks, submit time otherwise
+    #[arg(long, value_parser = duration::parse_duration)]
+    pub older_than: Option<time::Duration>,
+
+    /// Only tasks newer than this (e.g. 1h, 30m)
+    #[arg(long, value_parser = duration::parse_duration)]
+    pub newer_than: Option<time::Duration>,
+
+    /// Print only the task rows (no lease line, header or totals)
+    #[arg(long)]
+    pub concise: bool,
+
+    /// Show tasks as a tree of `--after` dependencies
+    #[arg(long)]
+    pub tree: bool,
+
+    /// Group failed tasks by failure reason (OOM, NON_ZERO_EXIT, ...)
+    #[arg(long, conflicts_with = "tree")]
+    pub by_reason: bool,
+
+    /// Add a LOGS column with the size of each task's stdout+stderr logs
+    #[arg(long)]
+    pub include_logs_size: bool,
+
+    /// Print each task as this template instead of a table, e.g. '{id}\t{state}\t{runtime}'
+    /// (fields: {id} {state} {node} {runtime} {command}; {{ and }} for literal braces)
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_template, conflicts_with_all = ["tree", "by_reason"])]
+    pub output_template: Option<String>,
+}
+
+impl TaskListOptions {
+    /// Whether `ts` falls inside the requested age window.
+    pub fn in_window(&self, ts: OffsetDateTime, now: OffsetDateTime) -> bool {
+        let age = now - ts;
+        if let Some(min) = self.older_than {
+            if age < min {
+                return false;
+            }
+        }
+        if let Some(max) = self.newer_than {
+            if age > max {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// One line of `leaseq tasks` output
+#[derive(Debug, Clone)]
+pub struct TaskRow {
+    pub task_id: String,
+    pub state: &'static str,
+    pub node: String,
+    pub command: String,
+    pub ts: OffsetDateTime, // finished_at for done tasks, created_at otherwise
+    pub depends_on: Vec<String>,
+    pub failure: Option<models::FailureReason>,
+    pub logs_bytes: Option<u64>, // stdout+stderr on disk, with --include-logs-size
+    pub runtime_s: Option<f64>, // finished tasks only
+    pub signal: Option<i32>, // the signal that killed a finished task
+}
+
 pub async fn run(
     lease: Option<String>,
     state: Option<String>,
     node: Option<String>,
     search: Option<String>,
+) -> Result<()> {
+    run_with_options(lease, state, node, search, TaskListOptions::default()).await
+}
+
+pub async fn run_with_options(
+    lease: Option<String>,
+    state: Option<String>,
+    node: Option<String>,
+    search: Option<String>,
+    opts: TaskListOptions,
 ) -> Result<()> {
     let lease_id = lease.unwrap_or_else(config::local_lease_id);
 
-    let root = if lease_id.starts_with("local:") {
-        config::runtime_dir().join(&lease_id)
-    } else {
-        config::leaseq_home_dir().join("runs").join(&lease_id)
-    };
+    let root = config::lease_root(&lease_id);
 
     let state_filter = state
         .as_ref()
         .and_then(|s| TaskStateFilter::from_str(s))
         .unwrap_or(TaskStateFilter::All);
 
+    let rows = collect_tasks(
+        &root,
+        state_filter,
+        node.as_deref(),
+        search.as_deref(),
+        &opts,
+        OffsetDateTime::now_utc(),
+    )?;
+
+    if let Some(template) = &opts.output_template {
+        for row in &rows {
+            println!("{}", render_template(template, row));
+        }
+        return Ok(());
+    }
+
+    // Size columns to the data on a terminal; keep stable fixed widths when piped
+    let layout = if std::io::stdout().is_terminal() {
+        let cols = crossterm::terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
+        TableLayout::Fit(cols)
+    } else {
+        TableLayout::Fixed
+    };
+    let lines = if opts.tree {
+        let mut lines = vec!["TASK STATE COMMAND".to_string(), "-".repeat(60)];
+        lines.extend(render_tree(&rows));
+        lines
+    } else if opts.by_reason {
+        let mut lines = vec!["REASON TASKS".to_string(), "-".repeat(60)];
+        lines.extend(render_by_reason(&rows));
+        lines
+    } else {
+        render_table(&rows, layout)
+    };
+
+    if opts.concise {
+        for line in lines.iter().skip(2) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    println!("Lease: {}", lease_id);
+    for line in &lines {
+        println!("{}", line);
+    }
+    println!("{}", lines[1]);
+    println!("Total: {} tasks", rows.len());
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableLayout {
+    /// Classic fixed column widths, command cut at 40 characters
+    Fixed,
+    /// Columns sized to their contents within this many terminal columns
+    Fit(usize),
+}
+
+/// Header, separator and one line per row.
+pub fn render_table(rows: &[TaskRow], layout: TableLayout) -> Vec<String> {
+    // The LOGS column only appears when sizes were collected
+    let sizes = rows.iter().any(|r| r.logs_bytes.is_some());
+    let logs_cell = |r: &TaskRow| r.logs_bytes.map(format_bytes).unwrap_or_else(|| "-".to_string());
+    let (id_w, state_w, node_w, logs_w, cmd_w) = match layout {
+        TableLayout::Fixed => (10, 10, 12, if sizes { 7 } else { 0 }, 40),
+        TableLayout::Fit(total) => {
+            let width = |header: &str, f: &dyn Fn(&TaskRow) -> usize| {
+                rows.iter().map(f).max().unwrap_or(0).max(header.len())
+            };
+            let id_w = width("TASK", &|r| r.task_id.chars().count());
+            let state_w = width("STATE", &|r| r.state.len());
+            let node_w = width("NODE", &|r| r.node.chars().count());
+            let logs_w = if sizes { width("LOGS", &|r| logs_cell(r).len()) } else { 0 };
+            // Whatever is left goes to the command, but never squeeze it to nothing
+            let used = id_w + state_w + node_w + 3 + if sizes { logs_w + 1 } else { 0 };
+            let cmd_w = total.saturating_sub(used).max(20);
+            (id_w, state_w, node_w, logs_w, cmd_w)
+        }
+    };
+    let logs = |cell: &str| if sizes { format!("{:>logs_w$} ", cell) } else { String::new() };
+
+    let mut lines = Vec::with_capacity(rows.len() + 2);
+    lines.push(format!(
+        "{:<id_w$} {:<state_w$} {:<node_w$} {}COMMAND",
+        "TASK", "STATE", "NODE", logs("LOGS")
+    ));
+    let sep_w = match layout {
+        TableLayout::Fixed => 60 + if sizes { logs_w + 1 } else { 0 },
+        TableLayout::Fit(total) => {
+            total.min(id_w + state_w + node_w + cmd_w + 3 + if sizes { logs_w + 1 } else { 0 })
+        }
+    };
+    lines.push("-".repeat(sep_w));
+    let colored = color::enabled();
+    for row in rows {
+        // Ahead of the command, so truncation can't hide it
+        let command = match row.signal {
+            Some(s) => format!("[killed by {}] {}", models::signal_name(s), row.command),
+            None => row.command.clone(),
+        };
+        lines.push(format!(
+            "{:<id_w$} {} {:<node_w$} {}{}",
+            row.task_id,
+            color::state(&format!("{:<state_w$}", row.state), row.state, colored),
+            row.node,
+            logs(&logs_cell(row)),
+            truncate(&command, cmd_w)
+        ));
+    }
+    lines
+}
+
+/// Fields `--output-template` can refer to.
+pub const TEMPLATE_FIELDS: &[&str] = &["id", "state", "node", "runtime", "command"];
+
+/// Split a template into literal text and `{field}` placeholders; `{{` and
+/// `}}` stand for literal braces.
+fn template_parts(template: &str) -> Result<Vec<(bool, String)>, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(format!("unclosed '{{{}' in template", field)),
+                    }
+                }
+                if !TEMPLATE_FIELDS.contains(&field.as_str()) {
+                    return Err(format!(
+                        "unknown field {{{}}} in template (fields: {})",
+                        field,
+                        TEMPLATE_FIELDS.iter().map(|f| format!("{{{}}}", f)).collect::<Vec<_>>().join(" ")
+                    ));
+                }
+                parts.push((false, std::mem::take(&mut text)));
+                parts.push((true, field));
+            }
+            '}' => return Err("unmatched '}' in template (use }} for a literal brace)".to_string()),
+            c => text.push(c),
+        }
+    }
+    parts.push((false, text));
+    Ok(parts)
+}
+
+/// clap parser for `--output-template`: rejects unknown fields up front.
+pub fn parse_template(template: &str) -> Result<String, String> {
+    template_parts(template).map(|_| template.to_string())
+}
+
+/// One task rendered through an `--output-template`. The runtime is in
+/// seconds, `-` for tasks that haven't finished.
+pub fn render_template(template: &str, row: &TaskRow) -> String {
+    let Ok(parts) = template_parts(template) else {
+        return template.to_string();
+    };
+    parts
+        .into_iter()
+        .map(|(is_field, part)| match (is_field, part.as_str()) {
+            (false, _) => part,
+            (true, "id") => row.task_id.clone(),
+            (true, "state") => row.state.to_string(),
+            (true, "node") => row.node.clone(),
+            (true, "runtime") => row.runtime_s.map_or_else(|| "-".to_string(), |s| format!("{:.1}", s)),
+            (true, "command") => row.command.clone(),
+            (true, _) => String::new(),
+        })
+        .collect()
+}
+
+/// Bytes as a short size for the LOGS column: `512B`, `1.5K`, `20.0M`, ...
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+/// Combined size of a task's stdout and stderr logs; a shared `--combine-output`
+/// log counts once. Missing files count as 0.
+pub fn logs_size(root: &Path, task_id: &str) -> u64 {
+    let paths: HashSet<PathBuf> = [false, true].iter().map(|stderr| logs::log_path(root, task_id, *stderr)).collect();
+    paths.iter().filter_map(|p| std::fs::metadata(p).ok()).map(|m| m.len()).sum()
+}
+
+/// Failed tasks grouped by failure reason, largest group first: a
+/// `REASON  count (share%)` line followed by that group's task IDs.
+/// Results written before reasons were recorded count as UNKNOWN.
+pub fn render_by_reason(rows: &[TaskRow]) -> Vec<String> {
+    let failed: Vec<&TaskRow> = rows.iter().filter(|r| r.state == "FAILED").collect();
+    let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for row in &failed {
+        let reason = row.failure.map(|f| f.as_str()).unwrap_or("UNKNOWN");
+        groups.entry(reason.to_string()).or_default().push(&row.task_id);
+    }
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    // Stable on ties, so equal-sized groups stay alphabetical
+    groups.sort_by_key(|(_, ids)| std::cmp::Reverse(ids.len()));
+
+    let mut lines = Vec::new();
+    for (reason, ids) in groups {
+        let share = 100.0 * ids.len() as f64 / failed.len() as f64;
+        lines.push(format!("{:<18} {} ({:.0}%)", reason, ids.len(), share));
+        lines.push(format!("  {}", ids.join(" ")));
+    }
+    lines
+}
+
+/// Indented dependency tree: tasks without (listed) dependencies at the top,
+/// each dependent below the tasks it waits for. A task with several
+/// dependencies appears under each of them; cycles are cut and flagged.
+pub fn render_tree(rows: &[TaskRow]) -> Vec<String> {
+    let by_id: HashMap<&str, &TaskRow> = rows.iter().map(|r| (r.task_id.as_str(), r)).collect();
+    let mut children: HashMap<&str, Vec<&TaskRow>> = HashMap::new();
+    for row in rows {
+        for dep in &row.depends_on {
+            if by_id.contains_key(dep.as_str()) {
+                children.entry(dep.as_str()).or_default().push(row);
+            }
+        }
+    }
+
+    fn visit<'a>(
+        row: &'a TaskRow,
+        depth: usize,
+        children: &HashMap<&str, Vec<&'a TaskRow>>,
+        stack: &mut Vec<&'a str>,
+        seen: &mut HashSet<&'a str>,
+        lines: &mut Vec<String>,
+    ) {
+        let indent = if depth == 0 {
+            String::new()
+        } else {
+            format!("{}└─ ", "   ".repeat(depth - 1))
+        };
+        if stack.contains(&row.task_id.as_str()) {
+            lines.push(format!("{}{} [CYCLE]", indent, row.task_id));
+            return;
+        }
+        let state = color::state(row.state, row.state, color::enabled());
+        lines.push(format!("{}{} {} {}", indent, row.task_id, state, truncate(&row.command, 40)));
+        seen.insert(row.task_id.as_str());
+        stack.push(row.task_id.as_str());
+        for child in children.get(row.task_id.as_str()).into_iter().flatten() {
+            visit(child, depth + 1, children, stack, seen, lines);
+        }
+        stack.pop();
+    }
+
+    let mut lines = Vec::new();
+    let mut seen = HashSet::new();
+    let roots = rows
+        .iter()
+        .filter(|r| !r.depends_on.iter().any(|d| by_id.contains_key(d.as_str())));
+    for row in roots {
+        visit(row, 0, &children, &mut Vec::new(), &mut seen, &mut lines);
+    }
+    // Whatever is left is only reachable through a cycle
+    for row in rows {
+        if !seen.contains(row.task_id.as_str()) {
+            visit(row, 0, &children, &mut Vec::new(), &mut seen, &mut lines);
+        }
+    }
+    lines
+}
+
+/// Gather matching tasks in display order: running/stuck, pending, then done/failed.
+pub fn collect_tasks(
+    root: &Path,
+    state_filter: TaskStateFilter,
+    node: Option<&str>,
+    search: Option<&str>,
+    opts: &TaskListOptions,
+    now: OffsetDateTime,
+) -> Result<Vec<TaskRow>> {
+    let mut rows = Vec::new();
+
     // Load heartbeats to check node liveness
     let mut node_status = HashMap::new();
     let hb_dir = root.join("hb");
-    let now = time::OffsetDateTime::now_utc();
     if hb_dir.exists() {
         for f in lfs::list_files_sorted(&hb_dir).unwrap_or_default() {
-            if let Ok(hb) = lfs::read_json::<models::Heartbeat, _>(&f) {
-                // If HB is older than 2 mins (same threshold as add.rs), consider stale
-                let is_alive = (now - hb.ts).as_seconds_f64() < 120.0;
+            if let Ok(hb) = lfs::read_heartbeat(&f) {
+                let is_alive = now - hb.ts < models::NODE_LIVE_THRESHOLD;
                 node_status.insert(hb.node, is_alive);
             }
         }
     }
 
-    println!("Lease: {}", lease_id);
-    println!("{:<10} {:<10} {:<12} COMMAND", "TASK", "STATE", "NODE");
-    println!("{}", "-".repeat(60));
-
-    // Collect and display tasks
-    let mut task_count = 0;
-
     // Running (or Stuck) tasks (claimed)
     if state_filter == TaskStateFilter::All 
         || state_filter == TaskStateFilter::Running 
         || state_filter == TaskStateFilter::Stuck 
     {
-        let claimed_dir = root.join("claimed");
-        if claimed_dir.exists() {
-            for entry in std::fs::read_dir(&claimed_dir)? {
-                let entry = entry?;
-                if entry.path().is_dir() {
-                    let node_name = entry.file_name().to_string_lossy().into_owned();
-
-                    if let Some(ref n) = node {
-                        if &node_name != n {
-                            continue;
-                        }
-                    }
+        for (node_name, node_dir) in lfs::node_dirs(root, "claimed")?.into_iter().filter(|(n, _)| node.is_none_or(|only| n == only)) {
+            // Check liveness
+            // If no heartbeat found, assume dead/stuck (safe default)
+            let is_alive = *node_status.get(&node_name).unwrap_or(&false);
+            let display_state = if is_alive { "RUNNING" } else { "STUCK" };
 
-                    // Check liveness
-                    // If no heartbeat found, assume dead/stuck (safe default)
-                    let is_alive = *node_status.get(&node_name).unwrap_or(&false);
-                    let display_state = if is_alive { "RUNNING" } else { "STUCK" };
+            // Apply filter
+            if state_filter == TaskStateFilter::Running && !is_alive {
+                continue;
+            }
+            if state_filter == TaskStateFilter::Stuck && is_alive {
+                continue;
+            }
 
-                    // Apply filter
-                    if state_filter == TaskStateFilter::Running && !is_alive {
-                        continue;
+            for task_file in lfs::list_files_sorted(node_dir)? {
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
+                    if let Some(s) = search {
+                        if !spec.command.contains(s) && !spec.task_id.contains(s) {
+                            continue;
+                        }
                     }
-                    if state_filter == TaskStateFilter::Stuck && is_alive {
+                    if !opts.in_window(spec.created_at, now) {
                         continue;
                     }
-
-                    for task_file in lfs::list_files_sorted(entry.path())? {
-                        if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
-                            if let Some(ref s) = search {
-                                if !spec.command.contains(s) && !spec.task_id.contains(s) {
-                                    continue;
-                                }
-                            }
-                            println!(
-                                "{:<10} {:<10} {:<12} {}",
-                                spec.task_id,
-                                display_state,
-                                node_name,
-                                truncate(&spec.command, 40)
-                            );
-                            task_count += 1;
-                        }
-                    }
+                    let state = if is_alive && possibly_hung(root, &spec, now) {
+                        "POSSIBLY_HUNG"
+                    } else {
+                        display_state
+                    };
+                    rows.push(TaskRow {
+                        task_id: spec.task_id,
+                        state,
+                        node: node_name.clone(),
+                        command: spec.command,
+                        ts: spec.created_at,
+                        depends_on: spec.depends_on,
+                        failure: None,
+                        logs_bytes: None,
+                        runtime_s: None,
+                        signal: None,
+                    });
                 }
             }
         }
@@ -121,36 +487,29 @@ pub async fn run(
 
     // Pending tasks (inbox)
     if state_filter == TaskStateFilter::All || state_filter == TaskStateFilter::Pending {
-        let inbox_dir = root.join("inbox");
-        if inbox_dir.exists() {
-            for entry in std::fs::read_dir(&inbox_dir)? {
-                let entry = entry?;
-                if entry.path().is_dir() {
-                    let node_name = entry.file_name().to_string_lossy().into_owned();
-
-                    if let Some(ref n) = node {
-                        if &node_name != n {
+        for (node_name, node_dir) in lfs::node_dirs(root, "inbox")?.into_iter().filter(|(n, _)| node.is_none_or(|only| n == only)) {
+            for task_file in lfs::list_files_sorted(node_dir)? {
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
+                    if let Some(s) = search {
+                        if !spec.command.contains(s) && !spec.task_id.contains(s) {
                             continue;
                         }
                     }
-
-                    for task_file in lfs::list_files_sorted(entry.path())? {
-                        if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&task_file) {
-                            if let Some(ref s) = search {
-                                if !spec.command.contains(s) && !spec.task_id.contains(s) {
-                                    continue;
-                                }
-                            }
-                            println!(
-                                "{:<10} {:<10} {:<12} {}",
-                                spec.task_id,
-                                "PENDING",
-                                node_name,
-                                truncate(&spec.command, 40)
-                            );
-                            task_count += 1;
-                        }
+                    if !opts.in_window(spec.created_at, now) {
+                        continue;
                     }
+                    rows.push(TaskRow {
+                        task_id: spec.task_id,
+                        state: "PENDING",
+                        node: node_name.clone(),
+                        command: spec.command,
+                        ts: spec.created_at,
+                        depends_on: spec.depends_on,
+                        failure: None,
+                        logs_bytes: None,
+                        runtime_s: None,
+                        signal: None,
+                    });
                 }
             }
         }
@@ -161,73 +520,119 @@ pub async fn run(
         || state_filter == TaskStateFilter::Done
         || state_filter == TaskStateFilter::Failed
     {
-        let done_dir = root.join("done");
-        if done_dir.exists() {
-            for entry in std::fs::read_dir(&done_dir)? {
-                let entry = entry?;
-                if entry.path().is_dir() {
-                    let node_name = entry.file_name().to_string_lossy().into_owned();
-
-                    if let Some(ref n) = node {
-                        if &node_name != n {
-                            continue;
-                        }
-                    }
+        let mut results = Vec::new();
+        for (_, node_dir) in lfs::node_dirs(root, "done")?.into_iter().filter(|(n, _)| node.is_none_or(|only| n == only)) {
+            for result_file in lfs::list_files_sorted(node_dir)? {
+                // Only process result files
+                if models::DoneFileKind::of(&result_file) != models::DoneFileKind::Result {
+                    continue;
+                }
+                if let Some(result) = quarantine::read_verified_result(root, &result_file) {
+                    results.push((result, result_file));
+                }
+            }
+        }
 
-                    for result_file in lfs::list_files_sorted(entry.path())? {
-                        // Only process result files
-                        if !result_file
-                            .file_name()
-                            .map(|n| n.to_string_lossy().ends_with(".result.json"))
-                            .unwrap_or(false)
-                        {
-                            continue;
-                        }
+        // A retried or requeued task can have several results: show only its
+        // latest attempt, and none if it is already listed as queued or running
+        let mut latest: HashMap<String, OffsetDateTime> = HashMap::new();
+        for (result, _) in &results {
+            let at = latest.entry(result.task_id.clone()).or_insert(result.finished_at);
+            *at = (*at).max(result.finished_at);
+        }
+        let mut listed: HashSet<String> = rows.iter().map(|r| r.task_id.clone()).collect();
 
-                        if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&result_file) {
-                            let task_state = if result.exit_code == 0 { "DONE" } else { "FAILED" };
-
-                            // Filter by state
-                            if state_filter == TaskStateFilter::Done && result.exit_code != 0 {
-                                continue;
-                            }
-                            if state_filter == TaskStateFilter::Failed && result.exit_code == 0 {
-                                continue;
-                            }
-
-                            if let Some(ref s) = search {
-                                if !result.task_id.contains(s) && !result.command.contains(s) {
-                                    continue;
-                                }
-                            }
-
-                            let cmd_display = if result.command.is_empty() {
-                                format!("exit={}", result.exit_code)
-                            } else {
-                                truncate(&result.command, 40)
-                            };
-                            println!(
-                                "{:<10} {:<10} {:<12} {}",
-                                result.task_id, task_state, result.node, cmd_display
-                            );
-                            task_count += 1;
-                        }
-                    }
+        for (result, result_file) in results {
+            if latest.get(&result.task_id) != Some(&result.finished_at) || !listed.insert(result.task_id.clone()) {
+                continue;
+            }
+            let task_state = if result.exit_code == 0 { "DONE" } else { "FAILED" };
+
+            // Filter by state
+            if state_filter == TaskStateFilter::Done && result.exit_code != 0 {
+                continue;
+            }
+            if state_filter == TaskStateFilter::Failed && result.exit_code == 0 {
+                continue;
+            }
+
+            if let Some(s) = search {
+                if !result.task_id.contains(s) && !result.command.contains(s) {
+                    continue;
                 }
             }
+            if !opts.in_window(result.finished_at, now) {
+                continue;
+            }
+
+            let cmd_display = if result.command.is_empty() {
+                format!("exit={}", result.exit_code)
+            } else {
+                result.command
+            };
+            // The archived spec sits next to its result
+            let spec_file = result_file.with_file_name(format!(
+                "{}.json",
+                result_file.file_name().unwrap_or_default().to_string_lossy().trim_end_matches(".result.json")
+            ));
+            let depends_on = lfs::read_json::<models::TaskSpec, _>(&spec_file)
+                .map(|spec| spec.depends_on)
+                .unwrap_or_default();
+            rows.push(TaskRow {
+                task_id: result.task_id,
+                state: task_state,
+                node: result.node,
+                command: cmd_display,
+                ts: result.finished_at,
+                depends_on,
+                failure: result.failure,
+                logs_bytes: None,
+                runtime_s: Some(result.runtime_s),
+                signal: result.signal,
+            });
         }
     }
 
-    println!("{}", "-".repeat(60));
-    println!("Total: {} tasks", task_count);
+    if opts.include_logs_size {
+        for row in &mut rows {
+            row.logs_bytes = Some(logs_size(root, &row.task_id));
+        }
+    }
 
-    Ok(())
+    Ok(rows)
+}
+
+/// A task on a live node whose logs haven't changed for its `no_output_s`.
+/// The heartbeat only proves the runner is alive, not that the task is progressing.
+pub fn possibly_hung(root: &Path, spec: &models::TaskSpec, now: OffsetDateTime) -> bool {
+    let Some(limit) = spec.no_output_s else {
+        return false;
+    };
+
+    // Log files are created when the task starts, so no file means not started yet
+    let last_output = [false, true]
+        .iter()
+        .filter_map(|stderr| {
+            std::fs::metadata(logs::log_path(root, &spec.task_id, *stderr))
+                .and_then(|m| m.modified())
+                .ok()
+        })
+        .max();
+
+    match last_output {
+        Some(modified) => {
+            let quiet = now - OffsetDateTime::from(modified);
+            quiet.whole_seconds() >= limit as i64
+        }
+        None => false,
+    }
 }
 
 fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
+    if s.chars().count() <= max_len {
         s.to_string()
     } else {
-        format!("{}...", &s[..max_len - 3])
+        let kept: String = s.chars().take(max_len.saturating_sub(3)).collect();
+        format!("{}...", kept)
     }
 }
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use leaseq_core::{batch, config, fs as lfs};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+pub async fn run(file: PathBuf, lease: Option<String>) -> Result<()> {
+    let content = std::fs::read_to_string(&file)
+        .context(format!("Failed to read {}", file.display()))?;
+
+    let parsed = match batch::parse_batch_file(&file, &content) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}:{}:{}: {}", file.display(), e.line, e.column, e.message);
+            if let Some(line) = content.lines().nth(e.line.saturating_sub(1)) {
+                eprintln!("  | {}", line);
+                eprintln!("  | {}^", " ".repeat(e.column.saturating_sub(1)));
+            }
+            return Err(anyhow::anyhow!("{} is not a valid batch file", file.display()));
+        }
+    };
+
+    // Node names can only be checked against a known lease
+    let known_nodes = lease
+        .or_else(|| parsed.lease.clone())
+        .map(|lease_id| {
+            let root = config::lease_root(&lease_id);
+            known_nodes(&root)
+        });
+
+    let issues = batch::validate_batch(&parsed, known_nodes.as_ref());
+    if issues.is_empty() {
+        println!("{}: OK ({} tasks)", file.display(), parsed.tasks.len());
+        return Ok(());
+    }
+
+    for issue in &issues {
+        eprintln!("{}: {}", file.display(), issue);
+    }
+    Err(anyhow::anyhow!("{} problem(s) found in {}", issues.len(), file.display()))
+}
+
+/// Nodes that have ever reported a heartbeat or own a queue in this lease.
+fn known_nodes(root: &Path) -> HashSet<String> {
+    let mut nodes = HashSet::new();
+
+    for f in lfs::list_files_sorted(root.join("hb")).unwrap_or_default() {
+        if let Ok(hb) = lfs::read_heartbeat(&f) {
+            nodes.insert(hb.node);
+        }
+    }
+
+    for (node, _) in lfs::node_dirs(root, "inbox").unwrap_or_default() {
+        nodes.insert(node);
+    }
+
+    nodes
+}
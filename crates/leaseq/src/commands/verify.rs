@@ -0,0 +1,217 @@
+use crate::commands::{logs, submit};
+use anyhow::{Context, Result};
+use leaseq_core::{config, fs as lfs, gpu, models};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// An inconsistency in a lease's directories. Paths are absolute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// Still in claimed/ although its result is in done/, e.g. the runner died
+    /// between writing the result and archiving the spec
+    ClaimedButDone { claimed: PathBuf, result: PathBuf },
+    /// A file in logs/ that belongs to no task of the lease
+    OrphanedLog { path: PathBuf },
+    /// Pending tasks or results sharing one idempotency key
+    DuplicateKey { key: String, files: Vec<PathBuf> },
+    /// A heartbeat from a node with no queues in the lease
+    StrayHeartbeat { node: String, path: PathBuf },
+}
+
+impl Issue {
+    /// Duplicate keys are only reported: which task should go is the user's
+    /// call, and the runner skips keys it has already executed anyway.
+    pub fn fixable(&self) -> bool {
+        !matches!(self, Issue::DuplicateKey { .. })
+    }
+
+    fn describe(&self, root: &Path) -> String {
+        let rel = |p: &Path| p.strip_prefix(root).unwrap_or(p).display().to_string();
+        match self {
+            Issue::ClaimedButDone { claimed, result } => {
+                format!("claimed but done: {} (result {})", rel(claimed), rel(result))
+            }
+            Issue::OrphanedLog { path } => format!("orphaned log: {}", rel(path)),
+            Issue::DuplicateKey { key, files } => format!(
+                "duplicate idempotency key {}: {}",
+                key,
+                files.iter().map(|f| rel(f)).collect::<Vec<_>>().join(", ")
+            ),
+            Issue::StrayHeartbeat { node, path } => {
+                format!("heartbeat for unknown node {}: {}", node, rel(path))
+            }
+        }
+    }
+}
+
+pub async fn run(lease: Option<String>, fix: bool) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+    if !root.exists() {
+        return Err(anyhow::anyhow!("Lease {} not found at {}", lease_id, root.display()));
+    }
+
+    let issues = verify(&root)?;
+    if issues.is_empty() {
+        println!("Lease {}: OK", lease_id);
+        return Ok(());
+    }
+
+    let mut remaining = 0;
+    for issue in &issues {
+        if fix && issue.fixable() {
+            match fix_issue(&root, issue) {
+                Ok(()) => println!("Fixed {}", issue.describe(&root)),
+                Err(e) => {
+                    println!("{} (not fixed: {:#})", issue.describe(&root), e);
+                    remaining += 1;
+                }
+            }
+        } else {
+            println!("{}", issue.describe(&root));
+            remaining += 1;
+        }
+    }
+
+    if remaining == 0 {
+        println!("Fixed {} issue(s) in lease {}", issues.len(), lease_id);
+        return Ok(());
+    }
+    let hint = if !fix && issues.iter().any(Issue::fixable) { "; run with --fix to repair" } else { "" };
+    Err(anyhow::anyhow!("{} issue(s) found in lease {}{}", remaining, lease_id, hint))
+}
+
+/// Scan the lease at `root` for inconsistencies. Nothing is changed.
+pub fn verify(root: &Path) -> Result<Vec<Issue>> {
+    let mut issues = Vec::new();
+    let mut task_ids = HashSet::new();
+    let mut keys: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    // Results of `allow_rerun` tasks, which share their key on purpose
+    let mut reruns = HashSet::new();
+
+    for (node, dir) in lfs::node_dirs(root, "done")? {
+        for f in lfs::list_files_sorted(&dir)? {
+            // Skipped and cancelled tasks never ran, so they can't duplicate a run
+            match models::DoneFileKind::of(&f) {
+                models::DoneFileKind::Result => {
+                    if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) {
+                        task_ids.insert(result.task_id);
+                        keys.entry(result.idempotency_key).or_default().push(f);
+                    }
+                }
+                models::DoneFileKind::Spec => {
+                    if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&f) {
+                        if spec.allow_rerun {
+                            let stem = f.file_name().unwrap_or_default().to_string_lossy();
+                            reruns.insert(dir.join(format!("{}.result.json", stem.trim_end_matches(".json"))));
+                        }
+                        task_ids.insert(spec.task_id);
+                    }
+                }
+                _ => {
+                    if let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) {
+                        task_ids.insert(result.task_id);
+                    }
+                }
+            }
+        }
+
+        for claimed in lfs::list_files_sorted(root.join("claimed").join(&node))? {
+            let stem = claimed.file_name().unwrap_or_default().to_string_lossy();
+            let stem = stem.trim_end_matches(".json");
+            let result = ["result", "skipped", "cancelled"]
+                .iter()
+                .map(|kind| dir.join(format!("{}.{}.json", stem, kind)))
+                .find(|p| p.exists());
+            if let Some(result) = result {
+                issues.push(Issue::ClaimedButDone { claimed, result });
+            }
+        }
+    }
+
+    for queue in ["inbox", "claimed"] {
+        for (_, dir) in lfs::node_dirs(root, queue)? {
+            for f in lfs::list_files_sorted(&dir)? {
+                if issues.iter().any(|i| matches!(i, Issue::ClaimedButDone { claimed, .. } if *claimed == f)) {
+                    continue;
+                }
+                if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&f) {
+                    task_ids.insert(spec.task_id);
+                    if !spec.allow_rerun {
+                        keys.entry(spec.idempotency_key).or_default().push(f);
+                    }
+                }
+            }
+        }
+    }
+
+    for (key, mut files) in keys {
+        files.retain(|f| !reruns.contains(f));
+        if files.len() > 1 {
+            issues.push(Issue::DuplicateKey { key, files });
+        }
+    }
+
+    // Logs named with --stdout-to/--stderr-to belong to the task whose pointer names them
+    let logs = lfs::list_files_sorted(root.join("logs"))?;
+    let pointed: HashSet<PathBuf> = logs
+        .iter()
+        .filter_map(|p| p.file_name()?.to_str()?.strip_suffix(".where"))
+        .filter(|id| task_ids.contains(*id))
+        .flat_map(|id| [logs::log_path(root, id, false), logs::log_path(root, id, true)])
+        .collect();
+    for path in logs {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let task_id = [".out", ".err", ".log", ".where"].iter().find_map(|ext| name.strip_suffix(ext));
+        if task_id.is_some_and(|id| !task_ids.contains(id)) && !pointed.contains(&path) {
+            issues.push(Issue::OrphanedLog { path });
+        }
+    }
+
+    let mut nodes = HashSet::new();
+    for queue in ["inbox", "claimed", "done"] {
+        nodes.extend(lfs::node_dirs(root, queue)?.into_iter().map(|(node, _)| node));
+    }
+    for path in lfs::list_files_sorted(root.join("hb"))? {
+        let Some(node) = path.file_name().unwrap_or_default().to_string_lossy().strip_suffix(".json").map(String::from)
+        else {
+            continue;
+        };
+        if !nodes.contains(&node) {
+            issues.push(Issue::StrayHeartbeat { node, path });
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Repair one fixable issue the way the runner would have left things. A
+/// task claimed but done on a node whose runner is live is refused: the
+/// runner passes through that state between writing a result and archiving
+/// the spec, and still holds the task's GPUs until then.
+pub fn fix_issue(root: &Path, issue: &Issue) -> Result<()> {
+    match issue {
+        Issue::ClaimedButDone { claimed, result } => {
+            let node = claimed.parent().and_then(|p| p.file_name()).unwrap_or_default().to_string_lossy();
+            if submit::node_is_live(root, &node) {
+                return Err(anyhow::anyhow!("the runner of node {} is live and may still be archiving it", node));
+            }
+            // Finish the archive step that was cut short
+            let archived = result.with_file_name(claimed.file_name().unwrap_or_default());
+            if archived.exists() {
+                lfs::remove_file_if_exists(claimed)?;
+            } else {
+                std::fs::rename(claimed, &archived).context(format!("Failed to archive {}", claimed.display()))?;
+            }
+            if let Ok(spec) = lfs::read_json::<models::TaskSpec, _>(&archived) {
+                gpu::release(root, &node, &spec.task_id)?;
+            }
+        }
+        Issue::OrphanedLog { path } | Issue::StrayHeartbeat { path, .. } => {
+            lfs::remove_file_if_exists(path)?;
+        }
+        Issue::DuplicateKey { .. } => {}
+    }
+    Ok(())
+}
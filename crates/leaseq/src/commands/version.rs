@@ -0,0 +1,35 @@
+use anyhow::Result;
+use crate::commands::OutputFormat;
+use leaseq_core::models;
+use serde::Serialize;
+
+/// What this binary speaks, in the shape `--format json` emits. Tools can
+/// compare `schema_version` with the one runners put in their heartbeats.
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub crate_version: String,
+    pub schema_version: u32,
+    pub lease_types: Vec<String>,
+}
+
+pub fn info() -> VersionInfo {
+    VersionInfo {
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: models::SCHEMA_VERSION,
+        lease_types: models::LEASE_TYPES.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+pub async fn run(format: OutputFormat) -> Result<()> {
+    let info = info();
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&info)?),
+        OutputFormat::Human => println!(
+            "leaseq {} (schema {}, lease types: {})",
+            info.crate_version,
+            info.schema_version,
+            info.lease_types.join(", ")
+        ),
+    }
+    Ok(())
+}
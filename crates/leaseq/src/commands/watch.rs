@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use leaseq_core::{config, fs as lfs, models};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub async fn run(lease: Option<String>, interval: u64, once: bool) -> Result<()> {
+    let lease_id = lease.unwrap_or_else(config::local_lease_id);
+
+    let root = config::lease_root(&lease_id);
+
+    loop {
+        for event in check_nodes(&root, time::OffsetDateTime::now_utc())? {
+            println!("{}", serde_json::to_string(&event)?);
+        }
+        if once {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(interval.max(1))).await;
+    }
+}
+
+/// Compare every node's heartbeat against the last `NodeDown`/`NodeUp` in
+/// its events log and append an event for each node that crossed the
+/// staleness threshold since. A node with no such event yet counts as up, so
+/// `NodeUp` only ever follows a `NodeDown`. Returns the events appended.
+pub fn check_nodes(root: &Path, now: time::OffsetDateTime) -> Result<Vec<models::Event>> {
+    let mut emitted = Vec::new();
+    for f in lfs::list_files_sorted(root.join("hb"))? {
+        let Ok(hb) = lfs::read_heartbeat(&f) else {
+            continue;
+        };
+        let log = events_log(root, &hb.node);
+        let was_down = last_node_down(&log)?;
+        let is_down = now - hb.ts >= models::NODE_LIVE_THRESHOLD;
+        let event = match (was_down, is_down) {
+            (false, true) => models::Event::NodeDown { node: hb.node.clone(), last_seen: hb.ts },
+            (true, false) => models::Event::NodeUp { node: hb.node.clone() },
+            _ => continue,
+        };
+        lfs::append_json_line(&log, &event)
+            .with_context(|| format!("Failed to append to {}", log.display()))?;
+        emitted.push(event);
+    }
+    Ok(emitted)
+}
+
+pub fn events_log(root: &Path, node: &str) -> PathBuf {
+    root.join("events").join(node).join(models::EVENTS_LOG_FILE)
+}
+
+/// Whether the latest node event in `log` is a `NodeDown`.
+fn last_node_down(log: &Path) -> Result<bool> {
+    let content = match std::fs::read_to_string(log) {
+        Ok(c) => c,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", log.display())),
+    };
+    Ok(content
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<models::Event>(line).ok())
+        .find_map(|event| match event {
+            models::Event::NodeDown { .. } => Some(true),
+            models::Event::NodeUp { .. } => Some(false),
+            _ => None,
+        })
+        .unwrap_or(false))
+}
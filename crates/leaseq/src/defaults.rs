@@ -0,0 +1,90 @@
+//! Default flag values from config files, so the same `--lease` or
+//! `--partition` needn't be repeated on every invocation. Flags given on the
+//! command line always win; then the nearest `.leaseq.toml` from the current
+//! directory upwards, then farther ones, then `~/.leaseq/config.toml`.
+
+use anyhow::{Context, Result};
+use leaseq_core::config;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::commands::lease::CreateLeaseArgs;
+
+/// Project config file name, looked up in the current directory and its parents.
+pub const PROJECT_FILE: &str = ".leaseq.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    /// `--lease` for every command that takes one
+    pub lease: Option<String>,
+    /// `lease create --partition`
+    pub partition: Option<String>,
+    /// `lease create --qos`
+    pub qos: Option<String>,
+    /// `lease create --account`
+    pub account: Option<String>,
+    /// `lease create --time`
+    pub time: Option<String>,
+    /// `lease create --gpus-per-node`
+    pub gpus_per_node: Option<u32>,
+}
+
+impl Defaults {
+    /// Values of `self`, falling back to `other` where `self` has none.
+    fn or(self, other: Defaults) -> Defaults {
+        Defaults {
+            lease: self.lease.or(other.lease),
+            partition: self.partition.or(other.partition),
+            qos: self.qos.or(other.qos),
+            account: self.account.or(other.account),
+            time: self.time.or(other.time),
+            gpus_per_node: self.gpus_per_node.or(other.gpus_per_node),
+        }
+    }
+
+    /// Fill in the `lease create` options not given on the command line.
+    /// `gpus_given` says whether `--gpus-per-node` was, since it has a default.
+    pub fn apply_to_create(&self, args: &mut CreateLeaseArgs, gpus_given: bool) {
+        fill(&mut args.partition, &self.partition);
+        fill(&mut args.qos, &self.qos);
+        fill(&mut args.account, &self.account);
+        fill(&mut args.time, &self.time);
+        if let (false, Some(n)) = (gpus_given, self.gpus_per_node) {
+            args.gpus_per_node = n;
+        }
+    }
+}
+
+/// Set `flag` from the config if it wasn't given.
+pub fn fill(flag: &mut Option<String>, default: &Option<String>) {
+    if flag.is_none() {
+        flag.clone_from(default);
+    }
+}
+
+/// The user config, `~/.leaseq/config.toml` (under `$LEASEQ_HOME` if set).
+pub fn user_config_path() -> PathBuf {
+    config::leaseq_home_dir().join("config.toml")
+}
+
+/// Merge every config file that applies to `cwd`, nearest first.
+pub fn load(cwd: &Path) -> Result<Defaults> {
+    let mut defaults = Defaults::default();
+    for dir in cwd.ancestors() {
+        let path = dir.join(PROJECT_FILE);
+        if path.is_file() {
+            defaults = defaults.or(read(&path)?);
+        }
+    }
+    let user = user_config_path();
+    if user.is_file() {
+        defaults = defaults.or(read(&user)?);
+    }
+    Ok(defaults)
+}
+
+pub fn read(path: &Path) -> Result<Defaults> {
+    let content = std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content).context(format!("Invalid config file {}", path.display()))
+}
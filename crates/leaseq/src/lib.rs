@@ -1,2 +1,4 @@
+pub mod color;
 pub mod commands;
+pub mod defaults;
 pub mod tui;
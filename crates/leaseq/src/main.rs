@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use anyhow::Result;
 use std::path::PathBuf;
 use leaseq::commands;
@@ -7,6 +7,10 @@ use leaseq::tui;
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Color output: auto (terminal and no NO_COLOR), always or never
+    #[arg(long, global = true, value_enum, default_value_t = leaseq::color::ColorChoice::Auto)]
+    color: leaseq::color::ColorChoice,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -15,16 +19,27 @@ struct Cli {
 enum Commands {
     /// Submit a task to an existing lease
     Submit {
-        #[arg(last = true, required = true)]
+        #[arg(last = true, required_unless_present_any = ["from_csv", "batch"])]
         command: Vec<String>,
 
+        /// Submit one task per CSV row (`command,key=value,...`, or a header row naming env vars)
+        #[arg(long, value_name = "FILE", conflicts_with = "command")]
+        from_csv: Option<PathBuf>,
+
+        /// Submit one task per line of FILE (`-` for stdin), skipping blank lines and # comments
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["command", "from_csv"])]
+        batch: Option<PathBuf>,
+
         #[arg(long)]
         lease: Option<String>,
 
         #[arg(long)]
         node: Option<String>,
+
+        #[command(flatten)]
+        opts: Box<commands::submit::SubmitOptions>,
     },
-    /// Allocate a new interactive lease (mimics salloc but persistent)
+    /// Allocate a new interactive lease (mimics salloc but persistent); tasks are queued with `submit`
     Add {
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         slurm_args: Vec<String>,
@@ -33,6 +48,10 @@ enum Commands {
     Status {
         #[arg(long)]
         lease: Option<String>,
+
+        /// Output format; json emits {lease, nodes, running, pending}
+        #[arg(long, value_enum, default_value_t = commands::OutputFormat::Human)]
+        format: commands::OutputFormat,
     },
     /// List tasks with filters
     Tasks {
@@ -50,6 +69,9 @@ enum Commands {
         /// Search in command or task ID
         #[arg(long)]
         search: Option<String>,
+
+        #[command(flatten)]
+        opts: commands::tasks::TaskListOptions,
     },
     /// Show task logs
     Logs {
@@ -63,9 +85,26 @@ enum Commands {
         #[arg(long)]
         stderr: bool,
 
-        /// Show only the last N lines
+        /// Show only the last N lines (N bytes with --raw)
         #[arg(long)]
         tail: Option<usize>,
+
+        /// Write the file's exact bytes, without line or UTF-8 handling
+        #[arg(long)]
+        raw: bool,
+
+        /// Remove ANSI escape sequences (default when stdout is not a terminal)
+        #[arg(long, overrides_with = "no_strip_ansi", conflicts_with = "raw")]
+        strip_ansi: bool,
+
+        /// Keep ANSI escape sequences even when piped
+        #[arg(long, overrides_with = "strip_ansi")]
+        no_strip_ansi: bool,
+
+        /// Render the log, colors included, to a standalone HTML file for
+        /// sharing; prints its path and opens it in a browser if there is one
+        #[arg(long, conflicts_with = "raw")]
+        open_browser: bool,
     },
     /// Follow task output in real-time
     Follow {
@@ -83,15 +122,39 @@ enum Commands {
         /// Follow stderr instead of stdout
         #[arg(long)]
         stderr: bool,
+
+        /// First print output from this long ago, e.g. 1m (an estimate from a steady output rate; far off for bursty output)
+        #[arg(long, value_name = "DURATION", value_parser = leaseq_core::duration::parse_duration)]
+        since: Option<time::Duration>,
+
+        /// First print the last N lines
+        #[arg(long, value_name = "N", conflicts_with = "since")]
+        lines: Option<usize>,
+
+        /// Stop when the task finishes and exit with its exit code
+        #[arg(long)]
+        follow_exit_code: bool,
     },
     /// Cancel a task
     Cancel {
-        /// Task ID to cancel
+        /// Task ID, unique prefix of one, or the task's UUID
         task: String,
 
         #[arg(long)]
         lease: Option<String>,
     },
+    /// Queue a finished task again as a new task
+    Retry {
+        /// Task ID of the finished task
+        task: String,
+
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Run it on the node it ran on before instead of picking one
+        #[arg(long)]
+        same_node: bool,
+    },
     /// Open an interactive shell in the lease
     Shell {
         #[arg(long)]
@@ -111,6 +174,12 @@ enum Commands {
     /// Manage leases
     #[command(subcommand)]
     Lease(commands::lease::LeaseCommands),
+    /// Inspect or act on tasks submitted with --group-as
+    #[command(subcommand)]
+    Group(commands::group::GroupCommands),
+    /// Take nodes out of (or back into) task routing
+    #[command(subcommand)]
+    Node(commands::node::NodeCommands),
     /// Run the task runner (used internally by daemon)
     Run {
         /// Lease ID (e.g., local:myhost or slurm jobid)
@@ -124,6 +193,145 @@ enum Commands {
         /// Root directory for execution (overrides default lookup)
         #[arg(long)]
         root: Option<PathBuf>,
+
+        /// Write task logs here instead of <root>/logs (default: $LEASEQ_LOG_DIR)
+        #[arg(long)]
+        log_dir: Option<PathBuf>,
+
+        /// How much of the runner's own environment tasks inherit: none, safe or all
+        /// (default: $LEASEQ_ENV_INHERIT, else all)
+        #[arg(long, value_name = "POLICY")]
+        env_inherit: Option<leaseq_core::config::EnvInherit>,
+
+        /// GPUs on this node to hand out (default: $LEASEQ_GPUS, else all of $CUDA_VISIBLE_DEVICES, else count from nvidia-smi)
+        #[arg(long)]
+        gpus: Option<u32>,
+
+        /// CPUs on this node to hand out (default: $LEASEQ_CPUS, else the number of cores)
+        #[arg(long)]
+        cpus: Option<u32>,
+
+        /// Tasks to run at once (default: $LEASEQ_PARALLEL, else the lease's `parallel`, else 1)
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        parallel: Option<u32>,
+
+        /// Start each line of the task logs with `[<task_id> <timestamp>] ` (default: $LEASEQ_PREFIX_LINES)
+        #[arg(long)]
+        prefix_lines: bool,
+    },
+    /// List, delete or restore quarantined (unparseable) files
+    PurgeCorrupt {
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Delete all quarantined files
+        #[arg(long, conflicts_with = "restore")]
+        delete: bool,
+
+        /// Move files that now parse back to where they came from
+        #[arg(long)]
+        restore: bool,
+    },
+    /// Delete logs of finished tasks in the lease's logs/ dir (not --log-dir or --output-dir), optionally keeping those that match a pattern
+    PruneLogs {
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Keep logs with a line matching this regex (e.g. "NaN|Traceback")
+        #[arg(long, value_name = "REGEX")]
+        keep_matching: Option<String>,
+
+        /// Only list what would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Queue a directory of TaskSpec JSON files written by other tools
+    Import {
+        /// Directory holding one spec per *.json file
+        dir: PathBuf,
+
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Queue everything on this node instead of each spec's target_node
+        #[arg(long)]
+        node: Option<String>,
+
+        /// Remove the files once they are queued
+        #[arg(long = "move")]
+        move_files: bool,
+    },
+    /// Check a batch job file without submitting it
+    Validate {
+        /// Batch file (JSON, or YAML if it ends in .yaml or .yml)
+        file: PathBuf,
+
+        /// Check node names against this lease
+        #[arg(long)]
+        lease: Option<String>,
+    },
+    /// Show the version and the on-disk schema version this binary speaks
+    Version {
+        /// Output format; json emits {crate_version, schema_version, lease_types}
+        #[arg(long, value_enum, default_value_t = commands::OutputFormat::Human)]
+        format: commands::OutputFormat,
+
+        /// Same as --format json
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+    },
+    /// Check a lease's directories for inconsistencies
+    Verify {
+        /// Lease ID (defaults to the local lease)
+        lease: Option<String>,
+
+        /// Repair what can be repaired safely
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Append NodeDown/NodeUp events when a node's heartbeat goes stale or comes back
+    Watch {
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Seconds between heartbeat checks
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+
+        /// Check once and exit, e.g. from cron
+        #[arg(long)]
+        once: bool,
+    },
+    /// Expire pending tasks older than their maximum pending age (runners do this too)
+    GcPending {
+        #[arg(long)]
+        lease: Option<String>,
+    },
+    /// Remove heartbeats of nodes that have been silent for a while
+    GcNodes {
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Age of the last heartbeat before a node is dropped
+        #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = leaseq_core::duration::parse_duration)]
+        older_than: time::Duration,
+    },
+    /// Time a batch of no-op tasks through a lease's runners
+    Bench {
+        #[arg(long)]
+        lease: Option<String>,
+
+        /// Submit to this node instead of routing each task
+        #[arg(long)]
+        node: Option<String>,
+
+        /// How many tasks to submit
+        #[arg(long, short = 'n', default_value_t = 20)]
+        tasks: usize,
+
+        /// Give up after this many seconds
+        #[arg(long, default_value_t = 600)]
+        timeout: u64,
     },
 }
 
@@ -131,43 +339,131 @@ enum Commands {
 enum DaemonCommands {
     /// Start the local runner daemon
     Start,
+    /// Run the local runner in the foreground (no detach, no pid file; for containers/systemd)
+    #[command(alias = "run-foreground")]
+    Run,
     /// Stop the local runner daemon
     Stop,
     /// Show daemon status
     Status,
 }
 
+/// Fill in flags the command line left out from `.leaseq.toml` / `~/.leaseq/config.toml`.
+/// The files are only read for commands that take those flags, so a broken
+/// one can't keep e.g. a runner from starting.
+fn apply_defaults(command: &mut Option<Commands>, matches: &clap::ArgMatches) -> Result<()> {
+    let defaults = || leaseq::defaults::load(&std::env::current_dir()?);
+    use commands::group::GroupCommands as G;
+    use commands::node::NodeCommands as N;
+    match command {
+        Some(
+            Commands::Submit { lease, .. }
+            | Commands::Status { lease, .. }
+            | Commands::Tasks { lease, .. }
+            | Commands::Logs { lease, .. }
+            | Commands::Follow { lease, .. }
+            | Commands::Cancel { lease, .. }
+            | Commands::Retry { lease, .. }
+            | Commands::Shell { lease, .. }
+            | Commands::Tui { lease }
+            | Commands::PurgeCorrupt { lease, .. }
+            | Commands::PruneLogs { lease, .. }
+            | Commands::Import { lease, .. }
+            | Commands::Validate { lease, .. }
+            | Commands::Verify { lease, .. }
+            | Commands::Watch { lease, .. }
+            | Commands::GcPending { lease }
+            | Commands::GcNodes { lease, .. }
+            | Commands::Bench { lease, .. }
+            | Commands::Group(G::Status { lease, .. } | G::Cancel { lease, .. } | G::Wait { lease, .. })
+            | Commands::Node(N::Cordon { lease, .. } | N::Uncordon { lease, .. }),
+        ) => {
+            leaseq::defaults::fill(lease, &defaults()?.lease);
+            if let Some(id) = lease {
+                *id = leaseq_core::config::resolve_lease_id(id);
+            }
+        }
+        Some(
+            Commands::Run { lease, .. }
+            | Commands::Lease(
+                commands::lease::LeaseCommands::Set { lease_id: lease, .. }
+                | commands::lease::LeaseCommands::Release { lease_id: lease },
+            ),
+        ) => *lease = leaseq_core::config::resolve_lease_id(lease),
+        Some(Commands::Lease(commands::lease::LeaseCommands::Create(args))) => {
+            let gpus_given = matches
+                .subcommand_matches("lease")
+                .and_then(|m| m.subcommand_matches("create"))
+                .and_then(|m| m.value_source("gpus_per_node"))
+                == Some(clap::parser::ValueSource::CommandLine);
+            defaults()?.apply_to_create(args, gpus_given);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    apply_defaults(&mut cli.command, &matches)?;
+    leaseq::color::set_choice(cli.color);
+
+    // Everything but a runner with an explicit --root lives under the runtime dir
+    if !matches!(cli.command, Some(Commands::Run { root: Some(_), .. })) {
+        leaseq_core::config::ensure_runtime_dir()?;
+    }
 
     match cli.command {
-        Some(Commands::Submit { command, lease, node }) => {
-            commands::submit::run(command, lease, node).await
-        }
+        Some(Commands::Submit { command, from_csv, batch, lease, node, opts }) => match (from_csv, batch) {
+            (Some(file), _) => commands::submit::run_csv(file, lease, node, *opts).await,
+            (_, Some(file)) => commands::submit::run_batch(file, lease, node, *opts).await,
+            (None, None) => commands::submit::run_with_options(command, lease, node, *opts).await,
+        },
         Some(Commands::Add { slurm_args }) => {
             commands::add::run(slurm_args).await
         }
-        Some(Commands::Status { lease }) => {
-            commands::status::run(lease).await
+        Some(Commands::Status { lease, format }) => {
+            commands::status::run(lease, format).await
         }
-        Some(Commands::Tasks { lease, state, node, search }) => {
-            commands::tasks::run(lease, state, node, search).await
+        Some(Commands::Tasks { lease, state, node, search, opts }) => {
+            commands::tasks::run_with_options(lease, state, node, search, opts).await
         }
-        Some(Commands::Logs { task, lease, stderr, tail }) => {
-            commands::logs::run(task, lease, stderr, tail).await
+        Some(Commands::Logs { task, lease, stderr, tail, raw, strip_ansi, no_strip_ansi, open_browser }) => {
+            let strip_ansi = match (strip_ansi, no_strip_ansi) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+            commands::logs::run(task, lease, stderr, tail, raw, strip_ansi, open_browser).await
         }
-        Some(Commands::Follow { task, lease, node, stderr }) => {
-            commands::follow::run(task, lease, node, stderr).await
+        Some(Commands::Follow { task, lease, node, stderr, since, lines, follow_exit_code }) => {
+            let replay = match (lines, since) {
+                (Some(n), _) => commands::follow::Replay::Lines(n),
+                (None, Some(window)) => commands::follow::Replay::Since(window),
+                (None, None) => commands::follow::Replay::None,
+            };
+            match commands::follow::run(task, lease, node, stderr, replay, follow_exit_code).await? {
+                Some(code) => std::process::exit(code),
+                None => Ok(()),
+            }
         }
         Some(Commands::Cancel { task, lease }) => {
             commands::cancel::run(task, lease).await
         }
+        Some(Commands::Retry { task, lease, same_node }) => {
+            commands::retry::run(task, lease, same_node).await
+        }
         Some(Commands::Shell { lease, node }) => {
             commands::shell::run(lease, node).await
         }
         Some(Commands::Daemon(cmd)) => match cmd {
             DaemonCommands::Start => commands::daemon::start().await,
+            DaemonCommands::Run => {
+                tracing_subscriber::fmt::init();
+                commands::daemon::run_foreground().await
+            }
             DaemonCommands::Stop => commands::daemon::stop().await,
             DaemonCommands::Status => commands::daemon::status().await,
         },
@@ -177,9 +473,64 @@ async fn main() -> Result<()> {
         Some(Commands::Lease(cmd)) => {
             commands::lease::run(cmd).await
         }
-        Some(Commands::Run { lease, node, root }) => {
+        Some(Commands::Group(cmd)) => {
+            commands::group::run(cmd).await
+        }
+        Some(Commands::Node(cmd)) => {
+            commands::node::run(cmd).await
+        }
+        Some(Commands::Run { lease, node, root, log_dir, env_inherit, gpus, cpus, parallel, prefix_lines }) => {
             tracing_subscriber::fmt::init();
-            commands::run::run(commands::run::RunArgs { lease, node, root }).await
+            let root = root.unwrap_or_else(|| leaseq_core::config::lease_root(&lease));
+            // Command-line flags win over the lease and the environment
+            let mut config = leaseq_core::config::RunConfig::load(&root);
+            if log_dir.is_some() {
+                config.log_dir = log_dir;
+            }
+            if gpus.is_some() {
+                config.gpus = gpus;
+            }
+            if cpus.is_some() {
+                config.cpus = cpus;
+            }
+            if let Some(n) = parallel {
+                config.parallel = n;
+            }
+            if let Some(policy) = env_inherit {
+                config.env_inherit = policy;
+            }
+            config.prefix_lines |= prefix_lines;
+            commands::run::run(commands::run::RunArgs { lease, node, root: Some(root), config: Some(config) }).await
+        }
+        Some(Commands::PurgeCorrupt { lease, delete, restore }) => {
+            commands::purge_corrupt::run(lease, delete, restore).await
+        }
+        Some(Commands::PruneLogs { lease, keep_matching, dry_run }) => {
+            commands::prune_logs::run(lease, keep_matching, dry_run).await
+        }
+        Some(Commands::Import { dir, lease, node, move_files }) => {
+            commands::import::run(dir, lease, node, move_files).await
+        }
+        Some(Commands::Validate { file, lease }) => {
+            commands::validate::run(file, lease).await
+        }
+        Some(Commands::Version { format, json }) => {
+            commands::version::run(if json { commands::OutputFormat::Json } else { format }).await
+        }
+        Some(Commands::Verify { lease, fix }) => {
+            commands::verify::run(lease, fix).await
+        }
+        Some(Commands::Watch { lease, interval, once }) => {
+            commands::watch::run(lease, interval, once).await
+        }
+        Some(Commands::GcPending { lease }) => {
+            commands::gc_pending::run(lease).await
+        }
+        Some(Commands::GcNodes { lease, older_than }) => {
+            commands::gc_nodes::run(lease, older_than).await
+        }
+        Some(Commands::Bench { lease, node, tasks, timeout }) => {
+            commands::bench::run(lease, node, tasks, timeout).await
         }
         None => {
             // Default to TUI
@@ -0,0 +1,110 @@
+use leaseq_core::{fs as lfs, models};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use time::OffsetDateTime;
+
+/// Entries kept for the activity pane; older ones scroll away.
+pub const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityKind {
+    Claimed,
+    Started,
+    Finished,
+    Failed,
+    Cancelled,
+    Skipped,
+}
+
+impl ActivityKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Claimed => "CLAIMED",
+            Self::Started => "STARTED",
+            Self::Finished => "FINISHED",
+            Self::Failed => "FAILED",
+            Self::Cancelled => "CANCELLED",
+            Self::Skipped => "SKIPPED",
+        }
+    }
+}
+
+/// One line of the activity pane.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    pub at: OffsetDateTime,
+    pub kind: ActivityKind,
+    pub task_id: String,
+    pub node: String,
+}
+
+impl Activity {
+    pub fn line(&self) -> String {
+        let t = self.at.time();
+        format!(
+            "{:02}:{:02}:{:02} {:<9} {} on {}",
+            t.hour(),
+            t.minute(),
+            t.second(),
+            self.kind.label(),
+            self.task_id,
+            self.node
+        )
+    }
+}
+
+/// What happened across the lease, oldest first, at most `MAX_ENTRIES`.
+///
+/// Runners don't write an event log, so this is rebuilt from the queues:
+/// a claimed spec's ctime is when the rename into claimed/ happened, and
+/// results carry their own start and finish times.
+pub fn collect(root: &Path) -> Vec<Activity> {
+    let mut entries = Vec::new();
+
+    for (node, dir) in lfs::node_dirs(root, "claimed").unwrap_or_default() {
+        for f in lfs::list_files_sorted(&dir).unwrap_or_default() {
+            let (Ok(spec), Ok(meta)) = (lfs::read_json::<models::TaskSpec, _>(&f), std::fs::metadata(&f)) else {
+                continue;
+            };
+            let ctime = meta.ctime() as i128 * 1_000_000_000 + meta.ctime_nsec() as i128;
+            let Ok(at) = OffsetDateTime::from_unix_timestamp_nanos(ctime) else {
+                continue;
+            };
+            entries.push(Activity { at, kind: ActivityKind::Claimed, task_id: spec.task_id, node: node.clone() });
+        }
+    }
+
+    for (node, dir) in lfs::node_dirs(root, "done").unwrap_or_default() {
+        for f in lfs::list_files_sorted(&dir).unwrap_or_default() {
+            let file_kind = models::DoneFileKind::of(&f);
+            if !file_kind.is_result() {
+                continue;
+            }
+            let Ok(result) = lfs::read_json::<models::TaskResult, _>(&f) else {
+                continue;
+            };
+            let kind = match file_kind {
+                models::DoneFileKind::Cancelled => ActivityKind::Cancelled,
+                models::DoneFileKind::Skipped => ActivityKind::Skipped,
+                _ if result.exit_code == 0 => ActivityKind::Finished,
+                _ => ActivityKind::Failed,
+            };
+            // Cancelled-while-pending, skipped and expired tasks never started
+            if result.runtime_s > 0.0 {
+                entries.push(Activity {
+                    at: result.started_at,
+                    kind: ActivityKind::Started,
+                    task_id: result.task_id.clone(),
+                    node: node.clone(),
+                });
+            }
+            entries.push(Activity { at: result.finished_at, kind, task_id: result.task_id, node: node.clone() });
+        }
+    }
+
+    // Stable: a task's start stays ahead of a finish in the same instant
+    entries.sort_by_key(|e| e.at);
+    let excess = entries.len().saturating_sub(MAX_ENTRIES);
+    entries.drain(..excess);
+    entries
+}
@@ -7,11 +7,16 @@ use crossterm::{
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::time::{Duration, Instant};
 use anyhow::Result;
-use leaseq_core::{config, fs as lfs, models};
+use leaseq_core::{config, fs as lfs, models, quarantine};
 use tui_textarea::TextArea;
 use crate::commands::{submit, lease};
 use std::collections::HashMap;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 
+use crate::tui::activity;
+use crate::tui::log_search::{self, LogSearch};
+use crate::tui::log_tail::{LogEvent, LogTail};
 use crate::tui::ui;
 
 pub struct App<'a> {
@@ -27,6 +32,8 @@ pub struct App<'a> {
     pub selected_node_idx: usize,
     pub selected_task_idx: usize,
     pub textarea: TextArea<'a>, // For adding task
+    pub task_gpus: TextArea<'a>, // GPUs of the task being added
+    pub task_gpus_active: bool,
 
     // Lease Form State
     pub lease_form: LeaseFormState<'a>,
@@ -43,11 +50,19 @@ pub struct App<'a> {
     // Filter State
     pub filter_state: FilterState,
 
+    // Activity pane (toggled with 'v'); only collected while shown
+    pub show_activity: bool,
+    pub activity: Vec<activity::Activity>,
+
     // Visible log height (set by UI)
     pub log_view_height: usize,
 
     // Status message (shown temporarily)
     pub status_message: Option<(String, std::time::Instant)>,
+
+    // Cancelled on quit; background work (log tails, spawn_background) stops with it
+    pub cancel: CancellationToken,
+    background: JoinSet<()>,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -65,6 +80,7 @@ pub enum Mode {
     NodeDetails,
     TaskActions,
     Help,
+    SearchLogs, // typing a `/` query in the zoomed logs pane
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -130,9 +146,12 @@ pub struct LogState {
     pub lines: Vec<String>,
     pub scroll: usize,
     pub auto_follow: bool,
-    pub file_pos: u64,
+    pub tail: Option<LogTail>, // Background reader; None restarts from the top
     pub show_stderr: bool,
+    pub combined: bool, // Task writes stdout and stderr to one log
     pub maximized: bool,
+    pub search: Option<LogSearch>, // active `/` search, highlighted when zoomed
+    pub search_input: String,      // query being typed in Mode::SearchLogs
 }
 
 impl Default for LogState {
@@ -142,9 +161,12 @@ impl Default for LogState {
             lines: Vec::new(),
             scroll: 0,
             auto_follow: true,
-            file_pos: 0,
+            tail: None,
             show_stderr: false,
+            combined: false,
             maximized: false,
+            search: None,
+            search_input: String::new(),
         }
     }
 }
@@ -160,10 +182,12 @@ pub struct NodeState {
 pub struct TaskState {
     pub id: String,
     pub command: String,
+    pub resolved_command: String, // as executed, once finished
     pub cwd: String,
     pub state: String,
     pub node: String,
     pub exit_code: Option<i32>,
+    pub signal: Option<i32>, // the signal that killed a finished task
     pub gpus_requested: u32,
     pub gpus_assigned: String,
     pub finished_at: Option<time::OffsetDateTime>,
@@ -230,13 +254,46 @@ impl<'a> App<'a> {
             selected_node_idx: 0,
             selected_task_idx: 0,
             textarea: TextArea::default(),
+            task_gpus: TextArea::default(),
+            task_gpus_active: false,
             lease_form: LeaseFormState::default(),
             logs_state: LogState::default(),
             node_modal: NodeModalState { selected: NodeModalAction::ViewStatus },
             task_modal: TaskModalState { selected: TaskModalAction::ViewLogs },
             filter_state: FilterState::default(),
+            show_activity: false,
+            activity: Vec::new(),
             log_view_height: 10,
             status_message: None,
+            cancel: CancellationToken::new(),
+            background: JoinSet::new(),
+        }
+    }
+
+    /// Run `task` until the app quits. It is given a token that is cancelled
+    /// on quit and should return soon after.
+    pub fn spawn_background<F, Fut>(&mut self, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.background.spawn(task(self.cancel.child_token()));
+    }
+
+    /// Background tasks that have not finished yet.
+    pub fn background_tasks(&self) -> usize {
+        self.background.len()
+    }
+
+    /// Cancel all background work and wait for it, aborting what doesn't
+    /// stop within a second.
+    pub async fn shutdown(&mut self) {
+        self.cancel.cancel();
+        let background = &mut self.background;
+        let drained = async { while background.join_next().await.is_some() {} };
+        if tokio::time::timeout(Duration::from_secs(1), drained).await.is_err() {
+            self.background.abort_all();
+            while self.background.join_next().await.is_some() {}
         }
     }
 
@@ -338,6 +395,7 @@ impl<'a> App<'a> {
         let mut terminal = Terminal::new(backend)?;
 
         let res = self.run_loop(&mut terminal).await;
+        self.shutdown().await;
 
         disable_raw_mode()?;
         execute!(
@@ -374,14 +432,21 @@ impl<'a> App<'a> {
                 .unwrap_or_else(|| Duration::from_secs(0));
 
             if event::poll(timeout)? {
+                let ev = event::read()?;
+                // Re-measure right away; the next draw lays out for the new size
+                if let Event::Resize(..) = ev {
+                    terminal.autoresize()?;
+                    continue;
+                }
                 match self.mode {
-                    Mode::Normal => self.handle_normal_input(event::read()?).await?,
-                    Mode::InputAdd => self.handle_input_add(event::read()?).await?,
-                    Mode::CreateLease => self.handle_create_lease_input(event::read()?).await?,
-                    Mode::NodeDetails => self.handle_node_details_input(event::read()?).await?,
-                    Mode::TaskActions => self.handle_task_actions_input(event::read()?).await?,
+                    Mode::Normal => self.handle_normal_input(ev).await?,
+                    Mode::InputAdd => self.handle_input_add(ev).await?,
+                    Mode::CreateLease => self.handle_create_lease_input(ev).await?,
+                    Mode::NodeDetails => self.handle_node_details_input(ev).await?,
+                    Mode::TaskActions => self.handle_task_actions_input(ev).await?,
+                    Mode::SearchLogs => self.handle_search_input(ev),
                     Mode::Help => {
-                        if let Event::Key(key) = event::read()? {
+                        if let Event::Key(key) = ev {
                             if key.code == KeyCode::Esc || key.code == KeyCode::Char('q') {
                                 self.mode = Mode::Normal;
                             }
@@ -511,6 +576,18 @@ impl<'a> App<'a> {
                     self.mode = Mode::InputAdd;
                     self.textarea = TextArea::default();
                     self.textarea.set_placeholder_text("Enter command...");
+                    self.task_gpus = TextArea::default();
+                    self.task_gpus.set_placeholder_text("0");
+                    self.task_gpus_active = false;
+                },
+                KeyCode::Char('/') if self.focus == Focus::Logs && self.logs_state.maximized => {
+                    self.logs_state.search_input.clear();
+                    self.mode = Mode::SearchLogs;
+                },
+                KeyCode::Char('n') | KeyCode::Char('N')
+                    if self.focus == Focus::Logs && self.logs_state.maximized && self.logs_state.search.is_some() =>
+                {
+                    self.jump_to_match(key.code == KeyCode::Char('n'));
                 },
                 KeyCode::Char('n') => {
                     self.mode = Mode::CreateLease;
@@ -525,7 +602,7 @@ impl<'a> App<'a> {
                 KeyCode::Char('e') => {
                     // Toggle stderr/stdout
                     self.logs_state.show_stderr = !self.logs_state.show_stderr;
-                    self.logs_state.file_pos = 0;
+                    self.logs_state.tail = None;
                     self.logs_state.lines.clear();
                     self.refresh_logs();
                 },
@@ -582,12 +659,66 @@ impl<'a> App<'a> {
                     // Cycle task filter
                     self.cycle_filter();
                 },
+                KeyCode::Char('v') => {
+                    self.show_activity = !self.show_activity;
+                    self.refresh_data();
+                },
                 _ => {}
             }
         }
         Ok(())
     }
 
+    fn handle_search_input(&mut self, event: Event) {
+        if let Event::Key(key) = event {
+            match key.code {
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Enter => {
+                    self.mode = Mode::Normal;
+                    let query = std::mem::take(&mut self.logs_state.search_input);
+                    if query.is_empty() {
+                        self.logs_state.search = None;
+                    } else {
+                        self.logs_state.search = Some(LogSearch::new(query));
+                        self.jump_to_match(true);
+                    }
+                },
+                KeyCode::Backspace => {
+                    self.logs_state.search_input.pop();
+                },
+                KeyCode::Char(c) => self.logs_state.search_input.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// Scroll the zoomed logs to the next (or previous) search match. This
+    /// stops following so the match stays in view.
+    fn jump_to_match(&mut self, forward: bool) {
+        let logs = &mut self.logs_state;
+        let Some(search) = logs.search.as_mut() else {
+            return;
+        };
+        let height = self.log_view_height;
+        // Searching starts from what is on screen, even while following
+        let top = if logs.auto_follow {
+            logs.lines.len().saturating_sub(height)
+        } else {
+            logs.scroll.min(logs.lines.len().saturating_sub(height))
+        };
+        let found = if forward { search.next(&logs.lines, top) } else { search.prev(&logs.lines, top) };
+        match found {
+            Some(line) => {
+                logs.auto_follow = false;
+                logs.scroll = log_search::scroll_to(line, top, height);
+            }
+            None => {
+                let msg = format!("Pattern not found: {}", search.query);
+                self.set_status(msg);
+            }
+        }
+    }
+
     async fn handle_node_details_input(&mut self, event: Event) -> Result<()> {
         if let Event::Key(key) = event {
             match key.code {
@@ -658,7 +789,7 @@ impl<'a> App<'a> {
                              if !self.tasks.is_empty() {
                                 let task = &self.tasks[self.selected_task_idx];
                                 self.logs_state.task_id = Some(task.id.clone());
-                                self.logs_state.file_pos = 0;
+                                self.logs_state.tail = None;
                                 self.logs_state.lines.clear();
                                 self.logs_state.auto_follow = true;
                                 self.refresh_logs();
@@ -670,11 +801,7 @@ impl<'a> App<'a> {
                              // Move file from claimed to inbox
                              if !self.tasks.is_empty() {
                                  let task = &self.tasks[self.selected_task_idx];
-                                 let root = if self.lease_id.starts_with("local:") {
-                                     config::runtime_dir().join(&self.lease_id)
-                                 } else {
-                                     config::leaseq_home_dir().join("runs").join(&self.lease_id)
-                                 };
+                                 let root = config::lease_root(&self.lease_id);
                                  
                                  // We need to find the file in 'claimed'
                                  let claimed_dir = root.join("claimed").join(&task.node);
@@ -715,14 +842,32 @@ impl<'a> App<'a> {
                 KeyCode::Esc => {
                     self.mode = Mode::Normal;
                 },
+                KeyCode::Tab | KeyCode::BackTab => {
+                    self.task_gpus_active = !self.task_gpus_active;
+                },
                 KeyCode::Enter => {
                     let cmd = self.textarea.lines().first().cloned().unwrap_or_default();
+                    let gpus = self.task_gpus.lines().first().cloned().unwrap_or_default();
+                    let gpus = match gpus.trim() {
+                        "" => 0,
+                        n => match n.parse::<u32>() {
+                            Ok(n) => n,
+                            Err(_) => {
+                                self.set_status(format!("Invalid GPU count: {}", n));
+                                return Ok(());
+                            }
+                        },
+                    };
                     if !cmd.trim().is_empty() {
-                        let _ = submit::add_task(cmd, Some(self.lease_id.clone()), None).await;
+                        let opts = submit::SubmitOptions { gpus, ..Default::default() };
+                        let _ = submit::add_task_with_options(cmd, Some(self.lease_id.clone()), None, &opts).await;
                         self.refresh_data();
                     }
                     self.mode = Mode::Normal;
                 },
+                _ if self.task_gpus_active => {
+                    self.task_gpus.input(key);
+                }
                 _ => {
                     self.textarea.input(key);
                 }
@@ -765,6 +910,13 @@ impl<'a> App<'a> {
                         account: None,
                         sbatch_arg: vec![],
                         wait: 0, // Don't wait in TUI mode
+                        self_test: false,
+                        restart_runner_on_fail: None,
+                        dependency: None,
+                        hold: false,
+                        check: false,
+                        comment: None,
+                        metadata: Vec::new(),
                     };
 
                     match lease::create_lease_quiet(args).await {
@@ -796,11 +948,7 @@ impl<'a> App<'a> {
     // ... (handle_input_add, handle_create_lease_input unchanged)
 
     pub fn refresh_data(&mut self) {
-        let root = if self.lease_id.starts_with("local:") {
-            config::runtime_dir().join(&self.lease_id)
-        } else {
-            config::leaseq_home_dir().join("runs").join(&self.lease_id)
-        };
+        let root = config::lease_root(&self.lease_id);
         // eprintln!("DEBUG: Refreshing data for lease {} at root {:?}", self.lease_id, root);
         
         let mut node_status = HashMap::new();
@@ -809,10 +957,14 @@ impl<'a> App<'a> {
         let hb_dir = root.join("hb");
         if let Ok(files) = lfs::list_files_sorted(&hb_dir) {
             for f in files {
-                if let Ok(hb) = lfs::read_json::<models::Heartbeat, _>(&f) {
+                if let Ok(hb) = lfs::read_heartbeat(&f) {
                     let age = (time::OffsetDateTime::now_utc() - hb.ts).as_seconds_f64();
-                    let is_alive = age < 120.0;
-                    let status = if is_alive { "OK" } else { "STALE" };
+                    let is_alive = age < models::NODE_LIVE_THRESHOLD.as_seconds_f64();
+                    let status = match (is_alive, crate::commands::node::is_cordoned(&root, &hb.node)) {
+                        (false, _) => "STALE",
+                        (true, true) => "CORDONED",
+                        (true, false) => "OK",
+                    };
                     new_nodes.push(NodeState {
                         name: hb.node.clone(),
                         status: status.to_string(),
@@ -841,10 +993,12 @@ impl<'a> App<'a> {
                                     new_tasks.push(TaskState {
                                         id: spec.task_id,
                                         command: spec.command,
+                                        resolved_command: String::new(),
                                         cwd: spec.cwd,
                                         state: if is_alive { "RUNNING".to_string() } else { "STUCK".to_string() },
                                         node: node_name.clone(),
                                         exit_code: None,
+                                        signal: None,
                                         gpus_requested: spec.gpus,
                                         gpus_assigned: String::new(), // Not known until done
                                         finished_at: None,
@@ -869,10 +1023,12 @@ impl<'a> App<'a> {
                                     new_tasks.push(TaskState {
                                         id: spec.task_id,
                                         command: spec.command,
+                                        resolved_command: String::new(),
                                         cwd: spec.cwd,
                                         state: "PENDING".to_string(),
                                         node: node_name.clone(),
                                         exit_code: None,
+                                        signal: None,
                                         gpus_requested: spec.gpus,
                                         gpus_assigned: String::new(),
                                         finished_at: None,
@@ -892,14 +1048,19 @@ impl<'a> App<'a> {
                     if entry.path().is_dir() {
                          if let Ok(files) = lfs::list_files_sorted(entry.path()) {
                             for f in files {
-                                if let Ok(res) = lfs::read_json::<models::TaskResult, _>(&f) {
+                                if !models::DoneFileKind::of(&f).is_result() {
+                                    continue;
+                                }
+                                if let Some(res) = quarantine::read_verified_result(&root, &f) {
                                     new_tasks.push(TaskState {
                                         id: res.task_id,
                                         command: res.command,
+                                        resolved_command: res.resolved_command,
                                         cwd: res.cwd,
                                         state: if res.exit_code == 0 { "DONE".to_string() } else { "FAILED".to_string() },
                                         node: res.node,
                                         exit_code: Some(res.exit_code),
+                                        signal: res.signal,
                                         gpus_requested: res.gpus_requested,
                                         gpus_assigned: res.gpus_assigned,
                                         finished_at: Some(res.finished_at),
@@ -932,72 +1093,61 @@ impl<'a> App<'a> {
 
         self.all_tasks = new_tasks;
         self.apply_filter();
+
+        self.activity = if self.show_activity { activity::collect(&root) } else { Vec::new() };
     }
     
     fn refresh_logs(&mut self) {
-        use std::io::{Read, Seek, SeekFrom};
+        const WAITING: &str = "(Waiting for output...)";
 
         let tid = match &self.logs_state.task_id {
             Some(t) => t.clone(),
             None => return,
         };
 
-        let root = if self.lease_id.starts_with("local:") {
-            config::runtime_dir().join(&self.lease_id)
-        } else {
-            config::leaseq_home_dir().join("runs").join(&self.lease_id)
+        let stale = match &self.logs_state.tail {
+            Some(tail) => !tail.is_for(&tid, self.logs_state.show_stderr),
+            None => true,
         };
-
-        let log_path = if self.logs_state.show_stderr {
-            root.join("logs").join(format!("{}.err", tid))
-        } else {
-            root.join("logs").join(format!("{}.out", tid))
-        };
-
-        if !log_path.exists() {
-            if self.logs_state.lines.is_empty() {
-                self.logs_state.lines.push("(Waiting for output...)".to_string());
-            }
-            return;
+        if stale {
+            let root = config::lease_root(&self.lease_id);
+            self.logs_state.lines.clear();
+            self.logs_state.tail = Some(LogTail::spawn(root, tid, self.logs_state.show_stderr, self.cancel.child_token()));
         }
 
-        // Incremental read from last position
-        if let Ok(mut file) = std::fs::File::open(&log_path) {
-            if let Ok(metadata) = file.metadata() {
-                let file_len = metadata.len();
-
-                // If file was truncated, reset
-                if file_len < self.logs_state.file_pos {
-                    self.logs_state.file_pos = 0;
-                    self.logs_state.lines.clear();
-                }
-
-                // Read new content
-                if file_len > self.logs_state.file_pos
-                    && file.seek(SeekFrom::Start(self.logs_state.file_pos)).is_ok()
-                {
-                    let mut new_content = String::new();
-                    if file.read_to_string(&mut new_content).is_ok() {
-                        for line in new_content.lines() {
-                            self.logs_state.lines.push(line.to_string());
-                        }
-                        self.logs_state.file_pos = file_len;
+        // Only drains the channel; file I/O happens on the tail's thread
+        let events = self.logs_state.tail.as_ref().map(|t| t.drain()).unwrap_or_default();
+        for event in events {
+            match event {
+                LogEvent::Source { combined } => self.logs_state.combined = combined,
+                LogEvent::Truncated => self.logs_state.lines.clear(),
+                LogEvent::Lines(new_lines) => {
+                    if self.logs_state.lines.len() == 1 && self.logs_state.lines[0] == WAITING {
+                        self.logs_state.lines.clear();
+                    }
+                    self.logs_state.lines.extend(new_lines);
 
-                        // Auto-scroll to end if following
-                        if self.logs_state.auto_follow && !self.logs_state.lines.is_empty() {
-                            self.logs_state.scroll = self.logs_state.lines.len().saturating_sub(1);
-                        }
+                    // Auto-scroll to end if following
+                    if self.logs_state.auto_follow && !self.logs_state.lines.is_empty() {
+                        self.logs_state.scroll = self.logs_state.lines.len().saturating_sub(1);
                     }
                 }
             }
         }
 
+        if self.logs_state.lines.is_empty() {
+            self.logs_state.lines.push(WAITING.to_string());
+        }
+
         // Limit buffer size (keep last 10000 lines)
         const MAX_LINES: usize = 10000;
         if self.logs_state.lines.len() > MAX_LINES {
             let drain_count = self.logs_state.lines.len() - MAX_LINES;
             self.logs_state.lines.drain(0..drain_count);
             self.logs_state.scroll = self.logs_state.scroll.saturating_sub(drain_count);
+            if let Some(search) = &mut self.logs_state.search {
+                search.shift(drain_count);
+            }
         }
     }
 }
@@ -0,0 +1,124 @@
+//! `/` search in the zoomed logs pane: plain substring matches over the
+//! buffered lines, stepped through with `n`/`N`.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogSearch {
+    pub query: String,
+    /// Line of the match jumped to last, if any
+    pub current: Option<usize>,
+}
+
+impl LogSearch {
+    pub fn new(query: impl Into<String>) -> Self {
+        Self { query: query.into(), current: None }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        !self.query.is_empty() && line.contains(&self.query)
+    }
+
+    /// Jump to the first match after the current one, or at/after `from` if
+    /// there is no current match yet. Wraps around the end of the buffer.
+    pub fn next(&mut self, lines: &[String], from: usize) -> Option<usize> {
+        let start = self.current.map_or(from, |c| c + 1);
+        let n = lines.len();
+        let found = (0..n).map(|i| (start + i) % n).find(|i| self.is_match(&lines[*i]));
+        self.current = found.or(self.current);
+        found
+    }
+
+    /// Jump to the last match before the current one (or before `from`),
+    /// wrapping around the start of the buffer.
+    pub fn prev(&mut self, lines: &[String], from: usize) -> Option<usize> {
+        let start = self.current.unwrap_or(from);
+        let n = lines.len();
+        let found = (1..=n).map(|i| (start + n - i) % n).find(|i| self.is_match(&lines[*i]));
+        self.current = found.or(self.current);
+        found
+    }
+
+    /// 1-based index of the current match and the total, for the pane title.
+    pub fn position(&self, lines: &[String]) -> (usize, usize) {
+        let mut index = 0;
+        let mut total = 0;
+        for (i, line) in lines.iter().enumerate() {
+            if self.is_match(line) {
+                total += 1;
+                if Some(i) == self.current {
+                    index = total;
+                }
+            }
+        }
+        (index, total)
+    }
+
+    /// Keep `current` on the same line after `removed` lines were dropped
+    /// from the front of the buffer.
+    pub fn shift(&mut self, removed: usize) {
+        self.current = self.current.and_then(|c| c.checked_sub(removed));
+    }
+}
+
+/// First visible line so that `line` is on screen: unchanged if it already
+/// is, otherwise with `line` centered.
+pub fn scroll_to(line: usize, scroll: usize, height: usize) -> usize {
+    if line >= scroll && line < scroll + height.max(1) {
+        scroll
+    } else {
+        line.saturating_sub(height / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer() -> Vec<String> {
+        ["epoch 1", "loss nan", "epoch 2", "ok", "loss nan", "epoch 3"].iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_next_and_prev_wrap_around() {
+        let lines = buffer();
+        let mut search = LogSearch::new("nan");
+
+        // The first jump starts from the top of the view
+        assert_eq!(search.next(&lines, 2), Some(4));
+        assert_eq!(search.position(&lines), (2, 2));
+        assert_eq!(search.next(&lines, 2), Some(1));
+        assert_eq!(search.next(&lines, 2), Some(4));
+        assert_eq!(search.prev(&lines, 0), Some(1));
+        assert_eq!(search.prev(&lines, 0), Some(4));
+        assert_eq!(search.position(&lines), (2, 2));
+
+        let mut search = LogSearch::new("epoch");
+        assert_eq!(search.prev(&lines, 3), Some(2));
+        assert_eq!(search.prev(&lines, 3), Some(0));
+        assert_eq!(search.prev(&lines, 3), Some(5));
+    }
+
+    #[test]
+    fn test_no_match_keeps_position() {
+        let lines = buffer();
+        let mut search = LogSearch::new("Traceback");
+        assert_eq!(search.next(&lines, 0), None);
+        assert_eq!(search.prev(&lines, 0), None);
+        assert_eq!(search.position(&lines), (0, 0));
+        assert_eq!(LogSearch::new("").next(&lines, 0), None);
+        assert_eq!(LogSearch::new("x").next(&[], 0), None);
+    }
+
+    #[test]
+    fn test_shift_and_scroll() {
+        let mut search = LogSearch { query: "nan".to_string(), current: Some(4) };
+        search.shift(3);
+        assert_eq!(search.current, Some(1));
+        search.shift(2);
+        assert_eq!(search.current, None);
+
+        // Visible matches don't move the view; others are centered
+        assert_eq!(scroll_to(12, 10, 5), 10);
+        assert_eq!(scroll_to(40, 10, 10), 35);
+        assert_eq!(scroll_to(2, 10, 10), 0);
+    }
+}
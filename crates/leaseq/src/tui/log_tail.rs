@@ -0,0 +1,251 @@
+use crate::commands::{follow, logs};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// Batches the reader may get ahead of the UI before it waits.
+const CHANNEL_CAPACITY: usize = 64;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How many polls a dangling partial line waits between checks for the task's
+/// result, which means reading every result of the lease.
+const FINISHED_CHECK_POLLS: u32 = 8;
+
+#[derive(Debug, PartialEq)]
+pub enum LogEvent {
+    /// Which file is being tailed: the combined `.log` or a single stream
+    Source { combined: bool },
+    /// The file shrank; lines received so far are stale
+    Truncated,
+    Lines(Vec<String>),
+}
+
+/// Tails a task log on a background thread so slow filesystems never stall the
+/// draw loop. Dropping it or cancelling `stop` stops the thread.
+pub struct LogTail {
+    task_id: String,
+    stderr: bool,
+    rx: Receiver<LogEvent>,
+    stop: CancellationToken,
+}
+
+impl LogTail {
+    pub fn spawn(root: PathBuf, task_id: String, stderr: bool, stop: CancellationToken) -> Self {
+        let (tx, rx) = mpsc::sync_channel(CHANNEL_CAPACITY);
+
+        let thread_stop = stop.clone();
+        let thread_task = task_id.clone();
+        std::thread::spawn(move || {
+            let mut pos = 0u64;
+            let mut partial = Vec::new();
+            let mut combined = None;
+            let mut idle_polls = 0u32;
+
+            while !thread_stop.is_cancelled() {
+                let path = logs::log_path(&root, &thread_task, stderr);
+                let is_combined = path.extension().map(|e| e == "log").unwrap_or(false);
+                if combined != Some(is_combined) {
+                    combined = Some(is_combined);
+                    if tx.send(LogEvent::Source { combined: is_combined }).is_err() {
+                        return;
+                    }
+                }
+
+                let finished = || {
+                    idle_polls += 1;
+                    idle_polls.is_multiple_of(FINISHED_CHECK_POLLS) && follow::latest_result(&root, &thread_task).is_some()
+                };
+                for event in read_new(&path, &mut pos, &mut partial, finished) {
+                    // Receiver gone: the UI moved on to another log
+                    if tx.send(event).is_err() {
+                        return;
+                    }
+                }
+
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        Self { task_id, stderr, rx, stop }
+    }
+
+    /// Whether this tail is reading the given task/stream.
+    pub fn is_for(&self, task_id: &str, stderr: bool) -> bool {
+        self.task_id == task_id && self.stderr == stderr
+    }
+
+    /// Everything received so far. Never touches the filesystem.
+    pub fn drain(&self) -> Vec<LogEvent> {
+        self.rx.try_iter().collect()
+    }
+}
+
+impl Drop for LogTail {
+    fn drop(&mut self) {
+        self.stop.cancel();
+    }
+}
+
+/// Incremental read from `pos`, resetting if the file was truncated. A `\r`
+/// ends a line too, so progress bars that redraw in place show each redraw.
+/// A last line still missing its newline waits in `partial` for the rest, so
+/// it is never shown in pieces; it is sent as is once `finished` says the
+/// task is done and no more is coming.
+fn read_new(path: &Path, pos: &mut u64, partial: &mut Vec<u8>, finished: impl FnOnce() -> bool) -> Vec<LogEvent> {
+    let mut events = Vec::new();
+
+    let Ok(mut file) = std::fs::File::open(path) else {
+        return events;
+    };
+    let Ok(metadata) = file.metadata() else {
+        return events;
+    };
+    let file_len = metadata.len();
+
+    if file_len < *pos {
+        *pos = 0;
+        partial.clear();
+        events.push(LogEvent::Truncated);
+    }
+
+    let mut lines = Vec::new();
+    if file_len > *pos {
+        let mut new_content = Vec::new();
+        if file.seek(SeekFrom::Start(*pos)).is_ok() && file.read_to_end(&mut new_content).is_ok() {
+            *pos += new_content.len() as u64;
+            partial.extend_from_slice(&new_content);
+            // Cut at a line end, so multi-byte characters are never split either
+            if let Some(end) = partial.iter().rposition(|b| *b == b'\n' || *b == b'\r') {
+                let complete: Vec<u8> = partial.drain(..=end).collect();
+                lines.extend(split_lines(&complete));
+            }
+        }
+    } else if !partial.is_empty() && finished() {
+        lines.extend(split_lines(partial));
+        partial.clear();
+    }
+    if !lines.is_empty() {
+        events.push(LogEvent::Lines(lines));
+    }
+
+    events
+}
+
+/// Lines ended by `\n`, `\r\n` or a lone `\r`; empty lines are kept, but not
+/// the empty pieces around a `\r`.
+fn split_lines(bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .flat_map(|line| {
+            let pieces: Vec<String> = line.split('\r').filter(|p| !p.is_empty()).map(String::from).collect();
+            if pieces.is_empty() { vec![String::new()] } else { pieces }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn wait_for_lines(tail: &LogTail, want: usize) -> (Vec<String>, bool) {
+        let deadline = Instant::now() + Duration::from_secs(3);
+        let mut lines = Vec::new();
+        let mut truncated = false;
+        while lines.len() < want && Instant::now() < deadline {
+            for event in tail.drain() {
+                match event {
+                    LogEvent::Lines(l) => lines.extend(l),
+                    LogEvent::Truncated => {
+                        truncated = true;
+                        lines.clear();
+                    }
+                    LogEvent::Source { .. } => {}
+                }
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        (lines, truncated)
+    }
+
+    #[test]
+    fn test_lines_flow_through_channel() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        std::fs::create_dir_all(root.join("logs")).unwrap();
+        let log = root.join("logs").join("T1.out");
+
+        // Started before the file exists, like a freshly claimed task
+        let tail = LogTail::spawn(root.clone(), "T1".to_string(), false, CancellationToken::new());
+        assert!(tail.is_for("T1", false));
+
+        std::fs::write(&log, "a\nb\n").unwrap();
+        let (lines, _) = wait_for_lines(&tail, 2);
+        assert_eq!(lines, vec!["a", "b"]);
+
+        // Only the appended part is sent
+        let mut f = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+        std::io::Write::write_all(&mut f, b"c\n").unwrap();
+        let (lines, _) = wait_for_lines(&tail, 1);
+        assert_eq!(lines, vec!["c"]);
+
+        std::fs::write(&log, "x\n").unwrap();
+        let (lines, truncated) = wait_for_lines(&tail, 1);
+        assert!(truncated);
+        assert_eq!(lines, vec!["x"]);
+    }
+
+    #[test]
+    fn test_partial_line_waits_for_its_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("T1.out");
+        std::fs::write(&log, "").unwrap();
+        let (mut pos, mut partial) = (0, Vec::new());
+        let mut feed = |bytes: &[u8], finished: bool| {
+            let mut f = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+            std::io::Write::write_all(&mut f, bytes).unwrap();
+            let mut lines = Vec::new();
+            for event in read_new(&log, &mut pos, &mut partial, || finished) {
+                match event {
+                    LogEvent::Lines(l) => lines.extend(l),
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+            lines
+        };
+
+        assert!(feed(b"epo", false).is_empty());
+        assert!(feed(b"ch 1 lo", false).is_empty());
+        assert_eq!(feed(b"ss=0.5\nepoch 2", false), vec!["epoch 1 loss=0.5"]);
+        // Split inside the two bytes of an 'é'
+        assert!(feed(b" \xc3", false).is_empty());
+        assert_eq!(feed(b"\xa9t\n", false), vec!["epoch 2 \u{00e9}t"]);
+        // A final line without a newline shows once the task is done
+        assert!(feed(b"done", false).is_empty());
+        assert!(feed(b"", false).is_empty());
+        assert_eq!(feed(b"", true), vec!["done"]);
+        assert!(feed(b"", true).is_empty());
+    }
+
+    #[test]
+    fn test_carriage_return_ends_a_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("T1.out");
+        // A progress bar redrawing in place, then a normal line
+        std::fs::write(&log, "10%|#  |\r50%|## |\r100%|###|\r\nok\r\n\nnext").unwrap();
+        let (mut pos, mut partial) = (0, Vec::new());
+        let events = read_new(&log, &mut pos, &mut partial, || false);
+        assert_eq!(
+            events,
+            vec![LogEvent::Lines(vec![
+                "10%|#  |".to_string(),
+                "50%|## |".to_string(),
+                "100%|###|".to_string(),
+                "ok".to_string(),
+                String::new(),
+            ])]
+        );
+        assert_eq!(partial, b"next");
+    }
+}
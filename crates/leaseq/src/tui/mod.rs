@@ -1,4 +1,7 @@
+pub mod activity;
 pub mod app;
+pub mod log_search;
+pub mod log_tail;
 pub mod ui;
 
 use anyhow::Result;
@@ -6,7 +6,9 @@ use ratatui::{
     Frame,
 };
 
+use crate::tui::activity::ActivityKind;
 use crate::tui::app::{App, Focus, Mode, NodeModalAction, TaskModalAction};
+use leaseq_core::models;
 
 fn truncate_str(s: &str, max_len: usize) -> String {
     if s.len() > max_len {
@@ -16,7 +18,17 @@ fn truncate_str(s: &str, max_len: usize) -> String {
     }
 }
 
+/// Smallest terminal the normal layout fits: header (3) + panes (8) + logs (10) + footer (1).
+pub const MIN_WIDTH: u16 = 60;
+pub const MIN_HEIGHT: u16 = 22;
+
 pub fn draw(f: &mut Frame, app: &mut App) {
+    let area = f.area();
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        draw_too_small(f, area);
+        return;
+    }
+
     if app.logs_state.maximized {
         // Maximized logs view: header + logs + footer
         let chunks = Layout::default()
@@ -57,7 +69,19 @@ pub fn draw(f: &mut Frame, app: &mut App) {
         draw_nodes(f, app, top_row[0]);
         draw_tasks(f, app, top_row[1]);
         draw_task_detail(f, app, top_row[2]);
-        draw_logs(f, app, chunks[2]);
+        if app.show_activity {
+            let bottom_row = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Percentage(60),  // Logs
+                    Constraint::Percentage(40),  // Activity
+                ])
+                .split(chunks[2]);
+            draw_logs(f, app, bottom_row[0]);
+            draw_activity(f, app, bottom_row[1]);
+        } else {
+            draw_logs(f, app, chunks[2]);
+        }
         draw_footer(f, app, chunks[3]);
     }
 
@@ -82,6 +106,18 @@ pub fn draw(f: &mut Frame, app: &mut App) {
     }
 }
 
+/// Shown instead of the layout when the fixed-height panes wouldn't fit.
+fn draw_too_small(f: &mut Frame, area: Rect) {
+    let msg = vec![
+        Line::from("Terminal too small"),
+        Line::from(format!("{}x{}, need {}x{}", area.width, area.height, MIN_WIDTH, MIN_HEIGHT)),
+    ];
+    let p = Paragraph::new(msg)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(p, area);
+}
+
 fn draw_header(f: &mut Frame, app: &App, area: Rect) {
     let title = Paragraph::new(format!(" LeaseQ Monitor | Lease: {} ", app.lease_id))
         .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
@@ -201,7 +237,11 @@ fn draw_task_detail(f: &mut Frame, app: &App, area: Rect) {
             _ => Color::White,
         };
 
-        let exit_str = task.exit_code.map(|c| format!("{}", c)).unwrap_or_else(|| "-".to_string());
+        let exit_str = match (task.exit_code, task.signal) {
+            (Some(c), Some(s)) => format!("{} (killed by {})", c, models::signal_name(s)),
+            (Some(c), None) => format!("{}", c),
+            _ => "-".to_string(),
+        };
 
         // GPU display
         let gpu_str = if task.gpus_requested == 0 {
@@ -213,7 +253,7 @@ fn draw_task_detail(f: &mut Frame, app: &App, area: Rect) {
         };
 
         // Vertical layout for column display
-        let lines = vec![
+        let mut lines = vec![
             Line::from(vec![
                 Span::styled("ID: ", Style::default().fg(Color::DarkGray)),
                 Span::styled(&task.id, Style::default().add_modifier(Modifier::BOLD)),
@@ -246,6 +286,11 @@ fn draw_task_detail(f: &mut Frame, app: &App, area: Rect) {
                 Span::styled(&task.command, Style::default().fg(Color::Cyan)),
             ]),
         ];
+        if !task.resolved_command.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::from(vec![Span::styled("Ran:", Style::default().fg(Color::DarkGray))]));
+            lines.push(Line::from(vec![Span::raw(&task.resolved_command)]));
+        }
 
         let p = Paragraph::new(lines).block(block).wrap(Wrap { trim: false });
         f.render_widget(p, area);
@@ -262,10 +307,28 @@ fn draw_logs(f: &mut Frame, app: &mut App, area: Rect) {
     let border_style = if is_focused { Style::default().fg(Color::Yellow) } else { Style::default() };
 
     let task_label = app.logs_state.task_id.as_deref().unwrap_or("(none)");
-    let stream = if app.logs_state.show_stderr { "stderr" } else { "stdout" };
+    let stream = if app.logs_state.combined {
+        "combined"
+    } else if app.logs_state.show_stderr {
+        "stderr"
+    } else {
+        "stdout"
+    };
     let follow_indicator = if app.logs_state.auto_follow { " [FOLLOW]" } else { "" };
     let max_indicator = if app.logs_state.maximized { " [MAX]" } else { "" };
-    let title = format!(" Logs: {} ({}){}{}  ", task_label, stream, follow_indicator, max_indicator);
+    // Matches are only highlighted in the zoomed view, where n/N work
+    let search = app.logs_state.search.as_ref().filter(|_| app.logs_state.maximized);
+    let search_indicator = match search {
+        Some(s) => {
+            let (index, total) = s.position(&app.logs_state.lines);
+            format!(" [/{} {}/{}]", s.query, index, total)
+        }
+        None => String::new(),
+    };
+    let title = format!(
+        " Logs: {} ({}){}{}{}  ",
+        task_label, stream, follow_indicator, max_indicator, search_indicator
+    );
 
     let block = Block::default()
         .borders(Borders::ALL)
@@ -287,9 +350,13 @@ fn draw_logs(f: &mut Frame, app: &mut App, area: Rect) {
         .logs_state
         .lines
         .iter()
+        .enumerate()
         .skip(start)
         .take(inner_height)
-        .map(|s| Line::from(s.as_str()))
+        .map(|(i, line)| match search {
+            Some(s) if s.is_match(line) => highlight_matches(line, &s.query, s.current == Some(i)),
+            _ => Line::from(line.as_str()),
+        })
         .collect();
 
     let p = Paragraph::new(visible_lines)
@@ -299,9 +366,55 @@ fn draw_logs(f: &mut Frame, app: &mut App, area: Rect) {
     f.render_widget(p, area);
 }
 
+/// The newest entries of the lease's activity feed, latest at the bottom.
+fn draw_activity(f: &mut Frame, app: &App, area: Rect) {
+    let inner_height = area.height.saturating_sub(2) as usize;
+    let start = app.activity.len().saturating_sub(inner_height);
+    let lines: Vec<Line> = app.activity[start..]
+        .iter()
+        .map(|a| {
+            let color = match a.kind {
+                ActivityKind::Finished => Color::Green,
+                ActivityKind::Failed => Color::Red,
+                ActivityKind::Cancelled => Color::Yellow,
+                ActivityKind::Skipped => Color::DarkGray,
+                ActivityKind::Claimed | ActivityKind::Started => Color::Cyan,
+            };
+            Line::styled(a.line(), Style::default().fg(color))
+        })
+        .collect();
+
+    let p = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Activity (UTC) "));
+    f.render_widget(p, area);
+}
+
+/// `line` with each occurrence of `query` highlighted; brighter on the
+/// line of the current match.
+fn highlight_matches<'a>(line: &'a str, query: &str, current: bool) -> Line<'a> {
+    let bg = if current { Color::LightRed } else { Color::Yellow };
+    let style = Style::default().fg(Color::Black).bg(bg);
+    let mut spans = Vec::new();
+    let mut rest = line;
+    while let Some(at) = rest.find(query) {
+        if at > 0 {
+            spans.push(Span::raw(&rest[..at]));
+        }
+        spans.push(Span::styled(&rest[at..at + query.len()], style));
+        rest = &rest[at + query.len()..];
+    }
+    if !rest.is_empty() {
+        spans.push(Span::raw(rest));
+    }
+    Line::from(spans)
+}
+
 fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
-    // Show status message if present, otherwise show help
-    if let Some((msg, _)) = &app.status_message {
+    // The search prompt replaces everything else while typing
+    if app.mode == Mode::SearchLogs {
+        let p = Paragraph::new(format!("/{}", app.logs_state.search_input))
+            .style(Style::default().fg(Color::Yellow));
+        f.render_widget(p, area);
+    } else if let Some((msg, _)) = &app.status_message {
         let p = Paragraph::new(msg.as_str())
             .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
             .alignment(Alignment::Center);
@@ -311,10 +424,10 @@ fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
             if app.logs_state.auto_follow {
                 "Enter/z:Minimize | f:Static | e:Stderr | g:Top | Backspace:Tasks | q:Quit | ?:Help"
             } else {
-                "Enter/z:Minimize | f:Follow | e:Stderr | j/k:Scroll | ^u/d:Page | g/G:Jump | q:Quit"
+                "Enter/z:Minimize | f:Follow | j/k:Scroll | ^u/d:Page | g/G:Jump | /:Search | n/N:Match | q:Quit"
             }
         } else {
-            "h/j/k/l:Nav | Enter:Select | z:Zoom | F:Filter | a:Add | n:Lease | e:Stderr | q:Quit | ?:Help"
+            "h/j/k/l:Nav | Enter:Select | z:Zoom | F:Filter | v:Activity | a:Add | n:Lease | e:Stderr | q:Quit | ?:Help"
         };
         let p = Paragraph::new(text)
             .style(Style::default().fg(Color::DarkGray))
@@ -324,15 +437,26 @@ fn draw_footer(f: &mut Frame, app: &App, area: Rect) {
 }
 
 fn draw_add_task_popup(f: &mut Frame, app: &App) {
-    let block = Block::default()
-        .borders(Borders::ALL)
-        .title(" Add Task ")
-        .style(Style::default().fg(Color::Cyan));
-    let area = centered_rect(60, 20, f.area());
+    let area = centered_rect(60, 25, f.area());
     f.render_widget(Clear, area); // Clear background
-    #[allow(deprecated)]
-    f.render_widget(app.textarea.widget(), block.inner(area));
-    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)])
+        .split(area);
+
+    let inputs = [
+        (" Add Task (Tab to cycle, Enter to Submit) ", &app.textarea, !app.task_gpus_active),
+        ("GPUs", &app.task_gpus, app.task_gpus_active),
+    ];
+    for (i, (label, textarea, is_active)) in inputs.into_iter().enumerate() {
+        let style = if is_active { Style::default().fg(Color::Yellow) } else { Style::default().fg(Color::Cyan) };
+        let block = Block::default().borders(Borders::ALL).title(label).style(style);
+
+        #[allow(deprecated)]
+        f.render_widget(textarea.widget(), block.inner(chunks[i]));
+        f.render_widget(block, chunks[i]);
+    }
 }
 
 fn draw_create_lease_popup(f: &mut Frame, app: &App) {
@@ -531,10 +655,12 @@ fn draw_help_popup(f: &mut Frame) {
         "  g        Jump to start",
         "  G        Jump to end (enables follow)",
         "  f        Toggle follow/static mode",
+        "  /        Search (zoomed); n/N next/previous match",
         "",
         "Other:",
         "  z        Toggle zoom logs",
         "  e        Toggle stdout/stderr",
+        "  v        Toggle activity pane (claims, starts, finishes)",
         "  q        Quit",
         "  ?        Show this help",
         "  Esc      Close popups",
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use leaseq::defaults::{self, Defaults};
+use std::fs;
+use std::path::Path;
+
+fn leaseq(home: &Path, cwd: &Path, args: &[&str]) -> Result<std::process::Output> {
+    Ok(std::process::Command::new(env!("CARGO_BIN_EXE_leaseq"))
+        .args(args)
+        .current_dir(cwd)
+        .env("LEASEQ_RUNTIME_DIR", home.join("runtime"))
+        .env("LEASEQ_HOME", home.join("home"))
+        .output()?)
+}
+
+#[test]
+fn test_project_config_sets_default_lease() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let home = temp_dir.path();
+    let project = home.join("project");
+    let subdir = project.join("src").join("deep");
+    fs::create_dir_all(&subdir)?;
+    fs::write(project.join(defaults::PROJECT_FILE), "lease = \"local:from-project\"\n")?;
+
+    let lease_of = |args: &[&str]| -> Result<String> {
+        let out = leaseq(home, &subdir, args)?;
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        let stdout = String::from_utf8(out.stdout)?;
+        let line = stdout.lines().find(|l| l.starts_with("lease:")).expect("lease line");
+        Ok(line.trim_start_matches("lease:").trim().to_string())
+    };
+
+    // Found by walking up from a subdirectory
+    assert_eq!(lease_of(&["submit", "--dry-run", "--", "true"])?, "local:from-project");
+    // The flag wins
+    assert_eq!(lease_of(&["submit", "--dry-run", "--lease", "local:flag", "--", "true"])?, "local:flag");
+    Ok(())
+}
+
+#[test]
+fn test_config_precedence_and_errors() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let outer = temp_dir.path();
+    let inner = outer.join("inner");
+    fs::create_dir_all(&inner)?;
+    fs::write(outer.join(defaults::PROJECT_FILE), "lease = \"outer\"\npartition = \"gpu\"\n")?;
+    fs::write(inner.join(defaults::PROJECT_FILE), "lease = \"inner\"\ngpus_per_node = 4\n")?;
+
+    // Nearer files win key by key; farther ones fill the gaps
+    let loaded = defaults::load(&inner)?;
+    assert_eq!(loaded.lease.as_deref(), Some("inner"));
+    assert_eq!(loaded.partition.as_deref(), Some("gpu"));
+    assert_eq!(loaded.gpus_per_node, Some(4));
+
+    let mut args = leaseq::commands::lease::CreateLeaseArgs {
+        nodes: 1,
+        time: None,
+        partition: Some("cpu".to_string()),
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: Vec::new(),
+        wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
+    };
+    loaded.apply_to_create(&mut args, false);
+    assert_eq!(args.partition.as_deref(), Some("cpu"));
+    assert_eq!(args.gpus_per_node, 4);
+
+    // A typo is reported rather than ignored
+    fs::write(inner.join(defaults::PROJECT_FILE), "leese = \"inner\"\n")?;
+    let err = defaults::load(&inner).unwrap_err();
+    assert!(format!("{:#}", err).contains("leese"), "{:#}", err);
+    assert_eq!(defaults::read(&outer.join(defaults::PROJECT_FILE))?, Defaults {
+        lease: Some("outer".to_string()),
+        partition: Some("gpu".to_string()),
+        ..Default::default()
+    });
+    Ok(())
+}
+
+#[test]
+fn test_broken_config_only_fails_commands_that_use_it() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let home = temp_dir.path();
+    let project = home.join("project");
+    fs::create_dir_all(&project)?;
+    fs::write(project.join(defaults::PROJECT_FILE), "lease = \n")?;
+
+    // Nothing of the daemon's is configured there, so it isn't read
+    let out = leaseq(home, &project, &["daemon", "status"])?;
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let out = leaseq(home, &project, &["submit", "--dry-run", "--", "true"])?;
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(defaults::PROJECT_FILE));
+    Ok(())
+}
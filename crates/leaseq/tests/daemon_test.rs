@@ -0,0 +1,118 @@
+use anyhow::Result;
+use leaseq::commands;
+use leaseq_core::{fs as lfs, models};
+use std::fs;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+
+const NODE: &str = "node-fg";
+
+#[tokio::test]
+async fn test_foreground_runner_processes_task_and_stops_on_signal() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path().join("lease");
+    let inbox = root.join("inbox").join(NODE);
+    fs::create_dir_all(&inbox)?;
+
+    let spec = models::TaskSpec {
+        task_id: "T-FG".to_string(),
+        idempotency_key: "key-fg".to_string(),
+        lease_id: models::LeaseId("local:fg".to_string()),
+        target_node: NODE.to_string(),
+        seq: 1,
+        created_at: OffsetDateTime::now_utc(),
+        cwd: ".".to_string(),
+        command: "echo foreground".to_string(),
+        ..Default::default()
+    };
+    lfs::atomic_write_json(inbox.join("0000000000000001_T-FG.json"), &spec)?;
+
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let args = commands::run::RunArgs {
+        lease: "local:fg".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(root.clone()),
+        ..Default::default()
+    };
+    let handle = tokio::spawn(commands::run::run_with_shutdown(args, shutdown_rx));
+
+    // Wait for the task to be processed
+    let result_path = root.join("done").join(NODE).join("0000000000000001_T-FG.result.json");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !result_path.exists() && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let result: models::TaskResult = lfs::read_json(&result_path)?;
+    assert_eq!(result.exit_code, 0);
+
+    // Signal shutdown: the runner returns Ok promptly instead of looping forever
+    shutdown_tx.send(true)?;
+    let res = tokio::time::timeout(Duration::from_secs(3), handle)
+        .await
+        .expect("runner should stop after shutdown signal")?;
+    assert!(res.is_ok());
+
+    // Final heartbeat shows nothing running
+    let hb: models::Heartbeat = lfs::read_json(root.join("hb").join(format!("{}.json", NODE)))?;
+    assert_eq!(hb.running_task_id, None);
+
+    Ok(())
+}
+
+/// Alive and not a zombie waiting to be reaped.
+#[cfg(target_os = "linux")]
+fn process_alive(pid: i32) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => !stat.rsplit(") ").next().is_some_and(|rest| rest.starts_with('Z')),
+        Err(_) => false,
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_daemon_stop_kills_running_task() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let leaseq = |args: &[&str]| -> Result<std::process::Output> {
+        Ok(std::process::Command::new(env!("CARGO_BIN_EXE_leaseq"))
+            .args(args)
+            .env("LEASEQ_RUNTIME_DIR", temp_dir.path().join("runtime"))
+            .env("LEASEQ_HOME", temp_dir.path().join("home"))
+            .output()?)
+    };
+
+    let started = leaseq(&["daemon", "start"])?;
+    assert!(started.status.success(), "{}", String::from_utf8_lossy(&started.stderr));
+
+    let pid_file = temp_dir.path().join("task.pid");
+    let command = format!("echo $$ > {}; exec sleep 300", pid_file.display());
+    let submitted = leaseq(&["submit", "--", &command])?;
+    assert!(submitted.status.success(), "{}", String::from_utf8_lossy(&submitted.stderr));
+
+    let deadline = Instant::now() + Duration::from_secs(15);
+    let pid = loop {
+        if let Ok(pid) = fs::read_to_string(&pid_file).map(|s| s.trim().parse::<i32>()) {
+            break pid?;
+        }
+        if Instant::now() > deadline {
+            leaseq(&["daemon", "stop"])?;
+            panic!("task did not start");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    assert!(process_alive(pid));
+
+    let stopped = leaseq(&["daemon", "stop"])?;
+    assert!(stopped.status.success());
+    assert!(String::from_utf8_lossy(&stopped.stdout).contains("Stopped daemon"), "{}", String::from_utf8_lossy(&stopped.stdout));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while process_alive(pid) && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(!process_alive(pid), "task process {} outlived the daemon", pid);
+
+    // Stopping again is a no-op
+    let again = leaseq(&["daemon", "stop"])?;
+    assert!(again.status.success());
+    Ok(())
+}
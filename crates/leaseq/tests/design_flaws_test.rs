@@ -54,6 +54,7 @@ async fn test_zombie_tasks_crash_recovery() -> Result<()> {
         env: std::collections::HashMap::new(),
         gpus: 0,
         command: "echo 'I should be recovered'".to_string(),
+        ..Default::default()
     };
     
     // Write directly to CLAIMED (simulating the crash state)
@@ -67,6 +68,7 @@ async fn test_zombie_tasks_crash_recovery() -> Result<()> {
         lease: lease_id.to_string(),
         node: Some(node.to_string()),
         root: None,
+        ..Default::default()
     });
 
     // Run for a short time
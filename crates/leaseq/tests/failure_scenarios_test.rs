@@ -61,9 +61,14 @@ async fn test_add_picks_dead_node() -> Result<()> {
         node: dead_node.to_string(),
         ts: old_time,
         running_task_id: None,
+        running_task_ids: Vec::new(),
         pending_estimate: 0,
         runner_pid: 1234,
         version: "0.1.0".to_string(),
+        schema_version: models::SCHEMA_VERSION,
+        cpus: 0,
+        errors_since_start: 0,
+        last_error: None,
     };
     lfs::atomic_write_json(&hb_file, &hb)?;
 
@@ -76,13 +81,65 @@ async fn test_add_picks_dead_node() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_add_routes_to_fresh_node() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "job-fresh-node-test";
+    let runs_dir = ctx.home.join("runs").join(lease_id);
+    let hb_dir = runs_dir.join("hb");
+
+    // The stale node sorts first, so taking the first heartbeat would pick it
+    let now = OffsetDateTime::now_utc();
+    for (node, ts) in [("a-stale-node", now - time::Duration::hours(1)), ("b-fresh-node", now)] {
+        let hb = models::Heartbeat {
+            node: node.to_string(),
+            ts,
+            running_task_id: None,
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1234,
+            version: "0.1.0".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        };
+        lfs::atomic_write_json(hb_dir.join(format!("{}.json", node)), &hb)?;
+    }
+
+    commands::submit::run(vec!["echo".to_string(), "foo".to_string()], Some(lease_id.to_string()), None).await?;
+    assert_eq!(lfs::list_files_sorted(runs_dir.join("inbox").join("b-fresh-node"))?.len(), 1);
+    assert!(!runs_dir.join("inbox").join("a-stale-node").exists());
+
+    // With --detach-check an explicit stale node is refused
+    let opts = commands::submit::SubmitOptions { detach_check: true, ..Default::default() };
+    let err = commands::submit::run_with_options(
+        vec!["true".to_string()],
+        Some(lease_id.to_string()),
+        Some("a-stale-node".to_string()),
+        opts,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("not live"), "{}", err);
+
+    // With no fresh node at all the error names the stale ones
+    fs::remove_file(hb_dir.join("b-fresh-node.json"))?;
+    let err = commands::submit::run(vec!["true".to_string()], Some(lease_id.to_string()), None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("No active nodes found"));
+    assert!(err.to_string().contains("a-stale-node"), "{}", err);
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_multiple_runners_concurrency() -> Result<()> {
     let ctx = TestContext::new()?;
     let lease_id = "local:concurrent";
     
     // We will manually inject tasks for two nodes to simulate load distribution
-    // (since 'add' doesn't round-robin yet)
+    // (pinned per node rather than left to node selection)
     let node1 = "node-1";
     let node2 = "node-2";
     
@@ -104,6 +161,7 @@ async fn test_multiple_runners_concurrency() -> Result<()> {
             env: std::collections::HashMap::new(),
             gpus: 0,
             command: format!("echo executed on {}", node),
+            ..Default::default()
         };
         let f = inbox.join("task.json");
         lfs::atomic_write_json(&f, &spec)?;
@@ -114,12 +172,14 @@ async fn test_multiple_runners_concurrency() -> Result<()> {
         lease: lease_id.to_string(),
         node: Some(node1.to_string()),
         root: None,
+        ..Default::default()
     });
     
     let run_node2 = commands::run::run(commands::run::RunArgs {
         lease: lease_id.to_string(),
         node: Some(node2.to_string()),
         root: None,
+        ..Default::default()
     });
 
     // Let them run for a bit (they loop forever, so we need to timeout)
@@ -166,6 +226,7 @@ async fn test_blocking_task_heartbeat_gap() -> Result<()> {
         lease: lease_id.to_string(),
         node: Some(node.to_string()),
         root: None,
+        ..Default::default()
     });
     
     // We want to sample the heartbeat file WHILE it is running.
@@ -176,14 +237,17 @@ async fn test_blocking_task_heartbeat_gap() -> Result<()> {
         
         let hb_file = ctx.runtime.join(lease_id).join("hb").join(format!("{}.json", node));
         
-        // Read initial heartbeat
+        // Read initial heartbeat; the claim is published without waiting for a beat
         let hb1: models::Heartbeat = lfs::read_json(&hb_file).expect("HB file missing");
+        assert!(hb1.running_task_id.is_some(), "{:?}", hb1);
+        assert_eq!(hb1.runner_pid, std::process::id());
         
         // Wait 5.5s (task still sleeping, HB interval is 5s, so it should update)
         tokio::time::sleep(Duration::from_millis(5500)).await;
         
         // Read again
         let hb2: models::Heartbeat = lfs::read_json(&hb_file)?;
+        assert_eq!(hb2.running_task_id, hb1.running_task_id);
         
         // NOW we expect hb2.ts > hb1.ts because background thread should be updating it!
         
@@ -210,3 +274,244 @@ async fn test_blocking_task_heartbeat_gap() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_strict_submit_refuses_unresolved_node() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "local:strict-test";
+    fs::create_dir_all(ctx.runtime.join(lease_id))?;
+    let submit = |strict: bool| {
+        let opts = commands::submit::SubmitOptions { strict, dry_run: true, quiet: true, ..Default::default() };
+        commands::submit::run_with_options(vec!["true".to_string()], Some(lease_id.to_string()), None, opts)
+    };
+
+    // Without a runner the local lease still falls back to this host...
+    submit(false).await?;
+    // ...but --strict won't guess
+    let err = submit(true).await.unwrap_err();
+    assert!(err.to_string().contains("--strict: no node could be resolved"), "{}", err);
+
+    // An explicit node is not a fallback
+    let opts = commands::submit::SubmitOptions { strict: true, dry_run: true, ..Default::default() };
+    commands::submit::run_with_options(vec!["true".to_string()], Some(lease_id.to_string()), Some("node-x".to_string()), opts)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_routing_skips_cordoned_node() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "job-cordon-test";
+    let runs_dir = ctx.home.join("runs").join(lease_id);
+    let hb_dir = runs_dir.join("hb");
+
+    // Both healthy; a-node sorts first and would be picked
+    for node in ["a-node", "b-node"] {
+        let hb = models::Heartbeat {
+            node: node.to_string(),
+            ts: OffsetDateTime::now_utc(),
+            running_task_id: None,
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1234,
+            version: "0.1.0".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        };
+        lfs::atomic_write_json(hb_dir.join(format!("{}.json", node)), &hb)?;
+    }
+    commands::node::cordon(&runs_dir, "a-node")?;
+
+    commands::submit::run(vec!["true".to_string()], Some(lease_id.to_string()), None).await?;
+    assert_eq!(lfs::list_files_sorted(runs_dir.join("inbox").join("b-node"))?.len(), 1);
+    assert!(!runs_dir.join("inbox").join("a-node").exists());
+
+    let report = commands::status::collect(lease_id, &runs_dir)?;
+    let cordoned: Vec<_> = report.nodes.iter().filter(|n| n.cordoned).map(|n| n.node.as_str()).collect();
+    assert_eq!(cordoned, ["a-node"]);
+
+    // An explicit --node still reaches it, and uncordon restores routing
+    commands::submit::run(vec!["true".to_string()], Some(lease_id.to_string()), Some("a-node".to_string())).await?;
+    assert_eq!(lfs::list_files_sorted(runs_dir.join("inbox").join("a-node"))?.len(), 1);
+    commands::node::uncordon(&runs_dir, "a-node")?;
+    assert!(!commands::node::is_cordoned(&runs_dir, "a-node"));
+
+    // A node name can't lead the marker out of the lease
+    assert!(commands::node::cordon(&runs_dir, "../../escaped").is_err());
+    assert!(!runs_dir.join("control").join("../../escaped").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_by_uuid() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "job-cancel-uuid-test";
+    let runs_dir = ctx.home.join("runs").join(lease_id);
+    for _ in 0..2 {
+        commands::submit::run(vec!["true".to_string()], Some(lease_id.to_string()), Some("node-a".to_string())).await?;
+    }
+    let inbox = runs_dir.join("inbox").join("node-a");
+    let specs: Vec<models::TaskSpec> =
+        lfs::list_files_sorted(&inbox)?.iter().map(lfs::read_json).collect::<std::io::Result<_>>()?;
+    assert_eq!(specs.len(), 2);
+
+    // Either UUID form resolves to the task's full ID
+    let found = commands::cancel::find_task(&runs_dir, &specs[1].uuid.simple().to_string())?;
+    assert_eq!(found.task_id, specs[1].task_id);
+    assert_eq!(found.state, "PENDING");
+
+    commands::cancel::run(specs[0].uuid.to_string(), Some(lease_id.to_string())).await?;
+    let remaining: Vec<models::TaskSpec> =
+        lfs::list_files_sorted(&inbox)?.iter().map(lfs::read_json).collect::<std::io::Result<_>>()?;
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].task_id, specs[1].task_id);
+    let cancelled: Vec<models::TaskResult> = lfs::list_files_sorted(runs_dir.join("done").join("node-a"))?
+        .iter()
+        .filter(|f| models::DoneFileKind::of(f) == models::DoneFileKind::Cancelled)
+        .map(lfs::read_json)
+        .collect::<std::io::Result<_>>()?;
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].task_id, specs[0].task_id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_inherit_slurm_env() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "job-slurm-env-test";
+    let inbox = ctx.home.join("runs").join(lease_id).join("inbox").join("node-a");
+    env::set_var("SLURM_JOB_ID", "4242");
+
+    for inherit in [false, true] {
+        let opts = commands::submit::SubmitOptions { inherit_slurm_env: inherit, quiet: true, ..Default::default() };
+        commands::submit::run_with_options(vec!["true".to_string()], Some(lease_id.to_string()), Some("node-a".to_string()), opts)
+            .await?;
+    }
+    env::remove_var("SLURM_JOB_ID");
+
+    let specs: Vec<models::TaskSpec> =
+        lfs::list_files_sorted(&inbox)?.iter().map(lfs::read_json).collect::<std::io::Result<_>>()?;
+    assert_eq!(specs.len(), 2);
+    assert_eq!(specs[0].env.get("SLURM_JOB_ID"), None);
+    assert_eq!(specs[1].env.get("SLURM_JOB_ID").map(String::as_str), Some("4242"));
+    // Everything else is captured either way
+    assert!(specs[0].env.contains_key("LEASEQ_HOME"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bench_reports_throughput() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "local:bench-test";
+    let root = ctx.runtime.join(lease_id);
+    let runner = tokio::spawn(commands::run::run(commands::run::RunArgs {
+        lease: lease_id.to_string(),
+        node: Some("node-1".to_string()),
+        ..Default::default()
+    }));
+
+    let report =
+        commands::bench::bench(&root, lease_id, Some("node-1".to_string()), 2, Duration::from_secs(60)).await;
+    runner.abort();
+    let report = report?;
+    assert_eq!(report.tasks, 2);
+    assert!(report.tasks_per_sec > 0.0, "{:?}", report);
+    assert!(report.mean_claim_latency > Duration::ZERO, "{:?}", report);
+    // Two archived specs and their results
+    assert!(report.scanned_files >= 4, "{:?}", report);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_running_task() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "local:cancel-running";
+    let root = ctx.runtime.join(lease_id);
+    let runner = tokio::spawn(commands::run::run(commands::run::RunArgs {
+        lease: lease_id.to_string(),
+        node: Some("node-1".to_string()),
+        ..Default::default()
+    }));
+    let opts = commands::submit::SubmitOptions { quiet: true, ..Default::default() };
+    let spec = commands::submit::add_task_with_options(
+        "sleep 60".to_string(),
+        Some(lease_id.to_string()),
+        Some("node-1".to_string()),
+        &opts,
+    )
+    .await?;
+
+    let wait_for = |dir: PathBuf, suffix: &'static str| async move {
+        for _ in 0..300 {
+            let found =
+                lfs::list_files_sorted(&dir).unwrap_or_default().into_iter().find(|f| f.to_string_lossy().ends_with(suffix));
+            if found.is_some() {
+                return found;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        None
+    };
+    wait_for(root.join("claimed").join("node-1"), ".json").await.expect("task was claimed");
+    // Give bash a moment to start the sleep
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    commands::cancel::run(spec.task_id.clone(), Some(lease_id.to_string())).await?;
+    let cancelled = wait_for(root.join("done").join("node-1"), ".cancelled.json").await;
+    runner.abort();
+
+    let result: models::TaskResult = lfs::read_json(cancelled.expect("running task was cancelled"))?;
+    assert_eq!(result.task_id, spec.task_id);
+    assert_eq!(result.exit_code, -1);
+    assert_eq!(result.failure, Some(models::FailureReason::Cancelled));
+    assert!(result.runtime_s < 30.0, "{}", result.runtime_s);
+    // The request is consumed, and the spec archived
+    assert!(lfs::list_files_sorted(root.join("control").join("node-1"))?.is_empty());
+    assert!(lfs::list_files_sorted(root.join("claimed").join("node-1"))?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_cwd_fails_without_running() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "local:missing-cwd";
+    let root = ctx.runtime.join(lease_id);
+    let inbox = root.join("inbox").join("node-1");
+    fs::create_dir_all(&inbox)?;
+    let marker = ctx.runtime.join("ran");
+    let bogus = ctx.runtime.join("no-such-dir");
+    let spec = models::TaskSpec {
+        task_id: "T-NOCWD".to_string(),
+        idempotency_key: "key-T-NOCWD".to_string(),
+        lease_id: models::LeaseId(lease_id.to_string()),
+        target_node: "node-1".to_string(),
+        seq: 1,
+        uuid: uuid::Uuid::new_v4(),
+        created_at: OffsetDateTime::now_utc(),
+        cwd: bogus.to_string_lossy().into_owned(),
+        command: format!("touch {}", marker.display()),
+        ..Default::default()
+    };
+    lfs::atomic_write_json(inbox.join("0000000000000001_T-NOCWD.json"), &spec)?;
+
+    let args = commands::run::RunArgs {
+        lease: lease_id.to_string(),
+        node: Some("node-1".to_string()),
+        ..Default::default()
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(3), commands::run::run(args)).await;
+
+    let done = root.join("done").join("node-1");
+    let result: models::TaskResult = lfs::read_json(done.join("0000000000000001_T-NOCWD.result.json"))?;
+    assert_eq!(result.exit_code, models::CWD_MISSING_EXIT_CODE);
+    assert_eq!(result.failure, Some(models::FailureReason::SpawnError));
+    let stderr = fs::read_to_string(root.join(&result.stderr))?;
+    assert!(stderr.contains(&format!("cwd not found: {}", bogus.display())), "{}", stderr);
+    // Never ran, not in "." or anywhere else, and the spec moved to done/
+    assert!(!marker.exists());
+    assert!(done.join("0000000000000001_T-NOCWD.json").exists());
+    assert!(lfs::list_files_sorted(root.join("claimed").join("node-1"))?.is_empty());
+    Ok(())
+}
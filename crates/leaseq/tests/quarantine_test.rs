@@ -0,0 +1,226 @@
+use anyhow::Result;
+use leaseq::commands;
+use leaseq_core::{fs as lfs, models, quarantine};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tempfile::TempDir;
+use time::OffsetDateTime;
+
+const NODE: &str = "node-1";
+
+struct TestContext {
+    _temp_dir: TempDir,
+    root: PathBuf,
+}
+
+impl TestContext {
+    fn new() -> Result<Self> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().join("lease");
+        fs::create_dir_all(root.join("inbox").join(NODE))?;
+        Ok(Self { _temp_dir: temp_dir, root })
+    }
+
+    async fn run_for(&self, duration: Duration) {
+        let args = commands::run::RunArgs {
+            lease: "local:test".to_string(),
+            node: Some(NODE.to_string()),
+            root: Some(self.root.clone()),
+            ..Default::default()
+        };
+        let _ = tokio::time::timeout(duration, commands::run::run(args)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_runner_quarantines_then_purge_deletes() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let bad = ctx.root.join("inbox").join(NODE).join("0000000000000001_TBAD.json");
+    fs::write(&bad, "{ \"task_id\": ")?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    assert!(!bad.exists());
+    assert!(!ctx.root.join("claimed").join(NODE).join("0000000000000001_TBAD.json").exists());
+
+    let files = quarantine::list(&ctx.root)?;
+    assert_eq!(files.len(), 1);
+    let record = files[0].record.as_ref().expect("quarantine record");
+    assert_eq!(record.original, format!("claimed/{}/0000000000000001_TBAD.json", NODE));
+    assert!(!record.error.is_empty());
+
+    for f in &files {
+        quarantine::remove(f)?;
+    }
+    assert!(quarantine::list(&ctx.root)?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_restore_moves_repaired_task_to_inbox() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let claimed = ctx.root.join("claimed").join(NODE);
+    fs::create_dir_all(&claimed)?;
+    let path = claimed.join("0000000000000001_TFIX.json");
+    fs::write(&path, "garbage")?;
+    let quarantined = quarantine::quarantine(&ctx.root, &path, "expected value")?;
+
+    // Still corrupt: restore refuses and leaves it in place
+    let files = quarantine::list(&ctx.root)?;
+    assert!(commands::purge_corrupt::restore_file(&ctx.root, &files[0]).is_err());
+    assert!(quarantined.exists());
+
+    // Repaired by hand
+    let spec = models::TaskSpec {
+        task_id: "TFIX".to_string(),
+        idempotency_key: "k-fix".to_string(),
+        lease_id: models::LeaseId("local:test".to_string()),
+        target_node: NODE.to_string(),
+        seq: 1,
+        created_at: OffsetDateTime::now_utc(),
+        cwd: ".".to_string(),
+        command: "true".to_string(),
+        ..Default::default()
+    };
+    lfs::atomic_write_json(&quarantined, &spec)?;
+
+    let dest = commands::purge_corrupt::restore_file(&ctx.root, &files[0])?;
+    assert_eq!(dest, ctx.root.join("inbox").join(NODE).join("0000000000000001_TFIX.json"));
+    assert!(dest.exists());
+    assert!(quarantine::list(&ctx.root)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_prune_logs_keeps_matching() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let logs = ctx.root.join("logs");
+    let done = ctx.root.join("done").join(NODE);
+    fs::create_dir_all(&logs)?;
+
+    for (task_id, stdout) in [("T-NAN", "step 1\nloss=NaN\n"), ("T-OK", "step 1\nloss=0.1\n")] {
+        fs::write(logs.join(format!("{}.out", task_id)), stdout)?;
+        fs::write(logs.join(format!("{}.err", task_id)), "")?;
+        let result = models::TaskResult {
+            task_id: task_id.to_string(),
+            idempotency_key: format!("key-{}", task_id),
+            node: NODE.to_string(),
+            started_at: OffsetDateTime::now_utc(),
+            finished_at: OffsetDateTime::now_utc(),
+            exit_code: 0,
+            stdout: format!("logs/{}.out", task_id),
+            stderr: format!("logs/{}.err", task_id),
+            runtime_s: 0.0,
+            command: "train".to_string(),
+            resolved_command: String::new(),
+            cwd: String::new(),
+            gpus_requested: 0,
+            gpus_assigned: String::new(),
+            checksum: None,
+            failure: None,
+            signal: None,
+        };
+        lfs::atomic_write_json(done.join(format!("1_{}.result.json", task_id)), &result)?;
+    }
+    // Still running: never pruned
+    fs::write(logs.join("T-RUNNING.out"), "loss=0.2\n")?;
+    // Finished once, but queued again under the same ID
+    fs::write(logs.join("T-OK2.out"), "loss=0.3\n")?;
+    let mut result = lfs::read_json::<models::TaskResult, _>(done.join("1_T-OK.result.json"))?;
+    result.task_id = "T-OK2".to_string();
+    lfs::atomic_write_json(done.join("2_T-OK2.result.json"), &result)?;
+    let requeued = models::TaskSpec { task_id: "T-OK2".to_string(), ..Default::default() };
+    lfs::atomic_write_json(ctx.root.join("inbox").join(NODE).join("3_T-OK2.json"), &requeued)?;
+    // Written to the task's own --output-dir: the user's file, not pruned
+    let out_dir = ctx.root.join("outputs").join("T-OUT");
+    fs::create_dir_all(&out_dir)?;
+    fs::write(out_dir.join("T-OUT.out"), "loss=0.4\n")?;
+    let location = models::LogLocation {
+        stdout: out_dir.join("T-OUT.out").to_string_lossy().into_owned(),
+        stderr: out_dir.join("T-OUT.err").to_string_lossy().into_owned(),
+    };
+    lfs::atomic_write_json(logs.join("T-OUT.where"), &location)?;
+    result.task_id = "T-OUT".to_string();
+    lfs::atomic_write_json(done.join("4_T-OUT.result.json"), &result)?;
+
+    let keep = regex::Regex::new("NaN")?;
+    let report = commands::prune_logs::prune_logs(&ctx.root, Some(&keep), true)?;
+    assert_eq!(report.deleted.len(), 2);
+    assert!(logs.join("T-OK.out").exists(), "dry run deletes nothing");
+
+    commands::prune_logs::prune_logs(&ctx.root, Some(&keep), false)?;
+    assert!(logs.join("T-NAN.out").exists());
+    assert!(logs.join("T-NAN.err").exists());
+    assert!(!logs.join("T-OK.out").exists());
+    assert!(!logs.join("T-OK.err").exists());
+    assert!(logs.join("T-RUNNING.out").exists());
+    assert!(logs.join("T-OK2.out").exists());
+    assert!(out_dir.join("T-OUT.out").exists());
+    assert!(done.join("1_T-OK.result.json").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_in_flight_temp_files_are_never_parsed() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // A half-written spec from atomic_write_json, left behind in both queues
+    let name = ".tmp.0000000000000001_THALF.json.6f1c";
+    let claimed = ctx.root.join("claimed").join(NODE);
+    fs::create_dir_all(&claimed)?;
+    fs::write(claimed.join(name), "{ \"task_id\": ")?;
+    fs::write(ctx.root.join("inbox").join(NODE).join(name), "{ \"task_id\": ")?;
+
+    let rows = commands::tasks::collect_tasks(
+        &ctx.root,
+        commands::tasks::TaskStateFilter::All,
+        None,
+        None,
+        &Default::default(),
+        OffsetDateTime::now_utc(),
+    )?;
+    assert!(rows.is_empty());
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    // Not recovered as a zombie, not claimed, not quarantined
+    assert!(claimed.join(name).exists());
+    assert!(ctx.root.join("inbox").join(NODE).join(name).exists());
+    assert!(quarantine::list(&ctx.root)?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_task_id_with_slash_cannot_escape_logs() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // logs/../../escaped.out would land next to the lease directory
+    let spec = models::TaskSpec {
+        task_id: "../../escaped".to_string(),
+        idempotency_key: "k-escape".to_string(),
+        lease_id: models::LeaseId("local:test".to_string()),
+        target_node: NODE.to_string(),
+        seq: 1,
+        created_at: OffsetDateTime::now_utc(),
+        cwd: ".".to_string(),
+        command: "echo out".to_string(),
+        ..Default::default()
+    };
+    lfs::atomic_write_json(ctx.root.join("inbox").join(NODE).join("0000000000000001_TESC.json"), &spec)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let outside = ctx.root.parent().unwrap();
+    for ext in ["out", "err", "log", "where"] {
+        assert!(!outside.join(format!("escaped.{}", ext)).exists(), "escaped.{} written", ext);
+    }
+    assert!(lfs::list_files_sorted(ctx.root.join("done").join(NODE))?.is_empty());
+    let files = quarantine::list(&ctx.root)?;
+    assert_eq!(files.len(), 1);
+    assert!(files[0].record.as_ref().unwrap().error.contains("invalid task ID"));
+
+    let err = commands::logs::run("../../escaped".to_string(), Some("local:test".to_string()), false, None, true, None, false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("invalid task ID"), "{}", err);
+    Ok(())
+}
@@ -43,9 +43,14 @@ async fn test_tasks_reporting_stuck() -> Result<()> {
         node: node.to_string(),
         ts: OffsetDateTime::now_utc() - time::Duration::minutes(3),
         running_task_id: Some("T1".to_string()),
+        running_task_ids: Vec::new(),
         pending_estimate: 0,
         runner_pid: 1234,
         version: "0.1.0".to_string(),
+        schema_version: models::SCHEMA_VERSION,
+        cpus: 0,
+        errors_since_start: 0,
+        last_error: None,
     };
     lfs::atomic_write_json(&hb_dir.join(format!("{}.json", node)), &hb)?;
 
@@ -65,6 +70,7 @@ async fn test_tasks_reporting_stuck() -> Result<()> {
         env: std::collections::HashMap::new(),
         gpus: 0,
         command: "stale job".to_string(),
+        ..Default::default()
     };
     lfs::atomic_write_json(&claimed_dir.join("task.json"), &spec)?;
 
@@ -83,3 +89,350 @@ async fn test_tasks_reporting_stuck() -> Result<()> {
 
     Ok(())
 }
+
+fn result_at(task_id: &str, exit_code: i32, finished_at: OffsetDateTime) -> models::TaskResult {
+    models::TaskResult {
+        task_id: task_id.to_string(),
+        idempotency_key: format!("key-{}", task_id),
+        node: "node1".to_string(),
+        started_at: finished_at,
+        finished_at,
+        exit_code,
+        stdout: String::new(),
+        stderr: String::new(),
+        runtime_s: 0.0,
+        command: format!("job {}", task_id),
+        resolved_command: String::new(),
+        cwd: ".".to_string(),
+        gpus_requested: 0,
+        gpus_assigned: String::new(),
+        checksum: None,
+        failure: None,
+        signal: None,
+    }
+    .with_checksum()
+}
+
+#[test]
+fn test_tasks_time_window_filters() -> Result<()> {
+    use commands::tasks::{collect_tasks, TaskListOptions, TaskStateFilter};
+
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path();
+    let done_dir = root.join("done").join("node1");
+    let now = OffsetDateTime::now_utc();
+
+    let seeded = [
+        ("T-RECENT-FAIL", 1, now - time::Duration::minutes(10)),
+        ("T-RECENT-OK", 0, now - time::Duration::minutes(20)),
+        ("T-OLD-FAIL", 1, now - time::Duration::hours(3)),
+        ("T-ANCIENT", 0, now - time::Duration::days(2)),
+    ];
+    for (i, (id, code, at)) in seeded.iter().enumerate() {
+        lfs::atomic_write_json(
+            done_dir.join(format!("{:03}_{}.result.json", i, id)),
+            &result_at(id, *code, *at),
+        )?;
+    }
+
+    let ids = |state, opts: &TaskListOptions| -> Result<Vec<String>> {
+        Ok(collect_tasks(root, state, None, None, opts, now)?
+            .into_iter()
+            .map(|r| r.task_id)
+            .collect())
+    };
+
+    // Failures from the last hour
+    let last_hour = TaskListOptions { newer_than: Some(time::Duration::hours(1)), ..Default::default() };
+    assert_eq!(ids(TaskStateFilter::Failed, &last_hour)?, vec!["T-RECENT-FAIL"]);
+    assert_eq!(ids(TaskStateFilter::All, &last_hour)?, vec!["T-RECENT-FAIL", "T-RECENT-OK"]);
+
+    // Older than a day
+    let old = TaskListOptions { older_than: Some(time::Duration::days(1)), ..Default::default() };
+    assert_eq!(ids(TaskStateFilter::All, &old)?, vec!["T-ANCIENT"]);
+
+    // Between one hour and one day
+    let between = TaskListOptions {
+        older_than: Some(time::Duration::hours(1)),
+        newer_than: Some(time::Duration::days(1)),
+        ..Default::default()
+    };
+    assert_eq!(ids(TaskStateFilter::All, &between)?, vec!["T-OLD-FAIL"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_tasks_table_fits_long_node_names() {
+    use commands::tasks::{render_table, TableLayout, TaskRow};
+
+    let long_node = "gpu-node-a100-rack17-slot04.cluster.example.org";
+    let rows = vec![
+        TaskRow {
+            task_id: "T1".to_string(),
+            state: "RUNNING",
+            node: long_node.to_string(),
+            command: "python train.py --epochs 100".to_string(),
+            ts: OffsetDateTime::now_utc(),
+            depends_on: vec![],
+            failure: None,
+            logs_bytes: None,
+            runtime_s: None,
+            signal: None,
+        },
+        TaskRow {
+            task_id: "T2".to_string(),
+            state: "PENDING",
+            node: "n1".to_string(),
+            command: "x".repeat(300),
+            ts: OffsetDateTime::now_utc(),
+            depends_on: vec![],
+            failure: None,
+            logs_bytes: None,
+            runtime_s: None,
+            signal: None,
+        },
+    ];
+
+    let lines = render_table(&rows, TableLayout::Fit(160));
+    assert!(lines[2].contains(long_node));
+    assert!(lines[2].ends_with("python train.py --epochs 100"));
+    // Columns line up: commands start at the same offset
+    let offset = lines[2].find("python").unwrap();
+    assert_eq!(&lines[3][offset..offset + 3], "xxx");
+    // Long commands are cut to the terminal width
+    assert!(lines.iter().all(|l| l.chars().count() <= 160));
+    assert!(lines[3].ends_with("..."));
+
+    // Piped output keeps the fixed 40-char command column
+    let fixed = render_table(&rows, TableLayout::Fixed);
+    assert_eq!(fixed[3].trim_start_matches(|c| c != 'x').chars().count(), 40);
+}
+
+#[test]
+fn test_tampered_result_is_quarantined() -> Result<()> {
+    use commands::tasks::{collect_tasks, TaskListOptions, TaskStateFilter};
+
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path();
+    let done_dir = root.join("done").join("node1");
+    let now = OffsetDateTime::now_utc();
+
+    lfs::atomic_write_json(done_dir.join("001_T-GOOD.result.json"), &result_at("T-GOOD", 0, now))?;
+
+    // Flip the exit code after the checksum was computed
+    let mut tampered = result_at("T-BAD", 1, now);
+    tampered.exit_code = 0;
+    let bad_path = done_dir.join("002_T-BAD.result.json");
+    lfs::atomic_write_json(&bad_path, &tampered)?;
+
+    let rows = collect_tasks(root, TaskStateFilter::All, None, None, &TaskListOptions::default(), now)?;
+    let ids: Vec<_> = rows.iter().map(|r| r.task_id.as_str()).collect();
+    assert_eq!(ids, vec!["T-GOOD"]);
+
+    assert!(!bad_path.exists());
+    let quarantined = leaseq_core::quarantine::list(root)?;
+    assert_eq!(quarantined.len(), 1);
+    assert_eq!(quarantined[0].record.as_ref().unwrap().error, "result checksum mismatch");
+    Ok(())
+}
+
+#[test]
+fn test_newest_result_wins_for_retried_task() -> Result<()> {
+    use commands::tasks::{collect_tasks, TaskListOptions, TaskStateFilter};
+
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path();
+    let done_dir = root.join("done").join("node1");
+    let now = OffsetDateTime::now_utc();
+
+    // The retry that succeeded sorts ahead of the attempt that failed
+    lfs::atomic_write_json(done_dir.join("001_T-RETRY.result.json"), &result_at("T-RETRY", 0, now))?;
+    let old = result_at("T-RETRY", 1, now - time::Duration::minutes(5));
+    lfs::atomic_write_json(done_dir.join("002_T-RETRY.result.json"), &old)?;
+
+    let rows = collect_tasks(root, TaskStateFilter::All, None, None, &TaskListOptions::default(), now)?;
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].state, "DONE");
+    // The superseded failure is not reported on its own either
+    let failed = collect_tasks(root, TaskStateFilter::Failed, None, None, &TaskListOptions::default(), now)?;
+    assert!(failed.is_empty());
+
+    let found = commands::cancel::find_task(root, "T-RETRY")?;
+    assert_eq!(found.state, "DONE");
+    Ok(())
+}
+
+#[test]
+fn test_quiet_task_flagged_possibly_hung() -> Result<()> {
+    use commands::tasks::{collect_tasks, TaskListOptions, TaskStateFilter};
+
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path();
+    let node = "node-live";
+    let now = OffsetDateTime::now_utc();
+
+    // Runner is healthy
+    lfs::atomic_write_json(
+        root.join("hb").join(format!("{}.json", node)),
+        &models::Heartbeat {
+            node: node.to_string(),
+            ts: now,
+            running_task_id: Some("T-QUIET".to_string()),
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1,
+            version: "test".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        },
+    )?;
+
+    let claimed = root.join("claimed").join(node);
+    let logs = root.join("logs");
+    fs::create_dir_all(&logs)?;
+    let ten_min_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(600);
+
+    for (id, limit, quiet) in [("T-QUIET", Some(300), true), ("T-CHATTY", Some(300), false), ("T-NOLIMIT", None, true)] {
+        let spec = models::TaskSpec {
+            task_id: id.to_string(),
+            lease_id: models::LeaseId("local:hung".to_string()),
+            target_node: node.to_string(),
+            created_at: now,
+            command: format!("job {}", id),
+            no_output_s: limit,
+            ..Default::default()
+        };
+        lfs::atomic_write_json(claimed.join(format!("{}.json", id)), &spec)?;
+        for ext in ["out", "err"] {
+            let f = fs::File::create(logs.join(format!("{}.{}", id, ext)))?;
+            if quiet {
+                f.set_modified(ten_min_ago)?;
+            }
+        }
+    }
+
+    let rows = collect_tasks(root, TaskStateFilter::All, None, None, &TaskListOptions::default(), now)?;
+    let state = |id: &str| rows.iter().find(|r| r.task_id == id).map(|r| r.state).unwrap();
+    assert_eq!(state("T-QUIET"), "POSSIBLY_HUNG");
+    assert_eq!(state("T-CHATTY"), "RUNNING");
+    assert_eq!(state("T-NOLIMIT"), "RUNNING");
+    Ok(())
+}
+
+#[test]
+fn test_tasks_tree_follows_dependencies() {
+    use commands::tasks::{render_tree, TaskRow};
+
+    let row = |id: &str, state: &'static str, deps: &[&str]| TaskRow {
+        task_id: id.to_string(),
+        state,
+        node: "n1".to_string(),
+        command: format!("run {}", id),
+        ts: OffsetDateTime::now_utc(),
+        depends_on: deps.iter().map(|d| d.to_string()).collect(),
+        failure: None,
+        logs_bytes: None,
+        runtime_s: None,
+        signal: None,
+    };
+    let rows = vec![
+        row("C", "PENDING", &["B"]),
+        row("B", "RUNNING", &["A"]),
+        row("A", "DONE", &[]),
+        row("D", "PENDING", &["A"]),
+        row("X", "PENDING", &["Y"]),
+        row("Y", "PENDING", &["X"]),
+    ];
+
+    let lines = render_tree(&rows);
+    assert_eq!(
+        lines,
+        vec![
+            "A DONE run A",
+            "└─ B RUNNING run B",
+            "   └─ C PENDING run C",
+            "└─ D PENDING run D",
+            "X PENDING run X",
+            "└─ Y PENDING run Y",
+            "   └─ X [CYCLE]",
+        ]
+    );
+}
+
+#[test]
+fn test_status_json_sections() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let root = temp_dir.path();
+    let now = OffsetDateTime::now_utc();
+
+    for (node, age) in [("node-a", 5), ("node-b", 600)] {
+        let hb = models::Heartbeat {
+            node: node.to_string(),
+            ts: now - time::Duration::seconds(age),
+            running_task_id: None,
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1,
+            version: "0.1.0".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        };
+        lfs::atomic_write_json(root.join("hb").join(format!("{}.json", node)), &hb)?;
+    }
+    let seeded = [("claimed", "node-a", "T-RUN"), ("inbox", "node-a", "T-P1"), ("inbox", "node-b", "T-P2")];
+    for (i, (dir, node, task_id)) in seeded.iter().enumerate() {
+        let spec = models::TaskSpec {
+            task_id: task_id.to_string(),
+            target_node: node.to_string(),
+            seq: i as u64,
+            command: format!("job {}", task_id),
+            ..Default::default()
+        };
+        lfs::atomic_write_json(root.join(dir).join(node).join(format!("{}.json", task_id)), &spec)?;
+    }
+
+    let report = commands::status::collect("local:dash", root)?;
+    let doc = serde_json::to_value(&report)?;
+    assert_eq!(doc["lease"], "local:dash");
+    assert_eq!(doc["nodes"].as_array().unwrap().len(), 2);
+    assert_eq!(doc["running"].as_array().unwrap().len(), 1);
+    assert_eq!(doc["pending"].as_array().unwrap().len(), 2);
+    assert_eq!(doc["running"][0]["task_id"], "T-RUN");
+    assert_eq!(doc["running"][0]["node"], "node-a");
+    let stale: Vec<_> = doc["nodes"].as_array().unwrap().iter().map(|n| n["stale"].as_bool().unwrap()).collect();
+    assert_eq!(stale, [false, true]);
+    Ok(())
+}
+
+#[test]
+fn test_tasks_output_template_renders_fields() {
+    use commands::tasks::{parse_template, render_template, TaskRow};
+
+    let row = TaskRow {
+        task_id: "T1".to_string(),
+        state: "DONE",
+        node: "n1".to_string(),
+        command: "python train.py".to_string(),
+        ts: OffsetDateTime::now_utc(),
+        depends_on: vec![],
+        failure: None,
+        logs_bytes: None,
+        runtime_s: Some(12.34),
+        signal: None,
+    };
+    let template = parse_template("{id}\t{state} on {node} ({runtime}s): {command} {{raw}}").unwrap();
+    assert_eq!(render_template(&template, &row), "T1\tDONE on n1 (12.3s): python train.py {raw}");
+
+    // Unfinished tasks have no runtime yet
+    let pending = TaskRow { state: "PENDING", runtime_s: None, ..row };
+    assert_eq!(render_template("{id} {runtime}", &pending), "T1 -");
+
+    assert!(parse_template("{id} {exit}").unwrap_err().contains("{exit}"));
+    assert!(parse_template("{id").is_err());
+    assert!(parse_template("id}").is_err());
+}
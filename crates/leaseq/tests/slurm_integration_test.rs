@@ -90,6 +90,13 @@ cat $2 >> sbatch_script.log
         account: None,
         sbatch_arg: vec!["--exclusive".to_string()],
         wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
     };
 
     commands::lease::create_lease(args).await?;
@@ -166,6 +173,7 @@ async fn test_atomic_workflow_local() -> Result<()> {
         lease: lease_id.to_string(),
         node: Some("node-1".to_string()),
         root: None,
+        ..Default::default()
     };
 
     // Run runner for 2 seconds (plenty of time for "echo hello")
@@ -212,6 +220,7 @@ async fn test_failed_task() -> Result<()> {
         lease: lease_id.to_string(),
         node: Some("node-1".to_string()),
         root: None,
+        ..Default::default()
     };
 
     tokio::select! {
@@ -257,6 +266,7 @@ async fn test_duplicate_task_idempotency() -> Result<()> {
         env: std::collections::HashMap::new(),
         gpus: 0,
         command: "echo 1".to_string(),
+        ..Default::default()
     };
     
     // Write T1
@@ -266,7 +276,7 @@ async fn test_duplicate_task_idempotency() -> Result<()> {
 
     // Run runner to process T1
     {
-        let run_args = commands::run::RunArgs { lease: lease_id.to_string(), node: Some("node-1".to_string()), root: None };
+        let run_args = commands::run::RunArgs { lease: lease_id.to_string(), node: Some("node-1".to_string()), root: None, ..Default::default() };
         tokio::select! { _ = commands::run::run(run_args) => {}, _ = tokio::time::sleep(Duration::from_secs(1)) => {} };
     }
 
@@ -282,7 +292,7 @@ async fn test_duplicate_task_idempotency() -> Result<()> {
 
     // Run runner again
     {
-        let run_args = commands::run::RunArgs { lease: lease_id.to_string(), node: Some("node-1".to_string()), root: None };
+        let run_args = commands::run::RunArgs { lease: lease_id.to_string(), node: Some("node-1".to_string()), root: None, ..Default::default() };
         tokio::select! { _ = commands::run::run(run_args) => {}, _ = tokio::time::sleep(Duration::from_secs(1)) => {} };
     }
 
@@ -295,3 +305,407 @@ async fn test_duplicate_task_idempotency() -> Result<()> {
     
     Ok(())
 }
+
+#[test]
+fn test_keeper_script_reports_runner_launch_failure() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir_all(&bin_dir)?;
+
+    // srun that fails like a missing binary on the compute node
+    let srun = bin_dir.join("srun");
+    fs::write(&srun, "#!/bin/sh\necho \"srun: error: $2: command not found\" >&2\nexit 127\n")?;
+    fs::set_permissions(&srun, fs::Permissions::from_mode(0o755))?;
+
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 1,
+        time: None,
+        partition: None,
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: vec![],
+        wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
+    };
+    let script = commands::lease::keeper_script(&args, "/missing/leaseq");
+    assert!(script.contains("rc=$?"));
+    let script_path = temp_dir.path().join("keeper.sh");
+    fs::write(&script_path, &script)?;
+
+    let started = std::time::Instant::now();
+    let output = std::process::Command::new("bash")
+        .arg(&script_path)
+        .env("PATH", format!("{}:{}", bin_dir.display(), env::var("PATH").unwrap_or_default()))
+        .env("SLURM_JOB_ID", "4242")
+        .output()?;
+
+    // Exits with the runner's status right away instead of sleeping
+    assert_eq!(output.status.code(), Some(127));
+    assert!(started.elapsed() < Duration::from_secs(10));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("runner exited with status 127"), "stderr: {}", stderr);
+    assert!(stderr.contains("/missing/leaseq"), "stderr: {}", stderr);
+
+    // With --self-test the binary is checked before the runner starts
+    let script = commands::lease::keeper_script(&commands::lease::CreateLeaseArgs { self_test: true, ..args }, "/missing/leaseq");
+    fs::write(&script_path, &script)?;
+    let output = std::process::Command::new("bash")
+        .arg(&script_path)
+        .env("PATH", format!("{}:{}", bin_dir.display(), env::var("PATH").unwrap_or_default()))
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("self-test failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_keeper_script_restarts_crashed_runner() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let bin_dir = temp_dir.path().join("bin");
+    fs::create_dir_all(&bin_dir)?;
+
+    // srun whose runner crashes every time, counting the launches
+    let launches = temp_dir.path().join("launches");
+    let srun = bin_dir.join("srun");
+    fs::write(&srun, format!("#!/bin/sh\necho x >> '{}'\nexit 1\n", launches.display()))?;
+    fs::set_permissions(&srun, fs::Permissions::from_mode(0o755))?;
+
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 1,
+        time: None,
+        partition: None,
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: vec![],
+        wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
+    };
+    assert!(!commands::lease::keeper_script(&args, "leaseq").contains("while true"));
+
+    let script = commands::lease::keeper_script(
+        &commands::lease::CreateLeaseArgs { restart_runner_on_fail: Some(1), ..args },
+        "leaseq",
+    );
+    assert!(script.contains("while true; do"), "{}", script);
+    assert!(script.contains("[ $restarts -ge 1 ]"), "{}", script);
+    let script_path = temp_dir.path().join("keeper.sh");
+    fs::write(&script_path, &script)?;
+
+    let output = std::process::Command::new("bash")
+        .arg(&script_path)
+        .env("PATH", format!("{}:{}", bin_dir.display(), env::var("PATH").unwrap_or_default()))
+        .env("SLURM_JOB_ID", "4242")
+        .output()?;
+
+    // Launched once more, then gives up with the runner's status
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(&launches)?.lines().count(), 2);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("restarting (1/1)"), "stderr: {}", stderr);
+    assert!(stderr.contains("runner exited with status 1"), "stderr: {}", stderr);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slurm_lease_dependency() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let script_copy = ctx.bin_dir.join("submitted.sh");
+    ctx.write_mock_script(
+        "sbatch",
+        &format!("#!/bin/sh\n[ \"$1\" = \"--version\" ] && exit 0\ncp \"$2\" {}\necho 12346\n", script_copy.display()),
+    )?;
+
+    let dependency = commands::lease::parse_dependency("afterok:12345").map_err(anyhow::Error::msg)?;
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 1,
+        time: None,
+        partition: None,
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: vec![],
+        wait: 30,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: Some(dependency),
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
+    };
+    commands::lease::create_lease(args).await?;
+
+    let script = fs::read_to_string(&script_copy)?;
+    assert!(script.contains("#SBATCH --dependency=afterok:12345\n"), "script: {}", script);
+
+    assert!(commands::lease::parse_dependency("afterok:1:2,afterany:3_4?singleton").is_ok());
+    assert!(commands::lease::parse_dependency("after:5+10").is_ok());
+    assert!(commands::lease::parse_dependency("afterok").is_err());
+    assert!(commands::lease::parse_dependency("afterok:abc").is_err());
+    assert!(commands::lease::parse_dependency("whenever:1").is_err());
+    assert!(commands::lease::parse_dependency("afterok:5+10").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slurm_lease_hold_and_unhold() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let script_copy = ctx.bin_dir.join("submitted.sh");
+    let scontrol_log = ctx.bin_dir.join("scontrol.log");
+    ctx.write_mock_script(
+        "sbatch",
+        &format!("#!/bin/sh\n[ \"$1\" = \"--version\" ] && exit 0\ncp \"$2\" {}\necho 12347\n", script_copy.display()),
+    )?;
+    ctx.write_mock_script("scontrol", &format!("#!/bin/sh\necho \"$@\" >> {}\n", scontrol_log.display()))?;
+
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 1,
+        time: None,
+        partition: None,
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: vec![],
+        wait: 30,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: true,
+        check: false,
+        comment: None,
+        metadata: Vec::new(),
+    };
+    // Returns without waiting for (or cancelling) the held job
+    commands::lease::create_lease(args).await?;
+
+    let script = fs::read_to_string(&script_copy)?;
+    assert!(script.contains("#SBATCH --hold\n"), "script: {}", script);
+    let meta_path = ctx._home.join("runs").join("12347").join(models::LEASE_META_FILE);
+    let held = |path: &PathBuf| -> Result<bool> {
+        match leaseq_core::fs::read_json::<models::LeaseMeta, _>(path)? {
+            models::LeaseMeta::Slurm { held, .. } => Ok(held),
+            other => panic!("unexpected meta {:?}", other),
+        }
+    };
+    assert!(held(&meta_path)?);
+
+    commands::lease::run(commands::lease::LeaseCommands::Unhold { lease_id: "12347".to_string() }).await?;
+    assert_eq!(fs::read_to_string(&scontrol_log)?, "release 12347\n");
+    assert!(!held(&meta_path)?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lease_comment_shown_by_ls_and_status() -> Result<()> {
+    let ctx = TestContext::new()?;
+    ctx.write_mock_script("sbatch", "#!/bin/sh\n[ \"$1\" = \"--version\" ] && exit 0\necho 12348\n")?;
+    ctx.write_mock_script("squeue", "#!/bin/sh\necho '12348 RUNNING 0:05'\n")?;
+
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 1,
+        time: None,
+        partition: None,
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: vec![],
+        wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: Some("ablation sweep for the paper".to_string()),
+        metadata: vec![("owner".to_string(), "vision".to_string()), ("ticket".to_string(), "42".to_string())],
+    };
+    commands::lease::create_lease(args).await?;
+
+    let rows = commands::lease::collect_leases()?;
+    let row = rows.iter().find(|r| r.lease_id == "12348").expect("lease listed");
+    assert_eq!(row.status, "RUNNING (0:05)");
+    assert_eq!(row.comment.as_deref(), Some("ablation sweep for the paper"));
+    assert_eq!(row.metadata.get("owner").map(String::as_str), Some("vision"));
+    assert_eq!(row.metadata.get("ticket").map(String::as_str), Some("42"));
+
+    let report = commands::status::collect("12348", &ctx._home.join("runs").join("12348"))?;
+    assert_eq!(report.comment, row.comment);
+    assert_eq!(report.metadata, row.metadata);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lease_ls_reads_lease_meta() -> Result<()> {
+    let ctx = TestContext::new()?;
+    ctx.write_mock_script("sbatch", "#!/bin/sh\n[ \"$1\" = \"--version\" ] && exit 0\necho 12349\n")?;
+    ctx.write_mock_script("squeue", "#!/bin/sh\necho '12349 RUNNING 0:05'\n")?;
+
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 2,
+        time: None,
+        partition: None,
+        qos: None,
+        gpus_per_node: 0,
+        account: None,
+        sbatch_arg: vec!["--mem=64G".to_string()],
+        wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: false,
+        comment: None,
+        metadata: vec![],
+    };
+    commands::lease::create_lease(args).await?;
+    fs::create_dir_all(ctx._home.join("runs").join("12349").join("hb"))?;
+    fs::write(ctx._home.join("runs").join("12349").join("hb").join("node-1.json"), "{}")?;
+    // A run dir from before leases recorded their meta
+    fs::create_dir_all(ctx._home.join("runs").join("99999"))?;
+
+    let local_id = leaseq_core::config::local_lease_id();
+    let local_root = ctx.runtime.join(&local_id);
+    commands::daemon::write_local_meta(&local_root, &local_id)?;
+    let written = fs::read_to_string(local_root.join(models::LEASE_META_FILE))?;
+    // A second start keeps the lease's own settings
+    commands::daemon::write_local_meta(&local_root, &local_id)?;
+    assert_eq!(fs::read_to_string(local_root.join(models::LEASE_META_FILE))?, written);
+
+    let rows = commands::lease::collect_leases()?;
+    let row = |id: &str| rows.iter().find(|r| r.lease_id == id).cloned().expect("lease listed");
+
+    let slurm = row("12349");
+    assert_eq!(slurm.lease_type.as_deref(), Some("slurm"));
+    assert!(slurm.created_at.is_some());
+    assert_eq!(slurm.nodes, 1);
+    assert_eq!(slurm.sbatch_args, vec!["--mem=64G".to_string()]);
+
+    let local = row(&local_id);
+    assert_eq!(local.lease_type.as_deref(), Some("local"));
+    assert!(local.created_at.is_some());
+    assert!(local.sbatch_args.is_empty());
+
+    let bare = row("99999");
+    assert_eq!((bare.lease_type, bare.created_at, bare.nodes), (None, None, 0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_submit_and_tui_add_enqueue_the_same_task() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let lease_id = "local:submit-vs-add";
+    let inbox = ctx.runtime.join(lease_id).join("inbox").join("node-1");
+
+    commands::submit::run(
+        vec!["echo".to_string(), "same".to_string()],
+        Some(lease_id.to_string()),
+        Some("node-1".to_string()),
+    )
+    .await?;
+    commands::submit::add_task("echo same".to_string(), Some(lease_id.to_string()), Some("node-1".to_string())).await?;
+
+    let specs: Vec<models::TaskSpec> = leaseq_core::fs::list_files_sorted(&inbox)?
+        .iter()
+        .map(leaseq_core::fs::read_json)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(specs.len(), 2);
+    let (a, b) = (&specs[0], &specs[1]);
+    assert_eq!(a.command, b.command);
+    assert_eq!(a.target_node, b.target_node);
+    assert_eq!(a.lease_id, b.lease_id);
+    assert_eq!(a.cwd, b.cwd);
+    assert_ne!(a.task_id, b.task_id);
+    // Submitted words are kept as argv; the TUI's line goes through the shell
+    assert_eq!(a.argv, Some(vec!["echo".to_string(), "same".to_string()]));
+    assert_eq!(b.argv, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slurm_lease_check_warns_on_insufficient_resources() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let sinfo_log = ctx.bin_dir.join("sinfo.log");
+    ctx.write_mock_script(
+        "sinfo",
+        &format!(
+            "#!/bin/sh\necho \"$@\" >> {}\necho 'gpu01 gpu:a100:4(S:0-1) idle'\necho 'gpu02 gpu:a100:2 mixed*'\necho 'gpu03 gpu:a100:8 down*'\n",
+            sinfo_log.display()
+        ),
+    )?;
+    ctx.write_mock_script("sbatch", "#!/bin/sh\n[ \"$1\" = \"--version\" ] && exit 0\necho 12348\n")?;
+
+    let args = commands::lease::CreateLeaseArgs {
+        nodes: 2,
+        time: None,
+        partition: Some("gpu".to_string()),
+        qos: None,
+        gpus_per_node: 4,
+        account: None,
+        sbatch_arg: vec![],
+        wait: 0,
+        self_test: false,
+        restart_runner_on_fail: None,
+        dependency: None,
+        hold: false,
+        check: true,
+        comment: None,
+        metadata: Vec::new(),
+    };
+    // Only gpu01 has 4 GPUs; gpu03 would, but it is down
+    let warnings = commands::lease::preflight(&args)?;
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(warnings[0].contains("partition gpu has 1 usable node(s) with 4 or more GPUs"), "{}", warnings[0]);
+    assert!(fs::read_to_string(&sinfo_log)?.contains("-p gpu"));
+
+    let more_nodes = commands::lease::CreateLeaseArgs { nodes: 3, gpus_per_node: 0, ..args.clone() };
+    let warnings = commands::lease::preflight(&more_nodes)?;
+    assert!(warnings[0].contains("has 2 usable node(s) but --nodes 3"), "{:?}", warnings);
+
+    let fits = commands::lease::CreateLeaseArgs { nodes: 2, gpus_per_node: 2, ..args.clone() };
+    assert!(commands::lease::preflight(&fits)?.is_empty());
+
+    // Only a warning: the lease is still submitted
+    commands::lease::create_lease(args).await?;
+    assert!(ctx._home.join("runs").join("12348").join(models::LEASE_META_FILE).exists());
+    Ok(())
+}
+
+#[test]
+fn test_bare_lease_name_resolves_to_local_lease() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (home, runtime) = (dir.path().join("home"), dir.path().join("runtime"));
+    fs::create_dir_all(runtime.join("local:myhost").join("inbox").join("node-1"))?;
+    fs::create_dir_all(home.join("runs").join("12345"))?;
+
+    let status = |lease: &str| -> Result<serde_json::Value> {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_leaseq"))
+            .args(["status", "--lease", lease, "--format", "json"])
+            .env("LEASEQ_HOME", &home)
+            .env("LEASEQ_RUNTIME_DIR", &runtime)
+            .output()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    let report = status("myhost")?;
+    assert_eq!(report["lease"], "local:myhost");
+    assert_eq!(report["root"], runtime.join("local:myhost").display().to_string());
+    // Slurm job IDs and unknown names are left alone
+    assert_eq!(status("12345")?["root"], home.join("runs").join("12345").display().to_string());
+    assert_eq!(status("otherhost")?["lease"], "otherhost");
+    Ok(())
+}
@@ -0,0 +1,2269 @@
+use anyhow::Result;
+use leaseq::commands;
+use leaseq_core::{fs as lfs, models};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+use time::OffsetDateTime;
+
+const NODE: &str = "node-1";
+
+struct TestContext {
+    _temp_dir: TempDir,
+    root: PathBuf,
+}
+
+impl TestContext {
+    fn new() -> Result<Self> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().join("lease");
+        fs::create_dir_all(root.join("inbox").join(NODE))?;
+        Ok(Self { _temp_dir: temp_dir, root })
+    }
+
+    fn enqueue(&self, spec: &models::TaskSpec) -> Result<PathBuf> {
+        let path = self
+            .root
+            .join("inbox")
+            .join(NODE)
+            .join(format!("{:016}_{}.json", spec.seq, spec.task_id));
+        lfs::atomic_write_json(&path, spec)?;
+        Ok(path)
+    }
+
+    /// Run the runner against this root for a fixed time (it loops forever).
+    async fn run_for(&self, duration: Duration) {
+        let args = commands::run::RunArgs {
+            lease: "local:test".to_string(),
+            node: Some(NODE.to_string()),
+            root: Some(self.root.clone()),
+            ..Default::default()
+        };
+        let _ = tokio::time::timeout(duration, commands::run::run(args)).await;
+    }
+
+    fn result(&self, task_id: &str) -> Option<models::TaskResult> {
+        find_result(&self.root.join("done").join(NODE), task_id)
+    }
+}
+
+fn find_result(done_dir: &Path, task_id: &str) -> Option<models::TaskResult> {
+    for f in lfs::list_files_sorted(done_dir).ok()? {
+        if f.to_string_lossy().ends_with(".result.json") {
+            if let Ok(res) = lfs::read_json::<models::TaskResult, _>(&f) {
+                if res.task_id == task_id {
+                    return Some(res);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn spec(task_id: &str, command: &str) -> models::TaskSpec {
+    models::TaskSpec {
+        task_id: task_id.to_string(),
+        idempotency_key: format!("key-{}", task_id),
+        lease_id: models::LeaseId("local:test".to_string()),
+        target_node: NODE.to_string(),
+        seq: 1,
+        uuid: uuid::Uuid::new_v4(),
+        created_at: OffsetDateTime::now_utc(),
+        cwd: ".".to_string(),
+        command: command.to_string(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_combined_output_single_log() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-COMB", "echo out1; echo err1 >&2; echo out2");
+    task.combine_output = true;
+    ctx.enqueue(&task)?;
+
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    let res = ctx.result("T-COMB").expect("result should be written");
+    assert_eq!(res.stdout, "logs/T-COMB.log");
+    assert_eq!(res.stderr, res.stdout);
+
+    let content = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    // Login shell profiles may print their own noise first
+    assert!(content.ends_with("out1\nerr1\nout2\n"), "got {:?}", content);
+    assert!(!ctx.root.join("logs").join("T-COMB.out").exists());
+    assert!(!ctx.root.join("logs").join("T-COMB.err").exists());
+
+    // Readers resolve either stream to the combined file
+    let resolved = commands::logs::log_path(&ctx.root, "T-COMB", true);
+    assert_eq!(resolved, ctx.root.join("logs").join("T-COMB.log"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_custom_log_names() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-NAMED", "echo to-collector; echo oops >&2");
+    task.stdout_to = Some("collector.stdout".to_string());
+    task.stderr_to = Some("collector.stderr".to_string());
+    ctx.enqueue(&task)?;
+    // A crafted spec can't write outside the log dir
+    let mut escape = spec("T-ESCAPE", "true");
+    escape.seq = 2;
+    escape.stdout_to = Some("../escaped".to_string());
+    ctx.enqueue(&escape)?;
+
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    let res = ctx.result("T-NAMED").expect("result should be written");
+    assert_eq!(res.stdout, "logs/collector.stdout");
+    assert_eq!(res.stderr, "logs/collector.stderr");
+    assert!(!ctx.root.join("logs").join("T-NAMED.out").exists());
+
+    // `logs` finds them through the task ID
+    let mut out = Vec::new();
+    commands::logs::write_log(&commands::logs::log_path(&ctx.root, "T-NAMED", false), None, false, &mut out)?;
+    assert!(String::from_utf8(out)?.ends_with("to-collector\n"));
+    let mut err = Vec::new();
+    commands::logs::write_log(&commands::logs::log_path(&ctx.root, "T-NAMED", true), None, false, &mut err)?;
+    assert!(String::from_utf8(err)?.ends_with("oops\n"));
+    // ...and `verify` doesn't mistake them for orphans
+    let issues = commands::verify::verify(&ctx.root)?;
+    assert!(!issues.iter().any(|i| matches!(i, commands::verify::Issue::OrphanedLog { .. })), "{:?}", issues);
+
+    assert!(ctx.result("T-ESCAPE").is_none());
+    assert!(!ctx.root.join("escaped").exists());
+    assert_eq!(leaseq_core::quarantine::list(&ctx.root)?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_requeue_on_preempt_moves_task_to_new_lease() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let new_root = ctx._temp_dir.path().join("fresh");
+
+    // A node that held two claimed tasks and then vanished (stale heartbeat)
+    let gone = "node-gone";
+    let claimed = ctx.root.join("claimed").join(gone);
+    let mut marked = spec("T-MARKED", "python train.py");
+    marked.target_node = gone.to_string();
+    marked.requeue_on_preempt = true;
+    let mut plain = spec("T-PLAIN", "python eval.py");
+    plain.target_node = gone.to_string();
+    lfs::atomic_write_json(claimed.join("0000000000000001_T-MARKED.json"), &marked)?;
+    lfs::atomic_write_json(claimed.join("0000000000000002_T-PLAIN.json"), &plain)?;
+    // Finished, but the node went before its spec was archived
+    let mut finished = spec("T-FINISHED", "python export.py");
+    finished.target_node = gone.to_string();
+    finished.requeue_on_preempt = true;
+    lfs::atomic_write_json(claimed.join("0000000000000003_T-FINISHED.json"), &finished)?;
+    let result = models::TaskResult {
+        task_id: "T-FINISHED".to_string(),
+        idempotency_key: "key-T-FINISHED".to_string(),
+        node: gone.to_string(),
+        started_at: OffsetDateTime::now_utc(),
+        finished_at: OffsetDateTime::now_utc(),
+        exit_code: 0,
+        stdout: String::new(),
+        stderr: String::new(),
+        runtime_s: 0.0,
+        command: "python export.py".to_string(),
+        resolved_command: String::new(),
+        cwd: ".".to_string(),
+        gpus_requested: 1,
+        gpus_assigned: "1".to_string(),
+        checksum: None,
+        failure: None,
+        signal: None,
+    }
+    .with_checksum();
+    let done = ctx.root.join("done").join(gone);
+    lfs::atomic_write_json(done.join("0000000000000003_T-FINISHED.result.json"), &result)?;
+    // Both still hold a GPU in the dead node's ledger
+    leaseq_core::gpu::allocate(&ctx.root, gone, "T-MARKED", 1, 2)?;
+    leaseq_core::gpu::allocate(&ctx.root, gone, "T-FINISHED", 1, 2)?;
+    lfs::atomic_write_json(
+        ctx.root.join("hb").join(format!("{}.json", gone)),
+        &models::Heartbeat {
+            node: gone.to_string(),
+            ts: OffsetDateTime::now_utc() - time::Duration::hours(1),
+            running_task_id: Some("T-MARKED".to_string()),
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1,
+            version: "test".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        },
+    )?;
+
+    let requeued = commands::run::requeue_preempted(&ctx.root, &new_root, "99999", "node-new")?;
+    assert_eq!(requeued, vec!["T-MARKED".to_string()]);
+
+    let moved = new_root.join("inbox").join("node-new").join("0000000000000001_T-MARKED.json");
+    let spec: models::TaskSpec = lfs::read_json(&moved)?;
+    assert_eq!(spec.lease_id.0, "99999");
+    assert_eq!(spec.target_node, "node-new");
+    assert!(!claimed.join("0000000000000001_T-MARKED.json").exists());
+
+    // Unmarked tasks stay where they were
+    assert!(claimed.join("0000000000000002_T-PLAIN.json").exists());
+    // A finished one is archived next to its result instead of running again
+    assert!(!new_root.join("inbox").join("node-new").join("0000000000000003_T-FINISHED.json").exists());
+    assert!(done.join("0000000000000003_T-FINISHED.json").exists());
+    assert_eq!(leaseq_core::gpu::free_devices(&ctx.root, gone, 2)?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_oversized_command_rejected() {
+    let huge = "x".repeat(1024);
+    let err = commands::submit::check_command_len(&huge, 512).unwrap_err();
+    assert!(err.to_string().contains("--stdin-file"), "got: {}", err);
+    assert!(commands::submit::check_command_len("echo ok", 512).is_ok());
+}
+
+#[test]
+fn test_interactive_command_warned() {
+    use commands::submit::interactive_program;
+
+    assert_eq!(interactive_program("vim notes.txt", false).as_deref(), Some("vim"));
+    assert_eq!(interactive_program("EDITOR=x /usr/bin/less run.log", false).as_deref(), Some("less"));
+    assert_eq!(interactive_program("python", false).as_deref(), Some("python"));
+    assert_eq!(interactive_program("env FOO=1 bash", false).as_deref(), Some("bash"));
+    // A REPL with a script or input doesn't need a terminal
+    assert_eq!(interactive_program("python train.py", false), None);
+    assert_eq!(interactive_program("python < input.py", false), None);
+    assert_eq!(interactive_program("python", true), None);
+    assert_eq!(interactive_program("make -j8", false), None);
+
+    // --interactive runs it in the allocation with a pty
+    let cmd = commands::shell::interactive_command("1234", Some("gpu01"), &["vim".to_string(), "notes.txt".to_string()]);
+    assert_eq!(cmd.get_program(), "srun");
+    let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect();
+    assert_eq!(args, ["--jobid", "1234", "--nodelist", "gpu01", "--pty", "vim", "notes.txt"]);
+}
+
+#[tokio::test]
+async fn test_stdin_file_feeds_command() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let input = ctx._temp_dir.path().join("input.txt");
+    fs::write(&input, "line-a\nline-b\n")?;
+
+    let mut task = spec("T-STDIN", "wc -l");
+    task.stdin_file = Some(input.to_string_lossy().into_owned());
+    ctx.enqueue(&task)?;
+
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    let res = ctx.result("T-STDIN").expect("result should be written");
+    assert_eq!(res.exit_code, 0);
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert_eq!(out.trim(), "2");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_stdin_file_fails_task() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let input = ctx._temp_dir.path().join("gone.txt");
+    fs::write(&input, "line-a\n")?;
+
+    let mut task = spec("T-NOSTDIN", "wc -l");
+    task.stdin_file = Some(input.to_string_lossy().into_owned());
+    task.on_failure = Some("echo cleanup".to_string());
+    ctx.enqueue(&task)?;
+    // Moved away between submit and run
+    fs::remove_file(&input)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let res = ctx.result("T-NOSTDIN").expect("the task should fail, not stay claimed");
+    assert_ne!(res.exit_code, 0);
+    assert_eq!(res.failure, Some(models::FailureReason::SpawnError));
+    let err = fs::read_to_string(ctx.root.join(&res.stderr))?;
+    assert!(err.contains("cannot open stdin file") && err.contains("gone.txt"), "{}", err);
+    assert!(lfs::list_files_sorted(ctx.root.join("claimed").join(NODE))?.is_empty());
+    // Failed like any other task: the on_failure follow-up is queued
+    let queued = lfs::list_files_sorted(ctx.root.join("inbox").join(NODE))?;
+    let done: Vec<models::TaskResult> = lfs::list_files_sorted(ctx.root.join("done").join(NODE))?
+        .iter()
+        .filter(|f| f.to_string_lossy().ends_with(".result.json"))
+        .filter_map(|f| lfs::read_json(f).ok())
+        .collect();
+    assert!(
+        !queued.is_empty() || done.iter().any(|r| r.command == "echo cleanup"),
+        "on_failure task was not queued"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_working_copy_snapshot() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let project = ctx._temp_dir.path().join("project");
+    fs::create_dir_all(project.join("configs"))?;
+    fs::write(project.join("train.py"), "print('v1')\n")?;
+    fs::write(project.join("configs").join("a.yaml"), "lr: 0.1\n")?;
+    fs::write(project.join("configs").join("b.yaml"), "lr: 0.2\n")?;
+    fs::write(project.join("notes.txt"), "not captured\n")?;
+
+    let snapshot_rel = "snapshots/T-SNAP";
+    let copied = commands::submit::snapshot_files(
+        &["train.py".to_string(), "configs/*.yaml".to_string()],
+        &project,
+        &ctx.root.join(snapshot_rel),
+    )?;
+    assert_eq!(copied.len(), 3);
+    assert!(ctx.root.join(snapshot_rel).join("configs").join("b.yaml").exists());
+    assert!(!ctx.root.join(snapshot_rel).join("notes.txt").exists());
+
+    // Editing the original after submit doesn't affect the run
+    fs::write(project.join("configs").join("a.yaml"), "lr: 9.9\n")?;
+
+    let mut task = spec("T-SNAP", "cat configs/a.yaml");
+    task.cwd = project.to_string_lossy().into_owned();
+    task.snapshot_dir = Some(snapshot_rel.to_string());
+    task.run_in_snapshot = true;
+    ctx.enqueue(&task)?;
+
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    let res = ctx.result("T-SNAP").expect("result should be written");
+    assert_eq!(res.exit_code, 0);
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert!(out.ends_with("lr: 0.1\n"), "got {:?}", out);
+
+    // Patterns that match nothing are an error, not a silent empty snapshot
+    assert!(commands::submit::snapshot_files(&["*.missing".to_string()], &project, &ctx.root.join("x")).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_git_toplevel_resolves_repo_root() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let repo = temp_dir.path().join("repo");
+    let nested = repo.join("src").join("models");
+    fs::create_dir_all(&nested)?;
+
+    let status = std::process::Command::new("git").arg("init").arg("-q").arg(&repo).status()?;
+    assert!(status.success());
+
+    let top = commands::submit::git_toplevel(&nested)?;
+    assert_eq!(top.canonicalize()?, repo.canonicalize()?);
+
+    // Outside any repository it is an error rather than a silent fallback
+    let outside = temp_dir.path().join("plain");
+    fs::create_dir_all(&outside)?;
+    let err = commands::submit::git_toplevel(&outside).unwrap_err();
+    assert!(err.to_string().contains("not inside a git repository"), "got: {}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_custom_log_dir() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let scratch = ctx._temp_dir.path().join("scratch");
+    ctx.enqueue(&spec("T-SCRATCH", "echo to-scratch; echo oops >&2"))?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig { log_dir: Some(scratch.clone()), ..Default::default() }),
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(5), commands::run::run(args)).await;
+
+    let res = ctx.result("T-SCRATCH").expect("result should be written");
+    let stdout = PathBuf::from(&res.stdout);
+    assert!(stdout.is_absolute(), "got {}", res.stdout);
+    assert_eq!(stdout, scratch.join("T-SCRATCH.out"));
+    assert!(fs::read_to_string(&stdout)?.ends_with("to-scratch\n"));
+    assert!(!ctx.root.join("logs").join("T-SCRATCH.out").exists());
+
+    // Readers follow the recorded location
+    assert_eq!(commands::logs::log_path(&ctx.root, "T-SCRATCH", false), stdout);
+    assert_eq!(commands::logs::log_path(&ctx.root, "T-SCRATCH", true), scratch.join("T-SCRATCH.err"));
+    Ok(())
+}
+
+#[test]
+fn test_group_cancel_only_touches_group() -> Result<()> {
+    let ctx = TestContext::new()?;
+    for (seq, id) in ["G1", "G2", "G3", "OTHER"].iter().enumerate() {
+        let mut s = spec(id, "true");
+        s.seq = seq as u64 + 1;
+        if *id != "OTHER" {
+            s.batch_id = Some("sweep".to_string());
+        }
+        ctx.enqueue(&s)?;
+    }
+
+    let members = commands::group::group_members(&ctx.root, "sweep")?;
+    assert_eq!(members.len(), 3);
+    assert!(members.iter().all(|m| m.state == "PENDING"));
+
+    let mut cancelled = commands::group::cancel_group(&ctx.root, "sweep")?;
+    cancelled.sort();
+    assert_eq!(cancelled, vec!["G1", "G2", "G3"]);
+
+    // The other task is untouched; the group now reads as cancelled
+    let inbox = lfs::list_files_sorted(ctx.root.join("inbox").join(NODE))?;
+    assert_eq!(inbox.len(), 1);
+    assert!(inbox[0].to_string_lossy().ends_with("_OTHER.json"));
+    let members = commands::group::group_members(&ctx.root, "sweep")?;
+    assert_eq!(members.len(), 3);
+    assert!(members.iter().all(|m| m.state == "CANCELLED"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_dir_per_task_with_latest_link() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let runs = ctx._temp_dir.path().join("runs");
+
+    let first = commands::submit::prepare_output_dir(&runs, "T-OUT1")?;
+    assert!(first.is_dir());
+    assert_eq!(fs::read_link(runs.join("latest"))?, PathBuf::from("T-OUT1"));
+
+    let dir = commands::submit::prepare_output_dir(&runs, "T-OUT2")?;
+    assert_eq!(fs::read_link(runs.join("latest"))?, PathBuf::from("T-OUT2"));
+    assert_eq!(fs::canonicalize(runs.join("latest"))?, dir);
+
+    let mut s = spec("T-OUT2", "pwd");
+    s.cwd = dir.to_string_lossy().into_owned();
+    s.output_dir = Some(s.cwd.clone());
+    ctx.enqueue(&s)?;
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    // Runs inside its dir and logs there
+    let res = ctx.result("T-OUT2").expect("result should be written");
+    assert_eq!(PathBuf::from(&res.stdout), dir.join("T-OUT2.out"));
+    let out = fs::read_to_string(dir.join("T-OUT2.out"))?;
+    assert!(out.ends_with(&format!("{}\n", dir.display())), "stdout: {}", out);
+    assert_eq!(commands::logs::log_path(&ctx.root, "T-OUT2", false), dir.join("T-OUT2.out"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_submit_from_csv_with_labels() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let runtime = ctx._temp_dir.path().join("runtime");
+    std::env::set_var("LEASEQ_RUNTIME_DIR", &runtime);
+
+    let csv = ctx._temp_dir.path().join("sweep.csv");
+    fs::write(&csv, "echo a,lr=0.1,seed=1\n\"echo b, c\",lr=0.01\n")?;
+    let opts = commands::submit::SubmitOptions {
+        labels: vec![("sweep".to_string(), "s1".to_string())],
+        ..Default::default()
+    };
+    let specs =
+        commands::submit::submit_csv(&csv, Some("local:csv".to_string()), Some(NODE.to_string()), &opts).await?;
+
+    let inbox = lfs::list_files_sorted(runtime.join("local:csv").join("inbox").join(NODE))?;
+    assert_eq!(inbox.len(), 2);
+    let mut queued: Vec<models::TaskSpec> = inbox.iter().map(lfs::read_json).collect::<Result<_, _>>()?;
+    queued.sort_by(|a, b| a.command.cmp(&b.command));
+    assert_eq!(queued[0].command, "echo a");
+    assert_eq!(queued[1].command, "echo b, c");
+    assert_eq!(queued[0].labels.get("lr").map(String::as_str), Some("0.1"));
+    assert_eq!(queued[0].labels.get("seed").map(String::as_str), Some("1"));
+    assert_eq!(queued[1].labels.get("lr").map(String::as_str), Some("0.01"));
+    assert!(queued.iter().all(|s| s.labels.get("sweep").map(String::as_str) == Some("s1")));
+    assert_eq!(specs.len(), 2);
+
+    // A header row maps columns to env vars
+    fs::write(&csv, "command,LR\necho $LR,0.5\n")?;
+    let specs = commands::submit::submit_csv(&csv, Some("local:csv".to_string()), Some(NODE.to_string()), &Default::default()).await?;
+    assert_eq!(specs[0].env.get("LR").map(String::as_str), Some("0.5"));
+    assert_eq!(specs[0].command, "echo $LR");
+
+    std::env::remove_var("LEASEQ_RUNTIME_DIR");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_after_waits_for_dependency() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // Queued ahead of its dependency, so FIFO alone would run it first
+    let mut second = spec("T-SECOND", "true");
+    second.seq = 1;
+    second.depends_on = vec!["T-FIRST".to_string()];
+    ctx.enqueue(&second)?;
+    let mut first = spec("T-FIRST", "true");
+    first.seq = 2;
+    ctx.enqueue(&first)?;
+
+    ctx.run_for(Duration::from_secs(8)).await;
+
+    let first = ctx.result("T-FIRST").expect("dependency should run");
+    let second = ctx.result("T-SECOND").expect("dependent should run once unblocked");
+    assert!(second.started_at >= first.finished_at);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancelled_dependency_fails_dependent() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // Cancelled while still queued, so it only has a .cancelled.json record
+    let cancelled = models::TaskResult {
+        task_id: "T-GONE".to_string(),
+        idempotency_key: "key-T-GONE".to_string(),
+        node: NODE.to_string(),
+        started_at: OffsetDateTime::now_utc(),
+        finished_at: OffsetDateTime::now_utc(),
+        exit_code: -1,
+        stdout: String::new(),
+        stderr: String::new(),
+        runtime_s: 0.0,
+        command: "true".to_string(),
+        resolved_command: String::new(),
+        cwd: ".".to_string(),
+        gpus_requested: 0,
+        gpus_assigned: String::new(),
+        checksum: None,
+        failure: Some(models::FailureReason::Cancelled),
+        signal: None,
+    };
+    lfs::atomic_write_json(ctx.root.join("done").join(NODE).join("0000000000000000_T-GONE.cancelled.json"), &cancelled)?;
+    let mut down = spec("T-DOWN", "true");
+    down.depends_on = vec!["T-GONE".to_string()];
+    ctx.enqueue(&down)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let res = ctx.result("T-DOWN").expect("the dependent should fail, not stay pending");
+    assert_eq!(res.failure, Some(models::FailureReason::DependencyFailed));
+    Ok(())
+}
+
+#[test]
+fn test_after_unknown_task_rejected() -> Result<()> {
+    let ctx = TestContext::new()?;
+    ctx.enqueue(&spec("T-KNOWN", "true"))?;
+
+    commands::submit::check_dependencies(&ctx.root, &["T-KNOWN".to_string()])?;
+    let err = commands::submit::check_dependencies(&ctx.root, &["T-KNOWN".to_string(), "T-TYPO".to_string()])
+        .unwrap_err();
+    assert!(err.to_string().contains("T-TYPO"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lease_command_prefix() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let settings = commands::lease::update_settings(&ctx.root, |s| {
+        s.command_prefix = Some("nice -n 7".to_string());
+    })?;
+    assert_eq!(settings.command_prefix.as_deref(), Some("nice -n 7"));
+
+    ctx.enqueue(&spec("T-NICE", "nice"))?;
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    // `nice` with no arguments prints its own niceness: ours plus the prefix's 7
+    let base = String::from_utf8(std::process::Command::new("nice").output()?.stdout)?;
+    let expected = (base.trim().parse::<i32>()? + 7).min(19);
+    let res = ctx.result("T-NICE").expect("result should be written");
+    assert_eq!(res.exit_code, 0);
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert_eq!(out.lines().last(), Some(expected.to_string().as_str()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_env_inherit_none_passes_only_spec_env() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-ENV", "env");
+    task.env.insert("FROM_SPEC".to_string(), "yes".to_string());
+    ctx.enqueue(&task)?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig { env_inherit: leaseq_core::config::EnvInherit::None, ..Default::default() }),
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(5), commands::run::run(args)).await;
+
+    let names = |out: &str| -> std::collections::BTreeSet<String> {
+        out.lines().filter_map(|l| l.split_once('=')).map(|(k, _)| k.to_string()).collect()
+    };
+    // Whatever a login shell sets up by itself from an empty environment
+    let bare = std::process::Command::new("env").args(["-i", "bash", "-lc", "env"]).output()?;
+    let shell_own = names(&String::from_utf8_lossy(&bare.stdout));
+
+    let res = ctx.result("T-ENV").expect("result should be written");
+    assert_eq!(res.exit_code, 0);
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert!(out.lines().any(|l| l == "FROM_SPEC=yes"), "got {:?}", out);
+    let leaked: Vec<_> = names(&out)
+        .into_iter()
+        // The chain depth is set for every task, not inherited from the runner
+        .filter(|k| k != "FROM_SPEC" && k != models::CHAIN_DEPTH_ENV && !shell_own.contains(k))
+        .collect();
+    assert!(leaked.is_empty(), "runner env leaked into task: {:?}", leaked);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_priority_aging_prevents_starvation() -> Result<()> {
+    // An old low-priority task queued behind a fresh high-priority one
+    async fn first_claimed(aging: f64) -> Result<String> {
+        let ctx = TestContext::new()?;
+        commands::lease::update_settings(&ctx.root, |s| s.priority_aging = Some(aging))?;
+        let order = ctx._temp_dir.path().join("order");
+        let mut old = spec("T-OLD", &format!("echo old >> {}", order.display()));
+        old.seq = 1;
+        old.created_at = OffsetDateTime::now_utc() - time::Duration::hours(2);
+        let mut fresh = spec("T-FRESH", &format!("echo fresh >> {}", order.display()));
+        fresh.seq = 2;
+        fresh.priority = 50;
+        ctx.enqueue(&old)?;
+        ctx.enqueue(&fresh)?;
+
+        ctx.run_for(Duration::from_secs(6)).await;
+        let content = fs::read_to_string(&order)?;
+        Ok(content.lines().next().unwrap_or_default().to_string())
+    }
+
+    // Without aging the boost wins; after 120 minutes at 1/min the old task outranks it
+    assert_eq!(first_claimed(0.0).await?, "fresh");
+    assert_eq!(first_claimed(1.0).await?, "old");
+    Ok(())
+}
+
+#[test]
+fn test_logs_raw_is_byte_exact() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let logs = ctx.root.join("logs");
+    fs::create_dir_all(&logs)?;
+    // Invalid UTF-8, NULs, CRLF and no trailing newline
+    let content: Vec<u8> = (0..=255u8).chain(*b"\r\nline\r\n\xff\xfe tail").collect();
+    fs::write(logs.join("T-BIN.out"), &content)?;
+
+    let path = commands::logs::log_path(&ctx.root, "T-BIN", false);
+    let mut out = Vec::new();
+    let n = commands::logs::copy_raw(&path, None, &mut out)?;
+    assert_eq!(n as usize, content.len());
+    assert_eq!(out, content);
+
+    // --tail takes a byte window from the end
+    let mut out = Vec::new();
+    commands::logs::copy_raw(&path, Some(10), &mut out)?;
+    assert_eq!(out, &content[content.len() - 10..]);
+    let mut out = Vec::new();
+    commands::logs::copy_raw(&path, Some(100_000), &mut out)?;
+    assert_eq!(out, content);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_poll_errors_counted_in_heartbeat() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let inbox = ctx.root.join("inbox").join(NODE);
+    let swap = async {
+        // Once the runner is up, make its inbox unreadable as a directory
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        fs::remove_dir_all(&inbox).unwrap();
+        fs::write(&inbox, "not a directory").unwrap();
+    };
+    tokio::join!(ctx.run_for(Duration::from_secs(4)), swap);
+
+    let hb: models::Heartbeat = lfs::read_heartbeat(ctx.root.join("hb").join(format!("{}.json", NODE)))?;
+    assert!(hb.errors_since_start >= 2, "got {}", hb.errors_since_start);
+    let last = hb.last_error.expect("last error should be recorded");
+    assert!(last.starts_with("poll:"), "got {}", last);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_submit_dry_run_writes_nothing() -> Result<()> {
+    let lease = format!("local:dry-{}", uuid::Uuid::new_v4().simple());
+    let opts = commands::submit::SubmitOptions {
+        dry_run: true,
+        depends_on: vec!["T-UP".to_string()],
+        env: [("MODE".to_string(), "dry".to_string())].into(),
+        gpus: 2,
+        ..Default::default()
+    };
+    let spec =
+        commands::submit::add_task_with_options("echo hi".to_string(), Some(lease.clone()), Some(NODE.to_string()), &opts)
+            .await?;
+    assert_eq!(spec.target_node, NODE);
+    assert_eq!(spec.env.get("MODE").map(String::as_str), Some("dry"));
+    assert_eq!(spec.gpus, 2);
+
+    let human = commands::submit::format_spec(&spec, commands::OutputFormat::Human)?;
+    assert!(human.contains(&spec.task_id), "{}", human);
+    assert!(human.contains("command:  echo hi"), "{}", human);
+    assert!(human.contains("after:    T-UP"), "{}", human);
+    assert!(human.contains("gpus:     2"), "{}", human);
+    let json = commands::submit::format_spec(&spec, commands::OutputFormat::Json)?;
+    let parsed: models::TaskSpec = serde_json::from_str(&json)?;
+    assert_eq!(parsed.task_id, spec.task_id);
+    assert_eq!(parsed.command, "echo hi");
+
+    // Not even the lease directory is created
+    assert!(!leaseq_core::config::runtime_dir().join(&lease).exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_task_larger_than_node_fails() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut gpu_heavy = spec("T-GPU-BIG", "echo \"gpu=$CUDA_VISIBLE_DEVICES\"");
+    gpu_heavy.gpus = 3;
+    ctx.enqueue(&gpu_heavy)?;
+    let mut cpu_heavy = spec("T-CPU-BIG", "true");
+    cpu_heavy.seq = 2;
+    cpu_heavy.cpus = 5;
+    ctx.enqueue(&cpu_heavy)?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig { gpus: Some(2), cpus: Some(4), ..Default::default() }),
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(3), commands::run::run(args)).await;
+
+    // Failed without running, rather than run outside the ledger
+    for (id, wants) in [("T-GPU-BIG", "wants 3 GPUs"), ("T-CPU-BIG", "wants 5 CPUs")] {
+        let res = ctx.result(id).expect("oversized task should fail");
+        assert_eq!(res.failure, Some(models::FailureReason::SpawnError));
+        let err = fs::read_to_string(ctx.root.join(&res.stderr))?;
+        assert!(err.contains(wants), "{}", err);
+        assert!(fs::read_to_string(ctx.root.join(&res.stdout))?.is_empty());
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gpu_ledger_disjoint_across_runners() -> Result<()> {
+    let ctx = TestContext::new()?;
+    for (i, id) in ["T-GPU-A", "T-GPU-B"].iter().enumerate() {
+        let mut task = spec(id, "echo \"gpu=$CUDA_VISIBLE_DEVICES\"; sleep 2");
+        task.seq = i as u64;
+        task.gpus = 1;
+        ctx.enqueue(&task)?;
+    }
+
+    // Two runners serving the same node, sharing two GPUs
+    let runner = || {
+        let args = commands::run::RunArgs {
+            lease: "local:test".to_string(),
+            node: Some(NODE.to_string()),
+            root: Some(ctx.root.clone()),
+            config: Some(leaseq_core::config::RunConfig { gpus: Some(2), ..Default::default() }),
+        };
+        tokio::time::timeout(Duration::from_secs(8), commands::run::run(args))
+    };
+    let _ = tokio::join!(runner(), runner());
+
+    let a = ctx.result("T-GPU-A").expect("result A");
+    let b = ctx.result("T-GPU-B").expect("result B");
+    assert_eq!(a.gpus_assigned.split(',').count(), 1);
+    assert_ne!(a.gpus_assigned, b.gpus_assigned, "both tasks got GPU {}", a.gpus_assigned);
+    for res in [&a, &b] {
+        let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+        assert!(out.contains(&format!("gpu={}\n", res.gpus_assigned)), "got {:?}", out);
+    }
+
+    // Everything is handed back once the tasks finish
+    let ledger = leaseq_core::gpu::read_ledger(&ctx.root, NODE)?;
+    assert!(ledger.devices.is_empty(), "{:?}", ledger);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stdin_from_task_pipes_output() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // Downstream first in queue order: it has to wait for its source
+    let mut down = spec("T-CAT", "cat");
+    down.seq = 1;
+    down.stdin_from_task = Some("T-ECHO".to_string());
+    down.depends_on = vec!["T-ECHO".to_string()];
+    let mut up = spec("T-ECHO", "echo hi");
+    up.seq = 2;
+    ctx.enqueue(&down)?;
+    ctx.enqueue(&up)?;
+
+    ctx.run_for(Duration::from_secs(8)).await;
+
+    let res = ctx.result("T-CAT").expect("downstream result should be written");
+    assert_eq!(res.exit_code, 0);
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert!(out.contains("hi\n"), "got {:?}", out);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stdin_from_task_without_log_fails_task() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // The source succeeded, but its stdout log was pruned since
+    let source = models::TaskResult {
+        task_id: "T-PRUNED".to_string(),
+        idempotency_key: "key-T-PRUNED".to_string(),
+        node: NODE.to_string(),
+        started_at: OffsetDateTime::now_utc(),
+        finished_at: OffsetDateTime::now_utc(),
+        exit_code: 0,
+        stdout: "logs/T-PRUNED.out".to_string(),
+        stderr: "logs/T-PRUNED.err".to_string(),
+        runtime_s: 0.0,
+        command: "echo hi".to_string(),
+        resolved_command: String::new(),
+        cwd: ".".to_string(),
+        gpus_requested: 0,
+        gpus_assigned: String::new(),
+        checksum: None,
+        failure: None,
+        signal: None,
+    };
+    lfs::atomic_write_json(ctx.root.join("done").join(NODE).join("0000000000000000_T-PRUNED.result.json"), &source)?;
+
+    let mut down = spec("T-CAT", "cat");
+    down.stdin_from_task = Some("T-PRUNED".to_string());
+    down.depends_on = vec!["T-PRUNED".to_string()];
+    ctx.enqueue(&down)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let res = ctx.result("T-CAT").expect("the task should fail, not stay claimed");
+    assert_ne!(res.exit_code, 0);
+    assert_eq!(res.failure, Some(models::FailureReason::SpawnError));
+    let err = fs::read_to_string(ctx.root.join(&res.stderr))?;
+    assert!(err.contains("cannot open stdout of task T-PRUNED"), "{}", err);
+    assert!(lfs::list_files_sorted(ctx.root.join("claimed").join(NODE))?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failure_reasons_recorded_and_grouped() -> Result<()> {
+    use commands::tasks::{collect_tasks, render_by_reason, TaskListOptions, TaskStateFilter};
+    use models::FailureReason;
+
+    let ctx = TestContext::new()?;
+    let tasks = [
+        ("T-KILLED", "kill -9 $$", vec![]),
+        ("T-EXIT", "exit 3", vec![]),
+        ("T-DOWNSTREAM", "echo never", vec!["T-EXIT".to_string()]),
+        ("T-OK", "true", vec![]),
+    ];
+    for (i, (id, command, deps)) in tasks.into_iter().enumerate() {
+        let mut task = spec(id, command);
+        task.seq = i as u64;
+        task.depends_on = deps;
+        ctx.enqueue(&task)?;
+    }
+
+    ctx.run_for(Duration::from_secs(10)).await;
+
+    let reason = |id: &str| ctx.result(id).unwrap_or_else(|| panic!("no result for {}", id)).failure;
+    // SIGKILL is what the OOM killer sends
+    assert_eq!(reason("T-KILLED"), Some(FailureReason::Oom));
+    assert_eq!(reason("T-EXIT"), Some(FailureReason::NonZeroExit));
+    assert_eq!(reason("T-DOWNSTREAM"), Some(FailureReason::DependencyFailed));
+    assert_eq!(reason("T-OK"), None);
+
+    let opts = TaskListOptions { by_reason: true, ..Default::default() };
+    let rows = collect_tasks(&ctx.root, TaskStateFilter::Failed, None, None, &opts, OffsetDateTime::now_utc())?;
+    let lines = render_by_reason(&rows);
+    assert_eq!(lines.len(), 6, "{:?}", lines);
+    for (reason, task) in [("OOM", "T-KILLED"), ("NON_ZERO_EXIT", "T-EXIT"), ("DEPENDENCY_FAILED", "T-DOWNSTREAM")] {
+        let at = lines.iter().position(|l| l.starts_with(reason)).expect(reason);
+        assert!(lines[at].ends_with("1 (33%)"), "{:?}", lines);
+        assert_eq!(lines[at + 1].trim(), task);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_queue_position_of_submitted_task() -> Result<()> {
+    use commands::submit::{queue_position, QueuePosition};
+
+    let ctx = TestContext::new()?;
+    for (seq, id) in [(1, "T-FIRST"), (2, "T-SECOND"), (3, "T-NEW")] {
+        let mut task = spec(id, "true");
+        task.seq = seq;
+        ctx.enqueue(&task)?;
+    }
+
+    let pos = queue_position(&ctx.root, NODE, "T-NEW")?;
+    assert_eq!(pos, Some(QueuePosition { position: 3, backlog: 3 }));
+    assert_eq!(queue_position(&ctx.root, NODE, "T-FIRST")?.map(|p| p.position), Some(1));
+    // A prefix of another task's ID is not that task
+    assert_eq!(queue_position(&ctx.root, NODE, "T-NE")?, None);
+    assert_eq!(queue_position(&ctx.root, "other-node", "T-NEW")?, None);
+    Ok(())
+}
+
+#[test]
+fn test_node_order_strategies() -> Result<()> {
+    use commands::submit::{resolve_target_node, NodeOrder};
+
+    let ctx = TestContext::new()?;
+    let heartbeat = |node: &str, age: time::Duration| {
+        lfs::atomic_write_json(
+            ctx.root.join("hb").join(format!("{}.json", node)),
+            &models::Heartbeat {
+                node: node.to_string(),
+                ts: OffsetDateTime::now_utc() - age,
+                running_task_id: None,
+                running_task_ids: Vec::new(),
+                pending_estimate: 0,
+                runner_pid: 1,
+                version: "test".to_string(),
+                schema_version: models::SCHEMA_VERSION,
+                cpus: 0,
+                errors_since_start: 0,
+                last_error: None,
+            },
+        )
+    };
+    let queue = |state: &str, node: &str, seq: u64| {
+        let mut task = spec(&format!("T{}", seq), "true");
+        task.target_node = node.to_string();
+        lfs::atomic_write_json(ctx.root.join(state).join(node).join(format!("{:016}_T{}.json", seq, seq)), &task)
+    };
+    // node-a holds 2 tasks, node-b none, node-c one; node-d is idle but its runner is gone
+    for node in ["node-c", "node-a", "node-b"] {
+        heartbeat(node, time::Duration::ZERO)?;
+    }
+    heartbeat("node-d", time::Duration::hours(1))?;
+    queue("inbox", "node-a", 1)?;
+    queue("claimed", "node-a", 2)?;
+    queue("inbox", "node-c", 3)?;
+
+    let pick = |order| resolve_target_node(&ctx.root, "12345", None, order);
+    assert_eq!(pick(NodeOrder::First)?, "node-a");
+    assert_eq!(pick(NodeOrder::LeastLoaded)?, "node-b");
+    assert_eq!(resolve_target_node(&ctx.root, "12345", Some("node-d".to_string()), NodeOrder::First)?, "node-d");
+
+    // Round-robin continues after the node given the latest task, wrapping around
+    assert_eq!(pick(NodeOrder::RoundRobin)?, "node-a");
+    queue("inbox", "node-a", 4)?;
+    assert_eq!(pick(NodeOrder::RoundRobin)?, "node-b");
+    queue("done", "node-b", 5)?;
+    assert_eq!(pick(NodeOrder::RoundRobin)?, "node-c");
+
+    let picked: std::collections::HashSet<String> = (0..60).map(|_| pick(NodeOrder::Random)).collect::<Result<_>>()?;
+    assert!(!picked.contains("node-d"));
+    assert!(picked.len() > 1, "random placement always chose {:?}", picked);
+
+    // Equally loaded nodes take turns, starting after the node given the latest task
+    queue("inbox", "node-b", 6)?;
+    assert_eq!(pick(NodeOrder::LeastLoaded)?, "node-c");
+    queue("done", "node-c", 7)?;
+    assert_eq!(pick(NodeOrder::LeastLoaded)?, "node-b");
+
+    // One picker reads the queue once and keeps count of what it assigned
+    let mut picker = commands::submit::NodePicker::default();
+    let round_robin: Vec<String> = (0..4).map(|_| picker.pick(&ctx.root, "12345", None, NodeOrder::RoundRobin)).collect::<Result<_>>()?;
+    assert_eq!(round_robin, ["node-a", "node-b", "node-c", "node-a"]);
+    // node-a has 3 queued, node-b 1, node-c 1
+    let mut picker = commands::submit::NodePicker::default();
+    let least: Vec<String> = (0..5).map(|_| picker.pick(&ctx.root, "12345", None, NodeOrder::LeastLoaded)).collect::<Result<_>>()?;
+    assert_eq!(least, ["node-b", "node-c", "node-b", "node-c", "node-a"]);
+    Ok(())
+}
+
+#[test]
+fn test_submit_to_lease_not_started_yet() -> Result<()> {
+    use commands::submit::{resolve_target_node, NodeOrder};
+
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("runs").join("99999");
+    let err = resolve_target_node(&root, "99999", None, NodeOrder::default()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Lease 99999 has no running nodes yet; wait for it to start or specify --node"
+    );
+    assert!(!root.exists(), "nothing should be created for an unknown lease");
+
+    // Once a runner has been seen, a dead one is reported as such
+    lfs::atomic_write_json(
+        root.join("hb").join("node-1.json"),
+        &models::Heartbeat {
+            node: "node-1".to_string(),
+            ts: OffsetDateTime::now_utc() - time::Duration::hours(1),
+            running_task_id: None,
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1,
+            version: "test".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        },
+    )?;
+    let err = resolve_target_node(&root, "99999", None, NodeOrder::default()).unwrap_err();
+    assert!(err.to_string().contains("stale: node-1"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_hand_written_specs() -> Result<()> {
+    use commands::import::import_dir;
+
+    let ctx = TestContext::new()?;
+    let external = ctx._temp_dir.path().join("external");
+    fs::create_dir_all(&external)?;
+    let hand_written = |id: &str, uuid: &str, command: &str| {
+        format!(
+            r#"{{
+  "task_id": "{id}",
+  "idempotency_key": "ext-{id}",
+  "lease_id": "somewhere-else",
+  "target_node": "",
+  "seq": 7,
+  "uuid": "{uuid}",
+  "created_at": 1700000000,
+  "cwd": "/tmp",
+  "command": "{command}"
+}}"#
+        )
+    };
+    fs::write(external.join("a.json"), hand_written("T-EXT1", "00000000-0000-0000-0000-000000000001", "echo one"))?;
+    fs::write(external.join("b.json"), hand_written("T-EXT2", "00000000-0000-0000-0000-000000000002", "echo two"))?;
+    fs::write(external.join("notes.txt"), "not a spec")?;
+
+    // One bad file blocks the whole import, with its position reported
+    let broken = ctx._temp_dir.path().join("broken");
+    fs::create_dir_all(&broken)?;
+    fs::copy(external.join("a.json"), broken.join("a.json"))?;
+    fs::write(broken.join("b.json"), "{\n  \"task_id\": \"T-BAD\",\n  \"seq\": \"soon\"\n}")?;
+    let report = import_dir(&broken, &ctx.root, "local:test", Some(NODE.to_string()), false)?;
+    assert!(report.imported.is_empty());
+    assert_eq!(report.invalid.len(), 1);
+    assert_eq!((report.invalid[0].path.clone(), report.invalid[0].line), (broken.join("b.json"), 3));
+    assert!(lfs::list_files_sorted(ctx.root.join("inbox").join(NODE))?.is_empty());
+
+    // IDs and nodes that would reach outside the lease root are rejected
+    let escaping = ctx._temp_dir.path().join("escaping");
+    fs::create_dir_all(&escaping)?;
+    fs::write(escaping.join("a.json"), hand_written("../../T-OUT", "00000000-0000-0000-0000-000000000003", "echo out"))?;
+    fs::write(
+        escaping.join("b.json"),
+        hand_written("T-NODE", "00000000-0000-0000-0000-000000000004", "echo out")
+            .replace(r#""target_node": """#, r#""target_node": "../../outside""#),
+    )?;
+    let report = import_dir(&escaping, &ctx.root, "local:test", None, false)?;
+    assert!(report.imported.is_empty());
+    let messages: Vec<&str> = report.invalid.iter().map(|p| p.message.as_str()).collect();
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+    assert!(messages[0].contains("invalid task ID") && messages[1].contains("invalid node name"), "{:?}", messages);
+    assert!(!ctx._temp_dir.path().join("outside").exists() && !ctx.root.join("outside").exists());
+
+    let report = import_dir(&external, &ctx.root, "local:test", Some(NODE.to_string()), true)?;
+    assert!(report.invalid.is_empty());
+    let ids: Vec<&str> = report.imported.iter().map(|s| s.task_id.as_str()).collect();
+    assert_eq!(ids, ["T-EXT1", "T-EXT2"]);
+    assert!(report.imported.iter().all(|s| s.lease_id.0 == "local:test" && s.target_node == NODE));
+    assert!(!external.join("a.json").exists() && external.join("notes.txt").exists());
+
+    ctx.run_for(Duration::from_secs(8)).await;
+    for id in ["T-EXT1", "T-EXT2"] {
+        let result = ctx.result(id).unwrap_or_else(|| panic!("{} was not run", id));
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.idempotency_key, format!("ext-{}", id));
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_heartbeat_reports_task_without_waiting_for_a_beat() -> Result<()> {
+    use leaseq_core::config::RunConfig;
+
+    let ctx = TestContext::new()?;
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        // No beat during the test: only task changes write the heartbeat
+        config: Some(RunConfig {
+            heartbeat_interval: Duration::from_secs(600),
+            poll_interval: Duration::from_millis(50),
+            ..Default::default()
+        }),
+    };
+    let runner = tokio::spawn(commands::run::run(args));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    ctx.enqueue(&spec("T-HB", "sleep 1"))?;
+
+    let hb_path = ctx.root.join("hb").join(format!("{}.json", NODE));
+    let running_task = || lfs::read_heartbeat(&hb_path).ok().and_then(|hb| hb.running_task_id);
+    let mut seen_running = false;
+    for _ in 0..200 {
+        if ctx.result("T-HB").is_some() {
+            break;
+        }
+        seen_running |= running_task().as_deref() == Some("T-HB");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(seen_running, "heartbeat never reported T-HB");
+    assert!(ctx.result("T-HB").is_some());
+    // Cleared as soon as the task is done
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    runner.abort();
+    assert_eq!(running_task(), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_parallel_slots_run_tasks_concurrently() -> Result<()> {
+    use leaseq_core::config::RunConfig;
+
+    let ctx = TestContext::new()?;
+    let mut dup = spec("T-PAR-DUP", "sleep 1");
+    // Same key as T-PAR-A, and claimed while A runs in the other slot
+    dup.idempotency_key = "key-T-PAR-A".to_string();
+    for (i, task) in [spec("T-PAR-A", "sleep 1"), dup, spec("T-PAR-B", "sleep 1"), spec("T-PAR-C", "sleep 1")]
+        .iter_mut()
+        .enumerate()
+    {
+        task.seq = i as u64;
+        ctx.enqueue(task)?;
+    }
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(RunConfig { parallel: 2, poll_interval: Duration::from_millis(50), ..Default::default() }),
+    };
+    let runner = tokio::spawn(commands::run::run(args));
+
+    let hb_path = ctx.root.join("hb").join(format!("{}.json", NODE));
+    let mut most_running = 0;
+    for _ in 0..200 {
+        if ["T-PAR-A", "T-PAR-B", "T-PAR-C"].iter().all(|id| ctx.result(id).is_some()) {
+            break;
+        }
+        if let Ok(hb) = lfs::read_heartbeat(&hb_path) {
+            most_running = most_running.max(hb.running_task_ids.len());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    runner.abort();
+
+    assert_eq!(most_running, 2, "never ran two tasks at once");
+    for id in ["T-PAR-A", "T-PAR-B", "T-PAR-C"] {
+        assert_eq!(ctx.result(id).map(|r| r.exit_code), Some(0), "{}", id);
+    }
+    let skipped: Vec<_> = lfs::list_files_sorted(ctx.root.join("done").join(NODE))?
+        .into_iter()
+        .filter(|f| models::DoneFileKind::of(f) == models::DoneFileKind::Skipped)
+        .collect();
+    assert_eq!(skipped.len(), 1, "{:?}", skipped);
+    assert!(!ctx.root.join("logs").join("T-PAR-DUP.out").exists());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_runner_honors_run_config() -> Result<()> {
+    use leaseq_core::config::RunConfig;
+
+    let ctx = TestContext::new()?;
+    let scratch = ctx._temp_dir.path().join("configured-logs");
+    ctx.enqueue(&spec("T-CFG", "echo configured"))?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(RunConfig {
+            heartbeat_interval: Duration::from_millis(100),
+            poll_interval: Duration::from_millis(50),
+            log_dir: Some(scratch.clone()),
+            gpus: Some(0),
+            cpus: None,
+            parallel: 1,
+            ..Default::default()
+        }),
+    };
+    let runner = tokio::spawn(commands::run::run(args));
+
+    // Count heartbeat rewrites; the default interval would give at most one
+    let hb = ctx.root.join("hb").join(format!("{}.json", NODE));
+    let mut writes = std::collections::HashSet::new();
+    let start = std::time::Instant::now();
+    while start.elapsed() < Duration::from_millis(1500) {
+        if let Ok(mtime) = fs::metadata(&hb).and_then(|m| m.modified()) {
+            writes.insert(mtime);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    while ctx.result("T-CFG").is_none() && start.elapsed() < Duration::from_secs(10) {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    runner.abort();
+
+    assert!(writes.len() >= 5, "heartbeat written {} times in 1.5s", writes.len());
+    let res = ctx.result("T-CFG").expect("result should be written");
+    assert_eq!(PathBuf::from(&res.stdout), scratch.join("T-CFG.out"));
+    assert_eq!(fs::read_to_string(scratch.join("T-CFG.out"))?, "configured\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_on_success_queues_follow_up() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut ok = spec("T-FIRST", "true");
+    ok.seq = 1;
+    ok.on_success = Some("echo chained".to_string());
+    ok.on_failure = Some("echo rescue".to_string());
+    ctx.enqueue(&ok)?;
+    // A chain that has gone on too long is cut off
+    let mut deep = spec("T-DEEP", "true");
+    deep.seq = 2;
+    deep.chain_depth = models::MAX_CHAIN_DEPTH;
+    deep.on_success = Some("echo too-deep".to_string());
+    ctx.enqueue(&deep)?;
+
+    ctx.run_for(Duration::from_secs(10)).await;
+
+    let done = ctx.root.join("done").join(NODE);
+    let mut follow_ups = Vec::new();
+    for f in lfs::list_files_sorted(&done)? {
+        if models::DoneFileKind::of(&f) == models::DoneFileKind::Spec {
+            let s: models::TaskSpec = lfs::read_json(&f)?;
+            if s.task_id != "T-FIRST" && s.task_id != "T-DEEP" {
+                follow_ups.push(s);
+            }
+        }
+    }
+    assert_eq!(follow_ups.len(), 1, "{:?}", follow_ups.iter().map(|s| &s.command).collect::<Vec<_>>());
+    let next = &follow_ups[0];
+    assert_eq!(next.command, "echo chained");
+    assert_eq!(next.chain_depth, 1);
+    assert_eq!(next.cwd, ok.cwd);
+    assert_eq!(ctx.result(&next.task_id).map(|r| r.exit_code), Some(0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify_detects_and_fixes_claimed_but_done() -> Result<()> {
+    use commands::verify::{self, Issue};
+
+    let ctx = TestContext::new()?;
+    let queued = ctx.enqueue(&spec("T-DONE", "echo done"))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+    assert!(ctx.result("T-DONE").is_some());
+    assert!(verify::verify(&ctx.root)?.is_empty());
+
+    // A runner that died after writing the result but before archiving
+    let filename = queued.file_name().unwrap();
+    let claimed = ctx.root.join("claimed").join(NODE).join(filename);
+    fs::rename(ctx.root.join("done").join(NODE).join(filename), &claimed)?;
+    let orphan = ctx.root.join("logs").join("T-GONE.out");
+    fs::write(&orphan, "left behind")?;
+    let stray = ctx.root.join("hb").join("node-gone.json");
+    fs::write(&stray, "{}")?;
+    let mut dup = spec("T-DUP", "true");
+    dup.idempotency_key = "key-T-DONE".to_string();
+    dup.seq = 2;
+    let dup_path = ctx.enqueue(&dup)?;
+
+    let issues = verify::verify(&ctx.root)?;
+    assert_eq!(issues.len(), 4, "{:?}", issues);
+    assert!(matches!(&issues[0], Issue::ClaimedButDone { claimed: c, .. } if *c == claimed));
+    assert!(issues.contains(&Issue::OrphanedLog { path: orphan.clone() }));
+    assert!(issues.contains(&Issue::StrayHeartbeat { node: "node-gone".to_string(), path: stray.clone() }));
+    assert!(issues.iter().any(|i| matches!(i, Issue::DuplicateKey { key, files } if key == "key-T-DONE" && files.contains(&dup_path))));
+
+    // While the node's runner is live it may be about to archive the spec itself
+    assert!(verify::fix_issue(&ctx.root, &issues[0]).is_err());
+    assert!(claimed.exists());
+    let hb_path = ctx.root.join("hb").join(format!("{}.json", NODE));
+    let mut hb = lfs::read_heartbeat(&hb_path)?;
+    hb.ts -= time::Duration::hours(1);
+    lfs::atomic_write_json(&hb_path, &hb)?;
+
+    for issue in issues.iter().filter(|i| i.fixable()) {
+        verify::fix_issue(&ctx.root, issue)?;
+    }
+    assert!(!claimed.exists());
+    assert!(ctx.root.join("done").join(NODE).join(filename).exists());
+    assert!(!orphan.exists());
+    assert!(!stray.exists());
+    assert!(dup_path.exists(), "duplicates are only reported");
+
+    let remaining = verify::verify(&ctx.root)?;
+    assert_eq!(remaining.len(), 1);
+    assert!(!remaining[0].fixable());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_logs_strip_ansi() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let command = r"printf '\033[1;32mPASS\033[0m step 1\n\033]0;title\007\033[2K\033[Aepoch 2 \033[31mloss\033[0m\n'";
+    ctx.enqueue(&spec("T-ANSI", command))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+    assert_eq!(ctx.result("T-ANSI").expect("result should be written").exit_code, 0);
+
+    let path = commands::logs::log_path(&ctx.root, "T-ANSI", false);
+    let mut out = Vec::new();
+    commands::logs::write_log(&path, None, true, &mut out)?;
+    let text = String::from_utf8(out)?;
+    assert_eq!(text, "PASS step 1\nepoch 2 loss\n");
+    assert!(!text.contains('\x1b'));
+
+    let mut out = Vec::new();
+    commands::logs::write_log(&path, Some(1), false, &mut out)?;
+    assert!(String::from_utf8(out)?.contains("\x1b[31mloss"), "kept unless asked to strip");
+    Ok(())
+}
+
+#[test]
+fn test_logs_render_html() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let log = dir.path().join("T-HTML.out");
+    fs::write(&log, "step <1> & \"two\"\n\x1b[1;31mFAIL\x1b[0m done\n\x1b]0;title\x07\x1b[38;5;208morange\x1b[39m\n")?;
+    let out = dir.path().join("T-HTML.html");
+    commands::logs::write_html(&log, None, &out)?;
+
+    let html = fs::read_to_string(&out)?;
+    assert!(html.starts_with("<!DOCTYPE html>"), "{}", html);
+    assert!(html.contains("<title>T-HTML.out</title>"), "{}", html);
+    assert!(html.trim_end().ends_with("</html>"), "{}", html);
+    // Log text is escaped, colors become spans, other escapes vanish
+    assert!(html.contains("step &lt;1&gt; &amp; &quot;two&quot;"), "{}", html);
+    assert!(html.contains("<span style=\"color: #cd0000; font-weight: bold\">FAIL</span> done"), "{}", html);
+    assert!(html.contains("<span style=\"color: #ff8700\">orange</span>"), "{}", html);
+    assert!(!html.contains('\x1b') && !html.contains("title\x07"), "{}", html);
+    assert_eq!(html.matches("<span").count(), html.matches("</span>").count());
+    assert_eq!(html.matches("<pre>").count(), 1);
+    assert_eq!(html.matches("</pre>").count(), 1);
+
+    // Private to its owner, and never written through a planted symlink
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(fs::metadata(&out)?.permissions().mode() & 0o777, 0o600);
+    let target = dir.path().join("victim");
+    fs::write(&target, "keep")?;
+    let link = dir.path().join("planted.html");
+    std::os::unix::fs::symlink(&target, &link)?;
+    assert!(commands::logs::write_html(&log, None, &link).is_err());
+    assert_eq!(fs::read_to_string(&target)?, "keep");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_version_json_and_heartbeat_carry_schema_version() -> Result<()> {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_leaseq")).args(["version", "--json"]).output()?;
+    assert!(output.status.success());
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(info["crate_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(info["schema_version"], models::SCHEMA_VERSION);
+    assert_eq!(info["lease_types"], serde_json::json!(["local", "slurm"]));
+
+    let ctx = TestContext::new()?;
+    ctx.run_for(Duration::from_millis(500)).await;
+    let hb = lfs::read_heartbeat(ctx.root.join("hb").join(format!("{}.json", NODE)))?;
+    assert_eq!(hb.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(hb.schema_version, models::SCHEMA_VERSION);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backfill_runs_small_gpu_task_in_gap() -> Result<()> {
+    async fn run_with_one_free_gpu(backfill_limit: Option<u32>) -> Result<(bool, bool)> {
+        let ctx = TestContext::new()?;
+        commands::lease::update_settings(&ctx.root, |s| s.backfill_limit = backfill_limit)?;
+        // Another runner on the node holds one of its two GPUs
+        leaseq_core::gpu::allocate(&ctx.root, NODE, "T-OTHER", 1, 2)?;
+
+        let mut head = spec("T-BIG", "true");
+        head.gpus = 2;
+        ctx.enqueue(&head)?;
+        let mut follower = spec("T-SMALL", "true");
+        follower.gpus = 1;
+        follower.seq = 2;
+        ctx.enqueue(&follower)?;
+
+        let args = commands::run::RunArgs {
+            lease: "local:test".to_string(),
+            node: Some(NODE.to_string()),
+            root: Some(ctx.root.clone()),
+            config: Some(leaseq_core::config::RunConfig { gpus: Some(2), ..Default::default() }),
+        };
+        let _ = tokio::time::timeout(Duration::from_secs(2), commands::run::run(args)).await;
+        Ok((ctx.result("T-BIG").is_some(), ctx.result("T-SMALL").is_some()))
+    }
+
+    // The 1-GPU follower fits the free GPU and runs; the head keeps waiting
+    assert_eq!(run_with_one_free_gpu(None).await?, (false, true));
+    // Without backfill the head waits in the inbox and holds the others back
+    assert_eq!(run_with_one_free_gpu(Some(0)).await?, (false, false));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gpu_task_stays_in_inbox_until_gpus_free() -> Result<()> {
+    let ctx = TestContext::new()?;
+    leaseq_core::gpu::allocate(&ctx.root, NODE, "T-OTHER", 1, 2)?;
+    let mut task = spec("T-WIDE", "echo \"gpu=$CUDA_VISIBLE_DEVICES\"");
+    task.gpus = 2;
+    let queued = ctx.enqueue(&task)?;
+
+    let args = || commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig { gpus: Some(2), ..Default::default() }),
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(2), commands::run::run(args())).await;
+    // Not claimed while one of the two GPUs is taken
+    assert!(queued.exists());
+    assert!(lfs::list_files_sorted(ctx.root.join("claimed").join(NODE))?.is_empty());
+
+    leaseq_core::gpu::release(&ctx.root, NODE, "T-OTHER")?;
+    let _ = tokio::time::timeout(Duration::from_secs(2), commands::run::run(args())).await;
+    let result = ctx.result("T-WIDE").expect("ran once the GPUs were free");
+    assert_eq!(result.gpus_assigned, "0,1");
+    let out = fs::read_to_string(ctx.root.join(&result.stdout))?;
+    assert_eq!(out, "gpu=0,1\n");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_parallel_slots_dont_claim_the_same_gpus() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut first = spec("T-GPU-A", "sleep 2");
+    first.gpus = 2;
+    ctx.enqueue(&first)?;
+    let mut second = spec("T-GPU-B", "true");
+    second.gpus = 2;
+    second.seq = 2;
+    let queued = ctx.enqueue(&second)?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig {
+            gpus: Some(2),
+            parallel: 2,
+            poll_interval: Duration::from_millis(50),
+            ..Default::default()
+        }),
+    };
+    let runner = tokio::spawn(commands::run::run(args));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    // The free slot doesn't take the second task while the first holds both GPUs
+    let claimed = lfs::list_files_sorted(ctx.root.join("claimed").join(NODE))?;
+    assert_eq!(claimed.len(), 1, "{:?}", claimed);
+    assert!(claimed[0].to_string_lossy().contains("T-GPU-A"));
+    assert!(queued.exists());
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while ctx.result("T-GPU-B").is_none() && std::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    runner.abort();
+    assert_eq!(ctx.result("T-GPU-A").map(|r| r.gpus_assigned), Some("0,1".to_string()));
+    assert_eq!(ctx.result("T-GPU-B").map(|r| r.gpus_assigned), Some("0,1".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tasks_include_logs_size() -> Result<()> {
+    use commands::tasks::{collect_tasks, render_table, TableLayout, TaskListOptions, TaskStateFilter};
+
+    let ctx = TestContext::new()?;
+    ctx.enqueue(&spec("T-LOUD", "head -c 3000 /dev/zero; echo oops >&2"))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+
+    let opts = TaskListOptions { include_logs_size: true, ..Default::default() };
+    let rows = collect_tasks(&ctx.root, TaskStateFilter::All, None, None, &opts, OffsetDateTime::now_utc())?;
+    let size = |ext: &str| fs::metadata(ctx.root.join("logs").join(format!("T-LOUD.{}", ext))).map(|m| m.len());
+    assert_eq!(size("out")?, 3000);
+    // The login shell may add to stderr, so take whatever is on disk
+    assert_eq!(rows[0].logs_bytes, Some(3000 + size("err")?));
+
+    let lines = render_table(&rows, TableLayout::Fixed);
+    assert!(lines[0].contains("LOGS"));
+    assert!(lines[2].contains("K "), "{}", lines[2]);
+    assert_eq!(commands::tasks::format_bytes(512), "512B");
+    assert_eq!(commands::tasks::format_bytes(3000), "2.9K");
+    assert_eq!(commands::tasks::format_bytes(5 << 20), "5.0M");
+
+    // Without the flag there is no size and no column
+    let rows = collect_tasks(&ctx.root, TaskStateFilter::All, None, None, &TaskListOptions::default(), OffsetDateTime::now_utc())?;
+    assert_eq!(rows[0].logs_bytes, None);
+    assert!(!render_table(&rows, TableLayout::Fixed)[0].contains("LOGS"));
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cpus_limit_concurrent_tasks() -> Result<()> {
+    let ctx = TestContext::new()?;
+    // Three runners on a node reporting 4 CPUs
+    let runners: Vec<_> = (0..3)
+        .map(|_| {
+            let args = commands::run::RunArgs {
+                lease: "local:test".to_string(),
+                node: Some(NODE.to_string()),
+                root: Some(ctx.root.clone()),
+                config: Some(leaseq_core::config::RunConfig {
+                    cpus: Some(4),
+                    poll_interval: Duration::from_millis(100),
+                    ..Default::default()
+                }),
+            };
+            tokio::spawn(commands::run::run(args))
+        })
+        .collect();
+    // Let every runner finish its startup recovery before tasks show up
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let running = ctx.root.join("running");
+    fs::create_dir_all(&running)?;
+    let counts = ctx.root.join("counts");
+    let ids = ["T1", "T2", "T3", "T4"];
+    for (i, id) in ids.iter().enumerate() {
+        let dir = running.join(id);
+        let mut task = spec(
+            id,
+            &format!(
+                "mkdir {dir:?} && ls {running:?} | wc -l >> {counts:?}; sleep 1; rmdir {dir:?}",
+                dir = dir,
+                running = running,
+                counts = counts
+            ),
+        );
+        task.cpus = 2;
+        task.seq = i as u64 + 1;
+        ctx.enqueue(&task)?;
+    }
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(15);
+    while ids.iter().any(|id| ctx.result(id).is_none()) && std::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    for runner in &runners {
+        runner.abort();
+    }
+
+    for id in ids {
+        assert_eq!(ctx.result(id).map(|r| r.exit_code), Some(0), "task {}", id);
+    }
+    let seen: Vec<u32> = fs::read_to_string(&counts)?.lines().map(|l| l.trim().parse().unwrap()).collect();
+    assert_eq!(seen.len(), 4);
+    assert_eq!(seen.iter().max(), Some(&2), "{:?}", seen);
+
+    let hb: models::Heartbeat = lfs::read_json(ctx.root.join("hb").join(format!("{}.json", NODE)))?;
+    assert_eq!(hb.cpus, 4);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_cpu_task_stays_in_inbox_until_cpus_free() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut first = spec("T-CPU-A", "sleep 2");
+    first.cpus = 3;
+    ctx.enqueue(&first)?;
+    let mut second = spec("T-CPU-B", "true");
+    second.cpus = 2;
+    second.seq = 2;
+    let queued = ctx.enqueue(&second)?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig {
+            cpus: Some(4),
+            parallel: 2,
+            poll_interval: Duration::from_millis(50),
+            ..Default::default()
+        }),
+    };
+    let runner = tokio::spawn(commands::run::run(args));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    // The free slot doesn't claim a task that would only wait for CPUs
+    let claimed = lfs::list_files_sorted(ctx.root.join("claimed").join(NODE))?;
+    assert_eq!(claimed.len(), 1, "{:?}", claimed);
+    assert!(queued.exists());
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while ctx.result("T-CPU-B").is_none() && std::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    runner.abort();
+    assert_eq!(ctx.result("T-CPU-A").map(|r| r.exit_code), Some(0));
+    assert_eq!(ctx.result("T-CPU-B").map(|r| r.exit_code), Some(0));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_follow_lines_replays_then_follows() -> Result<()> {
+    use commands::follow::{last_lines_offset, tail_follow, Replay};
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("T1.out");
+    fs::write(&path, "one\ntwo\nthree\nfour\nfive\n")?;
+
+    let mut out = Vec::new();
+    let append = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut f = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        std::io::Write::write_all(&mut f, b"six\n").unwrap();
+    };
+    let follow = tokio::time::timeout(Duration::from_millis(1500), tail_follow(&path, Replay::Lines(3), &mut out));
+    let (followed, _) = tokio::join!(follow, append);
+    assert!(followed.is_err(), "follow runs until cancelled");
+    assert_eq!(String::from_utf8(out)?, "three\nfour\nfive\nsix\n");
+
+    // More lines than the file has, and a last line without a newline
+    fs::write(&path, "a\nb")?;
+    let mut file = fs::File::open(&path)?;
+    assert_eq!(last_lines_offset(&mut file, 1)?, 2);
+    assert_eq!(last_lines_offset(&mut file, 5)?, 0);
+    assert_eq!(last_lines_offset(&mut file, 0)?, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_follow_exits_with_task_code() -> Result<()> {
+    use commands::follow::{follow_task, Replay};
+
+    let ctx = TestContext::new()?;
+    ctx.enqueue(&spec("T-FOLLOW", "echo partial; sleep 1; echo last; exit 3"))?;
+
+    let mut out = Vec::new();
+    let follow = tokio::time::timeout(
+        Duration::from_secs(10),
+        follow_task(&ctx.root, "T-FOLLOW", false, Replay::Lines(100), &mut out),
+    );
+    let (code, _) = tokio::join!(follow, ctx.run_for(Duration::from_secs(3)));
+    assert_eq!(code.expect("follow stops once the task is done")?, 3);
+    // Nothing written before the result appeared is lost
+    assert_eq!(String::from_utf8(out)?, "partial\nlast\n");
+
+    // The CLI exits with the code itself
+    let runtime = ctx.root.parent().unwrap().join("runtime");
+    let done = runtime.join("local:follow").join("done").join(NODE);
+    let result = ctx.result("T-FOLLOW").unwrap();
+    lfs::atomic_write_json(done.join("T-FOLLOW.result.json"), &result)?;
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_leaseq"))
+        .args(["follow", "--task", "T-FOLLOW", "--lease", "local:follow", "--follow-exit-code"])
+        .env("LEASEQ_RUNTIME_DIR", &runtime)
+        .status()?;
+    assert_eq!(status.code(), Some(3));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_result_records_resolved_command() -> Result<()> {
+    let ctx = TestContext::new()?;
+    commands::lease::update_settings(&ctx.root, |s| s.command_prefix = Some("nice -n 5".to_string()))?;
+    ctx.enqueue(&spec("T-TPL", "echo {{task_id}} on {{node}} ${HOME:+x}"))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+
+    let result = ctx.result("T-TPL").expect("task should finish");
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.command, "echo {{task_id}} on {{node}} ${HOME:+x}");
+    assert_eq!(result.resolved_command, "nice -n 5 bash -lc 'echo T-TPL on node-1 ${HOME:+x}'");
+    assert_eq!(fs::read_to_string(ctx.root.join("logs").join("T-TPL.out"))?, "T-TPL on node-1 x\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_archive_is_finished_not_rerun() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let runs = ctx.root.join("runs.txt");
+    let task_file = ctx.enqueue(&spec("T-ARCH", &format!("echo ran >> {:?}", runs)))?;
+    let name = task_file.file_name().unwrap();
+    let done = ctx.root.join("done").join(NODE);
+
+    // A directory where the spec should be archived makes the rename fail
+    // after the result is written
+    fs::create_dir_all(done.join(name).join("blocker"))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+    assert!(ctx.result("T-ARCH").is_some());
+    assert!(ctx.root.join("claimed").join(NODE).join(name).exists(), "spec stays claimed");
+    assert!(!ctx.root.join("inbox").join(NODE).join(name).exists());
+
+    // Still blocked: recovery leaves it claimed instead of requeueing it
+    ctx.run_for(Duration::from_secs(2)).await;
+    assert!(ctx.root.join("claimed").join(NODE).join(name).exists());
+
+    // Unblocked: recovery archives it without running the task again
+    fs::remove_dir_all(done.join(name))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+    assert!(!ctx.root.join("claimed").join(NODE).join(name).exists());
+    assert!(done.join(name).is_file());
+    assert_eq!(fs::read_to_string(&runs)?, "ran\n");
+    assert!(commands::verify::verify(&ctx.root)?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_old_pending_task_expires() -> Result<()> {
+    let ctx = TestContext::new()?;
+    commands::lease::update_settings(&ctx.root, |s| s.max_pending_age_s = Some(3600))?;
+    // Queued for a node whose runner never came up
+    let ghost_inbox = ctx.root.join("inbox").join("ghost-node");
+    fs::create_dir_all(&ghost_inbox)?;
+    let queue = |mut task: models::TaskSpec, age: time::Duration| -> Result<()> {
+        task.target_node = "ghost-node".to_string();
+        task.created_at = OffsetDateTime::now_utc() - age;
+        lfs::atomic_write_json(ghost_inbox.join(format!("{:016}_{}.json", task.seq, task.task_id)), &task)?;
+        Ok(())
+    };
+    queue(spec("T-OLD", "true"), time::Duration::hours(2))?;
+    queue(spec("T-NEW", "true"), time::Duration::minutes(5))?;
+    // The task's own limit wins over the lease's
+    let mut short = spec("T-SHORT", "true");
+    short.max_pending_s = Some(60);
+    queue(short, time::Duration::minutes(5))?;
+
+    // Any runner of the lease sweeps every node's inbox
+    ctx.run_for(Duration::from_secs(2)).await;
+
+    let done = ctx.root.join("done").join("ghost-node");
+    for id in ["T-OLD", "T-SHORT"] {
+        let result = find_result(&done, id).expect("expired task has a result");
+        assert_eq!(result.failure, Some(models::FailureReason::Expired));
+        assert!(result.checksum_ok());
+    }
+    assert!(find_result(&done, "T-NEW").is_none());
+    // Archived next to their results, and nothing left claimed
+    let archived = lfs::list_files_sorted(&done)?
+        .iter()
+        .filter(|f| models::DoneFileKind::of(f) == models::DoneFileKind::Spec)
+        .count();
+    assert_eq!(archived, 2);
+    assert!(lfs::list_files_sorted(ctx.root.join("claimed").join("ghost-node"))?.is_empty());
+    let pending: Vec<_> = lfs::list_files_sorted(&ghost_inbox)?;
+    assert_eq!(pending.len(), 1);
+    assert!(pending[0].to_string_lossy().ends_with("_T-NEW.json"));
+
+    // Nothing left to expire
+    assert!(commands::gc_pending::expire_pending(&ctx.root, OffsetDateTime::now_utc())?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stale_heartbeats_are_compacted() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let heartbeat = |node: &str, age: time::Duration| {
+        lfs::atomic_write_json(
+            ctx.root.join("hb").join(format!("{}.json", node)),
+            &models::Heartbeat {
+                node: node.to_string(),
+                ts: OffsetDateTime::now_utc() - age,
+                running_task_id: None,
+                running_task_ids: Vec::new(),
+                pending_estimate: 0,
+                runner_pid: 1,
+                version: "test".to_string(),
+                schema_version: models::SCHEMA_VERSION,
+                cpus: 0,
+                errors_since_start: 0,
+                last_error: None,
+            },
+        )
+    };
+    heartbeat("array-1", time::Duration::hours(3))?;
+    heartbeat("array-2", time::Duration::minutes(5))?;
+
+    // Without a setting the runner keeps every heartbeat
+    ctx.run_for(Duration::from_secs(2)).await;
+    assert!(ctx.root.join("hb").join("array-1.json").exists());
+
+    commands::lease::update_settings(&ctx.root, |s| s.node_gc_age_s = Some(3600))?;
+    ctx.run_for(Duration::from_secs(2)).await;
+    let left: Vec<_> = lfs::list_files_sorted(ctx.root.join("hb"))?
+        .iter()
+        .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(left, ["array-2.json".to_string(), format!("{}.json", NODE)]);
+
+    // The command takes its own age
+    let removed = commands::gc_nodes::remove_stale_heartbeats(&ctx.root, 60, OffsetDateTime::now_utc())?;
+    assert_eq!(removed, ["array-2"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_timeout_stops_long_running_task() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut slow = spec("T-SLOW", "sleep 30; echo never");
+    slow.timeout_s = Some(1);
+    ctx.enqueue(&slow)?;
+    let mut quick = spec("T-QUICK", "echo done");
+    quick.seq = 2;
+    quick.timeout_s = Some(60);
+    ctx.enqueue(&quick)?;
+
+    ctx.run_for(Duration::from_secs(6)).await;
+
+    let res = ctx.result("T-SLOW").expect("timed-out task should get a result");
+    assert_eq!(res.exit_code, models::TIMEOUT_EXIT_CODE);
+    assert_eq!(res.failure, Some(models::FailureReason::Timeout));
+    assert!(res.runtime_s < 10.0, "stopped after {}s", res.runtime_s);
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert!(!out.contains("never"), "got {:?}", out);
+
+    // Finishing inside the timeout is an ordinary run
+    let res = ctx.result("T-QUICK").expect("quick task should finish");
+    assert_eq!(res.exit_code, 0);
+    assert_eq!(res.failure, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_allow_rerun_bypasses_idempotency_dedupe() -> Result<()> {
+    let ctx = TestContext::new()?;
+    for (i, (id, allow_rerun)) in [("T-FIRST", false), ("T-DUP", false), ("T-RERUN", true)].into_iter().enumerate() {
+        let mut task = spec(id, &format!("echo {}", id));
+        task.idempotency_key = "key-shared".to_string();
+        task.seq = i as u64;
+        task.allow_rerun = allow_rerun;
+        ctx.enqueue(&task)?;
+    }
+
+    ctx.run_for(Duration::from_secs(5)).await;
+
+    assert_eq!(ctx.result("T-FIRST").expect("first run").exit_code, 0);
+    // A plain resubmit of an executed key is skipped, not run
+    assert!(ctx.result("T-DUP").is_none());
+    let skipped = lfs::list_files_sorted(ctx.root.join("done").join(NODE))?
+        .into_iter()
+        .filter(|f| models::DoneFileKind::of(f) == models::DoneFileKind::Skipped)
+        .count();
+    assert_eq!(skipped, 1);
+    let rerun = ctx.result("T-RERUN").expect("rerun should execute");
+    assert_eq!(rerun.exit_code, 0);
+    assert_eq!(fs::read_to_string(ctx.root.join(&rerun.stdout))?.lines().last(), Some("T-RERUN"));
+
+    // The shared key is intended, not an inconsistency
+    let issues = commands::verify::verify(&ctx.root)?;
+    assert!(
+        !issues.iter().any(|i| matches!(i, commands::verify::Issue::DuplicateKey { .. })),
+        "{:?}",
+        issues
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_rejects_file_as_root() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("not-a-dir");
+    fs::write(&file, "x")?;
+
+    let run = |root: PathBuf| {
+        commands::run::run(commands::run::RunArgs {
+            lease: "local:test".to_string(),
+            node: Some(NODE.to_string()),
+            root: Some(root),
+            ..Default::default()
+        })
+    };
+    let err = run(file.clone()).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("Lease root {} is not a directory", file.display()));
+    // Nothing was created next to or under it
+    assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+
+    let nested = file.join("lease");
+    let err = run(nested.clone()).await.unwrap_err();
+    assert_eq!(err.to_string(), format!("Cannot create lease root {}", nested.display()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_submit_batch_from_stdin() -> Result<()> {
+    use std::io::Write;
+
+    let ctx = TestContext::new()?;
+    let runtime = ctx._temp_dir.path().join("runtime");
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_leaseq"))
+        .args(["submit", "--batch", "-", "--lease", "local:batch", "--node", NODE, "--label", "sweep=s1"])
+        .env("LEASEQ_RUNTIME_DIR", &runtime)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"# lr sweep\npython train.py --lr 0.1\n\n  python train.py --lr 0.01\n# python train.py --lr 1\n")?;
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "Submitted 2 task(s) from stdin");
+
+    let inbox = lfs::list_files_sorted(runtime.join("local:batch").join("inbox").join(NODE))?;
+    let queued: Vec<models::TaskSpec> = inbox.iter().map(lfs::read_json).collect::<Result<_, _>>()?;
+    let mut lines: Vec<&str> = queued.iter().map(|s| s.command.as_str()).collect();
+    lines.sort();
+    assert_eq!(lines, ["python train.py --lr 0.01", "python train.py --lr 0.1"]);
+    assert_ne!(queued[0].task_id, queued[1].task_id);
+    assert_ne!(queued[0].uuid, queued[1].uuid);
+    assert_ne!(queued[0].idempotency_key, queued[1].idempotency_key);
+    assert!(queued.iter().all(|s| s.labels.get("sweep").map(String::as_str) == Some("s1")));
+
+    // Nothing left after comments is an error, not an empty submit
+    let err = commands::submit::add_batch(vec![], Some("local:batch".to_string()), Some(NODE.to_string()), &Default::default())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("No commands"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_gpu_job_expands_lease_bundle() -> Result<()> {
+    use commands::submit::{add_task_with_options, SubmitOptions};
+
+    let lease = format!("local:bundle-{}", uuid::Uuid::new_v4().simple());
+    let root = leaseq_core::config::runtime_dir().join(&lease);
+    commands::lease::update_settings(&root, |s| {
+        s.cpus_per_gpu = Some(8);
+        s.mem_per_gpu_mb = Some(32 * 1024);
+    })?;
+    let submit = |opts: SubmitOptions| {
+        let lease = lease.clone();
+        async move {
+            let opts = SubmitOptions { dry_run: true, ..opts };
+            add_task_with_options("python train.py".to_string(), Some(lease), Some(NODE.to_string()), &opts).await
+        }
+    };
+
+    let spec = submit(SubmitOptions { gpu_job: Some(2), ..Default::default() }).await?;
+    assert_eq!((spec.gpus, spec.cpus, spec.mem_mb), (2, 16, 64 * 1024));
+    let human = commands::submit::format_spec(&spec, commands::OutputFormat::Human)?;
+    assert!(human.contains("mem:      65536MB"), "{}", human);
+
+    // Explicit values win over the lease's per-GPU ones
+    let spec = submit(SubmitOptions { gpu_job: Some(2), cpus: 4, mem_per_gpu: Some(1024), ..Default::default() }).await?;
+    assert_eq!((spec.gpus, spec.cpus, spec.mem_mb), (2, 4, 2048));
+
+    // Plain --gpus takes nothing from the bundle
+    let spec = submit(SubmitOptions { gpus: 2, ..Default::default() }).await?;
+    assert_eq!((spec.gpus, spec.cpus, spec.mem_mb), (2, 0, 0));
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retry_requeues_finished_task() -> Result<()> {
+    use commands::retry::retry_task;
+
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-FLAKY", "test -e retried && echo ok || { touch retried; exit 3; }");
+    task.cwd = ctx._temp_dir.path().to_string_lossy().into_owned();
+    task.labels.insert("sweep".to_string(), "s1".to_string());
+    task.batch_id = Some("flaky".to_string());
+    ctx.enqueue(&task)?;
+    ctx.run_for(Duration::from_secs(3)).await;
+    assert_eq!(ctx.result("T-FLAKY").expect("first attempt").exit_code, 3);
+    let states = || -> Result<Vec<(String, &'static str)>> {
+        Ok(commands::group::group_members(&ctx.root, "flaky")?.into_iter().map(|m| (m.task_id, m.state)).collect())
+    };
+    assert_eq!(states()?, [("T-FLAKY".to_string(), "FAILED")]);
+
+    let retry = retry_task(&ctx.root, "local:test", "T-FLAKY", true)?;
+    assert_ne!(retry.task_id, "T-FLAKY");
+    assert_ne!(retry.uuid, task.uuid);
+    assert_ne!(retry.idempotency_key, task.idempotency_key);
+    assert_eq!(retry.target_node, NODE);
+    assert_eq!((retry.command.as_str(), retry.cwd.as_str()), (task.command.as_str(), task.cwd.as_str()));
+    assert_eq!(retry.labels, task.labels);
+    assert!(retry.allow_rerun);
+    ctx.run_for(Duration::from_secs(3)).await;
+    let res = ctx.result(&retry.task_id).expect("retry should run");
+    assert_eq!(res.exit_code, 0);
+    assert_eq!(fs::read_to_string(ctx.root.join(&res.stdout))?.lines().last(), Some("ok"));
+    // The group counts the retry that succeeded, not the attempt it replaced
+    assert_eq!(retry.retry_of.as_deref(), Some("T-FLAKY"));
+    assert_eq!(states()?, [(retry.task_id.clone(), "DONE")]);
+    assert_eq!(retry_task(&ctx.root, "local:test", &retry.task_id, true)?.retry_of.as_deref(), Some("T-FLAKY"));
+    // Like cancel and logs, a unique prefix or the UUID finds the task
+    assert_eq!(retry_task(&ctx.root, "local:test", "T-FLA", true)?.retry_of.as_deref(), Some("T-FLAKY"));
+    assert_eq!(retry_task(&ctx.root, "local:test", &task.uuid.to_string(), true)?.retry_of.as_deref(), Some("T-FLAKY"));
+
+    // Without the archived spec, the result is enough to rebuild it
+    for f in lfs::list_files_sorted(ctx.root.join("done").join(NODE))? {
+        if models::DoneFileKind::of(&f) == models::DoneFileKind::Spec {
+            fs::remove_file(f)?;
+        }
+    }
+    let rebuilt = retry_task(&ctx.root, "local:test", "T-FLAKY", true)?;
+    assert_eq!((rebuilt.command.as_str(), rebuilt.cwd.as_str()), (task.command.as_str(), task.cwd.as_str()));
+    assert!(retry_task(&ctx.root, "local:test", "T-MISSING", true).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_watch_emits_node_down_and_up() -> Result<()> {
+    use commands::watch::{check_nodes, events_log};
+
+    let ctx = TestContext::new()?;
+    let seen = OffsetDateTime::now_utc();
+    lfs::atomic_write_json(
+        ctx.root.join("hb").join("node-1.json"),
+        &models::Heartbeat {
+            node: "node-1".to_string(),
+            ts: seen,
+            running_task_id: None,
+            running_task_ids: Vec::new(),
+            pending_estimate: 0,
+            runner_pid: 1,
+            version: "test".to_string(),
+            schema_version: models::SCHEMA_VERSION,
+            cpus: 0,
+            errors_since_start: 0,
+            last_error: None,
+        },
+    )?;
+    let kinds = |events: Vec<models::Event>| -> Vec<String> {
+        events.iter().map(|e| serde_json::to_value(e).unwrap()["type"].as_str().unwrap().to_string()).collect()
+    };
+
+    // Fresh heartbeat: nothing to report
+    assert!(check_nodes(&ctx.root, seen)?.is_empty());
+    assert!(!events_log(&ctx.root, "node-1").exists());
+
+    // Stale: one NodeDown, logged once however often the watcher looks
+    let later = seen + time::Duration::minutes(5);
+    assert_eq!(kinds(check_nodes(&ctx.root, later)?), ["NODE_DOWN"]);
+    assert!(check_nodes(&ctx.root, later)?.is_empty());
+    let log = fs::read_to_string(events_log(&ctx.root, "node-1"))?;
+    assert_eq!(log.lines().count(), 1);
+    match serde_json::from_str::<models::Event>(log.trim())? {
+        models::Event::NodeDown { node, last_seen } => {
+            assert_eq!(node, "node-1");
+            assert_eq!(last_seen.unix_timestamp(), seen.unix_timestamp());
+        }
+        other => panic!("expected NodeDown, got {:?}", other),
+    }
+
+    // Heartbeating again
+    assert_eq!(kinds(check_nodes(&ctx.root, seen + time::Duration::seconds(30))?), ["NODE_UP"]);
+    assert_eq!(fs::read_to_string(events_log(&ctx.root, "node-1"))?.lines().count(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_capture_rc_writes_exit_code_file() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let work = ctx.root.join("work");
+    fs::create_dir_all(&work)?;
+    let mut failing = spec("T-RC", "exit 3");
+    failing.cwd = work.to_string_lossy().into_owned();
+    failing.capture_rc = Some(models::DEFAULT_RC_FILE.to_string());
+    ctx.enqueue(&failing)?;
+    let rc_path = ctx.root.join("rc").join("ok.rc");
+    fs::create_dir_all(rc_path.parent().unwrap())?;
+    let mut ok = spec("T-RC-OK", "true");
+    ok.seq = 2;
+    ok.capture_rc = Some(rc_path.to_string_lossy().into_owned());
+    ctx.enqueue(&ok)?;
+
+    ctx.run_for(Duration::from_secs(4)).await;
+
+    assert_eq!(ctx.result("T-RC").expect("task should finish").exit_code, 3);
+    assert_eq!(fs::read_to_string(work.join(models::DEFAULT_RC_FILE))?.trim(), "3");
+    assert_eq!(fs::read_to_string(&rc_path)?.trim(), "0");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_capture_rc_written_for_task_that_never_started() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let rc_path = ctx.root.join("rc").join("nostdin.rc");
+    fs::create_dir_all(rc_path.parent().unwrap())?;
+    let mut task = spec("T-RC-NOSTART", "cat");
+    task.stdin_file = Some(ctx.root.join("gone.txt").to_string_lossy().into_owned());
+    task.capture_rc = Some(rc_path.to_string_lossy().into_owned());
+    ctx.enqueue(&task)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let res = ctx.result("T-RC-NOSTART").expect("task should fail");
+    assert_eq!(res.failure, Some(models::FailureReason::SpawnError));
+    assert_eq!(fs::read_to_string(&rc_path)?.trim(), res.exit_code.to_string());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_argv_task_runs_without_shell() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let tricky = "it's \"$HOME\"; `true` && {{task_id}}";
+    let mut task = spec("T-ARGV", "unused");
+    task.argv = Some(vec!["printf".to_string(), "%s|".to_string(), tricky.to_string()]);
+    ctx.enqueue(&task)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let res = ctx.result("T-ARGV").expect("argv task should finish");
+    assert_eq!(res.exit_code, 0);
+    // Quotes, $ and backticks reach the program untouched; placeholders still expand
+    let out = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert_eq!(out, "it's \"$HOME\"; `true` && T-ARGV|");
+    assert!(res.resolved_command.starts_with("printf "), "{}", res.resolved_command);
+    assert!(!res.resolved_command.contains("bash"), "{}", res.resolved_command);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signal_recorded_for_killed_task() -> Result<()> {
+    use commands::tasks::{collect_tasks, render_table, TableLayout, TaskStateFilter};
+
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-SIG", "kill -KILL $$");
+    task.argv = Some(vec!["sh".to_string(), "-c".to_string(), "kill -KILL $$".to_string()]);
+    ctx.enqueue(&task)?;
+
+    ctx.run_for(Duration::from_secs(3)).await;
+
+    let res = ctx.result("T-SIG").expect("killed task should finish");
+    assert_eq!(res.signal, Some(libc::SIGKILL));
+    assert_eq!(res.exit_code, -1);
+    assert_eq!(res.killed_by().as_deref(), Some("killed by SIGKILL"));
+
+    let rows = collect_tasks(&ctx.root, TaskStateFilter::All, None, None, &Default::default(), OffsetDateTime::now_utc())?;
+    let lines = render_table(&rows, TableLayout::Fixed);
+    assert!(lines.iter().any(|l| l.contains("[killed by SIGKILL]")), "{:#?}", lines);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prefix_lines_stamps_log_lines() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-PREFIX", "unused");
+    task.argv = Some(vec!["sh".to_string(), "-c".to_string(), "echo one; echo two; echo oops >&2".to_string()]);
+    ctx.enqueue(&task)?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig { prefix_lines: true, ..Default::default() }),
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(3), commands::run::run(args)).await;
+
+    let res = ctx.result("T-PREFIX").expect("task should finish");
+    assert_eq!(res.exit_code, 0);
+    let check = |log: &str, expected: &[&str]| -> Result<()> {
+        let content = fs::read_to_string(ctx.root.join(log))?;
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), expected.len(), "{}", content);
+        for (line, text) in lines.iter().zip(expected) {
+            let rest = line.strip_prefix("[T-PREFIX ").unwrap_or_else(|| panic!("no task ID: {}", line));
+            let (ts, body) = rest.split_once("] ").unwrap_or_else(|| panic!("no timestamp: {}", line));
+            // RFC 3339 in UTC, e.g. 2026-10-16T09:30:00.123Z
+            let year = OffsetDateTime::now_utc().year().to_string();
+            assert!(ts.starts_with(&year) && ts.as_bytes()[10] == b'T' && ts.ends_with('Z'), "{}", line);
+            assert_eq!(body, *text);
+        }
+        Ok(())
+    };
+    check(&res.stdout, &["one", "two"])?;
+    check(&res.stderr, &["oops"])?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prefix_lines_does_not_wait_for_background_processes() -> Result<()> {
+    let ctx = TestContext::new()?;
+    let mut task = spec("T-PREFIX-BG", "unused");
+    // The background sleep inherits the pipes and holds them open after sh exits
+    task.argv = Some(vec!["sh".to_string(), "-c".to_string(), "sleep 30 & echo hi".to_string()]);
+    ctx.enqueue(&task)?;
+
+    let args = commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(leaseq_core::config::RunConfig { prefix_lines: true, ..Default::default() }),
+    };
+    let _ = tokio::time::timeout(Duration::from_secs(10), commands::run::run(args)).await;
+
+    let res = ctx.result("T-PREFIX-BG").expect("task should finish before its background process");
+    assert_eq!(res.exit_code, 0);
+    let stdout = fs::read_to_string(ctx.root.join(&res.stdout))?;
+    assert!(stdout.starts_with("[T-PREFIX-BG ") && stdout.ends_with("] hi\n"), "{}", stdout);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_key_index_has_one_entry_per_key_under_concurrency() -> Result<()> {
+    use leaseq_core::config::RunConfig;
+
+    let ctx = TestContext::new()?;
+    // Run directly rather than through a login shell, to keep the slots busy together
+    let sleep = |id: &str| models::TaskSpec {
+        argv: Some(vec!["sleep".to_string(), "0.3".to_string()]),
+        ..spec(id, "sleep 0.3")
+    };
+    let ids: Vec<String> = (0..8).map(|i| format!("T-KEY-{}", i)).collect();
+    for (i, id) in ids.iter().enumerate() {
+        let mut task = sleep(id);
+        task.seq = i as u64 * 2;
+        ctx.enqueue(&task)?;
+        // A duplicate of each key, claimed while the original may still run
+        let mut dup = sleep(&format!("{}-DUP", id));
+        dup.idempotency_key = task.idempotency_key.clone();
+        dup.seq = i as u64 * 2 + 1;
+        ctx.enqueue(&dup)?;
+    }
+
+    let run = |ctx: &TestContext| commands::run::RunArgs {
+        lease: "local:test".to_string(),
+        node: Some(NODE.to_string()),
+        root: Some(ctx.root.clone()),
+        config: Some(RunConfig { parallel: 4, poll_interval: Duration::from_millis(50), ..Default::default() }),
+    };
+    // Which task of a pair runs and which is skipped depends on the race
+    let finished = || {
+        lfs::list_files_sorted(ctx.root.join("done").join(NODE))
+            .unwrap_or_default()
+            .iter()
+            .filter(|f| models::DoneFileKind::of(f).is_result())
+            .count()
+    };
+    let runner = tokio::spawn(commands::run::run(run(&ctx)));
+    for _ in 0..400 {
+        if finished() == 2 * ids.len() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    runner.abort();
+
+    let index_path = commands::run::key_index_path(&ctx.root, NODE);
+    let entries: Vec<models::KeyIndexEntry> = fs::read_to_string(&index_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let mut keys: Vec<&str> = entries.iter().map(|e| e.key.as_str()).collect();
+    keys.sort();
+    let mut expected: Vec<String> = ids.iter().map(|id| format!("key-{}", id)).collect();
+    expected.sort();
+    assert_eq!(keys, expected);
+    for entry in &entries {
+        assert!(ctx.root.join("done").join(NODE).join(&entry.result).exists(), "{:?}", entry);
+    }
+
+    // A restarted runner takes indexed keys from the index, not the results
+    let first = &entries[0];
+    fs::write(ctx.root.join("done").join(NODE).join(&first.result), "not json")?;
+    let mut again = spec("T-KEY-AGAIN", "echo again");
+    again.idempotency_key = first.key.clone();
+    ctx.enqueue(&again)?;
+    let _ = tokio::time::timeout(Duration::from_secs(2), commands::run::run(run(&ctx))).await;
+    assert!(!ctx.root.join("logs").join("T-KEY-AGAIN.out").exists());
+    assert_eq!(fs::read_to_string(&index_path)?.lines().count(), entries.len());
+    Ok(())
+}
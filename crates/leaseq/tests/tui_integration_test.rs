@@ -47,9 +47,14 @@ fn test_tui_stuck_task_state() -> Result<()> {
         node: node.to_string(),
         ts: OffsetDateTime::now_utc() - time::Duration::minutes(5),
         running_task_id: Some("T1".to_string()),
+        running_task_ids: Vec::new(),
         pending_estimate: 0,
         runner_pid: 1234,
         version: "0.1.0".to_string(),
+        schema_version: models::SCHEMA_VERSION,
+        cpus: 0,
+        errors_since_start: 0,
+        last_error: None,
     };
     lfs::atomic_write_json(&hb_dir.join(format!("{}.json", node)), &hb)?;
 
@@ -66,6 +71,7 @@ fn test_tui_stuck_task_state() -> Result<()> {
         env: std::collections::HashMap::new(),
         gpus: 0,
         command: "stale job".to_string(),
+        ..Default::default()
     };
     lfs::atomic_write_json(&claimed_dir.join("task.json"), &spec)?;
 
@@ -134,6 +140,7 @@ fn test_tui_recovery_action() -> Result<()> {
         env: std::collections::HashMap::new(),
         gpus: 0,
         command: "recover me".to_string(),
+        ..Default::default()
     };
     lfs::atomic_write_json(&claimed_dir.join("task.json"), &spec)?;
 
@@ -165,4 +172,134 @@ fn test_tui_recovery_action() -> Result<()> {
     assert!(claimed_files.is_empty());
 
     Ok(())
-}
\ No newline at end of file
+}
+#[test]
+fn test_tui_too_small_terminal() -> Result<()> {
+    use leaseq::tui::ui;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    let mut app = App::new(Some("local:tiny".to_string()));
+    let screen = |terminal: &Terminal<TestBackend>| -> String {
+        terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect()
+    };
+
+    // Smaller than the fixed-height panes, in both layouts
+    for maximized in [false, true] {
+        app.logs_state.maximized = maximized;
+        let mut terminal = Terminal::new(TestBackend::new(20, 5))?;
+        terminal.draw(|f| ui::draw(f, &mut app))?;
+        assert!(screen(&terminal).contains("Terminal too small"), "{}", screen(&terminal));
+    }
+
+    // Degenerate sizes must not panic either
+    for (w, h) in [(1, 1), (ui::MIN_WIDTH, 1), (1, ui::MIN_HEIGHT)] {
+        let mut terminal = Terminal::new(TestBackend::new(w, h))?;
+        terminal.draw(|f| ui::draw(f, &mut app))?;
+    }
+
+    // Growing past the minimum brings the normal layout back
+    app.logs_state.maximized = false;
+    let mut terminal = Terminal::new(TestBackend::new(ui::MIN_WIDTH, ui::MIN_HEIGHT))?;
+    terminal.draw(|f| ui::draw(f, &mut app))?;
+    let content = screen(&terminal);
+    assert!(!content.contains("Terminal too small"));
+    assert!(content.contains("Logs"), "{}", content);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_quit_cancels_background_tasks() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let mut app = App::new(Some("local:test".to_string()));
+    let stopped = Arc::new(AtomicUsize::new(0));
+    for _ in 0..3 {
+        let stopped = stopped.clone();
+        app.spawn_background(|cancel| async move {
+            cancel.cancelled().await;
+            stopped.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    // One that ignores cancellation is aborted instead of hanging the exit
+    app.spawn_background(|_| async {
+        tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+    });
+    assert_eq!(app.background_tasks(), 4);
+
+    app.shutdown().await;
+    assert!(app.cancel.is_cancelled());
+    assert_eq!(stopped.load(Ordering::SeqCst), 3);
+    assert_eq!(app.background_tasks(), 0);
+}
+
+#[test]
+fn test_activity_feed_is_time_ordered() -> Result<()> {
+    use leaseq::tui::activity::{self, ActivityKind};
+    use leaseq::tui::ui;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    let ctx = TestContext::new()?;
+    let lease_id = "local:tui-activity";
+    let runs_dir = ctx.runtime.join(lease_id);
+    let now = OffsetDateTime::now_utc();
+
+    let finish = |node: &str, task_id: &str, kind: &str, ago_min: i64, runtime_min: i64, exit_code: i32| {
+        let result = models::TaskResult {
+            task_id: task_id.to_string(),
+            idempotency_key: format!("key-{}", task_id),
+            node: node.to_string(),
+            started_at: now - time::Duration::minutes(ago_min + runtime_min),
+            finished_at: now - time::Duration::minutes(ago_min),
+            exit_code,
+            stdout: String::new(),
+            stderr: String::new(),
+            runtime_s: (runtime_min * 60) as f64,
+            command: "true".to_string(),
+            resolved_command: String::new(),
+            cwd: ".".to_string(),
+            gpus_requested: 0,
+            gpus_assigned: String::new(),
+            checksum: None,
+            failure: None,
+            signal: None,
+        };
+        let dir = runs_dir.join("done").join(node);
+        lfs::atomic_write_json(dir.join(format!("{}.{}.json", task_id, kind)), &result)
+    };
+    // Directory order (node-a first) is not time order
+    finish("node-b", "T-OLD", "result", 9, 1, 0)?;
+    finish("node-a", "T-BAD", "result", 4, 1, 1)?;
+    finish("node-a", "T-CXL", "cancelled", 3, 0, -1)?;
+    let running = models::TaskSpec { task_id: "T-RUN".to_string(), ..Default::default() };
+    lfs::atomic_write_json(runs_dir.join("claimed").join("node-b").join("T-RUN.json"), &running)?;
+
+    let feed = activity::collect(&runs_dir);
+    let seen: Vec<_> = feed.iter().map(|a| (a.kind, a.task_id.as_str())).collect();
+    assert_eq!(
+        seen,
+        [
+            (ActivityKind::Started, "T-OLD"),
+            (ActivityKind::Finished, "T-OLD"),
+            (ActivityKind::Started, "T-BAD"),
+            (ActivityKind::Failed, "T-BAD"),
+            (ActivityKind::Cancelled, "T-CXL"),
+            (ActivityKind::Claimed, "T-RUN"),
+        ]
+    );
+    assert!(feed.windows(2).all(|w| w[0].at <= w[1].at));
+
+    // Only collected, and drawn, once the pane is toggled on
+    let mut app = App::new(Some(lease_id.to_string()));
+    app.refresh_data();
+    assert!(app.activity.is_empty());
+    app.show_activity = true;
+    app.refresh_data();
+    assert_eq!(app.activity, feed);
+    let mut terminal = Terminal::new(TestBackend::new(120, 30))?;
+    terminal.draw(|f| ui::draw(f, &mut app))?;
+    let content: String = terminal.backend().buffer().content().iter().map(|c| c.symbol()).collect();
+    assert!(content.contains("Activity"), "{}", content);
+    assert!(content.contains("FAILED    T-BAD on node-a"), "{}", content);
+    Ok(())
+}